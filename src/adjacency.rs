@@ -0,0 +1,649 @@
+//! Which token may follow which, in one table.
+//!
+//! [`Calculator::parse`] and [`Calculator::verify_tokens`] both walk the
+//! token stream through [`adjacency`], bracketed by the `Start` and `End`
+//! pseudo-tokens. The table also decides when a `+` or `-` is unary and
+//! where implicit multiplication applies. Every (previous, next) pair is
+//! listed in `adjacency.txt` and checked by an exhaustive test, so a new
+//! token class can't fall through to a default.
+//!
+//! Because the verdict depends only on the pair, `(1+2)-(3+4)` can never
+//! become `(1+2)*(-(3+4))`: a sign after `)` is always binary, and only a
+//! number, a `(` or a function name right after an operand takes an
+//! implicit `*`.
+//!
+//! One pair is decided before tokens exist: a number literal with a name
+//! written straight against it, as in `3pi` or `2abc`. [`glued`] says
+//! whether that is two tokens or one malformed number.
+
+use std::fmt;
+
+use crate::custom::Registry;
+use crate::environment::ANS;
+use crate::{Calculator, Error, Function, NormalizationChange, Operator, Span, Token, function};
+
+/// The part of a token that matters for adjacency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenClass {
+    /// Before the first token.
+    Start,
+    /// A number, a constant or a variable.
+    Number,
+    /// `+` or `-` as tokenized: binary unless the table says otherwise.
+    Sign,
+    /// `*`, `/`, `//`, `%`, `^` or a registered operator.
+    Operator,
+    /// A sign already resolved to a prefix operator.
+    Unary,
+    /// A function name, built in or registered, which must be followed by
+    /// its `(`.
+    Function,
+    Open,
+    /// `)`, or a postfix `%`, which likewise ends the operand before it.
+    Close,
+    /// `name =`, which starts an expression or bracket.
+    Assign,
+    /// `,` between arguments, which an argument follows as it would a `(`.
+    Comma,
+    /// After the last token.
+    End,
+}
+
+impl TokenClass {
+    /// Every class; keep in step with the enum when adding one.
+    #[cfg(test)]
+    const ALL: [TokenClass; 11] = [
+        TokenClass::Start,
+        TokenClass::Number,
+        TokenClass::Sign,
+        TokenClass::Operator,
+        TokenClass::Unary,
+        TokenClass::Function,
+        TokenClass::Open,
+        TokenClass::Close,
+        TokenClass::Assign,
+        TokenClass::Comma,
+        TokenClass::End,
+    ];
+
+    fn of(token: &Token) -> Self {
+        match token {
+            Token::Number(_) | Token::Identifier(_) => TokenClass::Number,
+            Token::Op(Operator::Add | Operator::Subtract) => TokenClass::Sign,
+            Token::Op(
+                Operator::Multiply
+                | Operator::Divide
+                | Operator::FloorDivide
+                | Operator::Modulo
+                | Operator::Power,
+            ) => TokenClass::Operator,
+            Token::Custom { .. } => TokenClass::Operator,
+            Token::Op(Operator::Negate) => TokenClass::Unary,
+            Token::Function { .. } | Token::Call { .. } => TokenClass::Function,
+            Token::Bracket('(') => TokenClass::Open,
+            Token::Bracket(_) | Token::Op(Operator::Percent) => TokenClass::Close,
+            Token::Assign(_) => TokenClass::Assign,
+            Token::Comma => TokenClass::Comma,
+        }
+    }
+
+    #[cfg(test)]
+    fn name(self) -> &'static str {
+        match self {
+            TokenClass::Start => "start",
+            TokenClass::Number => "number",
+            TokenClass::Sign => "sign",
+            TokenClass::Operator => "operator",
+            TokenClass::Unary => "unary",
+            TokenClass::Function => "function",
+            TokenClass::Open => "open",
+            TokenClass::Close => "close",
+            TokenClass::Assign => "assign",
+            TokenClass::Comma => "comma",
+            TokenClass::End => "end",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Adjacency {
+    Allowed,
+    /// Allowed once the sign is read as unary: `-` becomes
+    /// `Operator::Negate` and `+` is dropped.
+    AsUnary,
+    /// A sign straight after a binary operator: allowed as a `-`, read as
+    /// `Operator::Negate`, so `3 - -2` and `2 ^ -1` work, but rejected as a
+    /// `+`, which would do nothing, as in `2 + + 3`.
+    Negation,
+    /// An operand followed by another operand: allowed with implicit
+    /// multiplication, which inserts a `*` between them.
+    Implicit,
+    /// Reported as `Error::UnexpectedToken` at the token, or as
+    /// `Error::InvalidExpression` for input without any.
+    Rejected,
+    /// `Start` as the next token or `End` as the previous one; the walk
+    /// never asks for these.
+    Impossible,
+}
+
+/// How the lexer reads a number literal with a name written straight
+/// against it, with no space between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Glued {
+    /// The literal, then the name as a token of its own, which the table
+    /// then judges as any `number` followed by a `number` or `function`.
+    Factors,
+    /// One malformed number, reported as `Error::InvalidNumber` covering
+    /// the literal and the name.
+    Malformed,
+}
+
+/// The rule for `name` written against a literal, `called` if a `(`
+/// follows it. Only with implicit multiplication, and only for a name
+/// known before evaluation (a constant, `ans`, or a function built in or
+/// in `registry` that is called), are they two factors, as in `3pi` and
+/// `2sqrt(4)`. Anything else, such as `2abc` or `2pi` without implicit
+/// multiplication, is one malformed number, rather than `2` next to a
+/// variable that fails later at evaluation. A literal suffix, the `i` of
+/// `3i`, is read before this is asked.
+pub(crate) fn glued(
+    name: &str,
+    called: bool,
+    implicit_multiplication: bool,
+    registry: &Registry,
+) -> Glued {
+    let known = function::constant(name).is_some()
+        || name == ANS
+        || (called && (Function::from_name(name).is_some() || registry.function(name).is_some()));
+    if implicit_multiplication && known {
+        Glued::Factors
+    } else {
+        Glued::Malformed
+    }
+}
+
+pub(crate) fn adjacency(prev: TokenClass, next: TokenClass) -> Adjacency {
+    use Adjacency::*;
+    use TokenClass::*;
+
+    match (prev, next) {
+        (End, _) | (_, Start) => Impossible,
+
+        // After an operand: a binary operator, a closing paren, the next
+        // argument or the end.
+        (Number | Close, Sign | Operator | Close | Comma | End) => Allowed,
+        (Number | Close, Number | Function | Open) => Implicit,
+        // Signs are never unary here, so nothing produces this pair.
+        (Number | Close, Unary) => Rejected,
+        // `2 x = 3` and `(1) x = 3`: an assignment is never a factor.
+        (Number | Close, Assign) => Rejected,
+
+        // A function name only ever opens its argument list.
+        (Function, Open) => Allowed,
+        (Function, _) => Rejected,
+
+        // Where an operand is expected: a number, an opening paren, a
+        // function call or a prefix operator, which is what a sign here
+        // must be.
+        (
+            Start | Sign | Operator | Unary | Open | Assign | Comma,
+            Number | Unary | Function | Open,
+        ) => Allowed,
+        (Sign | Operator, Sign) => Negation,
+        (Start | Unary | Open | Assign | Comma, Sign) => AsUnary,
+        (
+            Start | Sign | Operator | Unary | Open | Assign | Comma,
+            Operator | Close | Comma | End,
+        ) => Rejected,
+
+        // An assignment takes everything after it, so it may only start
+        // an expression or a bracket: `1 + x = 2` is rejected, not read as
+        // `1 + (x = 2)`.
+        (Start | Open | Assign | Comma, Assign) => Allowed,
+        (Sign | Operator | Unary, Assign) => Rejected,
+    }
+}
+
+/// Input that parsed, but perhaps not as its author meant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// An implicit multiplication right after a division or remainder, as
+    /// in `6 / 2(1 + 2)`. It is read left to right, as `(6 / 2) * (1 + 2)`,
+    /// though many readers expect `6 / (2 * (1 + 2))`. `position` is the
+    /// character (in the original input) that the `*` was inserted before.
+    AmbiguousImplicitMultiplication { position: usize },
+    /// A `%` followed by a sign and an operand, as in `10 % -3`. It is read
+    /// as a percentage, `(10%) - 3`, like any `%` before an operator,
+    /// though `10 % (-3)` may have been meant. `position` is the `%`'s, in
+    /// the original input.
+    AmbiguousPercent { position: usize },
+    /// A character [`Calculator::normalize`](crate::Calculator::normalize)
+    /// rewrote or dropped before tokenizing, such as an en dash read as `-`.
+    Normalized(NormalizationChange),
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::AmbiguousImplicitMultiplication { position } => write!(
+                f,
+                "implicit multiplication after a division at position {} is read left to right",
+                position
+            ),
+            ParseWarning::AmbiguousPercent { position } => write!(
+                f,
+                "'%' at position {} is read as a percentage, not a remainder",
+                position
+            ),
+            ParseWarning::Normalized(change) if change.to.is_empty() => {
+                write!(f, "'{}' at position {} is ignored", change.from, change.pos)
+            }
+            ParseWarning::Normalized(change) => write!(
+                f,
+                "'{}' at position {} is read as '{}'",
+                change.from, change.pos, change.to
+            ),
+        }
+    }
+}
+
+/// What [`walk`] does with verdicts that are only conditionally allowed.
+#[derive(Debug, Clone, Copy)]
+struct Mode {
+    /// Read signs in operand position as prefix operators, rather than
+    /// rejecting them like any other disallowed pair.
+    resolve_signs: bool,
+    /// Insert `*` where the table allows implicit multiplication.
+    implicit_multiplication: bool,
+}
+
+/// A walked token stream, plus the `extra` data of every token that an
+/// ambiguous implicit `*` was inserted before.
+type Walked<S> = (Vec<(Token, S)>, Vec<S>);
+
+/// Walks `tokens` through the table, carrying each token's `extra` data
+/// along; an inserted `*` gets a copy of the next token's. A rejected
+/// stream gives the `extra` data of the token that can't follow the one
+/// before it, or of the last token if the stream can't end there; `None`
+/// only if it is empty.
+fn walk<S: Clone>(
+    tokens: impl IntoIterator<Item = (Token, S)>,
+    mode: Mode,
+) -> Result<Walked<S>, Option<S>> {
+    let mut resolved = Vec::new();
+    let mut ambiguous = Vec::new();
+    let mut prev = TokenClass::Start;
+    let mut last = None;
+    // The last binary operator seen at each bracket depth, innermost last,
+    // to spot an implicit `*` that follows a division.
+    let mut binary: Vec<Option<Operator>> = vec![None];
+    for (token, extra) in tokens {
+        let next = TokenClass::of(&token);
+        let verdict = adjacency(prev, next);
+        match verdict {
+            Adjacency::Allowed => {}
+            Adjacency::AsUnary | Adjacency::Negation
+                if mode.resolve_signs
+                    && (verdict == Adjacency::AsUnary
+                        || token == Token::Op(Operator::Subtract)) =>
+            {
+                last = Some(extra.clone());
+                if token == Token::Op(Operator::Subtract) {
+                    resolved.push((Token::Op(Operator::Negate), extra));
+                }
+                prev = TokenClass::Unary;
+                continue;
+            }
+            Adjacency::Implicit if mode.implicit_multiplication => {
+                let last = binary.last_mut().expect("depth never drops below zero");
+                if matches!(
+                    last,
+                    Some(Operator::Divide | Operator::FloorDivide | Operator::Modulo)
+                ) {
+                    ambiguous.push(extra.clone());
+                }
+                *last = Some(Operator::Multiply);
+                resolved.push((Token::Op(Operator::Multiply), extra.clone()));
+            }
+            _ => return Err(Some(extra)),
+        }
+        last = Some(extra.clone());
+        match (&token, next) {
+            (Token::Op(op), TokenClass::Sign | TokenClass::Operator) => {
+                *binary.last_mut().expect("depth never drops below zero") = Some(op.clone());
+            }
+            (_, TokenClass::Open) => binary.push(None),
+            // A division in one argument says nothing about the next.
+            (_, TokenClass::Comma) => {
+                *binary.last_mut().expect("depth never drops below zero") = None
+            }
+            // `verify_tokens` doesn't check balance, so a `)` may close
+            // nothing.
+            (Token::Bracket(_), TokenClass::Close) if binary.len() > 1 => {
+                binary.pop();
+            }
+            _ => {}
+        }
+        resolved.push((token, extra));
+        prev = next;
+    }
+    match adjacency(prev, TokenClass::End) {
+        Adjacency::Allowed => Ok((resolved, ambiguous)),
+        _ => Err(last),
+    }
+}
+
+/// Tokens, their spans, and warnings about how they were read.
+pub(crate) type Parsed = (Vec<Token>, Vec<Span>, Vec<ParseWarning>);
+
+/// Validates freshly tokenized input, resolves unary signs and, if asked,
+/// fills in implicit multiplication, keeping `spans` in step with the
+/// tokens; used by `parse`.
+pub(crate) fn resolve(
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    implicit_multiplication: bool,
+) -> Result<Parsed, Error> {
+    let mode = Mode {
+        resolve_signs: true,
+        implicit_multiplication,
+    };
+    let (resolved, ambiguous) =
+        walk(tokens.into_iter().zip(spans), mode).map_err(|rejected| match rejected {
+            Some(span) => Error::UnexpectedToken { pos: span.start },
+            None => Error::InvalidExpression,
+        })?;
+    let (tokens, spans) = resolved.into_iter().unzip();
+    let warnings = ambiguous
+        .into_iter()
+        .map(|span| ParseWarning::AmbiguousImplicitMultiplication {
+            position: span.start,
+        })
+        .collect();
+    Ok((tokens, spans, warnings))
+}
+
+/// Where [`resolve`] would reject freshly tokenized input, if it would.
+pub(crate) fn rejected(
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    implicit_multiplication: bool,
+) -> Option<Span> {
+    let mode = Mode {
+        resolve_signs: true,
+        implicit_multiplication,
+    };
+    walk(tokens.into_iter().zip(spans), mode).err().flatten()
+}
+
+impl Calculator {
+    /// Checks that every token may follow the one before it, without
+    /// evaluating. Bracket balance is `parse`'s job and is not checked here.
+    /// Unary minus must already be `Operator::Negate`, and implicit
+    /// multiplication already spelled out, as `parse` leaves them; a
+    /// `Subtract` in operand position or a `(` straight after an operand is
+    /// rejected.
+    pub fn verify_tokens(tokens: &[Token]) -> Result<(), Error> {
+        let mode = Mode {
+            resolve_signs: false,
+            implicit_multiplication: false,
+        };
+        walk(tokens.iter().map(|token| (token.clone(), ())), mode)
+            .map(|_| ())
+            .map_err(|_| Error::InvalidExpression)
+    }
+
+    /// The index of the token that [`Calculator::verify_tokens`] rejects:
+    /// the first that can't follow the one before it, as the second `*` in
+    /// `5 * * 2`, or the last if the stream can't end there, as the `*` in
+    /// `5 *`. An empty stream is rejected at 0.
+    pub fn rejected_token(tokens: &[Token]) -> Option<usize> {
+        let mode = Mode {
+            resolve_signs: false,
+            implicit_multiplication: false,
+        };
+        walk(tokens.iter().cloned().zip(0..), mode)
+            .err()
+            .map(|at| at.unwrap_or(0))
+    }
+
+    /// Whether `expr` would parse, without evaluating it.
+    pub fn verify<T: AsRef<str>>(expr: T) -> Result<(), Error> {
+        Self::parse(expr).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EvalOptions;
+
+    const EXPECTATIONS: &str = include_str!("adjacency.txt");
+
+    #[test]
+    fn test_every_pair_matches_expectations() {
+        let expected: Vec<(&str, &str, &str)> = EXPECTATIONS
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                assert_eq!(fields.len(), 3, "malformed expectation: {:?}", line);
+                (fields[0], fields[1], fields[2])
+            })
+            .collect();
+
+        let mut checked = 0;
+        for prev in TokenClass::ALL {
+            for next in TokenClass::ALL {
+                let verdict = match adjacency(prev, next) {
+                    Adjacency::Allowed => "allowed",
+                    Adjacency::AsUnary => "unary",
+                    Adjacency::Negation => "negation",
+                    Adjacency::Implicit => "implicit",
+                    Adjacency::Rejected => "rejected",
+                    Adjacency::Impossible => continue,
+                };
+                let listed = expected
+                    .iter()
+                    .filter(|(p, n, _)| *p == prev.name() && *n == next.name())
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    listed.len(),
+                    1,
+                    "{} -> {} must be listed exactly once in adjacency.txt",
+                    prev.name(),
+                    next.name()
+                );
+                assert_eq!(listed[0].2, verdict, "{} -> {}", prev.name(), next.name());
+                checked += 1;
+            }
+        }
+        // Nothing listed that the table doesn't know about.
+        assert_eq!(checked, expected.len());
+    }
+
+    #[test]
+    fn test_glued_names() {
+        let implicit = EvalOptions {
+            implicit_multiplication: true,
+            ..EvalOptions::default()
+        };
+        let invalid = |literal: &str| {
+            Err(Error::InvalidNumber {
+                literal: literal.to_string(),
+                pos: 0,
+            })
+        };
+        let undefined = |name: &str| Err(Error::UndefinedVariable(name.to_string()));
+        let unexpected = |pos| Err(Error::UnexpectedToken { pos });
+        let pi = std::f64::consts::PI;
+        // Each input, then what it is without and with implicit
+        // multiplication.
+        let cases = [
+            // Digits against letters.
+            ("2abc", invalid("2abc"), invalid("2abc")),
+            ("2x", invalid("2x"), invalid("2x")),
+            ("2_000abc", invalid("2_000abc"), invalid("2_000abc")),
+            ("1.5x2", invalid("1.5x2"), invalid("1.5x2")),
+            ("1e3x", invalid("1e3x"), invalid("1e3x")),
+            ("2ex", invalid("2ex"), invalid("2ex")),
+            ("2pi", invalid("2pi"), Ok(2.0 * pi)),
+            ("2PI", invalid("2PI"), Ok(2.0 * pi)),
+            ("1e3pi", invalid("1e3pi"), Ok(1e3 * pi)),
+            ("2sqrt(4)", invalid("2sqrt"), Ok(4.0)),
+            ("2sqrt (4)", invalid("2sqrt"), Ok(4.0)),
+            ("2sqrt", invalid("2sqrt"), invalid("2sqrt")),
+            ("2ans", invalid("2ans"), Err(Error::NoPreviousResult)),
+            // Letters against digits are a name.
+            ("x2", undefined("x2"), undefined("x2")),
+            ("pi2", undefined("pi2"), undefined("pi2")),
+            // Digits or letters against brackets.
+            ("2(3)", unexpected(1), Ok(6.0)),
+            ("(2)3", unexpected(3), Ok(6.0)),
+            ("(2)pi", unexpected(3), Ok(2.0 * pi)),
+            (
+                "x(2)",
+                Err(Error::UnknownFunction {
+                    name: "x".to_string(),
+                    pos: 0,
+                }),
+                Err(Error::UnknownFunction {
+                    name: "x".to_string(),
+                    pos: 0,
+                }),
+            ),
+            // With a space between, two tokens as the table says.
+            ("2 pi", unexpected(2), Ok(2.0 * pi)),
+            ("2 abc", unexpected(2), undefined("abc")),
+            (
+                "2 2",
+                Err(Error::SpaceInNumber { pos: 1 }),
+                Err(Error::SpaceInNumber { pos: 1 }),
+            ),
+        ];
+        for (expr, without, with) in cases {
+            assert_eq!(Calculator::calculate(expr), without, "{}", expr);
+            assert_eq!(
+                Calculator::calculate_with(expr, &implicit),
+                with,
+                "{}",
+                expr
+            );
+        }
+
+        // A registered function is known too.
+        let mut calc = Calculator::builder().implicit_multiplication(true).build();
+        calc.register_function("double", 1, |args| Ok(args[0] * 2.0))
+            .unwrap();
+        assert_eq!(calc.calculate("3double(2)"), Ok(12.0));
+        assert_eq!(
+            calc.calculate("3doubled(2)"),
+            Err(Error::InvalidNumber {
+                literal: "3doubled".to_string(),
+                pos: 0,
+            })
+        );
+
+        assert_eq!(
+            invalid("2abc").unwrap_err().to_string(),
+            "invalid number '2abc' at position 0 (write '2 * abc' to multiply)"
+        );
+        for literal in ["1e5e5", "0xFG", "1__0", "2..5"] {
+            assert_eq!(
+                invalid(literal).unwrap_err().to_string(),
+                format!("invalid number '{}' at position 0", literal)
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_tokens() {
+        let number = Token::Number(1.0);
+        let plus = Token::Op(Operator::Add);
+        let open = Token::Bracket('(');
+        let close = Token::Bracket(')');
+
+        assert_eq!(Calculator::verify_tokens(&[Token::Number(1.0)]), Ok(()));
+        assert_eq!(
+            Calculator::verify_tokens(&[Token::Op(Operator::Negate), number.clone()]),
+            Ok(())
+        );
+        assert_eq!(
+            Calculator::verify_tokens(&[
+                open.clone(),
+                number.clone(),
+                plus.clone(),
+                number.clone(),
+                close.clone()
+            ]),
+            Ok(())
+        );
+        for tokens in [
+            vec![],
+            vec![plus.clone(), number.clone()],
+            vec![number.clone(), plus.clone()],
+            vec![number.clone(), number.clone()],
+            vec![open.clone(), close.clone()],
+            vec![number.clone(), open, number.clone(), close],
+            vec![Token::Op(Operator::Subtract), number.clone()],
+            vec![number.clone(), Token::Op(Operator::Negate)],
+        ] {
+            assert_eq!(
+                Calculator::verify_tokens(&tokens),
+                Err(Error::InvalidExpression),
+                "{:?}",
+                tokens
+            );
+        }
+    }
+
+    #[test]
+    fn test_rejected_token() {
+        let number = Token::Number(1.0);
+        let times = Token::Op(Operator::Multiply);
+        let open = Token::Bracket('(');
+        let close = Token::Bracket(')');
+
+        let cases = [
+            (vec![number.clone(), times.clone(), number.clone()], None),
+            (vec![], Some(0)),
+            (vec![number.clone(), times.clone()], Some(1)),
+            (vec![number.clone(), times.clone(), times.clone()], Some(2)),
+            (vec![number.clone(), number.clone()], Some(1)),
+            (vec![open.clone(), times.clone(), number.clone()], Some(1)),
+            (vec![open, close], Some(1)),
+            (vec![times, number], Some(0)),
+        ];
+        for (tokens, expected) in cases {
+            assert_eq!(
+                Calculator::rejected_token(&tokens),
+                expected,
+                "{:?}",
+                tokens
+            );
+            assert_eq!(
+                Calculator::verify_tokens(&tokens),
+                expected.map_or(Ok(()), |_| Err(Error::InvalidExpression))
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        assert_eq!(Calculator::verify("(1 + 2) * 3"), Ok(()));
+        // Evaluation errors are not verification errors.
+        assert_eq!(Calculator::verify("1 / 0"), Ok(()));
+        assert_eq!(
+            Calculator::verify("2 pi"),
+            Err(Error::UnexpectedToken { pos: 2 })
+        );
+        assert_eq!(
+            Calculator::verify("(1"),
+            Err(Error::MismatchedParens {
+                unmatched_open: vec![0],
+                unmatched_close: vec![]
+            })
+        );
+    }
+}
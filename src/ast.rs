@@ -0,0 +1,1201 @@
+//! Expressions as trees, built by precedence climbing over the tokens of
+//! [`Calculator::parse`].
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::custom::Registry;
+use crate::{
+    Assoc, Calculator, Error, EvalOptions, Function, Operator, Token, ZeroDivision, parser,
+};
+
+/// How deeply [`Calculator::parse_ast`] nests, the whole expression being
+/// the first level, before giving up with [`Error::TooDeep`]: every
+/// bracket, sign, assignment and right-hand side of a binary operator is
+/// another, since the parser and [`Expr::evaluate`] recurse into each.
+pub const MAX_AST_DEPTH: usize = 256;
+
+/// An expression as a tree, from [`Calculator::parse_ast`]. Brackets are
+/// gone, leaving only the grouping they gave, and `|x|` is a call to
+/// [`Function::Abs`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// A variable read.
+    Variable(String),
+    /// [`Operator::Negate`] or [`Operator::Percent`].
+    UnaryOp {
+        op: Operator,
+        operand: Box<Expr>,
+    },
+    BinaryOp {
+        op: Operator,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Call {
+        function: Function,
+        args: Vec<Expr>,
+    },
+    /// `name = value`, which is worth `value`.
+    Assign {
+        name: String,
+        value: Box<Expr>,
+    },
+    /// A call to a function registered on a [`ConfiguredCalculator`].
+    ///
+    /// [`ConfiguredCalculator`]: crate::ConfiguredCalculator
+    CustomCall {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// A binary operator registered on a [`ConfiguredCalculator`], with how
+    /// it binds.
+    ///
+    /// [`ConfiguredCalculator`]: crate::ConfiguredCalculator
+    CustomOp {
+        symbol: char,
+        precedence: u8,
+        assoc: Assoc,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+impl Calculator {
+    /// Parses `expr` into a tree that groups exactly as
+    /// [`Calculator::try_to_postfix`] orders it, so [`Expr::evaluate`] agrees
+    /// with [`Calculator::calculate`]:
+    ///
+    /// ```
+    /// use calculator::{Calculator, Expr, Operator};
+    ///
+    /// let ast = Calculator::parse_ast("-2 ^ 2").unwrap();
+    /// let Expr::UnaryOp { op: Operator::Negate, operand } = &ast else {
+    ///     panic!("{:?}", ast);
+    /// };
+    /// assert!(matches!(**operand, Expr::BinaryOp { op: Operator::Power, .. }));
+    /// assert_eq!(ast.evaluate(), Ok(-4.0));
+    /// ```
+    pub fn parse_ast<T: AsRef<str>>(expr: T) -> Result<Expr, Error> {
+        Self::parse_ast_in(expr.as_ref(), &EvalOptions::default(), &Registry::default())
+    }
+
+    /// [`Calculator::parse_ast`] under `options`, with what `registry` adds
+    /// to the language.
+    pub(crate) fn parse_ast_in(
+        expr: &str,
+        options: &EvalOptions,
+        registry: &Registry,
+    ) -> Result<Expr, Error> {
+        let (tokens, _, _) = Self::parse_in(expr, options, false, registry)?;
+        let mut parser = Parser {
+            tokens,
+            next: 0,
+            depth: 0,
+        };
+        let ast = parser.expression(0)?;
+        match parser.tokens.get(parser.next) {
+            None => Ok(ast),
+            Some(_) => Err(Error::InvalidExpression),
+        }
+    }
+
+    /// `expr` rewritten in a canonical form: spaced operators, `abs(x)` for
+    /// `|x|`, and brackets only where the grouping needs them.
+    ///
+    /// ```
+    /// use calculator::Calculator;
+    ///
+    /// assert_eq!(Calculator::to_infix("(2+3)*4").unwrap(), "(2 + 3) * 4");
+    /// assert_eq!(Calculator::to_infix("(2*3)+4").unwrap(), "2 * 3 + 4");
+    /// assert_eq!(Calculator::to_infix("2-(3-4)").unwrap(), "2 - (3 - 4)");
+    /// ```
+    pub fn to_infix<T: AsRef<str>>(expr: T) -> Result<String, Error> {
+        Ok(Self::parse_ast(expr)?.to_string())
+    }
+
+    /// `expr` with its constants worked out and trivial operations
+    /// dropped, by [`Expr::simplify`], printed as by
+    /// [`Calculator::to_infix`]:
+    ///
+    /// ```
+    /// use calculator::Calculator;
+    ///
+    /// assert_eq!(Calculator::simplify("2 * 3 + x * 1").unwrap(), "6 + x");
+    /// ```
+    pub fn simplify<T: AsRef<str>>(expr: T) -> Result<String, Error> {
+        Ok(Self::parse_ast(expr)?.simplify().to_string())
+    }
+
+    /// `expr` with the variables in `bindings` put in and every constant
+    /// subexpression worked out, as a tree and printed as by
+    /// [`Calculator::to_infix`]. What is left reads the other variables,
+    /// and evaluates with them as `expr` would with `bindings` too.
+    ///
+    /// A constant that fails, such as `1 / 0`, is the error here, since
+    /// `expr` fails whatever the other variables are. A call to a function
+    /// the calculator doesn't know is left as written, for whatever will
+    /// evaluate the result to supply, and so is a read of a variable after
+    /// the expression assigns it. Apart from folding, nothing is
+    /// rewritten: `x * 0` stays, where [`Calculator::simplify`] drops it.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use calculator::{Calculator, Error};
+    ///
+    /// let bindings = HashMap::from([("a".to_string(), 2.0), ("b".to_string(), 3.0)]);
+    /// let (_, text) = Calculator::partial_eval("a * b + c", &bindings).unwrap();
+    /// assert_eq!(text, "6 + c");
+    /// let (_, text) = Calculator::partial_eval("rate(a, c) * b", &bindings).unwrap();
+    /// assert_eq!(text, "rate(2, c) * 3");
+    /// assert_eq!(
+    ///     Calculator::partial_eval("c + a / (b - 3)", &bindings),
+    ///     Err(Error::DivisionByZero)
+    /// );
+    /// ```
+    pub fn partial_eval<T: AsRef<str>>(
+        expr: T,
+        bindings: &impl Borrow<HashMap<String, f64>>,
+    ) -> Result<(Expr, String), Error> {
+        let mut registry = Registry::default();
+        registry.unknown_calls = true;
+        let options = EvalOptions::default();
+        let ast = Self::parse_ast_in(expr.as_ref(), &options, &registry)?;
+        let partial = ast.partial(&mut bindings.borrow().clone(), &options)?;
+        let text = partial.to_string();
+        Ok((partial, text))
+    }
+}
+
+impl Expr {
+    /// The value of the expression, with the default [`EvalOptions`] and
+    /// operands worked out left to right, so an assignment is seen by
+    /// every read after it. Registered functions and operators are unknown
+    /// here; evaluate a tree that has them with
+    /// [`ConfiguredCalculator::evaluate_ast`](crate::ConfiguredCalculator::evaluate_ast).
+    pub fn evaluate(&self) -> Result<f64, Error> {
+        self.value(
+            &EvalOptions::default(),
+            &mut HashMap::new(),
+            &Registry::default(),
+        )
+    }
+
+    /// [`Expr::simplify_with`] with the default [`EvalOptions`].
+    pub fn simplify(&self) -> Expr {
+        self.simplify_with(&EvalOptions::default())
+    }
+
+    /// The expression with every constant subexpression worked out under
+    /// `options`, the ones it will later be evaluated with, and `x + 0`,
+    /// `0 + x`, `x - 0`, `x * 1`, `1 * x` and `x / 1` reduced to `x`, and
+    /// `x * 0` and `0 * x` to `0`. Calls and operators of a
+    /// `ConfiguredCalculator` are kept as written; see
+    /// [`ConfiguredCalculator::simplify`](crate::ConfiguredCalculator::simplify).
+    ///
+    /// Folding alone never hides an error: a constant whose operation
+    /// fails, such as `1 / 0`, or comes to NaN, is left as written. A zero
+    /// divisor is never folded, even under [`ZeroDivision::Ieee`], so
+    /// `1 / 0 * x` stays as written and the result evaluates as the
+    /// original would under either policy, whichever it is simplified
+    /// with. The
+    /// identities can hide one. They don't look inside `x`, so they drop
+    /// errors and NaN and infinite values along with it: `(1 / 0) * 0`,
+    /// `y * 0` with `y` unset and `(0 / 0 * y) * 0` all become `0`, which
+    /// evaluates where the original fails or is NaN. Only an `x` that
+    /// assigns is kept, since a later read may depend on it.
+    pub fn simplify_with(&self, options: &EvalOptions) -> Expr {
+        self.simplify_in(options, &Registry::default())
+    }
+
+    /// [`Expr::simplify_with`], also folding calls and operators that
+    /// `registry` has.
+    pub(crate) fn simplify_in(&self, options: &EvalOptions, registry: &Registry) -> Expr {
+        let simplified = match self {
+            Expr::Number(_) | Expr::Variable(_) => return self.clone(),
+            Expr::UnaryOp { op, operand } => Expr::UnaryOp {
+                op: op.clone(),
+                operand: Box::new(operand.simplify_in(options, registry)),
+            },
+            Expr::BinaryOp { op, lhs, rhs } => {
+                let lhs = lhs.simplify_in(options, registry);
+                let rhs = rhs.simplify_in(options, registry);
+                if let Some(reduced) = identity(op, &lhs, &rhs) {
+                    return reduced;
+                }
+                Expr::BinaryOp {
+                    op: op.clone(),
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                }
+            }
+            Expr::Call { function, args } => Expr::Call {
+                function: *function,
+                args: args
+                    .iter()
+                    .map(|arg| arg.simplify_in(options, registry))
+                    .collect(),
+            },
+            Expr::Assign { name, value } => {
+                return Expr::Assign {
+                    name: name.clone(),
+                    value: Box::new(value.simplify_in(options, registry)),
+                };
+            }
+            Expr::CustomCall { name, args } => Expr::CustomCall {
+                name: name.clone(),
+                args: args
+                    .iter()
+                    .map(|arg| arg.simplify_in(options, registry))
+                    .collect(),
+            },
+            Expr::CustomOp {
+                symbol,
+                precedence,
+                assoc,
+                lhs,
+                rhs,
+            } => Expr::CustomOp {
+                symbol: *symbol,
+                precedence: *precedence,
+                assoc: *assoc,
+                lhs: Box::new(lhs.simplify_in(options, registry)),
+                rhs: Box::new(rhs.simplify_in(options, registry)),
+            },
+        };
+        simplified.fold(options, registry).unwrap_or(simplified)
+    }
+
+    /// [`Calculator::partial_eval`] of the node, removing from `bindings`
+    /// each name it assigns, in the order the assignments are evaluated.
+    fn partial(
+        &self,
+        bindings: &mut HashMap<String, f64>,
+        options: &EvalOptions,
+    ) -> Result<Expr, Error> {
+        let mut partial = |expr: &Expr| expr.partial(bindings, options).map(Box::new);
+        let node = match self {
+            Expr::Number(_) => return Ok(self.clone()),
+            Expr::Variable(name) => {
+                return Ok(bindings
+                    .get(name)
+                    .map_or_else(|| self.clone(), |value| Expr::Number(*value)));
+            }
+            Expr::UnaryOp { op, operand } => Expr::UnaryOp {
+                op: op.clone(),
+                operand: partial(operand)?,
+            },
+            Expr::BinaryOp { op, lhs, rhs } => Expr::BinaryOp {
+                op: op.clone(),
+                lhs: partial(lhs)?,
+                rhs: partial(rhs)?,
+            },
+            Expr::Call { function, args } => Expr::Call {
+                function: *function,
+                args: args
+                    .iter()
+                    .map(|arg| partial(arg).map(|arg| *arg))
+                    .collect::<Result<_, _>>()?,
+            },
+            // Left as written, so later reads of the name are too.
+            Expr::Assign { name, value } => {
+                let value = partial(value)?;
+                bindings.remove(name);
+                return Ok(Expr::Assign {
+                    name: name.clone(),
+                    value,
+                });
+            }
+            Expr::CustomCall { name, args } => {
+                return Ok(Expr::CustomCall {
+                    name: name.clone(),
+                    args: args
+                        .iter()
+                        .map(|arg| partial(arg).map(|arg| *arg))
+                        .collect::<Result<_, _>>()?,
+                });
+            }
+            Expr::CustomOp {
+                symbol,
+                precedence,
+                assoc,
+                lhs,
+                rhs,
+            } => Expr::CustomOp {
+                symbol: *symbol,
+                precedence: *precedence,
+                assoc: *assoc,
+                lhs: partial(lhs)?,
+                rhs: partial(rhs)?,
+            },
+        };
+        let constant = match &node {
+            Expr::UnaryOp { operand, .. } => operand.as_number().is_some(),
+            Expr::BinaryOp { lhs, rhs, .. } => {
+                lhs.as_number().is_some() && rhs.as_number().is_some()
+            }
+            Expr::Call { args, .. } => args.iter().all(|arg| arg.as_number().is_some()),
+            _ => false,
+        };
+        if !constant {
+            return Ok(node);
+        }
+        match node.value(options, &mut HashMap::new(), &Registry::default())? {
+            // NaN has no literal to print as.
+            n if n.is_nan() => Ok(node),
+            n => Ok(Expr::Number(n)),
+        }
+    }
+
+    /// The node as a number, if its operands are all numbers and applying
+    /// it to them succeeds.
+    fn fold(&self, options: &EvalOptions, registry: &Registry) -> Option<Expr> {
+        let constant = match self {
+            Expr::UnaryOp { operand, .. } => operand.as_number().is_some(),
+            Expr::BinaryOp { lhs, rhs, .. } | Expr::CustomOp { lhs, rhs, .. } => {
+                lhs.as_number().is_some() && rhs.as_number().is_some()
+            }
+            Expr::Call { args, .. } | Expr::CustomCall { args, .. } => {
+                args.iter().all(|arg| arg.as_number().is_some())
+            }
+            _ => false,
+        };
+        if !constant {
+            return None;
+        }
+        let strict = EvalOptions {
+            zero_division: ZeroDivision::Error,
+            ..*options
+        };
+        match self.value(&strict, &mut HashMap::new(), registry) {
+            // NaN has no literal to print as.
+            Ok(n) if !n.is_nan() => Some(Expr::Number(n)),
+            _ => None,
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Expr::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Whether evaluating the node assigns a variable.
+    fn assigns(&self) -> bool {
+        match self {
+            Expr::Number(_) | Expr::Variable(_) => false,
+            Expr::UnaryOp { operand, .. } => operand.assigns(),
+            Expr::BinaryOp { lhs, rhs, .. } | Expr::CustomOp { lhs, rhs, .. } => {
+                lhs.assigns() || rhs.assigns()
+            }
+            Expr::Call { args, .. } | Expr::CustomCall { args, .. } => {
+                args.iter().any(Expr::assigns)
+            }
+            Expr::Assign { .. } => true,
+        }
+    }
+
+    /// Computes the operands, then applies this node as
+    /// [`Calculator::evaluate`] would apply its token, calling what
+    /// `registry` holds.
+    pub(crate) fn value(
+        &self,
+        options: &EvalOptions,
+        variables: &mut HashMap<String, f64>,
+        registry: &Registry,
+    ) -> Result<f64, Error> {
+        let (token, mut stack) = match self {
+            Expr::Number(n) => (Token::Number(*n), Vec::new()),
+            Expr::Variable(name) => (Token::Identifier(name.as_str().into()), Vec::new()),
+            Expr::UnaryOp { op, operand } => (
+                Token::Op(op.clone()),
+                vec![operand.value(options, variables, registry)?],
+            ),
+            Expr::BinaryOp { op, lhs, rhs } => {
+                let lhs = lhs.value(options, variables, registry)?;
+                (
+                    Token::Op(op.clone()),
+                    vec![lhs, rhs.value(options, variables, registry)?],
+                )
+            }
+            Expr::Call { function, args } => {
+                let token = Token::Function {
+                    function: *function,
+                    args: args.len() as u32,
+                };
+                let args = args
+                    .iter()
+                    .map(|arg| arg.value(options, variables, registry))
+                    .collect::<Result<_, _>>()?;
+                (token, args)
+            }
+            Expr::Assign { name, value } => (
+                Token::Assign(name.as_str().into()),
+                vec![value.value(options, variables, registry)?],
+            ),
+            Expr::CustomCall { name, args } => {
+                let token = Token::Call {
+                    name: name.as_str().into(),
+                    args: args.len() as u32,
+                };
+                let args = args
+                    .iter()
+                    .map(|arg| arg.value(options, variables, registry))
+                    .collect::<Result<_, _>>()?;
+                (token, args)
+            }
+            Expr::CustomOp {
+                symbol,
+                precedence,
+                assoc,
+                lhs,
+                rhs,
+            } => {
+                let token = Token::Custom {
+                    symbol: *symbol,
+                    precedence: *precedence,
+                    assoc: *assoc,
+                };
+                let lhs = lhs.value(options, variables, registry)?;
+                (token, vec![lhs, rhs.value(options, variables, registry)?])
+            }
+        };
+        Calculator::apply_token(&mut stack, &token, options, variables, registry)?;
+        Calculator::finish(stack)
+    }
+
+    /// How tightly the printed node holds together, on the scale of
+    /// [`Operator::precedence`]: a node needs brackets as an operand of
+    /// anything that binds tighter.
+    fn binding(&self) -> u8 {
+        match self {
+            Expr::UnaryOp { op, .. } | Expr::BinaryOp { op, .. } => op.precedence(),
+            Expr::CustomOp { precedence, .. } => *precedence,
+            // Printed with a sign in front, like a negation.
+            Expr::Number(n) if n.is_sign_negative() => Operator::Negate.precedence(),
+            Expr::Number(_) | Expr::Variable(_) | Expr::Call { .. } | Expr::CustomCall { .. } => {
+                u8::MAX
+            }
+            Expr::Assign { .. } => 0,
+        }
+    }
+
+    /// Whether the printed node starts with `-`. Such a node never needs
+    /// brackets on the right of a binary operator, since the sign takes in
+    /// only what binds tighter than it, just as the operator's own
+    /// right-hand side would.
+    fn is_signed(&self) -> bool {
+        match self {
+            Expr::UnaryOp { op, .. } => *op == Operator::Negate,
+            Expr::Number(n) => n.is_sign_negative(),
+            _ => false,
+        }
+    }
+}
+
+/// `lhs op rhs` reduced by one of [`Expr::simplify_with`]'s identities.
+fn identity(op: &Operator, lhs: &Expr, rhs: &Expr) -> Option<Expr> {
+    let (left, right) = (lhs.as_number(), rhs.as_number());
+    match op {
+        Operator::Add if left == Some(0.0) => Some(rhs.clone()),
+        Operator::Add | Operator::Subtract if right == Some(0.0) => Some(lhs.clone()),
+        Operator::Multiply if left == Some(0.0) && !rhs.assigns() => Some(lhs.clone()),
+        Operator::Multiply if right == Some(0.0) && !lhs.assigns() => Some(rhs.clone()),
+        Operator::Multiply if left == Some(1.0) => Some(rhs.clone()),
+        Operator::Multiply | Operator::Divide if right == Some(1.0) => Some(lhs.clone()),
+        _ => None,
+    }
+}
+
+/// Writes `lhs text rhs` for a binary operator binding at `precedence`,
+/// with brackets where reading it back would group it differently. A signed
+/// `rhs` needs them only after `%`, where `10 % - 3` reads as `(10%) - 3`.
+fn write_binary(
+    f: &mut fmt::Formatter<'_>,
+    text: &str,
+    (precedence, assoc): (u8, Assoc),
+    lhs: &Expr,
+    rhs: &Expr,
+) -> fmt::Result {
+    let right = assoc == Assoc::Right;
+    let lhs_grouped = lhs.binding() < precedence || (right && lhs.binding() == precedence);
+    let rhs_grouped = if rhs.is_signed() {
+        text == Operator::Modulo.text()
+    } else {
+        rhs.binding() < precedence || (!right && rhs.binding() == precedence)
+    };
+    write_operand(f, lhs, lhs_grouped)?;
+    write!(f, " {} ", text)?;
+    write_operand(f, rhs, rhs_grouped)
+}
+
+/// Writes `name(args)`, the arguments separated by commas.
+fn write_call(f: &mut fmt::Formatter<'_>, name: &str, args: &[Expr]) -> fmt::Result {
+    write!(f, "{}(", name)?;
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", arg)?;
+    }
+    write!(f, ")")
+}
+
+/// Writes `expr`, in brackets if `grouped`.
+fn write_operand(f: &mut fmt::Formatter<'_>, expr: &Expr, grouped: bool) -> fmt::Result {
+    if grouped {
+        write!(f, "({})", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+/// Prints the expression so that [`Calculator::parse_ast`] reads it back
+/// as the same tree, with brackets only where precedence or associativity
+/// needs them: `2 - (3 - 4)` keeps its brackets, `(2 - 3) - 4` loses them.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) if n.is_sign_negative() => write!(f, "-{}", Expr::Number(-n)),
+            // Too large for an f64 literal, which is how `1e999` got here.
+            Expr::Number(n) if n.is_infinite() => write!(f, "1e999"),
+            // `{}` never uses an exponent, so would spell out every digit
+            // of 1e300.
+            Expr::Number(n) if *n != 0.0 && !(1e-5..1e16).contains(n) => write!(f, "{:e}", n),
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::UnaryOp { op, operand } => {
+                let grouped = operand.binding() < op.precedence();
+                if op.is_postfix() {
+                    write_operand(f, operand, grouped)?;
+                    write!(f, "{}", op.text())
+                } else {
+                    write!(f, "{}", op.text())?;
+                    write_operand(f, operand, grouped)
+                }
+            }
+            Expr::BinaryOp { op, lhs, rhs } => {
+                let binding = parser::binding(&Token::Op(op.clone())).unwrap();
+                write_binary(f, op.text(), binding, lhs, rhs)
+            }
+            Expr::Call { function, args } => write_call(f, function.name(), args),
+            Expr::Assign { name, value } => write!(f, "{} = {}", name, value),
+            Expr::CustomCall { name, args } => write_call(f, name, args),
+            Expr::CustomOp {
+                symbol,
+                precedence,
+                assoc,
+                lhs,
+                rhs,
+            } => write_binary(f, &symbol.to_string(), (*precedence, *assoc), lhs, rhs),
+        }
+    }
+}
+
+/// How a binary operator token binds, or `None` for any other token.
+fn infix(token: &Token) -> Option<(u8, Assoc)> {
+    match token {
+        Token::Op(op) if op.is_unary() => None,
+        _ => parser::binding(token),
+    }
+}
+
+/// Precedence climbing over tokens that have passed `parse`'s checks, so
+/// brackets balance and every token may follow the one before it.
+struct Parser {
+    tokens: Vec<Token>,
+    next: usize,
+    /// Calls of `expression` in progress.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next)
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).cloned();
+        self.next += 1;
+        token
+    }
+
+    /// Operands joined by binary operators binding at least as tightly as
+    /// `min`. As in `try_to_postfix`, equal precedence groups to the left
+    /// except for `^`.
+    fn expression(&mut self, min: u8) -> Result<Expr, Error> {
+        self.depth += 1;
+        if self.depth > MAX_AST_DEPTH {
+            return Err(Error::TooDeep {
+                depth: self.depth,
+                max: MAX_AST_DEPTH,
+            });
+        }
+        let mut lhs = self.operand()?;
+        while let Some((precedence, assoc)) = self.peek().and_then(infix) {
+            if precedence < min {
+                break;
+            }
+            let token = self.bump();
+            let rhs = Box::new(match assoc {
+                Assoc::Right => self.expression(precedence)?,
+                Assoc::Left => self.expression(precedence + 1)?,
+            });
+            lhs = match token {
+                Some(Token::Custom { symbol, .. }) => Expr::CustomOp {
+                    symbol,
+                    precedence,
+                    assoc,
+                    lhs: Box::new(lhs),
+                    rhs,
+                },
+                Some(Token::Op(op)) => Expr::BinaryOp {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs,
+                },
+                _ => return Err(Error::InvalidExpression),
+            };
+        }
+        self.depth -= 1;
+        Ok(lhs)
+    }
+
+    /// One operand with any `%` after it, which binds tighter than
+    /// anything, so `-50%` is `-(50%)` and `2 ^ 50%` is `2 ^ (50%)`.
+    fn operand(&mut self) -> Result<Expr, Error> {
+        let mut operand = match self.bump() {
+            Some(Token::Number(n)) => Expr::Number(n),
+            Some(Token::Identifier(name)) => Expr::Variable(name.into_string()),
+            // Above `*` and below `^`, so `-2 * 3` is `(-2) * 3` and
+            // `-2 ^ 2` is `-(2 ^ 2)`.
+            Some(Token::Op(Operator::Negate)) => Expr::UnaryOp {
+                op: Operator::Negate,
+                operand: Box::new(self.expression(Operator::Negate.precedence() + 1)?),
+            },
+            // Everything up to the enclosing `)`, `,` or the end.
+            Some(Token::Assign(name)) => Expr::Assign {
+                name: name.into_string(),
+                value: Box::new(self.expression(0)?),
+            },
+            Some(Token::Bracket('(')) => {
+                let inner = self.expression(0)?;
+                self.close()?;
+                inner
+            }
+            Some(Token::Function { function, .. }) => Expr::Call {
+                function,
+                args: self.arguments()?,
+            },
+            Some(Token::Call { name, .. }) => Expr::CustomCall {
+                name: name.into_string(),
+                args: self.arguments()?,
+            },
+            _ => return Err(Error::InvalidExpression),
+        };
+        while self.peek() == Some(&Token::Op(Operator::Percent)) {
+            self.next += 1;
+            operand = Expr::UnaryOp {
+                op: Operator::Percent,
+                operand: Box::new(operand),
+            };
+        }
+        Ok(operand)
+    }
+
+    /// A call's bracketed arguments, separated by commas.
+    fn arguments(&mut self) -> Result<Vec<Expr>, Error> {
+        if self.bump() != Some(Token::Bracket('(')) {
+            return Err(Error::InvalidExpression);
+        }
+        let mut args = vec![self.expression(0)?];
+        while self.peek() == Some(&Token::Comma) {
+            self.next += 1;
+            args.push(self.expression(0)?);
+        }
+        self.close()?;
+        Ok(args)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        match self.bump() {
+            Some(Token::Bracket(c)) if c != '(' => Ok(()),
+            _ => Err(Error::InvalidExpression),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(n: f64) -> Box<Expr> {
+        Box::new(Expr::Number(n))
+    }
+
+    fn binary(op: Operator, lhs: Box<Expr>, rhs: Box<Expr>) -> Box<Expr> {
+        Box::new(Expr::BinaryOp { op, lhs, rhs })
+    }
+
+    fn unary(op: Operator, operand: Box<Expr>) -> Box<Expr> {
+        Box::new(Expr::UnaryOp { op, operand })
+    }
+
+    #[test]
+    fn test_structure() {
+        use Operator::*;
+
+        let cases = [
+            (
+                "1 + 2 * 3",
+                binary(Add, number(1.0), binary(Multiply, number(2.0), number(3.0))),
+            ),
+            (
+                "(1 + 2) * 3",
+                binary(Multiply, binary(Add, number(1.0), number(2.0)), number(3.0)),
+            ),
+            (
+                "8 - 4 - 2",
+                binary(
+                    Subtract,
+                    binary(Subtract, number(8.0), number(4.0)),
+                    number(2.0),
+                ),
+            ),
+            (
+                "2 ^ 3 ^ 2",
+                binary(Power, number(2.0), binary(Power, number(3.0), number(2.0))),
+            ),
+            (
+                "-2 ^ 2",
+                unary(Negate, binary(Power, number(2.0), number(2.0))),
+            ),
+            (
+                "-2 * 3",
+                binary(Multiply, unary(Negate, number(2.0)), number(3.0)),
+            ),
+            (
+                "2 ^ -1 ^ 2",
+                binary(
+                    Power,
+                    number(2.0),
+                    unary(Negate, binary(Power, number(1.0), number(2.0))),
+                ),
+            ),
+            ("-50%", unary(Negate, unary(Percent, number(50.0)))),
+            (
+                "2 ^ 50%",
+                binary(Power, number(2.0), unary(Percent, number(50.0))),
+            ),
+            (
+                "max(1, |x|)",
+                Box::new(Expr::Call {
+                    function: Function::Max,
+                    args: vec![
+                        Expr::Number(1.0),
+                        Expr::Call {
+                            function: Function::Abs,
+                            args: vec![Expr::Variable("x".to_string())],
+                        },
+                    ],
+                }),
+            ),
+            (
+                "a = b = 3",
+                Box::new(Expr::Assign {
+                    name: "a".to_string(),
+                    value: Box::new(Expr::Assign {
+                        name: "b".to_string(),
+                        value: number(3.0),
+                    }),
+                }),
+            ),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(Calculator::parse_ast(expr), Ok(*expected), "{}", expr);
+        }
+    }
+
+    /// Expressions covering every node, precedence level and odd
+    /// corner of the syntax.
+    const EXPRESSIONS: &[&str] = &[
+        "2 + 3 * 4",
+        "(2 + 3) * 4",
+        "10 - 6 / 2",
+        "8 / 2 / 2",
+        "7 // 2 % 3 * 4",
+        "-7 % 3",
+        "2 ^ 3 ^ 2",
+        "2 ** 3 ** 2",
+        "-2 ^ 2",
+        "2 ^ -1 ^ 2",
+        "2 ^ -3 * 4",
+        "2 * -3 ^ 2",
+        "-sqrt(4) ^ 2",
+        "1 - - - 1",
+        "+-+2",
+        "2 - - 3",
+        "25%",
+        "(10+5)%",
+        "-50% ^ 2",
+        "2 ^ 50%",
+        "200%%",
+        "10%3",
+        "10 % -3",
+        "|3 - 5| * 2",
+        "|2 - |3 - 5||",
+        "||3| - 5|",
+        "max(1, 2+3, 4)",
+        "max(min(1,2), 3)",
+        "pow(2, 10)",
+        "max(0 / 0, 1)",
+        "sin(pi / 6) + cos(0) - tan(e)",
+        "ln(2) * log10(1e3) / exp(1)",
+        "cbrt(-27) + abs(-2.5)",
+        "a = b = 3",
+        "y = (x = 5) * 2",
+        "(x = 1) + x",
+        "sqrt(x = 4) * x",
+        "x + (x = 1)",
+        "ans + 1",
+        "1 / 0",
+        "1 // 0 + 2",
+        "0 ^ -1",
+        "sqrt(-1)",
+        "1e308 * 10",
+        "0xFF - 0o17 + 0b1",
+        "1_000.5 * .5 - 2.",
+        "((((1))))",
+        "1e999 - 1e300 + 1e-9",
+    ];
+
+    #[test]
+    fn test_agrees_with_postfix() {
+        for &expr in EXPRESSIONS {
+            let postfix = Calculator::calculate(expr);
+            let tree = Calculator::parse_ast(expr).and_then(|ast| ast.evaluate());
+            match (&postfix, &tree) {
+                (Ok(a), Ok(b)) if a.is_nan() && b.is_nan() => {}
+                _ => assert_eq!(tree, postfix, "{}", expr),
+            }
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let cases = [
+            ("(2+3)*4", "(2 + 3) * 4"),
+            ("(2*3)+4", "2 * 3 + 4"),
+            ("2-(3-4)", "2 - (3 - 4)"),
+            ("(2-3)-4", "2 - 3 - 4"),
+            ("8/(4/2)", "8 / (4 / 2)"),
+            ("(8/4)/2", "8 / 4 / 2"),
+            ("8//(4*2)", "8 // (4 * 2)"),
+            ("2^(3^2)", "2 ^ 3 ^ 2"),
+            ("(2^3)^2", "(2 ^ 3) ^ 2"),
+            ("(-2)^2", "(-2) ^ 2"),
+            ("-(2^2)", "-2 ^ 2"),
+            ("-(2*3)", "-(2 * 3)"),
+            ("(-2)*3", "-2 * 3"),
+            ("2*(-3)", "2 * -3"),
+            ("2^(-3)", "2 ^ -3"),
+            ("1-(-(-1))", "1 - --1"),
+            ("10%(-3)", "10 % (-3)"),
+            ("(-5)%", "(-5)%"),
+            ("(2^3)%", "(2 ^ 3)%"),
+            ("(50%)^2", "50% ^ 2"),
+            ("|x-1|", "abs(x - 1)"),
+            ("max((1),2+3)", "max(1, 2 + 3)"),
+            ("a=b=(3)", "a = b = 3"),
+            ("y=(x=5)*2", "y = (x = 5) * 2"),
+            ("2**10", "2 ^ 10"),
+            ("0x10+.5", "16 + 0.5"),
+            ("1e300*1e-9", "1e300 * 1e-9"),
+            ("1e999", "1e999"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(
+                Calculator::to_infix(expr).as_deref(),
+                Ok(expected),
+                "{}",
+                expr
+            );
+        }
+
+        let ast = binary(Operator::Power, number(-3.0), number(2.0));
+        assert_eq!(ast.to_string(), "(-3) ^ 2");
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for &expr in EXPRESSIONS {
+            let Ok(ast) = Calculator::parse_ast(expr) else {
+                continue;
+            };
+            let printed = ast.to_string();
+            assert_eq!(
+                Calculator::parse_ast(&printed),
+                Ok(ast),
+                "{} -> {}",
+                expr,
+                printed
+            );
+            assert_eq!(
+                Calculator::to_infix(&printed),
+                Ok(printed.clone()),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_simplify() {
+        let cases = [
+            ("2 * 3 + x * 1", "6 + x"),
+            ("x + y * z", "x + y * z"),
+            ("(x + y) * (z - w) / v ^ u", "(x + y) * (z - w) / v ^ u"),
+            ("-x % 3", "-x % 3"),
+            ("0 + x - 0", "x"),
+            ("1 * x / 1", "x"),
+            ("x * 0 + 1", "1"),
+            ("-2 ^ 2 + max(1, 2) * x", "-4 + 2 * x"),
+            ("50% * x", "0.5 * x"),
+            ("max(1, 2, x) + (2 + 3)", "max(1, 2, x) + 5"),
+            ("y = (2 + 3) * x", "y = 5 * x"),
+            // Failing constants stay for evaluation to report...
+            ("1 / 0 + 2 * 3", "1 / 0 + 6"),
+            ("max(0 / 0, 1)", "max(0 / 0, 1)"),
+            // ...unless multiplied away, NaN and unset variables too.
+            ("(1 / 0) * 0", "0"),
+            ("(0 / 0 * y) * 0", "0"),
+            // An assignment stays for the read after it.
+            ("(x = 2 * 3) * 0 + x", "(x = 6) * 0 + x"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(
+                Calculator::simplify(expr).as_deref(),
+                Ok(expected),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::simplify("2 +"),
+            Err(Error::UnexpectedToken { pos: 2 })
+        );
+
+        let degrees = EvalOptions {
+            angle: crate::Angle::Degrees,
+            ..EvalOptions::default()
+        };
+        let ast = Calculator::parse_ast("sin(90) * x").unwrap();
+        assert_eq!(ast.simplify_with(&degrees), Expr::Variable("x".to_string()));
+        assert_ne!(ast.simplify(), Expr::Variable("x".to_string()));
+    }
+
+    #[test]
+    fn test_simplify_agrees_with_evaluate() {
+        for &expr in EXPRESSIONS {
+            let Ok(ast) = Calculator::parse_ast(expr) else {
+                continue;
+            };
+            let (original, simplified) = (ast.evaluate(), ast.simplify().evaluate());
+            match (&original, &simplified) {
+                (Ok(a), Ok(b)) if a.is_nan() && b.is_nan() => {}
+                _ => assert_eq!(simplified, original, "{}", expr),
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_eval() {
+        let bindings = HashMap::from([("a".to_string(), 2.0), ("b".to_string(), 0.0)]);
+        let cases = [
+            ("a * b + c", Ok("0 + c")),
+            ("c * (a + 1) ^ 2", Ok("c * 9")),
+            ("max(a, c, 3 * a)", Ok("max(2, c, 6)")),
+            ("|a - 5| + sqrt(c)", Ok("3 + sqrt(c)")),
+            ("c * 0 + 50%", Ok("c * 0 + 0.5")),
+            ("npv(a + 1, c)", Ok("npv(3, c)")),
+            ("npv(a, 1 / b)", Err(Error::DivisionByZero)),
+            ("c + a // b", Err(Error::DivisionByZero)),
+            (
+                "c * (-a) ^ 0.5",
+                Err(Error::DomainError {
+                    operation: "power",
+                    reason: "a negative base needs a whole exponent or an odd root",
+                }),
+            ),
+            ("sqrt(-a) + c", Ok("sqrt(-2) + c")),
+            ("(a = c) + a", Ok("(a = c) + a")),
+            ("a + (a = 1) + a", Ok("2 + (a = 1) + a")),
+        ];
+        for (expr, expected) in cases {
+            let partial = Calculator::partial_eval(expr, &bindings).map(|(ast, text)| {
+                assert_eq!(ast.to_string(), text);
+                text
+            });
+            assert_eq!(partial, expected.map(String::from), "{}", expr);
+        }
+        assert_eq!(
+            Calculator::partial_eval("2 * (3", &bindings),
+            Err(Calculator::calculate("2 * (3").unwrap_err())
+        );
+    }
+
+    #[test]
+    fn test_partial_eval_agrees_with_evaluate() {
+        // xorshift64*, so the assignments are the same on every run.
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut random = move |n: u64| {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            state.wrapping_mul(0x2545_f491_4f6c_dd1d) % n
+        };
+        let names = ["a", "b", "c", "x"];
+        let exprs = [
+            "a * b + c",
+            "(a - b) / (c - x) + 2 * 3",
+            "a ^ b - c // 2",
+            "x % (a + 1) + b%",
+            "max(a, b * 2, 1 - c) - min(x, 3)",
+            "-a ^ 2 + |b - c| * sqrt(x)",
+            "pow(a, 2) + div(b, c) + floordiv(x, 2)",
+            "sin(a) * cos(b) + ln(c) - exp(x / 10)",
+            "y = a * b + (x = c) * x",
+            "a + (a = b + 1) * a - c",
+            "(a + b) * 2 ^ (c - x)",
+        ];
+        for expr in exprs {
+            let ast = Calculator::parse_ast(expr).unwrap();
+            for _ in 0..200 {
+                let mut bound = HashMap::new();
+                let mut rest = HashMap::new();
+                for name in names {
+                    // Small whole values, so zeros and negative bases come up.
+                    let value = random(9) as f64 - 4.0 + [0.0, 0.5][random(2) as usize];
+                    let side = if random(2) == 0 {
+                        &mut bound
+                    } else {
+                        &mut rest
+                    };
+                    side.insert(name.to_string(), value);
+                }
+                let mut all = bound.clone();
+                all.extend(rest.clone());
+                let value = |tree: &Expr, variables: &HashMap<String, f64>| {
+                    let mut variables = variables.clone();
+                    format!(
+                        "{:?}",
+                        tree.value(
+                            &EvalOptions::default(),
+                            &mut variables,
+                            &Registry::default()
+                        )
+                    )
+                };
+                let original = value(&ast, &all);
+                let partial = match Calculator::partial_eval(expr, &bound) {
+                    Ok((partial, _)) => value(&partial, &rest),
+                    Err(e) => format!("{:?}", Err::<f64, _>(e)),
+                };
+                assert_eq!(
+                    partial, original,
+                    "{} with {:?} then {:?}",
+                    expr, bound, rest
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_zero_divisor_left_for_evaluation() {
+        let policies = [ZeroDivision::Error, ZeroDivision::Ieee];
+        // The zero divisor on either side, inside a call, and under each
+        // operator and function that divides.
+        let cases = [
+            ("1 / 0 * x", "1 / 0 * x"),
+            ("x * (1 / 0)", "x * (1 / 0)"),
+            ("x - 2 // 0", "x - 2 // 0"),
+            ("-1 % 0 + x", "-1 % 0 + x"),
+            ("0 / 0 + x", "0 / 0 + x"),
+            ("sqrt(4 / (2 - 2)) * x", "sqrt(4 / 0) * x"),
+            ("max(div(1, 0), x)", "max(div(1, 0), x)"),
+            ("floordiv(-1, 0) + 2 * 3", "floordiv(-1, 0) + 6"),
+            ("y = 1 / 0", "y = 1 / 0"),
+        ];
+        for (expr, expected) in cases {
+            let ast = Calculator::parse_ast(expr).unwrap();
+            for folded_with in policies {
+                let folded = ast.simplify_with(&EvalOptions {
+                    zero_division: folded_with,
+                    ..EvalOptions::default()
+                });
+                assert_eq!(folded.to_string(), expected, "{}", expr);
+                for policy in policies {
+                    let options = EvalOptions {
+                        zero_division: policy,
+                        ..EvalOptions::default()
+                    };
+                    for x in [-2.0, 0.0, 3.0] {
+                        let value = |tree: &Expr| {
+                            let mut variables = HashMap::from([("x".to_string(), x)]);
+                            format!(
+                                "{:?}",
+                                tree.value(&options, &mut variables, &Registry::default())
+                            )
+                        };
+                        assert_eq!(
+                            value(&folded),
+                            value(&ast),
+                            "{} folded under {:?}, evaluated under {:?} with x = {}",
+                            expr,
+                            folded_with,
+                            policy,
+                            x
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_errors_unchanged() {
+        for expr in ["", "2 +", "(1", "2 3", "1.2.3", "sqrt(1, 2)", "foo(1)"] {
+            assert_eq!(
+                Calculator::parse_ast(expr),
+                Err(Calculator::calculate(expr).unwrap_err()),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_too_deep() {
+        let brackets = MAX_AST_DEPTH - 1;
+        let nested = format!("{}1{}", "(".repeat(brackets), ")".repeat(brackets));
+        assert!(Calculator::parse_ast(&nested).is_ok());
+
+        let deeper = format!("({})", nested);
+        assert_eq!(
+            Calculator::parse_ast(&deeper),
+            Err(Error::TooDeep {
+                depth: MAX_AST_DEPTH + 1,
+                max: MAX_AST_DEPTH
+            })
+        );
+        let powers = format!("2{}", " ^ 1".repeat(MAX_AST_DEPTH));
+        assert!(matches!(
+            Calculator::parse_ast(&powers),
+            Err(Error::TooDeep { .. })
+        ));
+        assert_eq!(Calculator::calculate(&powers), Ok(2.0));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::{Calculator, Error, Span, Token};
+
+/// How the brackets of an expression pair up, found in one left-to-right
+/// pass. Parsing builds its `MismatchedParens` errors from this, and corpus
+/// statistics take bracket depth from it, so "balanced" means the same
+/// thing everywhere.
+///
+/// ```
+/// use calculator::Calculator;
+///
+/// let balance = Calculator::paren_balance("(1 + (2)) * (3");
+/// assert_eq!(balance.max_depth, 2);
+/// assert_eq!(balance.unmatched_open, vec![12]);
+/// assert!(balance.unmatched_close.is_empty());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParenBalance {
+    /// Character positions of every `(` left open, in order.
+    pub unmatched_open: Vec<usize>,
+    /// Character positions of every `)` that had nothing to close.
+    pub unmatched_close: Vec<usize>,
+    /// The deepest nesting reached; 0 with no brackets.
+    pub max_depth: usize,
+}
+
+impl ParenBalance {
+    /// Pairs up `(is_open, position)` brackets in order. A `)` with nothing
+    /// to close is recorded and otherwise ignored, so it doesn't make a
+    /// later `(` look matched.
+    fn of(brackets: impl IntoIterator<Item = (bool, usize)>) -> Self {
+        let mut balance = ParenBalance::default();
+        for (is_open, position) in brackets {
+            if is_open {
+                balance.unmatched_open.push(position);
+                balance.max_depth = balance.max_depth.max(balance.unmatched_open.len());
+            } else if balance.unmatched_open.pop().is_none() {
+                balance.unmatched_close.push(position);
+            }
+        }
+        balance
+    }
+
+    /// The brackets among `tokens`, positioned by their index.
+    pub(crate) fn of_indices(tokens: &[Token]) -> Self {
+        ParenBalance::of(
+            tokens
+                .iter()
+                .enumerate()
+                .filter_map(|(i, token)| match token {
+                    Token::Bracket(c) => Some((*c == '(', i)),
+                    _ => None,
+                }),
+        )
+    }
+
+    /// The brackets among `tokens`, positioned by their `spans`.
+    pub(crate) fn of_tokens(tokens: &[Token], spans: &[Span]) -> Self {
+        ParenBalance::of(
+            tokens
+                .iter()
+                .zip(spans)
+                .filter_map(|(token, span)| match token {
+                    Token::Bracket(c) => Some((*c == '(', span.start)),
+                    _ => None,
+                }),
+        )
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.unmatched_open.is_empty() && self.unmatched_close.is_empty()
+    }
+
+    /// Whether more input could still balance the brackets: some are open
+    /// and none were closed too early.
+    pub fn is_open(&self) -> bool {
+        !self.unmatched_open.is_empty() && self.unmatched_close.is_empty()
+    }
+
+    /// `Ok` when balanced, otherwise the `MismatchedParens` error parsing
+    /// reports.
+    pub(crate) fn check(self) -> Result<(), Error> {
+        if self.is_balanced() {
+            return Ok(());
+        }
+        Err(Error::MismatchedParens {
+            unmatched_open: self.unmatched_open,
+            unmatched_close: self.unmatched_close,
+        })
+    }
+}
+
+impl Calculator {
+    /// The bracket balance of `expr`. Unlike [`Calculator::parse`] this
+    /// never fails: every other character is skipped, so it answers for
+    /// inputs that don't tokenize, such as a line still being typed.
+    pub fn paren_balance<T: AsRef<str>>(expr: T) -> ParenBalance {
+        let normalized = Self::normalize(expr.as_ref());
+        ParenBalance::of(
+            normalized
+                .text
+                .chars()
+                .enumerate()
+                .filter(|(_, c)| matches!(c, '(' | ')'))
+                .map(|(i, c)| (c == '(', normalized.original_position(i))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_depth() {
+        let cases: [(&str, usize, usize, usize); 8] = [
+            // (expr, unmatched open, unmatched close, max depth)
+            ("", 0, 0, 0),
+            ("1 + 2", 0, 0, 0),
+            ("((1) + (2))", 0, 0, 2),
+            ("(((", 3, 0, 3),
+            (")))", 0, 3, 0),
+            (")(", 1, 1, 1),
+            ("(1)) + ((2)", 1, 1, 2),
+            ("sqrt((4)", 1, 0, 2),
+        ];
+        for (expr, open, close, depth) in cases {
+            let balance = Calculator::paren_balance(expr);
+            assert_eq!(balance.unmatched_open.len(), open, "{:?}", expr);
+            assert_eq!(balance.unmatched_close.len(), close, "{:?}", expr);
+            assert_eq!(balance.max_depth, depth, "{:?}", expr);
+        }
+    }
+
+    #[test]
+    fn test_positions_in_original_input() {
+        let balance = Calculator::paren_balance("(1)) + ((2)");
+        assert_eq!(balance.unmatched_close, vec![3]);
+        assert_eq!(balance.unmatched_open, vec![7]);
+        // Full-width brackets are normalized, but counted where they were.
+        let balance = Calculator::paren_balance("（1 + 2");
+        assert_eq!(balance.unmatched_open, vec![0]);
+    }
+
+    #[test]
+    fn test_agrees_with_parse() {
+        for expr in ["(1 + 2", "1 + 2)", "(1)) + ((2)", "((( 1", "(1 + (2)) * 3"] {
+            let balance = Calculator::paren_balance(expr);
+            match Calculator::parse(expr) {
+                Err(Error::MismatchedParens {
+                    unmatched_open,
+                    unmatched_close,
+                }) => {
+                    assert_eq!(unmatched_open, balance.unmatched_open, "{:?}", expr);
+                    assert_eq!(unmatched_close, balance.unmatched_close, "{:?}", expr);
+                }
+                Ok(_) => assert!(balance.is_balanced(), "{:?}", expr),
+                Err(e) => panic!("{:?}: unexpected {:?}", expr, e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_open() {
+        assert!(Calculator::paren_balance("(1 + (2").is_open());
+        assert!(!Calculator::paren_balance("(1 + 2)").is_open());
+        // No amount of further input fixes an early `)`.
+        assert!(!Calculator::paren_balance("1) + (2").is_open());
+    }
+}
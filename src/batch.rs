@@ -0,0 +1,700 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::{Calculator, EngineeringFormat, Error, json};
+
+/// Widest source snippet shown in the human-readable table.
+const SNIPPET_WIDTH: usize = 40;
+
+/// Outcome of one expression inside a [`BatchReport`].
+#[derive(Debug)]
+pub struct BatchItem {
+    /// Zero-based position of the expression in the batch.
+    pub index: usize,
+    /// One-based line the expression came from.
+    pub line: usize,
+    pub source: String,
+    pub result: Result<f64, Error>,
+}
+
+impl BatchItem {
+    /// Source text cut down to a width suitable for tables and messages.
+    pub fn snippet(&self) -> String {
+        Calculator::summarize(&self.source, SNIPPET_WIDTH)
+    }
+
+    /// One JSON object for this item, as `--json` prints per line:
+    /// `{"line":1,"expr":"5/0","ok":false,"error":"DivisionByZero",
+    /// "message":"division by zero"}`, or `"ok":true` with a `"value"`.
+    /// Non-finite values are encoded as in [`BatchReport::to_json`].
+    pub fn to_json(&self) -> String {
+        let outcome = match &self.result {
+            Ok(value) => format!("\"ok\":true,{}", json::number_field("value", *value)),
+            Err(e) => format!(
+                "\"ok\":false,\"error\":{},\"message\":{}",
+                json::string(e.kind()),
+                json::string(&e.to_string())
+            ),
+        };
+        format!(
+            "{{\"line\":{},\"expr\":{},{}}}",
+            self.line,
+            json::string(&self.source),
+            outcome
+        )
+    }
+}
+
+/// Summary of evaluating many expressions, shared by every multi-expression
+/// entry point so they all report failures the same way.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub items: Vec<BatchItem>,
+}
+
+/// Settings for [`Calculator::calculate_batch_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchOptions {
+    /// Number of items between progress callbacks (at least 1).
+    pub report_every: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions { report_every: 1000 }
+    }
+}
+
+/// Snapshot passed to the progress callback of
+/// [`Calculator::calculate_batch_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchProgress {
+    pub completed: usize,
+    pub errors: usize,
+    pub elapsed: Duration,
+}
+
+impl BatchReport {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn succeeded(&self) -> usize {
+        self.items.iter().filter(|item| item.result.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failures().count()
+    }
+
+    /// Items skipped because the batch was aborted.
+    pub fn not_evaluated(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| matches!(item.result, Err(Error::NotEvaluated)))
+            .count()
+    }
+
+    /// Whether every item was evaluated, i.e. the batch was not aborted.
+    pub fn is_complete(&self) -> bool {
+        self.not_evaluated() == 0
+    }
+
+    /// Items whose evaluation failed, in batch order. Items that were never
+    /// evaluated are not failures.
+    pub fn failures(&self) -> impl Iterator<Item = &BatchItem> {
+        self.items
+            .iter()
+            .filter(|item| matches!(&item.result, Err(e) if !matches!(e, Error::NotEvaluated)))
+    }
+
+    /// Exit status for the CLI: 0 when everything evaluated, 1 when only
+    /// evaluation errors occurred and 2 when any expression failed to parse.
+    pub fn worst_exit_code(&self) -> i32 {
+        self.failures()
+            .filter_map(|item| item.result.as_ref().err())
+            .map(exit_code)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Machine-readable rendering of the report as a single JSON object,
+    /// with each item in the array as [`BatchItem::to_json`] writes it.
+    /// A non-finite result is emitted as `"value":null` plus a `"special"`
+    /// field of `"infinity"`, `"-infinity"` or `"nan"`.
+    pub fn to_json(&self) -> String {
+        let items: Vec<String> = self.items.iter().map(BatchItem::to_json).collect();
+
+        format!(
+            "{{\"items\":[{}],\"succeeded\":{},\"failed\":{},\"not_evaluated\":{}}}",
+            items.join(","),
+            self.succeeded(),
+            self.failed(),
+            self.not_evaluated()
+        )
+    }
+}
+
+impl BatchReport {
+    /// The table `Display` prints, with each value written by `format`, so
+    /// an [`EngineeringFormat`] that is [`EngineeringFormat::aligned`]
+    /// lines the results up on their point.
+    pub fn to_table(&self, format: &EngineeringFormat) -> String {
+        let mut table = String::new();
+        self.write_table(&mut table, |value| format.format(value))
+            .expect("writing to a String can't fail");
+        table
+    }
+
+    fn write_table<W: fmt::Write>(&self, f: &mut W, value: impl Fn(f64) -> String) -> fmt::Result {
+        let snippets: Vec<String> = self.items.iter().map(BatchItem::snippet).collect();
+        let width = snippets
+            .iter()
+            .map(|s| s.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(10);
+
+        writeln!(f, "{:>5}  {:<width$}  result", "line", "expression")?;
+        for (item, snippet) in self.items.iter().zip(&snippets) {
+            match &item.result {
+                Ok(v) => writeln!(f, "{:>5}  {:<width$}  {}", item.line, snippet, value(*v))?,
+                Err(e) => writeln!(f, "{:>5}  {:<width$}  error: {}", item.line, snippet, e)?,
+            }
+        }
+        write!(
+            f,
+            "{} expressions: {} succeeded, {} failed",
+            self.len(),
+            self.succeeded(),
+            self.failed()
+        )?;
+        if !self.is_complete() {
+            write!(f, ", {} not evaluated", self.not_evaluated())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_table(f, |value| value.to_string())
+    }
+}
+
+/// 2 for input that doesn't parse, 1 for input that parsed but failed to
+/// evaluate. Every expression here comes from source text, so an
+/// `InvalidExpression` always means it is malformed, whether the parser or
+/// the evaluator noticed, as `UnexpectedToken` does: `5 *`, `2 + + 3` and
+/// `sqrt()` alike.
+fn exit_code(error: &Error) -> i32 {
+    match error {
+        Error::BadToken { .. }
+        | Error::InvalidNumber { .. }
+        | Error::UnknownFunction { .. }
+        | Error::MismatchedParens { .. }
+        | Error::MismatchedBars { .. }
+        | Error::SpaceInNumber { .. }
+        | Error::UnexpectedToken { .. }
+        | Error::TooDeep { .. }
+        | Error::ReservedName { .. }
+        | Error::ReservedWord(_)
+        | Error::WrongArity { .. }
+        | Error::InvalidFunction { .. }
+        | Error::InvalidOperator { .. }
+        | Error::IncompatibleFormat { .. }
+        | Error::InvalidStoredExpression
+        | Error::ConflictingVariable(_)
+        | Error::InvalidExpression => 2,
+        Error::DivisionByZero
+        | Error::Overflow
+        | Error::InexactDivision
+        | Error::NotReal
+        | Error::DomainError { .. }
+        | Error::NotAnInteger { .. }
+        | Error::UndefinedVariable(_)
+        | Error::AmbiguousName(_)
+        | Error::NoPreviousResult
+        | Error::NoConvergence { .. }
+        | Error::NotEvaluated => 1,
+    }
+}
+
+/// Whether `line` of a formula file holds an expression, rather than being
+/// blank or a `#` comment.
+fn is_expression(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty() && !line.starts_with('#')
+}
+
+/// Evaluates each line of `input` that isn't blank or a `#` comment,
+/// handing the outcome to `emit`, and returns the worst exit status.
+fn stream<R, F>(input: R, mut emit: F) -> io::Result<i32>
+where
+    R: BufRead,
+    F: FnMut(&BatchItem) -> io::Result<()>,
+{
+    let mut worst = 0;
+    let mut index = 0;
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if !is_expression(&line) {
+            continue;
+        }
+        let source = line.trim();
+        let item = BatchItem {
+            index,
+            line: i + 1,
+            source: source.to_string(),
+            result: Calculator::calculate(source),
+        };
+        if let Err(e) = &item.result {
+            worst = worst.max(exit_code(e));
+        }
+        emit(&item)?;
+        index += 1;
+    }
+    Ok(worst)
+}
+
+impl Calculator {
+    /// Evaluates every expression independently; one failure never stops the
+    /// rest of the batch.
+    pub fn calculate_batch<I, S>(exprs: I) -> BatchReport
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self::calculate_batch_with(exprs, &BatchOptions::default(), |_| {
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// Evaluates every line of `text` that isn't blank or a `#` comment, as
+    /// read from a formula file. Items keep the line number they came from.
+    pub fn calculate_lines(text: &str) -> BatchReport {
+        let items = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| is_expression(line))
+            .enumerate()
+            .map(|(index, (line, source))| BatchItem {
+                index,
+                line: line + 1,
+                source: source.to_string(),
+                result: Self::calculate(source),
+            })
+            .collect();
+        BatchReport { items }
+    }
+
+    /// [`Calculator::calculate_lines`] over the contents of the file at
+    /// `path`. Only reading the file can fail; each line's own failure is in
+    /// its item.
+    pub fn calculate_file<P: AsRef<Path>>(path: P) -> io::Result<BatchReport> {
+        Ok(Self::calculate_lines(&fs::read_to_string(path)?))
+    }
+
+    /// Each expression line of the file at `path` with its result, in file
+    /// order. [`Calculator::calculate_file`] gives the same results along
+    /// with their line numbers.
+    pub fn evaluate_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<(String, Result<f64, Error>)>> {
+        let report = Self::calculate_file(path)?;
+        Ok(report
+            .items
+            .into_iter()
+            .map(|item| (item.source, item.result))
+            .collect())
+    }
+
+    /// Evaluates each line of `input` as it arrives, for use in a pipeline:
+    /// a value per line goes to `output`, and a failure goes to `errors` as
+    /// `line N: <error>` without stopping the stream. Blank lines and lines
+    /// starting with `#` are skipped. Returns the exit status
+    /// [`BatchReport::worst_exit_code`] would give for the same lines.
+    pub fn calculate_stream<R, W, E>(input: R, mut output: W, mut errors: E) -> io::Result<i32>
+    where
+        R: BufRead,
+        W: Write,
+        E: Write,
+    {
+        let status = stream(input, |item| match &item.result {
+            Ok(value) => writeln!(output, "{}", value),
+            Err(e) => writeln!(errors, "line {}: {}", item.line, e),
+        })?;
+        output.flush()?;
+        Ok(status)
+    }
+
+    /// [`Calculator::calculate_stream`], writing every line's outcome to
+    /// `output` as one [`BatchItem::to_json`] object per line.
+    pub fn calculate_stream_json<R, W>(input: R, mut output: W) -> io::Result<i32>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        let status = stream(input, |item| writeln!(output, "{}", item.to_json()))?;
+        output.flush()?;
+        Ok(status)
+    }
+
+    /// [`Calculator::calculate_batch`] with a progress callback, invoked
+    /// after every `options.report_every` items. Returning
+    /// `ControlFlow::Break` stops the batch: items evaluated so far keep
+    /// their results and the rest are reported as `Error::NotEvaluated`.
+    pub fn calculate_batch_with<I, S, F>(
+        exprs: I,
+        options: &BatchOptions,
+        mut progress: F,
+    ) -> BatchReport
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+        F: FnMut(BatchProgress) -> ControlFlow<()>,
+    {
+        let report_every = options.report_every.max(1);
+        let start = Instant::now();
+        let mut items = Vec::new();
+        let mut errors = 0;
+        let mut aborted = false;
+
+        for (index, expr) in exprs.into_iter().enumerate() {
+            let result = if aborted {
+                Err(Error::NotEvaluated)
+            } else {
+                Self::calculate(&expr)
+            };
+            if !aborted && result.is_err() {
+                errors += 1;
+            }
+            items.push(BatchItem {
+                index,
+                line: index + 1,
+                source: expr.as_ref().to_string(),
+                result,
+            });
+
+            if !aborted && items.len() % report_every == 0 {
+                let snapshot = BatchProgress {
+                    completed: items.len(),
+                    errors,
+                    elapsed: start.elapsed(),
+                };
+                aborted = progress(snapshot).is_break();
+            }
+        }
+
+        BatchReport { items }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_counts() {
+        let report = Calculator::calculate_batch(["2 + 3", "5 / 0", "(1"]);
+        assert_eq!(report.len(), 3);
+        assert_eq!(report.succeeded(), 1);
+        assert_eq!(report.failed(), 2);
+        assert_eq!(report.worst_exit_code(), 2);
+    }
+
+    #[test]
+    fn test_calculate_lines_skips_blanks_and_comments() {
+        let report = Calculator::calculate_lines("1 + 1\n\n   \n2 * (3\n# 2 +\n  # 1\n4 / 2\n");
+        let lines: Vec<(usize, usize)> = report.items.iter().map(|i| (i.index, i.line)).collect();
+        assert_eq!(lines, [(0, 1), (1, 4), (2, 7)]);
+        assert_eq!(report.succeeded(), 2);
+        assert_eq!(report.failed(), 1);
+    }
+
+    #[test]
+    fn test_evaluate_file() {
+        let path = std::env::temp_dir().join(format!("calculator-{}.txt", std::process::id()));
+        fs::write(&path, "1 + 2\n\n10 / 0\n2 @ 3\n(4 - 1) * 2\n").unwrap();
+        let results = Calculator::evaluate_file(&path);
+        let report = Calculator::calculate_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            results.unwrap(),
+            [
+                ("1 + 2".to_string(), Ok(3.0)),
+                ("10 / 0".to_string(), Err(Error::DivisionByZero)),
+                (
+                    "2 @ 3".to_string(),
+                    Err(Error::BadToken { ch: '@', pos: 2 })
+                ),
+                ("(4 - 1) * 2".to_string(), Ok(6.0)),
+            ]
+        );
+        let report = report.unwrap();
+        let lines: Vec<usize> = report.items.iter().map(|item| item.line).collect();
+        assert_eq!(lines, [1, 3, 4, 5]);
+        assert_eq!(report.worst_exit_code(), 2);
+
+        assert!(Calculator::evaluate_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_calculate_stream() {
+        let input = "# totals\n1 + 1\n\n10 / 0\n  # indented comment\n2 * 3\n2 @ 3\n7\n";
+        let (mut output, mut errors) = (Vec::new(), Vec::new());
+        let status = Calculator::calculate_stream(input.as_bytes(), &mut output, &mut errors);
+
+        assert_eq!(status.unwrap(), 2);
+        assert_eq!(String::from_utf8(output).unwrap(), "2\n6\n7\n");
+        assert_eq!(
+            String::from_utf8(errors).unwrap(),
+            "line 4: division by zero\nline 7: unexpected character '@' at position 2\n"
+        );
+    }
+
+    #[test]
+    fn test_calculate_stream_status() {
+        let status = |input: &str| {
+            Calculator::calculate_stream(input.as_bytes(), io::sink(), io::sink()).unwrap()
+        };
+        assert_eq!(status(""), 0);
+        assert_eq!(status("1\n# 1 / 0\n"), 0);
+        assert_eq!(status("1 / 0\n2"), 1);
+    }
+
+    #[test]
+    fn test_batch_exit_codes() {
+        assert_eq!(
+            Calculator::calculate_batch(["1 + 1", "2 * 2"]).worst_exit_code(),
+            0
+        );
+        assert_eq!(
+            Calculator::calculate_batch(["1 + 1", "1 / 0"]).worst_exit_code(),
+            1
+        );
+        assert_eq!(
+            Calculator::calculate_batch(Vec::<&str>::new()).worst_exit_code(),
+            0
+        );
+        for malformed in ["5 *", "2 + + 3", "2 3", "()", "sqrt()"] {
+            assert_eq!(
+                Calculator::calculate_batch(["1 / 0", malformed]).worst_exit_code(),
+                2,
+                "{}",
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn test_single_failure_among_thousand() {
+        let exprs: Vec<String> = (0..1000)
+            .map(|i| {
+                if i == 617 {
+                    "1 / 0".to_string()
+                } else {
+                    format!("{} + 1", i)
+                }
+            })
+            .collect();
+        let report = Calculator::calculate_batch(&exprs);
+
+        assert_eq!(report.succeeded(), 999);
+        let failures: Vec<&BatchItem> = report.failures().collect();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].index, 617);
+        assert_eq!(failures[0].line, 618);
+        assert_eq!(failures[0].source, "1 / 0");
+        assert_eq!(failures[0].result, Err(Error::DivisionByZero));
+
+        assert_eq!(report.items[616].result.as_ref().unwrap(), &617.0);
+        assert_eq!(report.items[618].result.as_ref().unwrap(), &619.0);
+    }
+
+    #[test]
+    fn test_progress_reported_every_n() {
+        let exprs: Vec<String> = (0..250)
+            .map(|i| {
+                if i % 50 == 0 {
+                    "1 / 0".to_string()
+                } else {
+                    i.to_string()
+                }
+            })
+            .collect();
+        let options = BatchOptions { report_every: 100 };
+        let mut seen = Vec::new();
+
+        let report = Calculator::calculate_batch_with(&exprs, &options, |progress| {
+            seen.push((progress.completed, progress.errors));
+            ControlFlow::Continue(())
+        });
+
+        assert_eq!(seen, vec![(100, 2), (200, 4)]);
+        assert!(report.is_complete());
+        assert_eq!(report.failed(), 5);
+    }
+
+    #[test]
+    fn test_abort_at_halfway() {
+        let exprs: Vec<String> = (0..1000).map(|i| format!("{} * 2", i)).collect();
+        let options = BatchOptions { report_every: 100 };
+
+        let report = Calculator::calculate_batch_with(&exprs, &options, |progress| {
+            if progress.completed >= 500 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+
+        assert_eq!(report.len(), 1000);
+        assert_eq!(report.succeeded(), 500);
+        assert_eq!(report.failed(), 0);
+        assert_eq!(report.not_evaluated(), 500);
+        assert!(!report.is_complete());
+        for item in &report.items[..500] {
+            assert_eq!(item.result.as_ref().unwrap(), &(item.index as f64 * 2.0));
+        }
+        for item in &report.items[500..] {
+            assert!(
+                matches!(item.result, Err(Error::NotEvaluated)),
+                "item {}",
+                item.index
+            );
+        }
+        assert!(
+            report
+                .to_string()
+                .ends_with("500 succeeded, 0 failed, 500 not evaluated")
+        );
+    }
+
+    #[test]
+    fn test_batch_display() {
+        let report = Calculator::calculate_batch(["2 * 3", "2 + @"]);
+        let table = report.to_string();
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].contains("2 * 3") && lines[1].ends_with("6"));
+        assert!(lines[2].ends_with("error: unexpected character '@' at position 4"));
+        assert_eq!(lines[3], "2 expressions: 1 succeeded, 1 failed");
+    }
+
+    #[test]
+    fn test_engineering_table() {
+        let report = Calculator::calculate_batch(["12400", "0.000045", "1 / 0", "3 * 1e-30"]);
+        let format = EngineeringFormat::new(3).si_prefix(true).aligned(true);
+        let table = report.to_table(&format);
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].ends_with("  12.4k    "), "{:?}", lines[1]);
+        assert!(lines[2].ends_with("  45.0µ    "), "{:?}", lines[2]);
+        assert!(lines[3].ends_with("error: division by zero"));
+        assert!(lines[4].ends_with("  3.00e-30 "), "{:?}", lines[4]);
+        // The values start in one column and are all as wide.
+        let start = lines[1].chars().count() - format.width();
+        for line in [lines[1], lines[2], lines[4]] {
+            assert_eq!(line.chars().count() - start, format.width(), "{:?}", line);
+        }
+        assert_eq!(lines[5], "4 expressions: 3 succeeded, 1 failed");
+    }
+
+    #[test]
+    fn test_batch_json() {
+        let report = Calculator::calculate_batch(["1 + 1", "say(\"hi\")"]);
+        assert_eq!(
+            report.to_json(),
+            "{\"items\":[\
+             {\"line\":1,\"expr\":\"1 + 1\",\"ok\":true,\"value\":2},\
+             {\"line\":2,\"expr\":\"say(\\\"hi\\\")\",\"ok\":false,\"error\":\"UnknownFunction\",\"message\":\"unknown function 'say' at position 0\"}\
+             ],\"succeeded\":1,\"failed\":1,\"not_evaluated\":0}"
+        );
+    }
+
+    #[test]
+    fn test_item_json() {
+        let input = "2+3\n5/0\n\n2 @ 3\n1e400\n\"quoted\\\n";
+        let mut output = Vec::new();
+        let status = Calculator::calculate_stream_json(input.as_bytes(), &mut output).unwrap();
+        assert_eq!(status, 2);
+
+        let output = String::from_utf8(output).unwrap();
+        let objects: Vec<json::strict::Value> = output
+            .lines()
+            .map(|line| json::strict::parse(line).unwrap())
+            .collect();
+        let field = |i: usize, key: &str| objects[i].get(key).unwrap_or_else(|| panic!("{}", key));
+        let number = |n: f64| json::strict::Value::Number(n);
+        let text = |s: &str| json::strict::Value::String(s.to_string());
+        assert_eq!(objects.len(), 5);
+
+        assert_eq!(field(0, "expr"), &text("2+3"));
+        assert_eq!(field(0, "line"), &number(1.0));
+        assert_eq!(field(0, "ok"), &json::strict::Value::Bool(true));
+        assert_eq!(field(0, "value"), &number(5.0));
+
+        assert_eq!(field(1, "ok"), &json::strict::Value::Bool(false));
+        assert_eq!(field(1, "error"), &text("DivisionByZero"));
+        assert_eq!(field(1, "message"), &text("division by zero"));
+        assert!(objects[1].get("value").is_none());
+
+        assert_eq!(field(2, "line"), &number(4.0));
+        assert_eq!(field(2, "error"), &text("BadToken"));
+        assert_eq!(
+            field(2, "message"),
+            &text("unexpected character '@' at position 2")
+        );
+
+        assert_eq!(field(3, "value"), &json::strict::Value::Null);
+        assert_eq!(field(3, "special"), &text("infinity"));
+
+        assert_eq!(field(4, "expr"), &text("\"quoted\\"));
+        assert_eq!(field(4, "error"), &text("BadToken"));
+    }
+
+    #[test]
+    fn test_batch_json_non_finite() {
+        let huge = format!("1{}", "0".repeat(400));
+        let report = Calculator::calculate_batch([
+            huge.clone(),
+            format!("0 - {}", huge),
+            format!("{0} - {0}", huge),
+        ]);
+
+        let parsed = json::strict::parse(&report.to_json()).unwrap();
+        let items = match parsed.get("items") {
+            Some(json::strict::Value::Array(items)) => items,
+            _ => panic!("Expected an items array"),
+        };
+
+        for (item, special) in items.iter().zip(["infinity", "-infinity", "nan"]) {
+            assert_eq!(item.get("ok"), Some(&json::strict::Value::Bool(true)));
+            assert_eq!(item.get("value"), Some(&json::strict::Value::Null));
+            assert_eq!(
+                item.get("special"),
+                Some(&json::strict::Value::String(special.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_snippet_truncation() {
+        let long = "1 + ".repeat(30) + "1";
+        let report = Calculator::calculate_batch([long.as_str()]);
+        let snippet = report.items[0].snippet();
+        assert!(snippet.chars().count() <= SNIPPET_WIDTH);
+        assert!(snippet.starts_with("1 + 1"));
+        assert!(snippet.ends_with("+ 1 (121 chars)"));
+    }
+}
@@ -0,0 +1,747 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::custom::Registry;
+use crate::{
+    Angle, Assoc, Calculator, Complex, DecimalSeparator, Division, Error, EvalOptions, Expr,
+    LocatedError, Token, Value, ZeroDivision,
+};
+
+/// Settings for a [`ConfiguredCalculator`], starting from the defaults the
+/// static [`Calculator`] API uses.
+///
+/// ```
+/// use calculator::{Angle, Calculator, Error};
+///
+/// let degrees = Calculator::builder().angle(Angle::Degrees).max_depth(2).build();
+/// assert_eq!(degrees.calculate("sin(90)"), Ok(1.0));
+/// assert_eq!(
+///     degrees.calculate("((( 1 )))"),
+///     Err(Error::TooDeep { depth: 3, max: 2 })
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CalculatorBuilder {
+    options: EvalOptions,
+}
+
+impl CalculatorBuilder {
+    pub fn new() -> Self {
+        CalculatorBuilder::default()
+    }
+
+    /// See [`EvalOptions::implicit_multiplication`].
+    pub fn implicit_multiplication(mut self, allowed: bool) -> Self {
+        self.options.implicit_multiplication = allowed;
+        self
+    }
+
+    pub fn angle(mut self, angle: Angle) -> Self {
+        self.options.angle = angle;
+        self
+    }
+
+    /// See [`EvalOptions::max_depth`].
+    pub fn max_depth(mut self, max: usize) -> Self {
+        self.options.max_depth = Some(max);
+        self
+    }
+
+    pub fn zero_division(mut self, policy: ZeroDivision) -> Self {
+        self.options.zero_division = policy;
+        self
+    }
+
+    /// See [`DecimalSeparator`].
+    pub fn decimal_separator(mut self, separator: DecimalSeparator) -> Self {
+        self.options.decimal_separator = separator;
+        self
+    }
+
+    pub fn division(mut self, division: Division) -> Self {
+        self.options.division = division;
+        self
+    }
+
+    /// See [`EvalOptions::real_roots`].
+    pub fn real_roots(mut self, allowed: bool) -> Self {
+        self.options.real_roots = allowed;
+        self
+    }
+
+    /// See [`EvalOptions::strict_input`].
+    pub fn strict_input(mut self, strict: bool) -> Self {
+        self.options.strict_input = strict;
+        self
+    }
+
+    pub fn build(self) -> ConfiguredCalculator {
+        ConfiguredCalculator {
+            options: self.options,
+            registry: Registry::default(),
+        }
+    }
+}
+
+/// A calculator with its own settings and functions, made by
+/// [`Calculator::builder`]. Its methods are the static API's, following
+/// the settings; the static ones are this with everything left as default
+/// and nothing registered.
+#[derive(Debug, Clone, Default)]
+pub struct ConfiguredCalculator {
+    options: EvalOptions,
+    registry: Registry,
+}
+
+impl ConfiguredCalculator {
+    pub fn options(&self) -> &EvalOptions {
+        &self.options
+    }
+
+    pub(crate) fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Makes `name(a, b, ...)` call `function` with `arity` arguments,
+    /// replacing any function registered under `name` before. Calling it
+    /// with another number of arguments is [`Error::WrongArity`], and the
+    /// error `function` returns is the expression's.
+    ///
+    /// ```
+    /// use calculator::{Calculator, Error};
+    ///
+    /// let mut calc = Calculator::builder().build();
+    /// calc.register_function("double", 1, |args| Ok(args[0] * 2.0))?;
+    /// assert_eq!(calc.calculate("double(21) + 1"), Ok(43.0));
+    /// assert_eq!(
+    ///     calc.calculate("double(1, 2)"),
+    ///     Err(Error::WrongArity { name: "double".to_string(), expected: 1, found: 2 })
+    /// );
+    /// assert_eq!(
+    ///     calc.register_function("sqrt", 1, |args| Ok(args[0])),
+    ///     Err(Error::ReservedWord("sqrt".to_string()))
+    /// );
+    /// # Ok::<(), Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidFunction`], registering nothing, if `arity` is zero,
+    /// since `name()` doesn't parse, or if `name` isn't a name (a letter,
+    /// then letters, digits and `_`), and [`Error::ReservedWord`] if
+    /// [`Calculator::is_reserved`] says it is already built in.
+    pub fn register_function<F>(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(&[f64]) -> Result<f64, Error> + Send + Sync + 'static,
+    {
+        if arity == 0 || !is_name(name) {
+            return Err(Error::InvalidFunction {
+                name: name.to_string(),
+                arity,
+            });
+        }
+        if Calculator::is_reserved(name) {
+            return Err(Error::ReservedWord(name.to_string()));
+        }
+        self.registry.insert_function(name, arity, function);
+        Ok(())
+    }
+
+    /// Makes `a @ b` call `operator` with `a` and `b`, binding as tightly
+    /// as `precedence` says on [`Operator::precedence`]'s scale, where `+`
+    /// is 10 and `*` 20. Registering `symbol` again replaces it.
+    ///
+    /// ```
+    /// use calculator::{Assoc, Calculator, Error};
+    ///
+    /// let mut calc = Calculator::builder().build();
+    /// calc.register_operator('@', 15, Assoc::Left, |l, r| Ok((l + r) / 2.0))?;
+    /// // Between `+` and `*`: 1 + ((2 * 3) @ 4)
+    /// assert_eq!(calc.calculate("1 + 2 * 3 @ 4"), Ok(6.0));
+    /// assert_eq!(
+    ///     calc.register_operator('×', 15, Assoc::Left, |l, r| Ok(l * r)),
+    ///     Err(Error::InvalidOperator { symbol: '×' })
+    /// );
+    /// # Ok::<(), Error>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidOperator`], registering nothing, if `symbol` already
+    /// means something: whitespace, a letter, a digit, one of
+    /// `+ - * / % ^ ( ) | , ; = . _`, or a character that is normalized to
+    /// one of those, such as `−`, `×` or `＊`.
+    pub fn register_operator<F>(
+        &mut self,
+        symbol: char,
+        precedence: u8,
+        assoc: Assoc,
+        operator: F,
+    ) -> Result<(), Error>
+    where
+        F: Fn(f64, f64) -> Result<f64, Error> + Send + Sync + 'static,
+    {
+        let text = symbol.to_string();
+        if symbol.is_whitespace()
+            || symbol.is_alphanumeric()
+            || "+-*/%^()|,;=._".contains(symbol)
+            || Calculator::normalize(&text).text != text
+        {
+            return Err(Error::InvalidOperator { symbol });
+        }
+        self.registry
+            .insert_operator(symbol, precedence, assoc, operator);
+        Ok(())
+    }
+
+    /// Calls `hook` whenever this calculator fails to parse an expression,
+    /// from any of its methods, replacing any hook set before; clones made
+    /// afterwards share it. The hook gets the input as
+    /// [`Calculator::summarize_masked`] renders it, so numbers never leave
+    /// the calculator, and the error the caller is about to receive.
+    /// Evaluation errors aren't reported.
+    ///
+    /// A panic inside the hook is caught and discarded; the caller still
+    /// gets its parse error.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use calculator::Calculator;
+    ///
+    /// let failures = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = Arc::clone(&failures);
+    /// let mut calc = Calculator::builder().build();
+    /// calc.on_parse_error(move |source, _| sink.lock().unwrap().push(source.to_string()));
+    /// let _ = calc.calculate("12 + f(1)");
+    /// let _ = calc.calculate("1 / 0");
+    /// assert_eq!(*failures.lock().unwrap(), ["<num> + f(<num>)"]);
+    /// ```
+    pub fn on_parse_error<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &Error) + Send + Sync + 'static,
+    {
+        self.registry.parse_error_hook = Some(Arc::new(hook));
+    }
+
+    /// Removes the hook set by [`ConfiguredCalculator::on_parse_error`], if
+    /// any.
+    pub fn clear_parse_error_hook(&mut self) {
+        self.registry.parse_error_hook = None;
+    }
+
+    /// [`Calculator::parse`]; use [`Calculator::parse_with`] to also see
+    /// warnings.
+    pub fn parse<T: AsRef<str>>(&self, expr: T) -> Result<Vec<Token>, Error> {
+        Calculator::parse_in(expr.as_ref(), &self.options, false, &self.registry)
+            .map(|(tokens, _, _)| tokens)
+    }
+
+    /// [`Calculator::try_to_postfix`]; no setting changes it.
+    pub fn try_to_postfix(&self, tokens: Vec<Token>) -> Result<Vec<Token>, Error> {
+        Calculator::try_to_postfix(tokens)
+    }
+
+    /// [`Calculator::to_postfix`].
+    #[deprecated(
+        note = "use `ConfiguredCalculator::try_to_postfix`, which reports unbalanced brackets"
+    )]
+    pub fn to_postfix(&self, tokens: Vec<Token>) -> Vec<Token> {
+        self.try_to_postfix(tokens).unwrap_or_default()
+    }
+
+    /// [`Calculator::evaluate`]. A call to a function this calculator
+    /// doesn't have is [`Error::UnknownFunction`] at position 0, since
+    /// postfix tokens don't keep positions.
+    pub fn evaluate(&self, tokens: Vec<Token>) -> Result<f64, Error> {
+        Calculator::evaluate_at(tokens, &self.options, &mut HashMap::new(), &self.registry)
+            .map_err(|(error, _)| error)
+    }
+
+    /// [`Calculator::calculate`].
+    pub fn calculate<T: AsRef<str>>(&self, expr: T) -> Result<f64, Error> {
+        self.calculate_located(expr).map_err(|e| e.error)
+    }
+
+    /// [`Calculator::calculate_located`].
+    pub fn calculate_located<T: AsRef<str>>(&self, expr: T) -> Result<f64, LocatedError> {
+        Calculator::calculate_located_in(expr.as_ref(), &self.options, &self.registry)
+    }
+
+    /// [`Calculator::calculate_integers`].
+    pub fn calculate_integers<T: AsRef<str>>(&self, expr: T) -> Result<Value, Error> {
+        Calculator::calculate_integers_in(expr.as_ref(), &self.options, &self.registry)
+    }
+
+    /// [`Calculator::calculate_complex`].
+    pub fn calculate_complex<T: AsRef<str>>(&self, expr: T) -> Result<Complex, Error> {
+        Calculator::calculate_complex_in(expr.as_ref(), &self.options, &self.registry)
+    }
+
+    /// [`Calculator::calculate_precise`].
+    pub fn calculate_precise<T: AsRef<str>>(
+        &self,
+        expr: T,
+        precision: usize,
+    ) -> Result<String, Error> {
+        Calculator::calculate_precise_in(expr.as_ref(), precision, &self.options, &self.registry)
+    }
+
+    /// [`Calculator::parse_ast`], read with this calculator's settings and
+    /// with its functions and operators as [`Expr::CustomCall`] and
+    /// [`Expr::CustomOp`].
+    pub fn parse_ast<T: AsRef<str>>(&self, expr: T) -> Result<Expr, Error> {
+        Calculator::parse_ast_in(expr.as_ref(), &self.options, &self.registry)
+    }
+
+    /// [`Expr::evaluate`] under this calculator's settings, calling its
+    /// functions and operators.
+    pub fn evaluate_ast(&self, ast: &Expr) -> Result<f64, Error> {
+        ast.value(&self.options, &mut HashMap::new(), &self.registry)
+    }
+
+    /// [`Calculator::simplify`], folding constants under this calculator's
+    /// settings and through its functions and operators, as
+    /// [`Expr::simplify_with`] does, with the same caveat about identities.
+    ///
+    /// ```
+    /// use calculator::{Angle, Calculator};
+    ///
+    /// let mut calc = Calculator::builder().angle(Angle::Degrees).build();
+    /// calc.register_function("double", 1, |args| Ok(args[0] * 2.0))?;
+    /// assert_eq!(calc.simplify("double(sin(90)) * x")?, "2 * x");
+    /// assert_eq!(calc.simplify("double(x) + 0")?, "double(x)");
+    /// # Ok::<(), calculator::Error>(())
+    /// ```
+    pub fn simplify<T: AsRef<str>>(&self, expr: T) -> Result<String, Error> {
+        let ast = self.parse_ast(expr)?;
+        Ok(ast.simplify_in(&self.options, &self.registry).to_string())
+    }
+}
+
+impl Calculator {
+    /// Starts configuring a [`ConfiguredCalculator`].
+    pub fn builder() -> CalculatorBuilder {
+        CalculatorBuilder::new()
+    }
+}
+
+/// Whether `name` is spelled as a name: a letter, then letters, digits and
+/// `_`.
+pub(crate) fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(char::is_alphabetic) && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_matches_static_api() {
+        let calculator = Calculator::builder().build();
+        assert_eq!(calculator.options(), &EvalOptions::default());
+        for expr in ["2 + 3 * 4", "sin(pi / 2)", "1 / 0", "2(3)", "((1))", "2 @"] {
+            assert_eq!(
+                calculator.calculate(expr),
+                Calculator::calculate(expr),
+                "{}",
+                expr
+            );
+            assert_eq!(calculator.parse(expr), Calculator::parse(expr), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_configurations_differ() {
+        let strict = Calculator::builder()
+            .max_depth(1)
+            .strict_input(true)
+            .build();
+        let loose = Calculator::builder()
+            .implicit_multiplication(true)
+            .angle(Angle::Degrees)
+            .zero_division(ZeroDivision::Ieee)
+            .division(Division::TruncateWhenBothIntegral)
+            .decimal_separator(DecimalSeparator::Comma)
+            .build();
+        let cases = [
+            ("2(3)", Err(Error::UnexpectedToken { pos: 1 }), Ok(6.0)),
+            ("cos(0) + sin(90)", Ok(1.0 + 90f64.sin()), Ok(2.0)),
+            ("1 / 0", Err(Error::DivisionByZero), Ok(f64::INFINITY)),
+            ("-1 // 0", Err(Error::DivisionByZero), Ok(f64::NEG_INFINITY)),
+            ("7 / 2", Ok(3.5), Ok(3.0)),
+            (
+                "max(2,5; 1)",
+                Err(Error::BadToken { ch: ';', pos: 7 }),
+                Ok(2.5),
+            ),
+            (
+                "sqrt((4))",
+                Err(Error::TooDeep { depth: 2, max: 1 }),
+                Ok(2.0),
+            ),
+            ("3 – 1", Err(Error::BadToken { ch: '–', pos: 2 }), Ok(2.0)),
+        ];
+        for (expr, from_strict, from_loose) in cases {
+            assert_eq!(strict.calculate(expr), from_strict, "{}", expr);
+            assert_eq!(loose.calculate(expr), from_loose, "{}", expr);
+        }
+        assert!(loose.calculate("0 / 0").unwrap().is_nan());
+        assert!(loose.calculate("1 % 0").unwrap().is_nan());
+    }
+
+    fn with_functions() -> ConfiguredCalculator {
+        let mut calc = Calculator::builder().build();
+        calc.register_function("double", 1, |args| Ok(args[0] * 2.0))
+            .unwrap();
+        calc.register_function("hypot", 2, |args| Ok(args[0].hypot(args[1])))
+            .unwrap();
+        calc.register_function("clamp01", 1, |args| Ok(args[0].clamp(0.0, 1.0)))
+            .unwrap();
+        calc.register_function("checked_div", 2, |args| match args {
+            [_, divisor] if *divisor == 0.0 => Err(Error::DivisionByZero),
+            [a, b] => Ok(a / b),
+            _ => unreachable!("arity is checked first"),
+        })
+        .unwrap();
+        calc
+    }
+
+    #[test]
+    fn test_registered_functions() {
+        let calc = with_functions();
+        let cases = [
+            ("double(21) + 1", 43.0),
+            ("hypot(3, 4)", 5.0),
+            ("2 * hypot(3, 2 + 2) - 1", 9.0),
+            ("hypot(double(3) / 2, -4) ^ 2", 25.0),
+            ("sqrt(hypot(9, 12)) * double(1)", 2.0 * 15f64.sqrt()),
+            ("hypot((1 + 2), sqrt(16))", 5.0),
+            ("clamp01(5) + clamp01(-5)", 1.0),
+            ("checked_div(7, 2)", 3.5),
+            ("hypot(x = 3, x + 1) + x", 8.0),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(calc.calculate(expr), Ok(expected), "{}", expr);
+        }
+        assert_eq!(
+            calc.calculate("1 + checked_div(1, 0)"),
+            Err(Error::DivisionByZero)
+        );
+        // The static API and other calculators don't know them.
+        assert_eq!(
+            Calculator::calculate("double(2)"),
+            Err(Error::UnknownFunction {
+                name: "double".to_string(),
+                pos: 0
+            })
+        );
+        let postfix = calc
+            .try_to_postfix(calc.parse("1 + double(2)").unwrap())
+            .unwrap();
+        assert_eq!(
+            Calculator::builder().build().evaluate(postfix.clone()),
+            Err(Error::UnknownFunction {
+                name: "double".to_string(),
+                pos: 0
+            })
+        );
+        assert_eq!(calc.evaluate(postfix), Ok(5.0));
+    }
+
+    #[test]
+    fn test_unknown_and_wrong_arity_calls() {
+        let calc = with_functions();
+        assert_eq!(
+            calc.calculate("1 + triple(2)"),
+            Err(Error::UnknownFunction {
+                name: "triple".to_string(),
+                pos: 4
+            })
+        );
+        for (expr, name, expected, found) in [
+            ("hypot(3)", "hypot", 2, 1),
+            ("1 + hypot(1, 2, 3)", "hypot", 2, 3),
+            ("double(hypot(3, 4), 1)", "double", 1, 2),
+            ("sqrt(16, 2)", "sqrt", 1, 2),
+        ] {
+            assert_eq!(
+                calc.calculate(expr),
+                Err(Error::WrongArity {
+                    name: name.to_string(),
+                    expected,
+                    found
+                }),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            calc.calculate("hypot(3)").unwrap_err().to_string(),
+            "'hypot' takes 2 arguments, not 1"
+        );
+        for (expr, pos) in [
+            ("double()", 7),
+            ("hypot(1,)", 8),
+            ("hypot(,1)", 6),
+            ("(1, 2)", 2),
+            ("1, 2", 1),
+            ("hypot(1,,2)", 8),
+        ] {
+            assert_eq!(
+                calc.calculate(expr),
+                Err(Error::UnexpectedToken { pos }),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::calculate("1, 2"),
+            Err(Error::UnexpectedToken { pos: 1 })
+        );
+    }
+
+    #[test]
+    fn test_registration() {
+        let mut calc = with_functions();
+        assert_eq!(
+            calc.register_function("double", 1, |args| Ok(args[0] * 3.0)),
+            Ok(())
+        );
+        assert_eq!(calc.calculate("double(2)"), Ok(6.0));
+        // A copy keeps the functions.
+        assert_eq!(calc.clone().calculate("hypot(5, 12)"), Ok(13.0));
+        assert!(format!("{:?}", calc).contains(r#"("hypot", 2)"#));
+
+        let rejected = |name: &str, arity| {
+            let mut calc = Calculator::builder().build();
+            let registered = calc.register_function(name, arity, |args| Ok(args[0]));
+            assert_eq!(
+                registered,
+                Err(Error::InvalidFunction {
+                    name: name.to_string(),
+                    arity
+                }),
+                "{}",
+                name
+            );
+            // Nothing was registered.
+            assert!(calc.registry().function(name).is_none(), "{}", name);
+        };
+        for name in ["2x", "a-b", ""] {
+            rejected(name, 1);
+        }
+        for name in ["sqrt", "pi", "E", "ans"] {
+            assert_eq!(
+                calc.register_function(name, 1, |args| Ok(args[0])),
+                Err(Error::ReservedWord(name.to_string()))
+            );
+            assert!(calc.registry().function(name).is_none(), "{}", name);
+        }
+        rejected("now", 0);
+        assert_eq!(
+            Error::InvalidFunction {
+                name: "now".to_string(),
+                arity: 0
+            }
+            .to_string(),
+            "can't register 'now': a function needs at least one argument"
+        );
+        assert_eq!(
+            Error::InvalidFunction {
+                name: "2x".to_string(),
+                arity: 1
+            }
+            .to_string(),
+            "can't register '2x': it isn't a name"
+        );
+        assert_eq!(
+            Error::ReservedWord("pi".to_string()).to_string(),
+            "can't register 'pi': it is the constant π"
+        );
+    }
+
+    #[test]
+    fn test_registered_operators() {
+        let mut calc = Calculator::builder().build();
+        calc.register_operator('@', 15, Assoc::Left, |l, r| Ok((l + r) / 2.0))
+            .unwrap();
+        calc.register_operator('&', 5, Assoc::Right, |l, r| Ok(l.max(r)))
+            .unwrap();
+        calc.register_operator('!', 15, Assoc::Right, |l, r| Ok(l - r))
+            .unwrap();
+        calc.register_operator('~', 50, Assoc::Left, |l, r| match r {
+            0.0 => Err(Error::DivisionByZero),
+            _ => Ok(l.rem_euclid(r)),
+        })
+        .unwrap();
+        let cases = [
+            // Below `*`: (2 * 3) @ 4, and above `+`: 1 + (2 @ 4).
+            ("2 * 3 @ 4", 5.0),
+            ("4 @ 2 * 3", 5.0),
+            ("1 + 2 @ 4", 4.0),
+            ("2 @ 4 + 1", 4.0),
+            ("1 + 2 * 3 @ 4 - 1", 5.0),
+            // Left-associative: (0 @ 4) @ 8.
+            ("0 @ 4 @ 8", 5.0),
+            // Right-associative: 10 ! (4 ! 1).
+            ("10 ! 4 ! 1", 7.0),
+            ("(2 @ 4) * 2", 6.0),
+            ("1 & 5 & 3 + 4", 7.0),
+            ("(-7) ~ 3", 2.0),
+            ("-7 ~ 3", -1.0),
+            // Tighter than `^` and `-` both: -((2 ~ 3) ^ 2).
+            ("-2 ~ 3 ^ 2", -4.0),
+            ("sqrt(16 @ 2) @ -3", 0.0),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(calc.calculate(expr), Ok(expected), "{}", expr);
+        }
+        assert_eq!(calc.calculate("1 ~ 0"), Err(Error::DivisionByZero));
+        for (expr, pos) in [("@ 1", 0), ("1 @", 2), ("1 @ @ 2", 4), ("(@ 2)", 1)] {
+            assert_eq!(
+                calc.calculate(expr),
+                Err(Error::UnexpectedToken { pos }),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::calculate("2 @ 4"),
+            Err(Error::BadToken { ch: '@', pos: 2 })
+        );
+        assert_eq!(
+            calc.calculate("2 # 4"),
+            Err(Error::BadToken { ch: '#', pos: 2 })
+        );
+    }
+
+    #[test]
+    fn test_located_rejections() {
+        let located = |calc: &ConfiguredCalculator, expr: &str| {
+            let e = calc.calculate_located(expr).unwrap_err();
+            (e.error, e.span.map(|span| (span.start, span.end)))
+        };
+        let mut calc = Calculator::builder().build();
+        calc.register_operator('@', 15, Assoc::Left, |l, r| Ok((l + r) / 2.0))
+            .unwrap();
+        assert_eq!(
+            located(&calc, "1 @ @ 2"),
+            (Error::UnexpectedToken { pos: 4 }, Some((4, 5)))
+        );
+        assert_eq!(
+            located(&calc, "1 / (2 @ 2 - 2)"),
+            (Error::DivisionByZero, Some((2, 3)))
+        );
+
+        // `2 pi` is a product, so it's the trailing `*` that's rejected.
+        let implicit = Calculator::builder().implicit_multiplication(true).build();
+        assert_eq!(
+            located(&implicit, "2 pi *"),
+            (Error::UnexpectedToken { pos: 5 }, Some((5, 6)))
+        );
+        assert_eq!(
+            Calculator::calculate_located("2 pi *").unwrap_err().span,
+            Some(crate::Span { start: 2, end: 4 })
+        );
+    }
+
+    #[test]
+    fn test_built_in_symbols_rejected() {
+        for symbol in [
+            '+', '-', '*', '/', '%', '^', '(', ')', '|', ',', ';', '=', '.', '_', 'x', '7', ' ',
+            '\u{2212}', '＊', '“', '×', '÷',
+        ] {
+            let mut calc = Calculator::builder().build();
+            assert_eq!(
+                calc.register_operator(symbol, 15, Assoc::Left, |l, _| Ok(l)),
+                Err(Error::InvalidOperator { symbol }),
+                "{:?}",
+                symbol
+            );
+            assert!(calc.registry().operator(symbol).is_none(), "{:?}", symbol);
+        }
+        assert_eq!(
+            Error::InvalidOperator { symbol: '×' }.to_string(),
+            "can't register '×' as an operator: it already means something"
+        );
+    }
+
+    #[test]
+    fn test_trees_with_registered_names() {
+        let mut calc = with_functions();
+        calc.register_operator('@', 15, Assoc::Left, |l, r| Ok((l + r) / 2.0))
+            .unwrap();
+        let ast = calc.parse_ast("1 + double(x) @ 3").unwrap();
+        let Expr::BinaryOp { rhs, .. } = &ast else {
+            panic!("{:?}", ast);
+        };
+        assert!(matches!(**rhs, Expr::CustomOp { symbol: '@', .. }));
+        assert_eq!(ast.to_string(), "1 + double(x) @ 3");
+        assert_eq!(calc.parse_ast(ast.to_string()), Ok(ast.clone()));
+        assert_eq!(
+            ast.evaluate(),
+            Err(Error::UndefinedVariable("x".to_string()))
+        );
+        assert_eq!(
+            calc.evaluate_ast(&calc.parse_ast("double(2) @ 2").unwrap()),
+            Ok(3.0)
+        );
+        assert_eq!(
+            Calculator::parse_ast("double(2)"),
+            Err(Error::UnknownFunction {
+                name: "double".to_string(),
+                pos: 0
+            })
+        );
+
+        let cases = [
+            ("double(2) @ 4 + x", "4 + x"),
+            ("hypot(3, 4) * y", "5 * y"),
+            ("double(x) * 1 @ 0", "double(x) @ 0"),
+            ("(1 @ 2) @ (3 @ x)", "1.5 @ (3 @ x)"),
+            ("checked_div(1, 0) + x", "checked_div(1, 0) + x"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(calc.simplify(expr).as_deref(), Ok(expected), "{}", expr);
+        }
+
+        // The calculator's settings apply to parsing and folding.
+        let european = Calculator::builder()
+            .decimal_separator(DecimalSeparator::Comma)
+            .implicit_multiplication(true)
+            .angle(Angle::Degrees)
+            .build();
+        assert_eq!(european.simplify("max(1,5; 2) x").as_deref(), Ok("2 * x"));
+        assert_eq!(
+            european.simplify("cos(60) + x").as_deref(),
+            Ok("0.5000000000000001 + x")
+        );
+        assert_eq!(
+            Calculator::simplify("max(1,5; 2) x"),
+            Err(Error::BadToken { ch: ';', pos: 7 })
+        );
+    }
+
+    #[test]
+    fn test_instance_pipeline() {
+        let degrees = Calculator::builder().angle(Angle::Degrees).build();
+        let tokens = degrees.parse("2 * cos(60)").unwrap();
+        assert_eq!(tokens, Calculator::parse("2 * cos(60)").unwrap());
+        let postfix = degrees.try_to_postfix(tokens).unwrap();
+        assert!((degrees.evaluate(postfix.clone()).unwrap() - 1.0).abs() < 1e-15);
+        assert_eq!(Calculator::evaluate(postfix), Ok(2.0 * 60f64.cos()));
+        // Depth counts every bracket, the ones around arguments included.
+        let shallow = Calculator::builder().max_depth(0).build();
+        assert_eq!(shallow.parse("1 + 2"), Calculator::parse("1 + 2"));
+        assert_eq!(
+            shallow.parse("abs(1)"),
+            Err(Error::TooDeep { depth: 1, max: 0 })
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Putting text on the system clipboard for the REPL's `:copy`, by running
+//! the platform's clipboard tool with the text on its standard input.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// A program that copies its standard input to the clipboard, and its
+/// arguments.
+type Tool = (&'static str, &'static [&'static str]);
+
+/// The tools tried, in order. On Linux `clip.exe` is there under WSL.
+const TOOLS: &[Tool] = if cfg!(target_os = "macos") {
+    &[("pbcopy", &[])]
+} else if cfg!(windows) {
+    &[("clip.exe", &[])]
+} else {
+    &[("xclip", &["-selection", "clipboard"]), ("clip.exe", &[])]
+};
+
+/// How a [`Repl`](crate::Repl) reaches the clipboard: copies the text, or
+/// says why it couldn't. Swapped out in tests, which mustn't touch the
+/// real one.
+pub(crate) type CopyText = fn(&str) -> Result<(), String>;
+
+/// Copies `text` with the first of the platform's tools that works.
+pub(crate) fn copy(text: &str) -> Result<(), String> {
+    copy_with(text, TOOLS, spawn)
+}
+
+/// Tries each of `tools` in turn through `spawn` until one copies `text`,
+/// or gives each one's failure.
+fn copy_with(
+    text: &str,
+    tools: &[Tool],
+    spawn: impl Fn(&str, &[&str], &str) -> io::Result<()>,
+) -> Result<(), String> {
+    let mut failures = Vec::new();
+    for (program, args) in tools {
+        match spawn(program, args, text) {
+            Ok(()) => return Ok(()),
+            Err(e) => failures.push(format!("{}: {}", program, e)),
+        }
+    }
+    Err(failures.join("; "))
+}
+
+/// Runs `program` with `text` on its standard input, which is closed
+/// before waiting so the tool sees the end of it.
+fn spawn(program: &str, args: &[&str], text: &str) -> io::Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("exited with {}", status)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_first_working_tool() {
+        let tools: &[Tool] = &[("missing", &[]), ("xclip", &["-selection", "clipboard"])];
+        let calls = RefCell::new(Vec::new());
+        let copied = copy_with("42", tools, |program, args, text| {
+            calls
+                .borrow_mut()
+                .push(format!("{} {:?} {}", program, args, text));
+            match program {
+                "missing" => Err(io::Error::from(io::ErrorKind::NotFound)),
+                _ => Ok(()),
+            }
+        });
+        assert_eq!(copied, Ok(()));
+        assert_eq!(
+            calls.into_inner(),
+            ["missing [] 42", "xclip [\"-selection\", \"clipboard\"] 42"]
+        );
+
+        let failed = copy_with("42", tools, |program, _, _| match program {
+            "missing" => Err(io::Error::from(io::ErrorKind::NotFound)),
+            _ => Err(io::Error::other("exited with exit status: 1")),
+        });
+        assert_eq!(
+            failed,
+            Err("missing: entity not found; xclip: exited with exit status: 1".to_string())
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Compiled expressions as plain Rust closures, for iterator pipelines.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::custom::Registry;
+use crate::{Calculator, Error, EvalOptions, StoredExpression};
+
+/// What a thread's closures evaluate with, kept between calls so a call
+/// allocates nothing once the buffers have grown. Each thread has its own,
+/// which keeps the closures `Sync`.
+#[derive(Default)]
+struct Scratch {
+    stack: Vec<f64>,
+    /// The single variable of [`StoredExpression::into_fn1`]'s closures,
+    /// and any the expression assigns.
+    variables: HashMap<String, f64>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::default();
+}
+
+impl StoredExpression {
+    /// The expression as a closure evaluating it, with default settings,
+    /// for the variables it is given, as
+    /// [`Calculator::evaluate_with_variables`] would. It owns the tokens,
+    /// so it outlives `self` and can be sent to and shared between
+    /// threads.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use calculator::StoredExpression;
+    ///
+    /// let area = StoredExpression::compile("w * h").unwrap().into_fn();
+    /// let sizes = [(2.0, 3.0), (4.0, 0.5)].map(|(w, h)| {
+    ///     HashMap::from([("w".to_string(), w), ("h".to_string(), h)])
+    /// });
+    /// let areas: Result<Vec<f64>, _> = sizes.iter().map(area).collect();
+    /// assert_eq!(areas, Ok(vec![6.0, 2.0]));
+    /// ```
+    pub fn into_fn(self) -> impl Fn(&HashMap<String, f64>) -> Result<f64, Error> + Send + Sync {
+        let tokens = self.tokens;
+        move |variables| {
+            SCRATCH.with_borrow_mut(|scratch| {
+                Calculator::evaluate_on(
+                    &mut scratch.stack,
+                    &tokens,
+                    &EvalOptions::default(),
+                    &mut variables.clone(),
+                    &Registry::default(),
+                )
+                .map_err(|(error, _)| error)
+            })
+        }
+    }
+
+    /// [`StoredExpression::into_fn`] for an expression of the one variable
+    /// `var`, taking its value. Reading any other is
+    /// [`Error::UndefinedVariable`].
+    ///
+    /// ```
+    /// use calculator::StoredExpression;
+    ///
+    /// let square = StoredExpression::compile("x^2").unwrap().into_fn1("x");
+    /// let squares: Result<Vec<f64>, _> = (1..=3).map(f64::from).map(square).collect();
+    /// assert_eq!(squares, Ok(vec![1.0, 4.0, 9.0]));
+    /// ```
+    pub fn into_fn1(self, var: &str) -> impl Fn(f64) -> Result<f64, Error> + Send + Sync {
+        let tokens = self.tokens;
+        let var = var.to_string();
+        move |x| {
+            SCRATCH.with_borrow_mut(|scratch| {
+                let variables = &mut scratch.variables;
+                // Left from the last call as it is unless that was another
+                // closure's variable or the expression assigned some.
+                if variables.len() == 1
+                    && let Some(value) = variables.get_mut(&var)
+                {
+                    *value = x;
+                } else {
+                    variables.clear();
+                    variables.insert(var.clone(), x);
+                }
+                Calculator::evaluate_on(
+                    &mut scratch.stack,
+                    &tokens,
+                    &EvalOptions::default(),
+                    variables,
+                    &Registry::default(),
+                )
+                .map_err(|(error, _)| error)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(expr: &str) -> StoredExpression {
+        StoredExpression::compile(expr).unwrap()
+    }
+
+    #[test]
+    fn test_in_map() {
+        let f = compile("3 * x + 1").into_fn1("x");
+        let values: Vec<f64> = (0..5).map(|i| f(f64::from(i)).unwrap()).collect();
+        assert_eq!(values, [1.0, 4.0, 7.0, 10.0, 13.0]);
+
+        let g = compile("a / b").into_fn();
+        let maps: Vec<HashMap<String, f64>> = (1..4)
+            .map(|b| HashMap::from([("a".to_string(), 6.0), ("b".to_string(), f64::from(b))]))
+            .collect();
+        assert_eq!(
+            maps.iter().map(&g).collect::<Result<Vec<_>, _>>(),
+            Ok(vec![6.0, 3.0, 2.0])
+        );
+        // The same answers as evaluating afresh, errors included.
+        for b in [0.0, 0.5] {
+            let map = HashMap::from([("a".to_string(), 1.0), ("b".to_string(), b)]);
+            assert_eq!(g(&map), Calculator::calculate_with_variables("a / b", &map));
+        }
+        assert_eq!(
+            g(&HashMap::new()),
+            Err(Error::UndefinedVariable("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_calls_stay_apart() {
+        let f = compile("(y = x * 2) + y").into_fn1("x");
+        let h = compile("t - 1").into_fn1("t");
+        let other = compile("x + y").into_fn1("x");
+        assert_eq!(f(1.0), Ok(4.0));
+        // `y` assigned by the call before isn't left behind for this one.
+        assert_eq!(other(1.0), Err(Error::UndefinedVariable("y".to_string())));
+        assert_eq!(h(5.0), Ok(4.0));
+        assert_eq!(f(2.5), Ok(10.0));
+        assert_eq!(h(f64::NAN).map(f64::is_nan), Ok(true));
+        let g = compile("x").into_fn();
+        let map = HashMap::from([("x".to_string(), 3.0)]);
+        assert_eq!(g(&map), Ok(3.0));
+        // Nor are assignments kept in the caller's map.
+        let assigns = compile("x = 9").into_fn();
+        assert_eq!(assigns(&map), Ok(9.0));
+        assert_eq!(map["x"], 3.0);
+    }
+
+    #[test]
+    fn test_two_threads() {
+        let f = compile("x * x - 1").into_fn1("x");
+        let g = compile("sqrt(a^2 + b^2)").into_fn();
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..2)
+                .map(|t| {
+                    let (f, g) = (&f, &g);
+                    scope.spawn(move || {
+                        let mut sum = 0.0;
+                        for i in 0..1000 {
+                            let x = f64::from(i + t * 1000);
+                            assert_eq!(f(x), Ok(x * x - 1.0));
+                            let map = HashMap::from([
+                                ("a".to_string(), 3.0 * x),
+                                ("b".to_string(), 4.0 * x),
+                            ]);
+                            sum += g(&map).unwrap();
+                        }
+                        sum
+                    })
+                })
+                .collect();
+            let sums: Vec<f64> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+            assert_eq!(sums, [5.0 * 499_500.0, 5.0 * 1_499_500.0]);
+        });
+    }
+}
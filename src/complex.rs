@@ -0,0 +1,532 @@
+//! Complex arithmetic, for [`Calculator::calculate_complex`].
+
+use std::collections::HashMap;
+use std::f64::consts::LN_10;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::custom::Registry;
+use crate::environment::ANS;
+use crate::{Angle, Calculator, Error, EvalOptions, Function, Operator, Token, ZeroDivision};
+
+/// The imaginary unit's name in [`Calculator::calculate_complex`], where
+/// a literal may also end in it.
+pub(crate) const IMAGINARY_UNIT: &str = "i";
+
+/// A result of [`Calculator::calculate_complex`]. It displays as `a+bi`,
+/// or as a real number when the imaginary part is negligible:
+///
+/// ```
+/// use calculator::Complex;
+///
+/// assert_eq!(Complex::new(2.0, -3.0).to_string(), "2-3i");
+/// assert_eq!(Complex::new(0.0, 1.0).to_string(), "i");
+/// assert_eq!(Complex::new(-1.0, 1.2e-16).to_string(), "-1");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    /// Whether the imaginary part is negligible: within `1e-12` of zero,
+    /// or of the real part's size when that is bigger than 1.
+    pub fn is_real(self) -> bool {
+        self.im.abs() <= 1e-12 * self.re.abs().max(1.0)
+    }
+
+    fn real(re: f64) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    fn is_zero(self) -> bool {
+        self.re == 0.0 && self.im == 0.0
+    }
+
+    fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Complex::new(self.re * factor, self.im * factor)
+    }
+
+    fn exp(self) -> Self {
+        let (sin, cos) = self.im.sin_cos();
+        Complex::new(cos, sin).scale(self.re.exp())
+    }
+
+    /// The principal logarithm, with the imaginary part in `(-π, π]`.
+    fn ln(self) -> Self {
+        Complex::new(self.abs().ln(), self.im.atan2(self.re))
+    }
+
+    /// The principal square root, with a real part of zero or more.
+    fn sqrt(self) -> Self {
+        let r = self.abs();
+        let re = ((r + self.re) / 2.0).sqrt();
+        let im = ((r - self.re) / 2.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn sin(self) -> Self {
+        let (sin, cos) = self.re.sin_cos();
+        Complex::new(sin * self.im.cosh(), cos * self.im.sinh())
+    }
+
+    fn cos(self) -> Self {
+        let (sin, cos) = self.re.sin_cos();
+        Complex::new(cos * self.im.cosh(), -sin * self.im.sinh())
+    }
+
+    /// Whole exponents multiply, so `i ^ 2` is exactly `-1`.
+    fn pow(self, exponent: Complex) -> Self {
+        let whole = exponent.im == 0.0
+            && exponent.re.fract() == 0.0
+            && exponent.re.abs() <= f64::from(i32::MAX);
+        if whole {
+            let mut n = exponent.re.abs() as u32;
+            let mut result = Complex::real(1.0);
+            let mut square = self;
+            while n > 0 {
+                if n % 2 == 1 {
+                    result = result * square;
+                }
+                square = square * square;
+                n /= 2;
+            }
+            return if exponent.re < 0.0 {
+                Complex::real(1.0) / result
+            } else {
+                result
+            };
+        }
+        if self.is_zero() {
+            return if exponent.re > 0.0 {
+                Complex::default()
+            } else {
+                Complex::new(f64::NAN, f64::NAN)
+            };
+        }
+        (exponent * self.ln()).exp()
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+/// Dividing by zero gives infinities and NaNs, as `f64` does.
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        if other.im == 0.0 {
+            return Complex::new(self.re / other.re, self.im / other.re);
+        }
+        let norm = other.re * other.re + other.im * other.im;
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / norm,
+            (self.im * other.re - self.re * other.im) / norm,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Complex::real(re)
+    }
+}
+
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_real() {
+            return write!(f, "{}", self.re);
+        }
+        let im = if self.im == 1.0 {
+            String::new()
+        } else if self.im == -1.0 {
+            "-".to_string()
+        } else {
+            self.im.to_string()
+        };
+        if self.re.abs() <= 1e-12 * self.im.abs() {
+            return write!(f, "{}i", im);
+        }
+        let sign = if im.starts_with('-') { "" } else { "+" };
+        write!(f, "{}{}{}i", self.re, sign, im)
+    }
+}
+
+impl Calculator {
+    /// [`Calculator::calculate_with`] over the complex numbers, where `i`
+    /// is the imaginary unit and a number may end in it:
+    ///
+    /// ```
+    /// use calculator::{Calculator, Complex, EvalOptions};
+    ///
+    /// let options = EvalOptions::default();
+    /// let product = Calculator::calculate_complex("(2 + 3i) * (1 - i)", &options);
+    /// assert_eq!(product, Ok(Complex::new(5.0, 1.0)));
+    /// assert_eq!(
+    ///     Calculator::calculate_complex("sqrt(-4)", &options).unwrap().to_string(),
+    ///     "2i"
+    /// );
+    /// ```
+    ///
+    /// `3i` is `3 * i`, so `2i ^ 2` is `2 * i ^ 2`, or `-2`. `i` can't be
+    /// assigned to.
+    ///
+    /// Each step with only real operands is worked out as
+    /// [`Calculator::calculate_with`] would, unless that gives NaN where a
+    /// complex power, root or logarithm exists: `sqrt(-1)`, `ln(-1)` and
+    /// `(-8) ^ (1 / 3)` have complex results. Dividing by zero, real or
+    /// complex, is [`Error::DivisionByZero`] unless
+    /// [`EvalOptions::zero_division`] says otherwise. `//`, `%`, `min`,
+    /// `max` and what a [`ConfiguredCalculator`](crate::ConfiguredCalculator)
+    /// registers are only defined for real numbers and are
+    /// [`Error::NotReal`] for anything else.
+    pub fn calculate_complex<T: AsRef<str>>(
+        expr: T,
+        options: &EvalOptions,
+    ) -> Result<Complex, Error> {
+        Self::calculate_complex_in(expr.as_ref(), options, &Registry::default())
+    }
+
+    /// [`Calculator::calculate_complex`], with what `registry` adds to the
+    /// language.
+    pub(crate) fn calculate_complex_in(
+        expr: &str,
+        options: &EvalOptions,
+        registry: &Registry,
+    ) -> Result<Complex, Error> {
+        let (tokens, _, _) = Self::parse_in(expr, options, true, registry)?;
+        let mut variables: HashMap<String, Complex> = HashMap::new();
+        let mut stack: Vec<Complex> = Vec::with_capacity(tokens.len() / 2 + 1);
+
+        for token in Self::try_to_postfix(tokens)? {
+            let value = match token {
+                Token::Number(n) => Complex::real(n),
+                Token::Identifier(name) => match variables.get(&*name) {
+                    Some(value) => *value,
+                    None if &*name == IMAGINARY_UNIT => Complex::I,
+                    None if &*name == ANS => return Err(Error::NoPreviousResult),
+                    None => return Err(Error::UndefinedVariable(name.into_string())),
+                },
+                Token::Assign(name) => match stack.last() {
+                    Some(value) => {
+                        variables.insert(name.into_string(), *value);
+                        continue;
+                    }
+                    None => return Err(Error::InvalidExpression),
+                },
+                token => {
+                    let arity = match &token {
+                        Token::Op(op) if op.is_unary() => 1,
+                        Token::Op(_) | Token::Custom { .. } => 2,
+                        Token::Function { args, .. } | Token::Call { args, .. } => *args as usize,
+                        _ => return Err(Error::InvalidExpression),
+                    };
+                    if arity == 0 || stack.len() < arity {
+                        return Err(Error::InvalidExpression);
+                    }
+                    let operands = stack.split_off(stack.len() - arity);
+                    let real = operands.iter().all(|z| z.im == 0.0);
+                    let x = if real {
+                        let mut floats: Vec<f64> = operands.iter().map(|z| z.re).collect();
+                        match Self::apply_token(
+                            &mut floats,
+                            &token,
+                            options,
+                            &mut HashMap::new(),
+                            registry,
+                        ) {
+                            Ok(()) => floats.pop(),
+                            // A negative base's power is complex instead.
+                            Err(Error::DomainError { .. }) if extends(&token) => None,
+                            Err(e) => return Err(e),
+                        }
+                    } else {
+                        None
+                    };
+                    match x {
+                        Some(x) if !(x.is_nan() && extends(&token)) => Complex::real(x),
+                        _ => apply(&token, &operands, options)?,
+                    }
+                }
+            };
+            stack.push(value);
+        }
+
+        match stack.len() {
+            1 => Ok(stack.pop().unwrap()),
+            _ => Err(Error::InvalidExpression),
+        }
+    }
+}
+
+/// Whether `token` has a complex result where its real one is NaN or an
+/// [`Error::DomainError`].
+fn extends(token: &Token) -> bool {
+    match token {
+        Token::Op(op) => *op == Operator::Power,
+        Token::Function { function, .. } => matches!(
+            function,
+            Function::Sqrt | Function::Ln | Function::Log10 | Function::Pow
+        ),
+        _ => false,
+    }
+}
+
+/// `token` applied to `operands`, at least one of them not real unless
+/// [`extends`] says the real result is NaN or a domain error.
+fn apply(token: &Token, operands: &[Complex], options: &EvalOptions) -> Result<Complex, Error> {
+    let angle = |z: Complex| match options.angle {
+        Angle::Radians => z,
+        Angle::Degrees => z.scale(std::f64::consts::PI / 180.0),
+    };
+    Ok(match (token, operands) {
+        (Token::Op(Operator::Negate), [z]) => -*z,
+        (Token::Op(Operator::Percent), [z]) => z.scale(0.01),
+        (Token::Op(Operator::Add), [l, r]) => *l + *r,
+        (Token::Op(Operator::Subtract), [l, r]) => *l - *r,
+        (Token::Op(Operator::Multiply), [l, r]) => *l * *r,
+        (Token::Op(Operator::Divide), [l, r])
+        | (
+            Token::Function {
+                function: Function::Div,
+                ..
+            },
+            [l, r],
+        ) => {
+            if r.is_zero() && options.zero_division == ZeroDivision::Error {
+                return Err(Error::DivisionByZero);
+            }
+            *l / *r
+        }
+        (Token::Op(Operator::Power), [l, r])
+        | (
+            Token::Function {
+                function: Function::Pow,
+                ..
+            },
+            [l, r],
+        ) => l.pow(*r),
+        (Token::Function { function, .. }, [z]) => match function {
+            Function::Sqrt => z.sqrt(),
+            Function::Cbrt => z.pow(Complex::real(1.0 / 3.0)),
+            Function::Sin => angle(*z).sin(),
+            Function::Cos => angle(*z).cos(),
+            Function::Tan => angle(*z).sin() / angle(*z).cos(),
+            Function::Ln => z.ln(),
+            Function::Log10 => z.ln() / Complex::real(LN_10),
+            Function::Exp => z.exp(),
+            Function::Abs => Complex::real(z.abs()),
+            _ => return Err(Error::NotReal),
+        },
+        _ => return Err(Error::NotReal),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calc(expr: &str) -> Result<Complex, Error> {
+        Calculator::calculate_complex(expr, &EvalOptions::default())
+    }
+
+    fn shown(expr: &str) -> String {
+        calc(expr).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_imaginary_unit() {
+        assert_eq!(calc("i * i"), Ok(Complex::real(-1.0)));
+        assert_eq!(calc("i ^ 2"), Ok(Complex::real(-1.0)));
+        assert_eq!(calc("i ^ -1"), Ok(Complex::new(0.0, -1.0)));
+        assert_eq!(calc("sqrt(-1)"), Ok(Complex::I));
+        assert_eq!(calc("sqrt(-4)"), Ok(Complex::new(0.0, 2.0)));
+        assert_eq!(calc("3i"), Ok(Complex::new(0.0, 3.0)));
+        assert_eq!(calc("2.5e1i"), Ok(Complex::new(0.0, 25.0)));
+        assert_eq!(calc("2i ^ 2"), Ok(Complex::real(-2.0)));
+        assert_eq!(calc("(2i) ^ 2"), Ok(Complex::real(-4.0)));
+        assert_eq!(calc("(1 + i) ^ 2"), Ok(Complex::new(0.0, 2.0)));
+        assert_eq!(calc("(x = 1 + i) * x"), Ok(Complex::new(0.0, 2.0)));
+        assert_eq!(calc("abs(3 + 4i)"), Ok(Complex::real(5.0)));
+        // Only a literal takes the suffix; a space or a name is an error
+        // without implicit multiplication.
+        assert_eq!(calc("3 i"), Err(Error::UnexpectedToken { pos: 2 }));
+        assert_eq!(
+            calc("3in"),
+            Err(Error::InvalidNumber {
+                literal: "3in".to_string(),
+                pos: 0
+            })
+        );
+        assert_eq!(calc("xi"), Err(Error::UndefinedVariable("xi".to_string())));
+        let implicit = EvalOptions {
+            implicit_multiplication: true,
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            Calculator::calculate_complex("2 i", &implicit),
+            Ok(Complex::new(0.0, 2.0))
+        );
+    }
+
+    #[test]
+    fn test_multiplication_and_division() {
+        assert_eq!(calc("(2 + 3i) * (1 - i)"), Ok(Complex::new(5.0, 1.0)));
+        assert_eq!(calc("(2 + 3i) * 2"), Ok(Complex::new(4.0, 6.0)));
+        assert_eq!(calc("(2 + 3i) / (1 - i)"), Ok(Complex::new(-0.5, 2.5)));
+        assert_eq!(calc("(4 + 2i) / 2"), Ok(Complex::new(2.0, 1.0)));
+        assert_eq!(calc("1 / i"), Ok(Complex::new(0.0, -1.0)));
+        assert_eq!(calc("(1 + i) / (1 + i)"), Ok(Complex::real(1.0)));
+        assert_eq!(calc("(1 + i) / 0"), Err(Error::DivisionByZero));
+        assert_eq!(calc("1 / (i - i)"), Err(Error::DivisionByZero));
+        assert_eq!(calc("div(2 + 3i, 1 - i)"), Ok(Complex::new(-0.5, 2.5)));
+        assert_eq!(calc("div(i, 0)"), Err(Error::DivisionByZero));
+        let ieee = EvalOptions {
+            zero_division: ZeroDivision::Ieee,
+            ..EvalOptions::default()
+        };
+        let z = Calculator::calculate_complex("i / (i - i)", &ieee).unwrap();
+        assert!(z.re.is_nan() && z.im == f64::INFINITY);
+    }
+
+    #[test]
+    fn test_complex_results_of_real_operands() {
+        for (expr, expected) in [
+            ("ln(-1)", "3.141592653589793i"),
+            ("log10(-100)", "2+1.3643763538418412i"),
+            ("(-1) ^ 0.5", "i"),
+            ("exp(i * pi)", "-1"),
+            ("exp(i * pi / 2)", "i"),
+            ("cos(i)", "1.5430806348152437"),
+            ("sin(i)", "1.1752011936438014i"),
+            ("2 - sqrt(-9)", "2-3i"),
+        ] {
+            assert_eq!(shown(expr), expected, "{}", expr);
+        }
+        // The principal cube root, not the real one `cbrt` gives.
+        let z = calc("(-8) ^ (1 / 3)").unwrap();
+        assert!((z.re - 1.0).abs() < 1e-12 && (z.im - 3f64.sqrt()).abs() < 1e-12);
+        assert_eq!(calc("cbrt(-8)"), Ok(Complex::real(-2.0)));
+        let real_roots = EvalOptions {
+            real_roots: true,
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            Calculator::calculate_complex("(-8) ^ (1 / 3)", &real_roots),
+            Ok(Complex::real(-2.0))
+        );
+    }
+
+    #[test]
+    fn test_real_expressions_unchanged() {
+        for expr in [
+            "2 + 3 * 4",
+            "0.1 + 0.2",
+            "7 // 2 % 3",
+            "sqrt(2)",
+            "max(1, 2, 3)",
+            "50% * -3",
+            "|2 - 5|",
+        ] {
+            assert_eq!(
+                calc(expr),
+                Calculator::calculate(expr).map(Complex::real),
+                "{}",
+                expr
+            );
+        }
+        // Outside complex mode `i` is an ordinary name and the suffix isn't
+        // read, so `3i` is a number with a name against it.
+        assert_eq!(
+            Calculator::calculate("3i"),
+            Err(Error::InvalidNumber {
+                literal: "3i".to_string(),
+                pos: 0
+            })
+        );
+        assert!(Calculator::calculate("sqrt(-1)").unwrap().is_nan());
+        assert_eq!(Calculator::calculate("(i = 2) * i"), Ok(4.0));
+    }
+
+    #[test]
+    fn test_real_only_operations() {
+        for expr in [
+            "i // 2",
+            "floordiv(i, 2)",
+            "i % 2",
+            "max(i, 1)",
+            "min(1, 2, i)",
+        ] {
+            assert_eq!(calc(expr), Err(Error::NotReal), "{}", expr);
+        }
+        assert_eq!(
+            calc("i = 2"),
+            Err(Error::ReservedName {
+                name: "i".to_string(),
+                pos: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        for (z, expected) in [
+            (Complex::new(2.0, 3.0), "2+3i"),
+            (Complex::new(2.0, -3.0), "2-3i"),
+            (Complex::new(5.0, 1.0), "5+i"),
+            (Complex::new(5.0, -1.0), "5-i"),
+            (Complex::new(-0.5, 2.5), "-0.5+2.5i"),
+            (Complex::new(0.0, -1.0), "-i"),
+            (Complex::new(1e-17, 2.0), "2i"),
+            (Complex::new(3.0, 0.0), "3"),
+            (Complex::new(1e6, 1e-7), "1000000"),
+            (Complex::new(1.0, 1e-7), "1+0.0000001i"),
+        ] {
+            assert_eq!(z.to_string(), expected, "{:?}", z);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Variables gathered from several sources without one silently
+//! overwriting another.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+use crate::Error;
+
+/// What [`VarContextBuilder::insert`] does with a name already given a
+/// different value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// Refuse it with [`Error::ConflictingVariable`].
+    #[default]
+    Error,
+    /// Keep the value given first and note a [`Conflict`] in
+    /// [`VarContext::warnings`].
+    Warn,
+}
+
+/// Whose value [`VarContext::merge`] keeps for a name both contexts give
+/// different values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// The context `merge` is called on, as defaults overridden by nothing.
+    PreferLeft,
+    /// The one given to it, as defaults overridden by a request's values.
+    PreferRight,
+    /// Neither: the merge is [`Error::ConflictingVariable`].
+    Error,
+}
+
+/// A name given two different values, and which one was kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub name: String,
+    pub kept: f64,
+    pub dropped: f64,
+}
+
+/// Variables for the evaluation entry points, such as
+/// [`Calculator::calculate_with_variables`](crate::Calculator::calculate_with_variables),
+/// which take one wherever they take a `HashMap`. Unlike a map's
+/// `insert`, building one never lets a later value replace an earlier one
+/// unnoticed: giving a name the value it already has is fine, and a
+/// different one is an error or a recorded warning, as the
+/// [`DuplicatePolicy`] says.
+///
+/// ```
+/// use calculator::{Calculator, Error, MergePolicy, VarContext};
+///
+/// let defaults = VarContext::builder().insert("rate", 0.2)?.insert("qty", 1.0)?.build();
+/// let query = VarContext::builder().insert("qty", 3.0)?.build();
+/// assert_eq!(
+///     defaults.clone().merge(query.clone(), MergePolicy::Error),
+///     Err(Error::ConflictingVariable("qty".to_string()))
+/// );
+/// let vars = defaults.merge(query, MergePolicy::PreferRight)?;
+/// assert_eq!(Calculator::calculate_with_variables("qty * 10 * rate", &vars), Ok(6.0));
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VarContext {
+    variables: HashMap<String, f64>,
+    warnings: Vec<Conflict>,
+}
+
+/// Builds a [`VarContext`] one variable at a time: see
+/// [`VarContext::builder`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VarContextBuilder {
+    context: VarContext,
+    on_duplicate: DuplicatePolicy,
+}
+
+impl VarContext {
+    /// A builder refusing conflicting values, unless told
+    /// [`VarContextBuilder::on_duplicate`] otherwise.
+    pub fn builder() -> VarContextBuilder {
+        VarContextBuilder::default()
+    }
+
+    /// The value of `name`.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.variables.get(name).copied()
+    }
+
+    /// Every variable and its value, in no particular order.
+    pub fn variables(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+    }
+
+    /// The conflicts let through under [`DuplicatePolicy::Warn`] or
+    /// settled by a [`MergePolicy`] preference, in the order they came up.
+    pub fn warnings(&self) -> &[Conflict] {
+        &self.warnings
+    }
+
+    /// Both contexts' variables, `policy` settling any name they give
+    /// different values; a name they agree on is no conflict. Each side's
+    /// warnings are kept, then the merge's own.
+    pub fn merge(mut self, other: VarContext, policy: MergePolicy) -> Result<VarContext, Error> {
+        self.warnings.extend(other.warnings);
+        for (name, value) in other.variables {
+            match self.variables.get(&name) {
+                Some(&existing) if !same(existing, value) => {
+                    let (kept, dropped) = match policy {
+                        MergePolicy::PreferLeft => (existing, value),
+                        MergePolicy::PreferRight => (value, existing),
+                        MergePolicy::Error => return Err(Error::ConflictingVariable(name)),
+                    };
+                    self.variables.insert(name.clone(), kept);
+                    self.warnings.push(Conflict {
+                        name,
+                        kept,
+                        dropped,
+                    });
+                }
+                _ => {
+                    self.variables.insert(name, value);
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl VarContextBuilder {
+    pub fn new() -> Self {
+        VarContextBuilder::default()
+    }
+
+    pub fn on_duplicate(mut self, policy: DuplicatePolicy) -> Self {
+        self.on_duplicate = policy;
+        self
+    }
+
+    /// Binds `name` to `value`. A name bound already to a different value
+    /// is [`Error::ConflictingVariable`], or under
+    /// [`DuplicatePolicy::Warn`] keeps the first value and records the
+    /// [`Conflict`]; the same value again, NaN included, changes nothing.
+    pub fn insert(mut self, name: impl Into<String>, value: f64) -> Result<Self, Error> {
+        let name = name.into();
+        match self.context.variables.get(&name) {
+            Some(&existing) if same(existing, value) => {}
+            Some(&existing) => match self.on_duplicate {
+                DuplicatePolicy::Error => return Err(Error::ConflictingVariable(name)),
+                DuplicatePolicy::Warn => self.context.warnings.push(Conflict {
+                    name,
+                    kept: existing,
+                    dropped: value,
+                }),
+            },
+            None => {
+                self.context.variables.insert(name, value);
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn build(self) -> VarContext {
+        self.context
+    }
+}
+
+/// Whether two values for a name agree: equal, or both NaN.
+fn same(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+/// So the entry points that take variables, such as
+/// [`Calculator::evaluate_with_variables`](crate::Calculator::evaluate_with_variables),
+/// take a context as they take a `HashMap`.
+impl Borrow<HashMap<String, f64>> for VarContext {
+    fn borrow(&self) -> &HashMap<String, f64> {
+        &self.variables
+    }
+}
+
+impl From<VarContext> for HashMap<String, f64> {
+    fn from(context: VarContext) -> Self {
+        context.variables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Calculator, StoredExpression};
+
+    fn conflicting(name: &str) -> Error {
+        Error::ConflictingVariable(name.to_string())
+    }
+
+    #[test]
+    fn test_duplicate_same_value() {
+        let context = VarContext::builder()
+            .insert("x", 2.0)
+            .and_then(|b| b.insert("x", 2.0))
+            .and_then(|b| b.insert("nan", f64::NAN))
+            .and_then(|b| b.insert("nan", f64::NAN))
+            .unwrap()
+            .build();
+        assert_eq!(context.get("x"), Some(2.0));
+        assert!(context.get("nan").unwrap().is_nan());
+        assert_eq!(context.warnings(), []);
+        assert_eq!(context.variables().count(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_different_value() {
+        let builder = VarContext::builder().insert("price", 10.0).unwrap();
+        assert_eq!(
+            builder.clone().insert("price", 12.0),
+            Err(conflicting("price"))
+        );
+
+        let context = builder
+            .on_duplicate(DuplicatePolicy::Warn)
+            .insert("price", 12.0)
+            .and_then(|b| b.insert("tax", 0.5))
+            .unwrap()
+            .build();
+        assert_eq!(context.get("price"), Some(10.0));
+        assert_eq!(context.get("tax"), Some(0.5));
+        assert_eq!(
+            context.warnings(),
+            [Conflict {
+                name: "price".to_string(),
+                kept: 10.0,
+                dropped: 12.0
+            }]
+        );
+        assert_eq!(
+            conflicting("price").to_string(),
+            "variable 'price' is given two different values"
+        );
+    }
+
+    #[test]
+    fn test_merge_precedence() {
+        let left = || {
+            VarContext::builder()
+                .insert("a", 1.0)
+                .and_then(|b| b.insert("shared", 5.0))
+                .and_then(|b| b.insert("both", 2.0))
+                .unwrap()
+                .build()
+        };
+        let right = || {
+            VarContext::builder()
+                .insert("b", 3.0)
+                .and_then(|b| b.insert("shared", 5.0))
+                .and_then(|b| b.insert("both", 4.0))
+                .unwrap()
+                .build()
+        };
+        let conflict = |kept, dropped| Conflict {
+            name: "both".to_string(),
+            kept,
+            dropped,
+        };
+
+        let merged = left().merge(right(), MergePolicy::PreferLeft).unwrap();
+        assert_eq!(merged.get("both"), Some(2.0));
+        assert_eq!(merged.warnings(), [conflict(2.0, 4.0)]);
+        let merged = left().merge(right(), MergePolicy::PreferRight).unwrap();
+        assert_eq!(merged.get("both"), Some(4.0));
+        assert_eq!(merged.warnings(), [conflict(4.0, 2.0)]);
+        for name in ["a", "b", "shared"] {
+            assert_eq!(merged.get(name), left().get(name).or(right().get(name)));
+        }
+        assert_eq!(
+            left().merge(right(), MergePolicy::Error),
+            Err(conflicting("both"))
+        );
+        // Agreeing on every name is no conflict under any policy.
+        assert_eq!(left().merge(left(), MergePolicy::Error), Ok(left()));
+    }
+
+    #[test]
+    fn test_entry_points_take_a_context() {
+        let context = VarContext::builder()
+            .insert("x", 3.0)
+            .and_then(|b| b.insert("y", 4.0))
+            .unwrap()
+            .build();
+        let map: HashMap<String, f64> = context.clone().into();
+        assert_eq!(
+            Calculator::calculate_with_variables("x * y", &context),
+            Calculator::calculate_with_variables("x * y", &map)
+        );
+        let postfix = Calculator::try_to_postfix(Calculator::parse("x + y").unwrap()).unwrap();
+        assert_eq!(
+            Calculator::evaluate_with_variables(postfix, &context),
+            Ok(7.0)
+        );
+        let (_, text) = Calculator::partial_eval("x * z", &context).unwrap();
+        assert_eq!(text, "3 * z");
+        let stored = StoredExpression::compile("sqrt(x^2 + y^2)").unwrap();
+        let mut evaluation = stored.start_evaluation(&context);
+        assert_eq!(
+            evaluation.step(usize::MAX),
+            crate::StepOutcome::Done(Ok(5.0))
+        );
+    }
+}
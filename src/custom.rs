@@ -0,0 +1,128 @@
+//! Functions and operators registered on a
+//! [`ConfiguredCalculator`](crate::ConfiguredCalculator) at runtime.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::Error;
+use crate::telemetry::{self, ParseErrorHook};
+
+/// Which way a chain of one operator groups: `a @ b @ c` is `(a @ b) @ c`
+/// for `Left` and `a @ (b @ c)` for `Right`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+type ApplyFunction = Arc<dyn Fn(&[f64]) -> Result<f64, Error> + Send + Sync>;
+type ApplyOperator = Arc<dyn Fn(f64, f64) -> Result<f64, Error> + Send + Sync>;
+
+#[derive(Clone)]
+pub(crate) struct CustomFunction {
+    pub(crate) arity: usize,
+    apply: ApplyFunction,
+}
+
+impl CustomFunction {
+    pub(crate) fn apply(&self, arguments: &[f64]) -> Result<f64, Error> {
+        (self.apply)(arguments)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CustomOperator {
+    pub(crate) precedence: u8,
+    pub(crate) assoc: Assoc,
+    apply: ApplyOperator,
+}
+
+impl CustomOperator {
+    pub(crate) fn apply(&self, left: f64, right: f64) -> Result<f64, Error> {
+        (self.apply)(left, right)
+    }
+}
+
+/// Registered functions by name and operators by symbol, and the hook for
+/// parse failures, which every parse is handed along with them. The static
+/// API uses an empty one.
+#[derive(Clone, Default)]
+pub(crate) struct Registry {
+    functions: HashMap<String, CustomFunction>,
+    operators: HashMap<char, CustomOperator>,
+    pub(crate) parse_error_hook: Option<ParseErrorHook>,
+    /// Whether `name(` for a name nothing is registered under is read as a
+    /// call anyway, to be left as written by [`Calculator::partial_eval`].
+    ///
+    /// [`Calculator::partial_eval`]: crate::Calculator::partial_eval
+    pub(crate) unknown_calls: bool,
+}
+
+impl Registry {
+    pub(crate) fn function(&self, name: &str) -> Option<&CustomFunction> {
+        self.functions.get(name)
+    }
+
+    pub(crate) fn operator(&self, symbol: char) -> Option<&CustomOperator> {
+        self.operators.get(&symbol)
+    }
+
+    /// Reports a failed parse of `source` to the hook, if one is set.
+    pub(crate) fn parse_failed(&self, source: &str, error: &Error) {
+        if let Some(hook) = &self.parse_error_hook {
+            telemetry::report(hook, source, error);
+        }
+    }
+
+    pub(crate) fn insert_function<F>(&mut self, name: &str, arity: usize, apply: F)
+    where
+        F: Fn(&[f64]) -> Result<f64, Error> + Send + Sync + 'static,
+    {
+        let function = CustomFunction {
+            arity,
+            apply: Arc::new(apply),
+        };
+        self.functions.insert(name.to_string(), function);
+    }
+
+    pub(crate) fn insert_operator<F>(
+        &mut self,
+        symbol: char,
+        precedence: u8,
+        assoc: Assoc,
+        apply: F,
+    ) where
+        F: Fn(f64, f64) -> Result<f64, Error> + Send + Sync + 'static,
+    {
+        let operator = CustomOperator {
+            precedence,
+            assoc,
+            apply: Arc::new(apply),
+        };
+        self.operators.insert(symbol, operator);
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut functions: Vec<(&String, usize)> = self
+            .functions
+            .iter()
+            .map(|(name, function)| (name, function.arity))
+            .collect();
+        functions.sort();
+        let mut operators: Vec<(char, u8, Assoc)> = self
+            .operators
+            .iter()
+            .map(|(symbol, op)| (*symbol, op.precedence, op.assoc))
+            .collect();
+        operators.sort_by_key(|(symbol, _, _)| *symbol);
+        f.debug_struct("Registry")
+            .field("functions", &functions)
+            .field("operators", &operators)
+            .field("parse_error_hook", &self.parse_error_hook.is_some())
+            .field("unknown_calls", &self.unknown_calls)
+            .finish()
+    }
+}
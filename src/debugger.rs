@@ -0,0 +1,293 @@
+//! Stepping through an evaluation one postfix token at a time, with the
+//! part of the source each step computes.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+use crate::custom::Registry;
+use crate::{Calculator, EvalOptions, LocatedError, Span, Token};
+
+/// What a call to [`Debugger::step`] did.
+#[derive(Debug, PartialEq)]
+pub enum DebugStep {
+    /// `token` was applied, computing the part of the source at `span`:
+    /// a whole subexpression for an operator or call, as `4 - 1` in
+    /// `3 * (4 - 1)`. [`Debugger::stack`] shows the values afterwards.
+    Applied { token: Token, span: Option<Span> },
+    /// Every token has been applied, or one failed, located as
+    /// [`Calculator::calculate_located`] locates it.
+    Done(Result<f64, LocatedError>),
+}
+
+/// An evaluation that applies one postfix token per [`Debugger::step`],
+/// for a debugger UI to show the value stack and highlight the source
+/// between steps. It works on a copy of the variables it starts with, so
+/// dropping it part-way changes nothing.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use calculator::{Debugger, DebugStep, Span};
+///
+/// let mut debugger = Debugger::start("2 + 3 * (4 - 1)", &HashMap::new()).unwrap();
+/// for _ in 0..4 {
+///     debugger.step(); // 2, 3, 4, 1
+/// }
+/// assert_eq!(debugger.next_span(), Some(Span { start: 8, end: 15 }));
+/// assert!(matches!(debugger.step(), DebugStep::Applied { .. }));
+/// assert_eq!(debugger.stack(), [2.0, 3.0, 3.0]);
+/// assert_eq!(debugger.run(), Ok(11.0));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    source: String,
+    tokens: Vec<Token>,
+    /// For each postfix token, the infix index it came from.
+    origins: Vec<usize>,
+    /// For each infix token, where it is in the source.
+    token_spans: Vec<Span>,
+    /// For each postfix token, the source its value covers.
+    spans: Vec<Option<Span>>,
+    next: usize,
+    stack: Vec<f64>,
+    variables: HashMap<String, f64>,
+    done: bool,
+}
+
+impl Debugger {
+    /// Parses `expr` and prepares to step through it with `variables`
+    /// bound, with default settings. Nothing runs until the first `step`,
+    /// so only parse errors are returned here.
+    pub fn start(
+        expr: &str,
+        variables: &impl Borrow<HashMap<String, f64>>,
+    ) -> Result<Self, LocatedError> {
+        let options = EvalOptions::default();
+        let (infix, token_spans) = Calculator::parse_located(expr, &options, &Registry::default())?;
+        let brackets = closing_brackets(&infix);
+        let (tokens, origins) = Calculator::try_to_postfix_with_origins(infix.clone())?;
+        let spans = subexpressions(&tokens, &origins, &infix, &brackets)
+            .into_iter()
+            .map(|range| {
+                let (first, last) = range?;
+                Some(Span {
+                    start: token_spans.get(first)?.start,
+                    end: token_spans.get(last)?.end,
+                })
+            })
+            .collect();
+        Ok(Debugger {
+            source: expr.to_string(),
+            tokens,
+            origins,
+            token_spans,
+            spans,
+            next: 0,
+            stack: Vec::new(),
+            variables: variables.borrow().clone(),
+            done: false,
+        })
+    }
+
+    /// Applies the next token.
+    ///
+    /// # Panics
+    ///
+    /// If called again after returning `Done`.
+    pub fn step(&mut self) -> DebugStep {
+        assert!(!self.done, "Debugger::step called after Done");
+        let Some(token) = self.tokens.get(self.next).cloned() else {
+            self.done = true;
+            let stack = std::mem::take(&mut self.stack);
+            return DebugStep::Done(Calculator::finish(stack).map_err(LocatedError::from));
+        };
+        let applied = Calculator::apply_token(
+            &mut self.stack,
+            &token,
+            &EvalOptions::default(),
+            &mut self.variables,
+            &Registry::default(),
+        );
+        let at = self.next;
+        self.next += 1;
+        match applied {
+            Ok(()) => DebugStep::Applied {
+                token,
+                span: self.spans[at],
+            },
+            Err(error) => {
+                self.done = true;
+                let token = self.origins.get(at).copied();
+                DebugStep::Done(Err(LocatedError {
+                    error,
+                    token,
+                    span: token.and_then(|i| self.token_spans.get(i).copied()),
+                }))
+            }
+        }
+    }
+
+    /// Steps to the end, as a debugger's "continue" does.
+    ///
+    /// # Panics
+    ///
+    /// If `step` has already returned `Done`.
+    pub fn run(&mut self) -> Result<f64, LocatedError> {
+        loop {
+            if let DebugStep::Done(result) = self.step() {
+                return result;
+            }
+        }
+    }
+
+    /// The values computed so far and not yet used, the most recent last.
+    pub fn stack(&self) -> &[f64] {
+        &self.stack
+    }
+
+    /// What the next step computes, or `None` once every token is applied.
+    pub fn next_span(&self) -> Option<Span> {
+        self.spans.get(self.next).copied().flatten()
+    }
+
+    /// The expression being stepped through.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+/// For each `(` in `infix`, the index of the `)` closing it.
+fn closing_brackets(infix: &[Token]) -> HashMap<usize, usize> {
+    let mut open = Vec::new();
+    let mut closing = HashMap::new();
+    for (i, token) in infix.iter().enumerate() {
+        match token {
+            Token::Bracket('(') => open.push(i),
+            Token::Bracket(_) => {
+                if let Some(start) = open.pop() {
+                    closing.insert(start, i);
+                }
+            }
+            _ => {}
+        }
+    }
+    closing
+}
+
+/// For each postfix token, the first and last infix tokens of the
+/// subexpression whose value it produces, brackets around it included.
+fn subexpressions(
+    postfix: &[Token],
+    origins: &[usize],
+    infix: &[Token],
+    brackets: &HashMap<usize, usize>,
+) -> Vec<Option<(usize, usize)>> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut covered = Vec::with_capacity(postfix.len());
+    for (token, &at) in postfix.iter().zip(origins) {
+        let operands = match token {
+            Token::Op(op) if op.is_unary() => 1,
+            Token::Op(_) | Token::Custom { .. } => 2,
+            Token::Function { args, .. } | Token::Call { args, .. } => *args as usize,
+            Token::Assign(_) => 1,
+            _ => 0,
+        };
+        let Some(from) = ranges.len().checked_sub(operands) else {
+            covered.push(None);
+            continue;
+        };
+        let (mut first, mut last) = ranges
+            .drain(from..)
+            .fold((at, at), |(first, last), (a, b)| {
+                (first.min(a), last.max(b))
+            });
+        if let Token::Function { .. } | Token::Call { .. } = token {
+            // Up to the `)` of the call's own brackets.
+            last = brackets.get(&(at + 1)).copied().unwrap_or(last);
+        }
+        // Brackets of its own, but not a call's, which belong to the call.
+        while first > 0
+            && infix[first - 1] == Token::Bracket('(')
+            && brackets.get(&(first - 1)) == Some(&(last + 1))
+            && !matches!(
+                first.checked_sub(2).map(|i| &infix[i]),
+                Some(Token::Function { .. } | Token::Call { .. })
+            )
+        {
+            first -= 1;
+            last += 1;
+        }
+        ranges.push((first, last));
+        covered.push(Some((first, last)));
+    }
+    covered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    /// Steps through `expr`, giving the source each step covered and the
+    /// stack after it.
+    fn trace(expr: &str) -> Vec<(&str, Vec<f64>)> {
+        let mut debugger = Debugger::start(expr, &HashMap::new()).unwrap();
+        let mut steps = Vec::new();
+        while let DebugStep::Applied { span, .. } = debugger.step() {
+            let span = span.unwrap();
+            steps.push((&expr[span.start..span.end], debugger.stack().to_vec()));
+        }
+        steps
+    }
+
+    #[test]
+    fn test_steps_cover_subexpressions() {
+        assert_eq!(
+            trace("2 + 3 * (4 - 1)"),
+            [
+                ("2", vec![2.0]),
+                ("3", vec![2.0, 3.0]),
+                ("4", vec![2.0, 3.0, 4.0]),
+                ("1", vec![2.0, 3.0, 4.0, 1.0]),
+                ("(4 - 1)", vec![2.0, 3.0, 3.0]),
+                ("3 * (4 - 1)", vec![2.0, 9.0]),
+                ("2 + 3 * (4 - 1)", vec![11.0]),
+            ]
+        );
+        assert_eq!(
+            trace("-max(1, |-2|) % 3"),
+            [
+                ("1", vec![1.0]),
+                ("2", vec![1.0, 2.0]),
+                ("-2", vec![1.0, -2.0]),
+                ("|-2|", vec![1.0, 2.0]),
+                ("max(1, |-2|)", vec![2.0]),
+                ("-max(1, |-2|)", vec![-2.0]),
+                ("3", vec![-2.0, 3.0]),
+                ("-max(1, |-2|) % 3", vec![-2.0]),
+            ]
+        );
+        assert_eq!(
+            trace("x = (2)"),
+            [("(2)", vec![2.0]), ("x = (2)", vec![2.0])]
+        );
+    }
+
+    #[test]
+    fn test_outcome_and_errors() {
+        let variables = HashMap::from([("x".to_string(), 4.0)]);
+        let mut debugger = Debugger::start("x / (x - 4)", &variables).unwrap();
+        assert_eq!(debugger.source(), "x / (x - 4)");
+        assert_eq!(debugger.next_span(), Some(Span { start: 0, end: 1 }));
+        let result = debugger.run().unwrap_err();
+        assert_eq!(result.error, Error::DivisionByZero);
+        assert_eq!(result.span, Some(Span { start: 2, end: 3 }));
+        assert_eq!(debugger.next_span(), None);
+
+        let mut debugger = Debugger::start("y = 2 * x", &variables).unwrap();
+        assert_eq!(debugger.run(), Ok(8.0));
+        assert_eq!(
+            Debugger::start("1 +", &variables).unwrap_err().error,
+            Error::UnexpectedToken { pos: 2 }
+        );
+    }
+}
@@ -0,0 +1,674 @@
+//! Exact decimal arithmetic, for [`Calculator::calculate_precise`].
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter;
+
+use crate::custom::Registry;
+use crate::environment::ANS;
+use crate::lexer::{literal_text, split_radix};
+use crate::{Calculator, Division, Error, EvalOptions, Function, Operator, Token, ZeroDivision};
+
+/// The most zeros a literal's exponent may add, and the most digits a
+/// power may have, before the value is worked out as an `f64` instead.
+const MAX_DIGITS: usize = 10_000;
+
+/// `±digits × 10^-scale`, with `digits` least significant first and no
+/// zeros at either end that don't change the value, so zero is empty.
+#[derive(Debug, Clone, PartialEq)]
+struct Decimal {
+    negative: bool,
+    digits: Vec<u8>,
+    scale: usize,
+}
+
+/// A value on the stack: `Exact` while every step was, `Float` once
+/// something could only be worked out as an `f64`.
+#[derive(Debug, Clone)]
+enum Number {
+    Exact(Decimal),
+    Float(f64),
+}
+
+impl Number {
+    fn to_f64(&self) -> f64 {
+        match self {
+            Number::Exact(decimal) => decimal.to_f64(),
+            Number::Float(x) => *x,
+        }
+    }
+}
+
+impl Calculator {
+    /// Works out `expr` in decimal, exactly, and returns the result as a
+    /// string, so `0.1 + 0.2` is `0.3` rather than the `f64` nearest it:
+    ///
+    /// ```
+    /// use calculator::Calculator;
+    ///
+    /// assert_eq!(Calculator::calculate_precise("0.1 + 0.2", 10).unwrap(), "0.3");
+    /// assert_eq!(Calculator::calculate_precise("2 / 3", 10).unwrap(), "0.6666666667");
+    /// assert_eq!(
+    ///     Calculator::calculate_precise("2 ^ 70 + 0.5", 10).unwrap(),
+    ///     "1180591620717411303424.5"
+    /// );
+    /// ```
+    ///
+    /// `+`, `-`, `*`, `//`, `%`, `^` with a whole exponent, postfix `%`,
+    /// `abs`, `min`, `max` and `pow` are exact. `/`, and `^` with a
+    /// negative exponent, are rounded to `precision` decimal places, half
+    /// away from zero. Trailing zeros after the point are dropped.
+    ///
+    /// Everything else is worked out as [`Calculator::calculate`] would,
+    /// and so is anything that uses its result: `sqrt(2)`, `pi` and
+    /// `2 ^ 0.5` are only as precise as an `f64`, and are written the way
+    /// `f64` displays them.
+    pub fn calculate_precise<T: AsRef<str>>(expr: T, precision: usize) -> Result<String, Error> {
+        Self::calculate_precise_in(
+            expr.as_ref(),
+            precision,
+            &EvalOptions::default(),
+            &Registry::default(),
+        )
+    }
+
+    /// [`Calculator::calculate_precise`], following `options` and with
+    /// what `registry` adds to the language.
+    pub(crate) fn calculate_precise_in(
+        expr: &str,
+        precision: usize,
+        options: &EvalOptions,
+        registry: &Registry,
+    ) -> Result<String, Error> {
+        let (tokens, spans, _) = Self::parse_in(expr, options, false, registry)?;
+        let (postfix, origins) = Self::try_to_postfix_with_origins(tokens)?;
+        let chars: Vec<char> = expr.chars().collect();
+        let mut variables: HashMap<String, Number> = HashMap::new();
+        let mut stack: Vec<Number> = Vec::with_capacity(postfix.len() / 2 + 1);
+
+        for (token, origin) in postfix.into_iter().zip(origins) {
+            let value = match token {
+                // The literal as written, before an `f64` rounded it.
+                Token::Number(n) => spans
+                    .get(origin)
+                    .map(|span| {
+                        let written: String = chars[span.start..span.end].iter().collect();
+                        literal_text(&written)
+                    })
+                    .and_then(|text| Decimal::parse(&text))
+                    .map_or(Number::Float(n), Number::Exact),
+                Token::Identifier(name) => match variables.get(&*name) {
+                    Some(value) => value.clone(),
+                    None if &*name == ANS => return Err(Error::NoPreviousResult),
+                    None => return Err(Error::UndefinedVariable(name.into_string())),
+                },
+                Token::Assign(name) => match stack.last() {
+                    Some(value) => {
+                        variables.insert(name.into_string(), value.clone());
+                        continue;
+                    }
+                    None => return Err(Error::InvalidExpression),
+                },
+                token => {
+                    let arity = match &token {
+                        Token::Op(op) if op.is_unary() => 1,
+                        Token::Op(_) | Token::Custom { .. } => 2,
+                        Token::Function { args, .. } | Token::Call { args, .. } => *args as usize,
+                        _ => return Err(Error::InvalidExpression),
+                    };
+                    if arity == 0 || stack.len() < arity {
+                        return Err(Error::InvalidExpression);
+                    }
+                    let operands = stack.split_off(stack.len() - arity);
+                    let decimals: Option<Vec<&Decimal>> = operands
+                        .iter()
+                        .map(|value| match value {
+                            Number::Exact(decimal) => Some(decimal),
+                            Number::Float(_) => None,
+                        })
+                        .collect();
+                    match decimals.and_then(|d| exact(&token, &d, precision, options)) {
+                        Some(result) => Number::Exact(result?),
+                        None => {
+                            let mut floats: Vec<f64> =
+                                operands.iter().map(Number::to_f64).collect();
+                            let mut unused = HashMap::new();
+                            Self::apply_token(&mut floats, &token, options, &mut unused, registry)?;
+                            Number::Float(floats.pop().expect("one result per token"))
+                        }
+                    }
+                }
+            };
+            stack.push(value);
+        }
+
+        match (stack.pop(), stack.is_empty()) {
+            (Some(Number::Exact(decimal)), true) => Ok(decimal.to_string()),
+            (Some(Number::Float(x)), true) => Ok(x.to_string()),
+            _ => Err(Error::InvalidExpression),
+        }
+    }
+}
+
+/// `token` applied to exact `operands`, or `None` where the result is a
+/// `Float`, worked out as usual.
+fn exact(
+    token: &Token,
+    operands: &[&Decimal],
+    precision: usize,
+    options: &EvalOptions,
+) -> Option<Result<Decimal, Error>> {
+    match (token, operands) {
+        (Token::Op(Operator::Negate), [x]) => Some(Ok(x.negated())),
+        (Token::Op(Operator::Percent), [x]) => Some(Ok(x.percent())),
+        (Token::Op(Operator::Add), [l, r]) => Some(Ok(l.add(r))),
+        (Token::Op(Operator::Subtract), [l, r]) => Some(Ok(l.add(&r.negated()))),
+        (Token::Op(Operator::Multiply), [l, r]) => Some(Ok(l.multiply(r))),
+        (Token::Op(Operator::Power), [l, r])
+        | (
+            Token::Function {
+                function: Function::Pow,
+                ..
+            },
+            [l, r],
+        ) => l.power(r, precision).map(Ok),
+        (Token::Op(Operator::Divide | Operator::FloorDivide | Operator::Modulo), [_, r])
+        | (
+            Token::Function {
+                function: Function::Div | Function::FloorDiv,
+                ..
+            },
+            [_, r],
+        ) if r.is_zero() => {
+            match options.zero_division {
+                ZeroDivision::Error => Some(Err(Error::DivisionByZero)),
+                // Only a float can be infinite or NaN.
+                ZeroDivision::Ieee => None,
+            }
+        }
+        (Token::Op(Operator::Divide), [l, r]) => {
+            let whole = l.scale == 0 && r.scale == 0;
+            Some(match options.division {
+                Division::TruncateWhenBothIntegral if whole => Ok(l.divide_whole(r).0),
+                Division::Exact if whole && !l.divide_whole(r).1.is_zero() => {
+                    Err(Error::InexactDivision)
+                }
+                _ => Ok(l.divide(r, precision)),
+            })
+        }
+        (
+            Token::Function {
+                function: Function::Div,
+                ..
+            },
+            [l, r],
+        ) => Some(Ok(l.divide(r, precision))),
+        (Token::Op(Operator::FloorDivide), [l, r])
+        | (
+            Token::Function {
+                function: Function::FloorDiv,
+                ..
+            },
+            [l, r],
+        ) => {
+            let (quotient, remainder) = l.divide_whole(r);
+            let rounds_down = !remainder.is_zero() && l.negative != r.negative;
+            Some(Ok(if rounds_down {
+                quotient.add(&Decimal::one().negated())
+            } else {
+                quotient
+            }))
+        }
+        (Token::Op(Operator::Modulo), [l, r]) => Some(Ok(l.divide_whole(r).1)),
+        (Token::Function { function, .. }, _) => {
+            let pick = |wanted: Ordering| {
+                operands
+                    .iter()
+                    .copied()
+                    .reduce(|best, x| if x.compare(best) == wanted { x } else { best })
+                    .cloned()
+                    .map(Ok)
+            };
+            match function {
+                Function::Abs => Some(Ok(Decimal {
+                    negative: false,
+                    ..operands[0].clone()
+                })),
+                Function::Min => pick(Ordering::Less),
+                Function::Max => pick(Ordering::Greater),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl Decimal {
+    fn new(negative: bool, digits: Vec<u8>, scale: usize) -> Self {
+        let mut decimal = Decimal {
+            negative,
+            digits,
+            scale,
+        };
+        while decimal.digits.last() == Some(&0) {
+            decimal.digits.pop();
+        }
+        let zeros = decimal.digits.iter().take_while(|d| **d == 0).count();
+        let zeros = zeros.min(decimal.scale);
+        decimal.digits.drain(..zeros);
+        decimal.scale -= zeros;
+        if decimal.digits.is_empty() {
+            decimal.negative = false;
+            decimal.scale = 0;
+        }
+        decimal
+    }
+
+    fn one() -> Self {
+        Decimal::whole(1)
+    }
+
+    fn whole(mut n: u32) -> Self {
+        let mut digits = Vec::new();
+        while n > 0 {
+            digits.push((n % 10) as u8);
+            n /= 10;
+        }
+        Decimal::new(false, digits, 0)
+    }
+
+    /// A literal such as `2.5`, `1e3`, `4E-2` or `0xFF`, or `None` for any
+    /// other spelling and for exponents past [`MAX_DIGITS`].
+    fn parse(text: &str) -> Option<Self> {
+        if let Some((radix, digits)) = split_radix(text) {
+            let base = Decimal::whole(radix);
+            return digits.chars().try_fold(Decimal::whole(0), |n, c| {
+                let digit = Decimal::whole(c.to_digit(radix)?);
+                Some(n.multiply(&base).add(&digit))
+            });
+        }
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(at) => (&text[..at], text[at + 1..].parse::<i64>().ok()?),
+            None => (text, 0),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let written = || whole.bytes().chain(fraction.bytes());
+        if mantissa == "." || !written().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        if exponent.unsigned_abs() > MAX_DIGITS as u64 {
+            return None;
+        }
+        let mut digits: Vec<u8> = written().rev().map(|b| b - b'0').collect();
+        let scale = fraction.len() as i64 - exponent;
+        if scale < 0 {
+            digits.splice(0..0, iter::repeat_n(0, scale.unsigned_abs() as usize));
+        }
+        Some(Decimal::new(false, digits, scale.max(0) as usize))
+    }
+
+    fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.to_string()
+            .parse()
+            .expect("a decimal is a valid f64 literal")
+    }
+
+    /// Both magnitudes at the larger of the two scales, and that scale.
+    fn aligned(&self, other: &Decimal) -> (Vec<u8>, Vec<u8>, usize) {
+        let scale = self.scale.max(other.scale);
+        (
+            shift(&self.digits, scale - self.scale),
+            shift(&other.digits, scale - other.scale),
+            scale,
+        )
+    }
+
+    fn compare(&self, other: &Decimal) -> Ordering {
+        if self.negative != other.negative {
+            return if self.negative {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            };
+        }
+        let (a, b, _) = self.aligned(other);
+        let magnitude = compare(&a, &b);
+        if self.negative {
+            magnitude.reverse()
+        } else {
+            magnitude
+        }
+    }
+
+    fn negated(&self) -> Decimal {
+        Decimal::new(!self.negative, self.digits.clone(), self.scale)
+    }
+
+    fn percent(&self) -> Decimal {
+        Decimal::new(self.negative, self.digits.clone(), self.scale + 2)
+    }
+
+    fn add(&self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = self.aligned(other);
+        if self.negative == other.negative {
+            return Decimal::new(self.negative, add(&a, &b), scale);
+        }
+        match compare(&a, &b) {
+            Ordering::Less => Decimal::new(other.negative, subtract(&b, &a), scale),
+            _ => Decimal::new(self.negative, subtract(&a, &b), scale),
+        }
+    }
+
+    fn multiply(&self, other: &Decimal) -> Decimal {
+        Decimal::new(
+            self.negative != other.negative,
+            multiply(&self.digits, &other.digits),
+            self.scale + other.scale,
+        )
+    }
+
+    /// `self / other` to `precision` decimal places, rounded half away
+    /// from zero. `other` isn't zero.
+    fn divide(&self, other: &Decimal, precision: usize) -> Decimal {
+        // self / other is (a / b) × 10^(other.scale - self.scale), so
+        // shifting by `precision` more leaves a whole number to round.
+        let places = (precision + other.scale) as i64 - self.scale as i64;
+        let (numerator, denominator) = if places >= 0 {
+            (shift(&self.digits, places as usize), other.digits.clone())
+        } else {
+            let places = places.unsigned_abs() as usize;
+            (self.digits.clone(), shift(&other.digits, places))
+        };
+        let (mut quotient, remainder) = divide(&numerator, &denominator);
+        if compare(&add(&remainder, &remainder), &denominator) != Ordering::Less {
+            quotient = add(&quotient, &[1]);
+        }
+        Decimal::new(self.negative != other.negative, quotient, precision)
+    }
+
+    /// The quotient rounded toward zero and the remainder, which has the
+    /// sign of `self` as `f64`'s `%` does. `other` isn't zero.
+    fn divide_whole(&self, other: &Decimal) -> (Decimal, Decimal) {
+        let (a, b, scale) = self.aligned(other);
+        let (quotient, remainder) = divide(&a, &b);
+        (
+            Decimal::new(self.negative != other.negative, quotient, 0),
+            Decimal::new(self.negative, remainder, scale),
+        )
+    }
+
+    /// `self ^ exponent`, or `None` unless `exponent` is whole and the
+    /// result fits in [`MAX_DIGITS`]. A negative exponent divides, to
+    /// `precision` places.
+    fn power(&self, exponent: &Decimal, precision: usize) -> Option<Decimal> {
+        if exponent.scale != 0 {
+            return None;
+        }
+        let odd = exponent.digits.first().is_some_and(|d| d % 2 == 1);
+        if self.is_zero() {
+            return match (exponent.is_zero(), exponent.negative) {
+                (true, _) => Some(Decimal::one()),
+                // Infinite, as a float.
+                (false, true) => None,
+                (false, false) => Some(Decimal::new(false, Vec::new(), 0)),
+            };
+        }
+        if self.digits == [1] && self.scale == 0 {
+            return Some(Decimal::new(self.negative && odd, vec![1], 0));
+        }
+        let mut n: usize = exponent
+            .digits
+            .iter()
+            .rev()
+            .try_fold(0usize, |n, d| n.checked_mul(10)?.checked_add(*d as usize))?;
+        if self.digits.len().max(self.scale).saturating_mul(n) > MAX_DIGITS {
+            return None;
+        }
+        let mut result = Decimal::one();
+        let mut square = self.clone();
+        while n > 0 {
+            if n % 2 == 1 {
+                result = result.multiply(&square);
+            }
+            n /= 2;
+            if n > 0 {
+                square = square.multiply(&square);
+            }
+        }
+        Some(if exponent.negative {
+            Decimal::one().divide(&result, precision)
+        } else {
+            result
+        })
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.negative { "-" } else { "" };
+        let digits: String = self
+            .digits
+            .iter()
+            .rev()
+            .map(|d| char::from(b'0' + d))
+            .collect();
+        let padded = format!("{:0>width$}", digits, width = self.scale + 1);
+        let (whole, fraction) = padded.split_at(padded.len() - self.scale);
+        match fraction {
+            "" => write!(f, "{}{}", sign, whole),
+            _ => write!(f, "{}{}.{}", sign, whole, fraction),
+        }
+    }
+}
+
+// Magnitudes: decimal digits, least significant first, with no zeros at
+// the most significant end.
+
+fn trim(digits: &mut Vec<u8>) {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+}
+
+/// `digits × 10^places`.
+fn shift(digits: &[u8], places: usize) -> Vec<u8> {
+    if digits.is_empty() {
+        return Vec::new();
+    }
+    let mut shifted = vec![0; places];
+    shifted.extend_from_slice(digits);
+    shifted
+}
+
+fn compare(a: &[u8], b: &[u8]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let d = a.get(i).unwrap_or(&0) + b.get(i).unwrap_or(&0) + carry;
+        sum.push(d % 10);
+        carry = d / 10;
+    }
+    if carry > 0 {
+        sum.push(carry);
+    }
+    sum
+}
+
+/// `a - b`, where `a` is at least `b`.
+fn subtract(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &d) in a.iter().enumerate() {
+        let take = b.get(i).unwrap_or(&0) + borrow;
+        borrow = u8::from(d < take);
+        difference.push(d + 10 * borrow - take);
+    }
+    trim(&mut difference);
+    difference
+}
+
+fn multiply(a: &[u8], b: &[u8]) -> Vec<u8> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut columns = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        for (j, &y) in b.iter().enumerate() {
+            columns[i + j] += u64::from(x) * u64::from(y);
+        }
+    }
+    let mut product = Vec::with_capacity(columns.len());
+    let mut carry = 0;
+    for column in columns {
+        let total = column + carry;
+        product.push((total % 10) as u8);
+        carry = total / 10;
+    }
+    trim(&mut product);
+    product
+}
+
+/// Long division: the quotient and remainder of `a / b`, where `b` isn't
+/// zero.
+fn divide(a: &[u8], b: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut quotient = vec![0; a.len()];
+    let mut remainder = Vec::with_capacity(b.len() + 1);
+    for i in (0..a.len()).rev() {
+        remainder.insert(0, a[i]);
+        trim(&mut remainder);
+        while compare(&remainder, b) != Ordering::Less {
+            remainder = subtract(&remainder, b);
+            quotient[i] += 1;
+        }
+    }
+    trim(&mut quotient);
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn precise(expr: &str) -> Result<String, Error> {
+        Calculator::calculate_precise(expr, 20)
+    }
+
+    #[test]
+    fn test_division_to_precision() {
+        assert_eq!(
+            Calculator::calculate_precise("1 / 3", 50).unwrap(),
+            format!("0.{}", "3".repeat(50))
+        );
+        let cases = [
+            ("2 / 3", 5, "0.66667"),
+            ("-2 / 3", 5, "-0.66667"),
+            ("7 / 2", 0, "4"),
+            ("-7 / 2", 0, "-4"),
+            ("1 / 8", 2, "0.13"),
+            ("1 / 8", 3, "0.125"),
+            ("6 / 3", 10, "2"),
+            ("1 / 3 * 3", 4, "0.9999"),
+            ("1 / 3000", 2, "0"),
+            ("12.5 / 0.05", 0, "250"),
+            ("1 / 7", 30, "0.142857142857142857142857142857"),
+        ];
+        for (expr, precision, expected) in cases {
+            assert_eq!(
+                Calculator::calculate_precise(expr, precision).as_deref(),
+                Ok(expected),
+                "{} to {}",
+                expr,
+                precision
+            );
+        }
+    }
+
+    #[test]
+    fn test_exact_decimals() {
+        assert_eq!(Calculator::calculate("0.1 + 0.2"), Ok(0.30000000000000004));
+        let cases = [
+            ("0.1 + 0.2", "0.3"),
+            ("0.3 - 0.1", "0.2"),
+            ("0.1 - 0.3", "-0.2"),
+            ("1.10 * 3", "3.3"),
+            ("2.50 * 4", "10"),
+            ("0.1 * 0.1", "0.01"),
+            ("1e3 + 4E-2", "1000.04"),
+            ("0xFF + 0.5", "255.5"),
+            ("0.000_1 + 1_000", "1000.0001"),
+            (".1 + .2", "0.3"),
+            ("2. * 3", "6"),
+            ("0xFFFFFFFFFFFFFFFFFFFF", "1208925819614629174706175"),
+            ("1.5e-3 * 2", "0.003"),
+            ("-0.0", "0"),
+            ("19.99 * 3 - 0.97", "59"),
+            ("25%", "0.25"),
+            ("3.5% * 200", "7"),
+            ("-7 // 2", "-4"),
+            ("7.5 // 2", "3"),
+            ("div(7, 2)", "3.5"),
+            ("floordiv(-7, 2)", "-4"),
+            ("-7 % 3", "-1"),
+            ("7.5 % 2", "1.5"),
+            ("2 ^ 100", "1267650600228229401496703205376"),
+            ("0.5 ^ 3", "0.125"),
+            ("(-0.2) ^ 3", "-0.008"),
+            ("2 ^ -3", "0.125"),
+            ("(-1) ^ 100000000001", "-1"),
+            ("0 ^ 0", "1"),
+            ("pow(1.1, 2)", "1.21"),
+            ("abs(-1.5)", "1.5"),
+            ("max(0.1, 0.25, 0.2)", "0.25"),
+            ("min(0.1, -0.25, 0.2)", "-0.25"),
+            (
+                "123456789012345678901234567890 * 987654321",
+                "121932631124828532112482853211126352690",
+            ),
+            ("(x = 0.1) * 3 + x", "0.4"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(precise(expr).as_deref(), Ok(expected), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_float_fallback() {
+        let cases = [
+            ("sqrt(2)", "1.4142135623730951"),
+            ("sqrt(4) + 0.1", "2.1"),
+            ("pi", "3.141592653589793"),
+            ("2 ^ 0.5", "1.4142135623730951"),
+            ("0 ^ -1", "inf"),
+            ("10 ^ 100000", "inf"),
+            ("1e100000 * 0", "NaN"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(precise(expr).as_deref(), Ok(expected), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_errors_as_in_float_mode() {
+        for expr in ["1 / 0", "5 % 0", "5 // 0.0", "1 +", ")(", "y", "sqrt(1, 2)"] {
+            assert_eq!(
+                precise(expr).map_err(|e| e.kind()),
+                Calculator::calculate(expr)
+                    .map(|x| x.to_string())
+                    .map_err(|e| e.kind()),
+                "{}",
+                expr
+            );
+        }
+    }
+}
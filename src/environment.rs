@@ -0,0 +1,423 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+use crate::custom::Registry;
+use crate::{Calculator, Error, EvalOptions, LocatedError, Token, function};
+
+/// The variable holding an [`Environment`]'s last successful result.
+pub(crate) const ANS: &str = "ans";
+
+/// Whether `name`, which isn't a variable, splits into two or more that
+/// are, or constants, as `xy` does with `x` and `y` bound: implicit
+/// multiplication never splits a name, so `xy` could be a typo for
+/// `x * y` and is [`Error::AmbiguousName`] rather than undefined.
+pub(crate) fn reads_as_product(name: &str, variables: &HashMap<String, f64>) -> bool {
+    let known = |piece: &str| variables.contains_key(piece) || function::constant(piece).is_some();
+    let bounds: Vec<usize> = name
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([name.len()])
+        .collect();
+    // Whether `name` from each bound on splits into known pieces.
+    let mut splits = vec![false; bounds.len()];
+    splits[bounds.len() - 1] = true;
+    for a in (0..bounds.len() - 1).rev() {
+        splits[a] = (a + 1..bounds.len()).any(|b| splits[b] && known(&name[bounds[a]..bounds[b]]));
+    }
+    !known(name) && splits[0]
+}
+
+/// Variables that last from one expression to the next, for a session of
+/// calculations such as `x = 5` followed by `x * 2 + 1`.
+///
+/// [`Calculator::calculate`] and the rest of the static API evaluate each
+/// expression in a fresh, empty environment: assignments work inside it,
+/// as in `(x = 3) * x`, and are forgotten afterwards.
+///
+/// `ans` is the value of the last expression that succeeded, so a result
+/// can be refined with `ans + 1`. It can't be assigned, and reading it
+/// before anything has succeeded is [`Error::NoPreviousResult`].
+///
+/// ```
+/// use calculator::{Environment, Error};
+///
+/// let mut env = Environment::new();
+/// assert_eq!(env.calculate("x = 5"), Ok(5.0));
+/// assert_eq!(env.calculate("x * 2 + 1"), Ok(11.0));
+/// assert_eq!(env.calculate("ans / 2"), Ok(5.5));
+/// assert_eq!(
+///     env.calculate("y + 1"),
+///     Err(Error::UndefinedVariable("y".to_string()))
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Environment {
+    variables: HashMap<String, f64>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    /// The value last assigned to `name`.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.variables.get(name).copied()
+    }
+
+    /// Every variable and its value, in no particular order, including
+    /// `ans` once something has succeeded.
+    pub fn variables(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+    }
+
+    /// Binds `name` to `value` as an assignment would, without checking the
+    /// name; `ans` stays as it was.
+    pub(crate) fn assign(&mut self, name: &str, value: f64) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    /// [`Calculator::calculate`], reading variables assigned by earlier
+    /// expressions and keeping the ones this one assigns. An expression
+    /// that fails assigns nothing, even where it got past an assignment
+    /// before failing, and leaves `ans` as it was.
+    pub fn calculate<T: AsRef<str>>(&mut self, expr: T) -> Result<f64, Error> {
+        self.calculate_located(expr).map_err(|e| e.error)
+    }
+
+    /// [`Environment::calculate`], with errors located as
+    /// [`Calculator::calculate_located`] does.
+    pub fn calculate_located<T: AsRef<str>>(&mut self, expr: T) -> Result<f64, LocatedError> {
+        let options = EvalOptions::default();
+        let registry = Registry::default();
+        let (tokens, spans) = Calculator::parse_located(expr.as_ref(), &options, &registry)?;
+        let (postfix, origins) = Calculator::try_to_postfix_with_origins(tokens)?;
+        let mut variables = self.variables.clone();
+        let value =
+            Calculator::evaluate_located(postfix, &origins, &options, &mut variables, &registry)
+                .map_err(|e| e.within(&spans))?;
+        variables.insert(ANS.to_string(), value);
+        self.variables = variables;
+        Ok(value)
+    }
+}
+
+impl Calculator {
+    /// [`Calculator::evaluate`] with `variables` bound, so an expression
+    /// can be parsed once and evaluated for many values. The map isn't
+    /// changed: assignments in the expression last only as long as it.
+    /// A [`VarContext`](crate::VarContext) does as well as a map.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use calculator::Calculator;
+    ///
+    /// let line = Calculator::try_to_postfix(Calculator::parse("a * x + b").unwrap()).unwrap();
+    /// let mut vars = HashMap::from([("a".to_string(), 2.0), ("b".to_string(), 1.0)]);
+    /// for (x, expected) in [(3.0, 7.0), (-1.0, -1.0)] {
+    ///     vars.insert("x".to_string(), x);
+    ///     assert_eq!(Calculator::evaluate_with_variables(line.clone(), &vars), Ok(expected));
+    /// }
+    /// ```
+    pub fn evaluate_with_variables(
+        tokens: Vec<Token>,
+        variables: &impl Borrow<HashMap<String, f64>>,
+    ) -> Result<f64, Error> {
+        let registry = Registry::default();
+        Self::evaluate_at(
+            tokens,
+            &EvalOptions::default(),
+            &mut variables.borrow().clone(),
+            &registry,
+        )
+        .map_err(|(error, _)| error)
+    }
+
+    /// [`Calculator::calculate`] with `variables` bound, as
+    /// [`Calculator::evaluate_with_variables`] does.
+    pub fn calculate_with_variables<T: AsRef<str>>(
+        expr: T,
+        variables: &impl Borrow<HashMap<String, f64>>,
+    ) -> Result<f64, Error> {
+        Self::evaluate_with_variables(Self::try_to_postfix(Self::parse(expr)?)?, variables)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Span;
+
+    #[test]
+    fn test_assign_and_read() {
+        let mut env = Environment::new();
+        assert_eq!(env.calculate("x = 5"), Ok(5.0));
+        assert_eq!(env.calculate("x * 2 + 1"), Ok(11.0));
+        assert_eq!(env.calculate("rate_2 = x / 10"), Ok(0.5));
+        assert_eq!(env.calculate("rate_2 * x"), Ok(2.5));
+        assert_eq!(env.get("x"), Some(5.0));
+        assert_eq!(env.get("y"), None);
+    }
+
+    #[test]
+    fn test_reassignment() {
+        let mut env = Environment::new();
+        env.calculate("x = 1").unwrap();
+        assert_eq!(env.calculate("x = 2"), Ok(2.0));
+        assert_eq!(env.calculate("x = x + 1"), Ok(3.0));
+        assert_eq!(env.calculate("x = x * x"), Ok(9.0));
+        assert_eq!(env.get("x"), Some(9.0));
+        // Reading its own new value needs it to exist already.
+        assert_eq!(
+            env.calculate("z = z + 1"),
+            Err(Error::UndefinedVariable("z".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_assignment_is_an_expression() {
+        let mut env = Environment::new();
+        assert_eq!(env.calculate("a = b = 3"), Ok(3.0));
+        assert_eq!((env.get("a"), env.get("b")), (Some(3.0), Some(3.0)));
+        assert_eq!(env.calculate("y = (x = 5) * 2"), Ok(10.0));
+        assert_eq!((env.get("x"), env.get("y")), (Some(5.0), Some(10.0)));
+        // Everything after the `=` is assigned, however loosely it binds.
+        assert_eq!(env.calculate("c = 1 + 2 * 3"), Ok(7.0));
+        assert_eq!(env.calculate("sqrt(d = 16) + d"), Ok(20.0));
+        // Left to right, so a variable can be used later in the line that
+        // assigns it.
+        assert_eq!(env.calculate("(n = 4) + n"), Ok(8.0));
+
+        for expr in [
+            "2 * x = 3",
+            "1 + x = 2",
+            "-x = 2",
+            "x =",
+            "(x =)",
+            "x = = 2",
+        ] {
+            assert!(env.calculate(expr).is_err(), "{}", expr);
+        }
+        assert_eq!(
+            env.calculate("2 * x = 3"),
+            Err(Error::UnexpectedToken { pos: 4 })
+        );
+    }
+
+    #[test]
+    fn test_failed_expression_assigns_nothing() {
+        let mut env = Environment::new();
+        env.calculate("x = 1").unwrap();
+        assert_eq!(env.calculate("x = 1 / 0"), Err(Error::DivisionByZero));
+        assert_eq!(
+            env.calculate("(y = 2) + missing"),
+            Err(Error::UndefinedVariable("missing".to_string()))
+        );
+        assert_eq!(env.get("x"), Some(1.0));
+        assert_eq!(env.get("y"), None);
+    }
+
+    #[test]
+    fn test_constants_and_functions_cannot_be_shadowed() {
+        let mut env = Environment::new();
+        assert_eq!(
+            env.calculate("pi = 3"),
+            Err(Error::ReservedName {
+                name: "pi".to_string(),
+                pos: 0
+            })
+        );
+        assert_eq!(
+            env.calculate("1 + (E = 2)"),
+            Err(Error::ReservedName {
+                name: "E".to_string(),
+                pos: 5
+            })
+        );
+        assert_eq!(
+            env.calculate("sqrt = 4"),
+            Err(Error::ReservedName {
+                name: "sqrt".to_string(),
+                pos: 0
+            })
+        );
+        assert_eq!(env.calculate("pi"), Ok(std::f64::consts::PI));
+        // Names that only start like a constant are ordinary variables.
+        assert_eq!(env.calculate("pie = 3"), Ok(3.0));
+        assert_eq!(
+            env.calculate("pie + e0"),
+            Err(Error::UndefinedVariable("e0".to_string()))
+        );
+        assert_eq!(env.variables().count(), 2);
+    }
+
+    #[test]
+    fn test_names_that_gain_a_meaning() {
+        // A formula written with a variable `min`, evaluated by a version
+        // with a built-in `min`: a bare name is still the variable.
+        let vars = HashMap::from([("min".to_string(), 3.0), ("rate".to_string(), 0.5)]);
+        assert_eq!(
+            Calculator::calculate_with_variables("min * 2", &vars),
+            Ok(6.0)
+        );
+        // A formula calling `min`, handed a variable of that name: neither
+        // reading is picked.
+        assert_eq!(
+            Calculator::calculate_with_variables("min(1, 2)", &vars),
+            Err(Error::AmbiguousName("min".to_string()))
+        );
+        assert_eq!(
+            Calculator::calculate_with_variables("max(1, 2)", &vars),
+            Ok(2.0)
+        );
+
+        // The same for a function registered after the formula was written,
+        // and for a variable assigned after a call was.
+        let mut calc = Calculator::builder().build();
+        calc.register_function("rate", 1, |args| Ok(args[0] / 100.0))
+            .unwrap();
+        assert_eq!(calc.calculate("rate(50)"), Ok(0.5));
+        assert_eq!(calc.calculate("(rate = 2) * 3"), Ok(6.0));
+        assert_eq!(
+            calc.calculate("(rate = 2) * rate(50)"),
+            Err(Error::AmbiguousName("rate".to_string()))
+        );
+        assert_eq!(
+            "ambiguous name 'rate': rename the variable, or write a product out with '*'",
+            Error::AmbiguousName("rate".to_string()).to_string()
+        );
+    }
+
+    #[test]
+    fn test_names_made_of_other_names() {
+        let implicit = Calculator::builder().implicit_multiplication(true).build();
+        let ambiguous = |name: &str| Err(Error::AmbiguousName(name.to_string()));
+        for (expr, expected) in [
+            ("(x = 2) * (y = 3) * xy", ambiguous("xy")),
+            ("(x = 2) * (y = 3) * x y", Ok(36.0)),
+            ("(x = 2) * (xy = 5) * xy", Ok(50.0)),
+            ("(x = 2) * xpi", ambiguous("xpi")),
+            ("(ab = 2) * (c = 3) * abc", ambiguous("abc")),
+            (
+                "(x = 2) * xz",
+                Err(Error::UndefinedVariable("xz".to_string())),
+            ),
+            (
+                "(x = 2) * x2",
+                Err(Error::UndefinedVariable("x2".to_string())),
+            ),
+        ] {
+            assert_eq!(implicit.calculate(expr), expected, "{}", expr);
+        }
+        // Without implicit multiplication `xy` can't be a product.
+        assert_eq!(
+            Calculator::calculate("(x = 2) * (y = 3) * xy"),
+            Err(Error::UndefinedVariable("xy".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_ans() {
+        let mut env = Environment::new();
+        assert_eq!(env.calculate("ans + 1"), Err(Error::NoPreviousResult));
+        assert_eq!(env.calculate("2 * 3"), Ok(6.0));
+        assert_eq!(env.calculate("ans + 1"), Ok(7.0));
+        assert_eq!(env.calculate("ans * ans"), Ok(49.0));
+        // Assignments are results too.
+        assert_eq!(env.calculate("x = 2"), Ok(2.0));
+        assert_eq!(env.get("ans"), Some(2.0));
+        assert_eq!(
+            env.calculate("ans = 5"),
+            Err(Error::ReservedName {
+                name: "ans".to_string(),
+                pos: 0
+            })
+        );
+        assert_eq!(env.calculate("ans / 0"), Err(Error::DivisionByZero));
+        assert_eq!(env.get("ans"), Some(2.0));
+        // Only the exact name; it's case-sensitive like other variables.
+        assert_eq!(
+            env.calculate("ANS"),
+            Err(Error::UndefinedVariable("ANS".to_string()))
+        );
+        assert_eq!(Calculator::calculate("ans"), Err(Error::NoPreviousResult));
+    }
+
+    fn bindings(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect()
+    }
+
+    #[test]
+    fn test_evaluate_with_variables() {
+        let line = Calculator::try_to_postfix(Calculator::parse("a * x + b").unwrap()).unwrap();
+        for (vars, expected) in [
+            (bindings(&[("a", 2.0), ("x", 3.0), ("b", 1.0)]), 7.0),
+            (bindings(&[("a", -1.0), ("x", 0.5), ("b", 10.0)]), 9.5),
+            (
+                bindings(&[("a", 0.0), ("x", 1e9), ("b", -4.0), ("unused", 1.0)]),
+                -4.0,
+            ),
+        ] {
+            assert_eq!(
+                Calculator::evaluate_with_variables(line.clone(), &vars),
+                Ok(expected),
+                "{:?}",
+                vars
+            );
+        }
+        assert_eq!(
+            Calculator::evaluate_with_variables(line, &bindings(&[("a", 2.0), ("x", 3.0)])),
+            Err(Error::UndefinedVariable("b".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_calculate_with_variables() {
+        let vars = bindings(&[("x", 4.0)]);
+        assert_eq!(
+            Calculator::calculate_with_variables("sqrt(x) + x", &vars),
+            Ok(6.0)
+        );
+        // Assignments don't reach the caller's map.
+        assert_eq!(
+            Calculator::calculate_with_variables("x = x * 2", &vars),
+            Ok(8.0)
+        );
+        assert_eq!(vars["x"], 4.0);
+        assert_eq!(
+            Calculator::calculate_with_variables("x + y", &vars),
+            Err(Error::UndefinedVariable("y".to_string()))
+        );
+        assert_eq!(
+            Calculator::calculate_with_variables("pi = x", &vars),
+            Err(Error::ReservedName {
+                name: "pi".to_string(),
+                pos: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_located_errors() {
+        let mut env = Environment::new();
+        let e = env.calculate_located("x = 1 + y").unwrap_err();
+        assert_eq!(e.error, Error::UndefinedVariable("y".to_string()));
+        assert_eq!(e.span, Some(Span { start: 8, end: 9 }));
+        assert_eq!(e.to_string(), "undefined variable 'y' at position 8");
+    }
+
+    #[test]
+    fn test_static_api_forgets_assignments() {
+        assert_eq!(Calculator::calculate("x = 4"), Ok(4.0));
+        assert_eq!(Calculator::calculate("(x = 4) * x"), Ok(16.0));
+        assert_eq!(
+            Calculator::calculate("x"),
+            Err(Error::UndefinedVariable("x".to_string()))
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{Calculator, Environment, Error, LocatedError, Token};
+
+/// Result of evaluating an expression twice: once as written and once with
+/// every literal rounded to a few significant figures.
+#[derive(Debug)]
+pub struct Estimate {
+    /// Result with rounded literals. Rounding can change the outcome (for
+    /// example `1 / (1.01 - 1)` at two figures divides by zero), so this is
+    /// kept separately from the exact result.
+    pub approx: Result<f64, Error>,
+    pub exact: f64,
+    pub sig_figs: usize,
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.approx {
+            Ok(approx) => write!(f, "≈ {:.*e}", self.sig_figs - 1, approx)?,
+            Err(e) => write!(f, "≈ error: {}", e)?,
+        }
+        write!(f, " (exact: {})", group_thousands(self.exact))
+    }
+}
+
+/// Rounds `value` to `sig_figs` significant figures.
+fn round_sig(value: f64, sig_figs: usize) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    format!("{:.*e}", sig_figs - 1, value).parse().unwrap()
+}
+
+/// `36482910.44` → `36,482,910.44`.
+fn group_thousands(value: f64) -> String {
+    let text = format!("{}", value);
+    let (sign, rest) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text.as_str()),
+    };
+    let (integer, fraction) = match rest.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (rest, None),
+    };
+    if !integer.bytes().all(|b| b.is_ascii_digit()) {
+        return text;
+    }
+
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+
+    match fraction {
+        Some(fraction) => format!("{}{}.{}", sign, grouped, fraction),
+        None => format!("{}{}", sign, grouped),
+    }
+}
+
+impl Calculator {
+    /// Evaluates `expr` exactly and again with every literal rounded to
+    /// `sig_figs` significant figures (at least 1), for order-of-magnitude
+    /// sanity checks.
+    pub fn estimate<T: AsRef<str>>(expr: T, sig_figs: usize) -> Result<Estimate, Error> {
+        let sig_figs = sig_figs.max(1);
+        let tokens = Self::parse(expr)?;
+        let rounded = round_literals(&tokens, sig_figs);
+
+        let exact = Self::evaluate(Self::try_to_postfix(tokens)?)?;
+        let approx = Self::evaluate(Self::try_to_postfix(rounded)?);
+
+        Ok(Estimate {
+            approx,
+            exact,
+            sig_figs,
+        })
+    }
+}
+
+impl Environment {
+    /// [`Calculator::estimate`] in this environment: the exact evaluation
+    /// is [`Environment::calculate_located`], keeping its assignments and
+    /// `ans`, and the rounded one reads the variables as they were before
+    /// it and assigns nothing.
+    pub(crate) fn estimate(
+        &mut self,
+        expr: &str,
+        sig_figs: usize,
+    ) -> Result<Estimate, LocatedError> {
+        let sig_figs = sig_figs.max(1);
+        let variables: HashMap<String, f64> = self
+            .variables()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        let exact = self.calculate_located(expr)?;
+        // It parsed once already.
+        let tokens = Calculator::parse(expr)?;
+        let approx = Calculator::try_to_postfix(round_literals(&tokens, sig_figs))
+            .and_then(|postfix| Calculator::evaluate_with_variables(postfix, &variables));
+
+        Ok(Estimate {
+            approx,
+            exact,
+            sig_figs,
+        })
+    }
+}
+
+/// `tokens` with every number rounded to `sig_figs` significant figures.
+fn round_literals(tokens: &[Token], sig_figs: usize) -> Vec<Token> {
+    tokens
+        .iter()
+        .map(|token| match token {
+            Token::Number(n) => Token::Number(round_sig(*n, sig_figs)),
+            other => other.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_sig() {
+        assert_eq!(round_sig(36_482_910.44, 2), 36_000_000.0);
+        assert_eq!(round_sig(0.012_34, 2), 0.012);
+        assert_eq!(round_sig(-987.6, 1), -1000.0);
+        assert_eq!(round_sig(0.0, 2), 0.0);
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands(36_482_910.44), "36,482,910.44");
+        assert_eq!(group_thousands(-1234.5), "-1,234.5");
+        assert_eq!(group_thousands(999.0), "999");
+        assert_eq!(group_thousands(1000.0), "1,000");
+        assert_eq!(group_thousands(f64::INFINITY), "inf");
+    }
+
+    #[test]
+    fn test_estimate_rounds_literals_not_result() {
+        let estimate = Calculator::estimate("6042.1 * 6038.15", 2).unwrap();
+        assert_eq!(estimate.approx.as_ref().unwrap(), &(6000.0 * 6000.0));
+        assert_eq!(estimate.exact, 6042.1 * 6038.15);
+        assert_eq!(
+            estimate.to_string(),
+            format!("≈ 3.6e7 (exact: {})", group_thousands(6042.1 * 6038.15))
+        );
+    }
+
+    #[test]
+    fn test_estimate_sig_figs_configurable() {
+        let estimate = Calculator::estimate("1234 + 5678", 3).unwrap();
+        assert_eq!(estimate.approx.unwrap(), 1230.0 + 5680.0);
+        assert_eq!(estimate.exact, 6912.0);
+    }
+
+    #[test]
+    fn test_estimate_errors() {
+        // Rounding alone can introduce an error the exact path doesn't have.
+        let estimate = Calculator::estimate("1 / (1.01 - 1)", 2).unwrap();
+        assert!(matches!(estimate.approx, Err(Error::DivisionByZero)));
+        assert!(
+            estimate
+                .to_string()
+                .starts_with("≈ error: division by zero (exact: ")
+        );
+
+        assert_eq!(
+            Calculator::estimate("5 / 0", 2).unwrap_err(),
+            Error::DivisionByZero
+        );
+    }
+}
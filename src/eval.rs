@@ -0,0 +1,271 @@
+//! Running postfix tokens to a value.
+
+use std::collections::HashMap;
+
+use crate::custom::Registry;
+use crate::environment::{self, ANS};
+use crate::{Angle, Calculator, Division, Error, EvalOptions, Token};
+
+impl Calculator {
+    pub fn evaluate(tokens: Vec<Token>) -> Result<f64, Error> {
+        Self::evaluate_with(tokens, &EvalOptions::default())
+    }
+
+    /// Evaluates postfix `tokens`, reading and assigning `variables` and
+    /// calling what `registry` holds; on failure also returns the index of
+    /// the token being applied, or `None` if the stream ended malformed.
+    pub(crate) fn evaluate_at(
+        tokens: Vec<Token>,
+        options: &EvalOptions,
+        variables: &mut HashMap<String, f64>,
+        registry: &Registry,
+    ) -> Result<f64, (Error, Option<usize>)> {
+        let mut stack = Vec::with_capacity(tokens.len() / 2 + 1);
+        Self::evaluate_on(&mut stack, &tokens, options, variables, registry)
+    }
+
+    /// [`Calculator::evaluate_at`] on `stack`, which is cleared first, so
+    /// a caller evaluating again and again can keep one buffer.
+    pub(crate) fn evaluate_on(
+        stack: &mut Vec<f64>,
+        tokens: &[Token],
+        options: &EvalOptions,
+        variables: &mut HashMap<String, f64>,
+        registry: &Registry,
+    ) -> Result<f64, (Error, Option<usize>)> {
+        stack.clear();
+        for (i, token) in tokens.iter().enumerate() {
+            Self::apply_token(stack, token, options, variables, registry)
+                .map_err(|e| (e, Some(i)))?;
+        }
+        match stack.as_slice() {
+            [value] => Ok(*value),
+            _ => Err((Error::InvalidExpression, None)),
+        }
+    }
+
+    /// Applies one postfix token to the value stack.
+    pub(crate) fn apply_token(
+        stack: &mut Vec<f64>,
+        token: &Token,
+        options: &EvalOptions,
+        variables: &mut HashMap<String, f64>,
+        registry: &Registry,
+    ) -> Result<(), Error> {
+        match token {
+            Token::Number(n) => stack.push(*n),
+            Token::Identifier(name) => match variables.get(name.as_str()) {
+                Some(value) => stack.push(*value),
+                None if name.as_str() == ANS => return Err(Error::NoPreviousResult),
+                None if options.implicit_multiplication
+                    && environment::reads_as_product(name, variables) =>
+                {
+                    return Err(Error::AmbiguousName(name.as_str().to_string()));
+                }
+                None => return Err(Error::UndefinedVariable(name.as_str().to_string())),
+            },
+            Token::Assign(name) => match stack.last() {
+                Some(value) => {
+                    variables.insert(name.as_str().to_string(), *value);
+                }
+                None => return Err(Error::InvalidExpression),
+            },
+            Token::Function { function, args } => {
+                if variables.contains_key(function.name()) {
+                    return Err(Error::AmbiguousName(function.name().to_string()));
+                }
+                let found = *args as usize;
+                if found == 0 || stack.len() < found {
+                    return Err(Error::InvalidExpression);
+                }
+                let mut arguments = stack.split_off(stack.len() - found);
+                if function.takes_angle() && options.angle == Angle::Degrees {
+                    arguments[0] = arguments[0].to_radians();
+                }
+                let value = match function.operator() {
+                    Some(op) => {
+                        let options = EvalOptions {
+                            division: Division::FloatAlways,
+                            ..*options
+                        };
+                        op.apply(arguments[0], arguments[1], &options)?
+                    }
+                    None => function.apply(&arguments)?,
+                };
+                stack.push(value);
+            }
+            Token::Call { name, args } => {
+                if variables.contains_key(name.as_str()) {
+                    return Err(Error::AmbiguousName(name.as_str().to_string()));
+                }
+                let found = *args as usize;
+                // Postfix carries no positions, so this is at 0 until an
+                // error located in the source is moved to the call.
+                let Some(function) = registry.function(name) else {
+                    return Err(Error::UnknownFunction {
+                        name: name.as_str().to_string(),
+                        pos: 0,
+                    });
+                };
+                if function.arity != found {
+                    return Err(Error::WrongArity {
+                        name: name.as_str().to_string(),
+                        expected: function.arity,
+                        found,
+                    });
+                }
+                if stack.len() < found {
+                    return Err(Error::InvalidExpression);
+                }
+                let arguments = stack.split_off(stack.len() - found);
+                stack.push(function.apply(&arguments)?);
+            }
+            Token::Custom { symbol, .. } => {
+                let Some(op) = registry.operator(*symbol) else {
+                    return Err(Error::BadToken {
+                        ch: *symbol,
+                        pos: 0,
+                    });
+                };
+                if stack.len() < 2 {
+                    return Err(Error::InvalidExpression);
+                }
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+                stack.push(op.apply(left, right)?);
+            }
+            Token::Op(op) if op.is_unary() => match stack.pop() {
+                Some(operand) => stack.push(op.apply(0.0, operand, options)?),
+                None => return Err(Error::InvalidExpression),
+            },
+            Token::Op(op) => {
+                if stack.len() < 2 {
+                    return Err(Error::InvalidExpression);
+                }
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+                stack.push(op.apply(left, right, options)?);
+            }
+            _ => return Err(Error::InvalidExpression),
+        }
+        Ok(())
+    }
+
+    /// The result once every token is applied: exactly one value must be left.
+    pub(crate) fn finish(mut stack: Vec<f64>) -> Result<f64, Error> {
+        match stack.len() {
+            1 => Ok(stack.pop().unwrap()),
+            _ => Err(Error::InvalidExpression),
+        }
+    }
+
+    /// The value of `expr`. Its errors carry positions in it where they
+    /// have any, as [`Calculator::calculate_located`] finds them.
+    pub fn calculate<T: AsRef<str>>(expr: T) -> Result<f64, Error> {
+        Self::calculate_located(expr).map_err(|e| e.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Operator;
+
+    #[test]
+    fn test_basic_arithmetic() {
+        assert_eq!(Calculator::calculate("2 + 3").unwrap(), 5.0);
+        assert_eq!(Calculator::calculate("10 - 4").unwrap(), 6.0);
+        assert_eq!(Calculator::calculate("3 * 4").unwrap(), 12.0);
+        assert_eq!(Calculator::calculate("15 / 3").unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_complex_expressions() {
+        assert_eq!(Calculator::calculate("1 + 2 * 3 + 4").unwrap(), 11.0);
+        assert_eq!(Calculator::calculate("(1 + 2) * (3 + 4)").unwrap(), 21.0);
+        assert_eq!(Calculator::calculate("10 + 5 * 2 - 3 / 3").unwrap(), 19.0);
+        assert_eq!(Calculator::calculate("100 / 4 / 5 + 2 * 3").unwrap(), 11.0);
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        assert_eq!(
+            Calculator::calculate("5 / 0").unwrap_err(),
+            Error::DivisionByZero
+        );
+        assert_eq!(
+            Calculator::calculate("10 / (2 - 2)").unwrap_err(),
+            Error::DivisionByZero
+        );
+    }
+
+    #[test]
+    fn test_empty_expression() {
+        assert_eq!(
+            Calculator::calculate("").unwrap_err(),
+            Error::InvalidExpression
+        );
+    }
+
+    #[test]
+    fn test_large_numbers() {
+        assert_eq!(
+            Calculator::calculate("1000000 + 2000000").unwrap(),
+            3000000.0
+        );
+        assert_eq!(Calculator::calculate("999999 * 2").unwrap(), 1999998.0);
+        assert_eq!(Calculator::calculate("1000000 / 1000").unwrap(), 1000.0);
+    }
+
+    #[test]
+    fn test_negative_results() {
+        assert_eq!(Calculator::calculate("3 - 5").unwrap(), -2.0);
+        assert_eq!(Calculator::calculate("10 / 2 - 8").unwrap(), -3.0);
+        assert_eq!(Calculator::calculate("(2 - 5) * 3").unwrap(), -9.0);
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq!(Calculator::calculate("2^10").unwrap(), 1024.0);
+        assert_eq!(Calculator::calculate("2^0.5").unwrap(), 2f64.sqrt());
+        assert_eq!(Calculator::calculate("2 ** 10").unwrap(), 1024.0);
+        assert_eq!(Calculator::calculate("3 * 2 ^ 2").unwrap(), 12.0);
+        assert_eq!(Calculator::calculate("2 ^ (1 + 2)").unwrap(), 8.0);
+        assert_eq!(Calculator::calculate("(1 + 1) ^ 3 - 1").unwrap(), 7.0);
+    }
+
+    #[test]
+    fn test_modulo() {
+        assert_eq!(Calculator::calculate("10 % 3").unwrap(), 1.0);
+        assert_eq!(Calculator::calculate("7.5 % 2").unwrap(), 1.5);
+        // The remainder takes the sign of the left operand.
+        assert_eq!(Calculator::calculate("-7 % 3").unwrap(), -1.0);
+        assert_eq!(Calculator::calculate("7 % (-3)").unwrap(), 1.0);
+        assert_eq!(Calculator::calculate("-7 % (-3)").unwrap(), -1.0);
+        assert_eq!(
+            Calculator::calculate("5 % 0").unwrap_err(),
+            Error::DivisionByZero
+        );
+    }
+
+    #[test]
+    fn test_fractional_results() {
+        assert_eq!(Calculator::calculate("1 / 2").unwrap(), 0.5);
+        assert_eq!(Calculator::calculate("3 / 4").unwrap(), 0.75);
+        assert_eq!(Calculator::calculate("7 / 8").unwrap(), 0.875);
+    }
+
+    #[test]
+    fn test_evaluate_function() {
+        let postfix = vec![
+            Token::Number(2.0),
+            Token::Number(3.0),
+            Token::Number(4.0),
+            Token::Op(Operator::Multiply),
+            Token::Op(Operator::Add),
+        ];
+
+        let result = Calculator::evaluate(postfix).unwrap();
+        assert_eq!(result, 14.0);
+    }
+}
@@ -0,0 +1,321 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+use crate::custom::Registry;
+use crate::{Calculator, ConfiguredCalculator, Error, EvalOptions, StoredExpression, Token};
+
+/// What a call to [`Evaluation::step`] got to.
+#[derive(Debug, PartialEq)]
+pub enum StepOutcome {
+    /// The budget ran out first; call `step` again to continue.
+    Pending,
+    Done(Result<f64, Error>),
+}
+
+/// A postfix evaluation that runs a bounded number of tokens at a time, so
+/// a host that can't block (an async server, a UI thread) can interleave
+/// it with other work. Between steps it holds only the value stack and
+/// the index of the next token; dropping it part-way is fine.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use calculator::{Calculator, EvalOptions, Evaluation, StepOutcome};
+///
+/// let postfix = Calculator::try_to_postfix(Calculator::parse("(x + 2) * 3").unwrap()).unwrap();
+/// let vars = HashMap::from([("x".to_string(), 1.0)]);
+/// let mut evaluation = Evaluation::start(postfix, &vars, &EvalOptions::default());
+/// let result = loop {
+///     match evaluation.step(2) {
+///         StepOutcome::Pending => continue, // yield to other work here
+///         StepOutcome::Done(result) => break result,
+///     }
+/// };
+/// assert_eq!(result, Ok(9.0));
+/// ```
+#[derive(Debug)]
+pub struct Evaluation {
+    tokens: Vec<Token>,
+    next: usize,
+    stack: Vec<f64>,
+    /// The variables bound at the start, and those assigned since; gone
+    /// with the evaluation.
+    variables: HashMap<String, f64>,
+    options: EvalOptions,
+    registry: Registry,
+    done: bool,
+}
+
+impl Evaluation {
+    /// Prepares to evaluate `postfix`, as produced by
+    /// [`Calculator::try_to_postfix`], with `variables` bound as
+    /// [`Calculator::evaluate_with_variables`] binds them, from a map or
+    /// a [`VarContext`](crate::VarContext). Nothing runs until the first
+    /// `step`.
+    pub fn start(
+        postfix: Vec<Token>,
+        variables: &impl Borrow<HashMap<String, f64>>,
+        options: &EvalOptions,
+    ) -> Self {
+        Self::start_in(postfix, variables.borrow(), options, &Registry::default())
+    }
+
+    /// [`Evaluation::start`], calling what `registry` registers.
+    fn start_in(
+        postfix: Vec<Token>,
+        variables: &HashMap<String, f64>,
+        options: &EvalOptions,
+        registry: &Registry,
+    ) -> Self {
+        Evaluation {
+            stack: Vec::with_capacity(postfix.len() / 2 + 1),
+            tokens: postfix,
+            next: 0,
+            variables: variables.clone(),
+            options: *options,
+            registry: registry.clone(),
+            done: false,
+        }
+    }
+
+    /// Applies at most `budget` tokens. The outcome is the same as
+    /// [`Calculator::evaluate_with`] gives, however the work is split.
+    ///
+    /// # Panics
+    ///
+    /// If called again after returning `Done`.
+    pub fn step(&mut self, budget: usize) -> StepOutcome {
+        assert!(!self.done, "Evaluation::step called after Done");
+
+        let end = self.tokens.len().min(self.next.saturating_add(budget));
+        while self.next < end {
+            let token = &self.tokens[self.next];
+            self.next += 1;
+            let applied = Calculator::apply_token(
+                &mut self.stack,
+                token,
+                &self.options,
+                &mut self.variables,
+                &self.registry,
+            );
+            if let Err(e) = applied {
+                return self.complete(Err(e));
+            }
+        }
+        if self.next < self.tokens.len() {
+            return StepOutcome::Pending;
+        }
+        let stack = std::mem::take(&mut self.stack);
+        self.complete(Calculator::finish(stack))
+    }
+
+    /// Tokens not yet applied.
+    pub fn remaining(&self) -> usize {
+        self.tokens.len() - self.next
+    }
+
+    fn complete(&mut self, result: Result<f64, Error>) -> StepOutcome {
+        self.done = true;
+        self.stack = Vec::new();
+        StepOutcome::Done(result)
+    }
+}
+
+impl StoredExpression {
+    /// An [`Evaluation`] of the stored tokens with `variables` bound, with
+    /// default settings.
+    pub fn start_evaluation(&self, variables: &impl Borrow<HashMap<String, f64>>) -> Evaluation {
+        Evaluation::start(self.tokens.clone(), variables, &EvalOptions::default())
+    }
+}
+
+impl ConfiguredCalculator {
+    /// [`Evaluation::start`] with this calculator's settings and the
+    /// functions and operators registered on it, so `postfix` from
+    /// [`ConfiguredCalculator::parse`] can be stepped.
+    pub fn start_evaluation(
+        &self,
+        postfix: Vec<Token>,
+        variables: &impl Borrow<HashMap<String, f64>>,
+    ) -> Evaluation {
+        Evaluation::start_in(postfix, variables.borrow(), self.options(), self.registry())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steps `evaluation` to the end, counting the calls it took.
+    fn run(mut evaluation: Evaluation, budget: usize) -> (Result<f64, Error>, usize) {
+        let mut calls = 0;
+        loop {
+            calls += 1;
+            if let StepOutcome::Done(result) = evaluation.step(budget) {
+                return (result, calls);
+            }
+        }
+    }
+
+    fn postfix(expr: &str) -> Vec<Token> {
+        Calculator::try_to_postfix(Calculator::parse(expr).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_budget_one_matches_one_shot() {
+        // A long expression: 1 + 2 * 3 - 4 / 5 + ... over 2_000 terms.
+        let expr: String = (1..2_000)
+            .map(|i| format!("{} {} ", i, ["+", "*", "-", "/"][i % 4]))
+            .chain(std::iter::once("1".to_string()))
+            .collect();
+        let tokens = postfix(&expr);
+        let expected = Calculator::evaluate(tokens.clone());
+
+        let (result, calls) = run(
+            Evaluation::start(tokens.clone(), &HashMap::new(), &EvalOptions::default()),
+            1,
+        );
+        assert_eq!(result, expected);
+        assert_eq!(calls, tokens.len());
+
+        for budget in [7, 1_000, usize::MAX] {
+            let evaluation =
+                Evaluation::start(tokens.clone(), &HashMap::new(), &EvalOptions::default());
+            assert_eq!(run(evaluation, budget).0, expected);
+        }
+    }
+
+    #[test]
+    fn test_errors_and_settings() {
+        for expr in ["10 / (2 - 2)", "sqrt(-1) * 0 + 7 // 0", "2 ^ 3 ^ 2"] {
+            let tokens = postfix(expr);
+            let expected = Calculator::evaluate(tokens.clone());
+            let (result, _) = run(
+                Evaluation::start(tokens, &HashMap::new(), &EvalOptions::default()),
+                1,
+            );
+            assert_eq!(result, expected, "{}", expr);
+        }
+
+        // A failure stops the run at the failing token.
+        let mut evaluation = Evaluation::start(
+            postfix("1 / 0 + 2 + 3"),
+            &HashMap::new(),
+            &EvalOptions::default(),
+        );
+        assert_eq!(
+            evaluation.step(3),
+            StepOutcome::Done(Err(Error::DivisionByZero))
+        );
+        assert_eq!(evaluation.remaining(), 4);
+
+        let options = EvalOptions {
+            division: crate::Division::TruncateWhenBothIntegral,
+            ..EvalOptions::default()
+        };
+        let (result, _) = run(
+            Evaluation::start(postfix("7 / 2"), &HashMap::new(), &options),
+            1,
+        );
+        assert_eq!(result, Ok(3.0));
+    }
+
+    #[test]
+    fn test_malformed_and_empty_streams() {
+        let mut evaluation =
+            Evaluation::start(Vec::new(), &HashMap::new(), &EvalOptions::default());
+        assert_eq!(
+            evaluation.step(0),
+            StepOutcome::Done(Err(Error::InvalidExpression))
+        );
+
+        let tokens = vec![Token::Number(1.0), Token::Number(2.0)];
+        let mut evaluation = Evaluation::start(tokens, &HashMap::new(), &EvalOptions::default());
+        assert_eq!(evaluation.step(0), StepOutcome::Pending);
+        assert_eq!(evaluation.remaining(), 2);
+        assert_eq!(
+            evaluation.step(5),
+            StepOutcome::Done(Err(Error::InvalidExpression))
+        );
+    }
+
+    #[test]
+    fn test_drop_in_progress() {
+        let mut evaluation = Evaluation::start(
+            postfix("1 + 2 + 3"),
+            &HashMap::new(),
+            &EvalOptions::default(),
+        );
+        assert_eq!(evaluation.step(2), StepOutcome::Pending);
+        drop(evaluation);
+    }
+
+    #[test]
+    fn test_stored_expression() {
+        let stored = StoredExpression::compile("(1 + 2) * 3").unwrap();
+        assert_eq!(
+            run(stored.start_evaluation(&HashMap::new()), 1).0,
+            stored.evaluate()
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        let tokens = postfix("a * x + b");
+        let mut vars = HashMap::from([("a".to_string(), 2.0), ("b".to_string(), 1.0)]);
+        for x in [3.0, -1.0] {
+            vars.insert("x".to_string(), x);
+            let evaluation = Evaluation::start(tokens.clone(), &vars, &EvalOptions::default());
+            assert_eq!(
+                run(evaluation, 1).0,
+                Calculator::evaluate_with_variables(tokens.clone(), &vars)
+            );
+        }
+
+        // Assignments are the evaluation's own.
+        let evaluation = Evaluation::start(postfix("(a = 5) * a"), &vars, &EvalOptions::default());
+        assert_eq!(run(evaluation, 1).0, Ok(25.0));
+        assert_eq!(vars["a"], 2.0);
+
+        let evaluation = Evaluation::start(tokens, &HashMap::new(), &EvalOptions::default());
+        assert_eq!(
+            run(evaluation, 1).0,
+            Err(Error::UndefinedVariable("a".to_string()))
+        );
+
+        let stored = StoredExpression::compile("x ^ 2").unwrap();
+        let vars = HashMap::from([("x".to_string(), 3.0)]);
+        assert_eq!(run(stored.start_evaluation(&vars), 1).0, Ok(9.0));
+    }
+
+    #[test]
+    fn test_configured_calculator() {
+        let mut calc = Calculator::builder().angle(crate::Angle::Degrees).build();
+        calc.register_function("double", 1, |args| Ok(args[0] * 2.0))
+            .unwrap();
+        calc.register_operator('@', 15, crate::Assoc::Left, |l, r| Ok((l + r) / 2.0))
+            .unwrap();
+        let expr = "double(x) @ sin(90)";
+        let tokens = calc.try_to_postfix(calc.parse(expr).unwrap()).unwrap();
+        let vars = HashMap::from([("x".to_string(), 2.0)]);
+        for budget in [1, 3, usize::MAX] {
+            let evaluation = calc.start_evaluation(tokens.clone(), &vars);
+            assert_eq!(run(evaluation, budget).0, Ok(2.5), "{}", budget);
+        }
+
+        // Without the registry the call can't be made.
+        let evaluation = Evaluation::start(tokens, &vars, &EvalOptions::default());
+        assert!(matches!(
+            run(evaluation, 1).0,
+            Err(Error::UnknownFunction { .. })
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "after Done")]
+    fn test_step_after_done_panics() {
+        let mut evaluation =
+            Evaluation::start(postfix("1"), &HashMap::new(), &EvalOptions::default());
+        let _ = evaluation.step(1);
+        let _ = evaluation.step(1);
+    }
+}
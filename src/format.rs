@@ -0,0 +1,312 @@
+use crate::Error;
+
+/// Width of an aligned suffix: enough for the exponent of any finite
+/// `f64`, as in `e-324`, with SI prefixes padded to match.
+const SUFFIX_WIDTH: usize = 5;
+
+/// SI prefixes from 10^-24 to 10^24, indexed by `exponent / 3 + 8`.
+const SI_PREFIXES: [&str; 17] = [
+    "y", "z", "a", "f", "p", "n", "µ", "m", "", "k", "M", "G", "T", "P", "E", "Z", "Y",
+];
+
+/// Engineering notation: a mantissa in [1, 1000) and an exponent that is a
+/// multiple of 3, written either as `e3`/`e-6` or as an SI prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineeringFormat {
+    sig_figs: usize,
+    si_prefix: bool,
+    aligned: bool,
+}
+
+impl EngineeringFormat {
+    /// `sig_figs` is clamped to at least 1.
+    pub fn new(sig_figs: usize) -> Self {
+        EngineeringFormat {
+            sig_figs: sig_figs.max(1),
+            si_prefix: false,
+            aligned: false,
+        }
+    }
+
+    /// Writes `12.4k` instead of `12.4e3`. Exponents outside the SI range
+    /// (beyond 10^±24) still use the exponent form.
+    pub fn si_prefix(mut self, si_prefix: bool) -> Self {
+        self.si_prefix = si_prefix;
+        self
+    }
+
+    /// Pads every finite value to the same [`EngineeringFormat::width`] so
+    /// columns line up: the mantissa is right-aligned, the exponent is
+    /// written with a sign and at least two digits (`e+03`), and the suffix,
+    /// SI prefix or exponent, is left-aligned in a field wide enough for
+    /// any exponent.
+    pub fn aligned(mut self, aligned: bool) -> Self {
+        self.aligned = aligned;
+        self
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return format!("{}", value);
+        }
+
+        let (negative, digits, exponent) = self.round(value);
+        let shift = exponent.rem_euclid(3) as usize;
+        let eng_exponent = exponent - shift as i32;
+
+        let mut integer: String = digits.chars().take(shift + 1).collect();
+        while integer.len() < shift + 1 {
+            integer.push('0');
+        }
+        let fraction: String = digits.chars().skip(shift + 1).collect();
+
+        let mut mantissa = String::new();
+        if negative {
+            mantissa.push('-');
+        }
+        mantissa.push_str(&integer);
+        if !fraction.is_empty() {
+            mantissa.push('.');
+            mantissa.push_str(&fraction);
+        }
+
+        let suffix = match self.prefix_for(eng_exponent) {
+            Some(prefix) => prefix.to_string(),
+            None if self.aligned => format!("e{:+03}", eng_exponent),
+            None => format!("e{}", eng_exponent),
+        };
+
+        if self.aligned {
+            format!(
+                "{:>width$}{:<SUFFIX_WIDTH$}",
+                mantissa,
+                suffix,
+                width = self.mantissa_width()
+            )
+        } else {
+            format!("{}{}", mantissa, suffix)
+        }
+    }
+
+    /// How many characters an aligned value takes.
+    pub fn width(&self) -> usize {
+        self.mantissa_width() + SUFFIX_WIDTH
+    }
+
+    /// Splits `value` into its sign, exactly `sig_figs` significant digits
+    /// and the decimal exponent of the first digit, after rounding (so
+    /// 999.96 at three figures becomes "100" with exponent 3).
+    fn round(&self, value: f64) -> (bool, String, i32) {
+        let scientific = format!("{:.*e}", self.sig_figs - 1, value.abs());
+        let (mantissa, exponent) = scientific.split_once('e').unwrap();
+        let digits = mantissa.replace('.', "");
+        let exponent = if value == 0.0 {
+            0
+        } else {
+            exponent.parse().unwrap()
+        };
+        (value.is_sign_negative() && value != 0.0, digits, exponent)
+    }
+
+    fn prefix_for(&self, exponent: i32) -> Option<&'static str> {
+        if !self.si_prefix || !(-24..=24).contains(&exponent) {
+            return None;
+        }
+        Some(SI_PREFIXES[(exponent / 3 + 8) as usize])
+    }
+
+    /// Widest mantissa this format can produce: a sign, up to three integer
+    /// digits plus the remaining figures, and the decimal point.
+    fn mantissa_width(&self) -> usize {
+        let point = if self.sig_figs > 1 { 1 } else { 0 };
+        1 + self.sig_figs.max(3) + point
+    }
+}
+
+/// `value` in engineering notation with `sig_figs` significant figures, e.g.
+/// `format_engineering(12_400.0, 3) == "12.4e3"`.
+pub fn format_engineering(value: f64, sig_figs: usize) -> String {
+    EngineeringFormat::new(sig_figs).format(value)
+}
+
+/// `value` written in base `radix`, with lowercase letters for digits past
+/// 9 and a `-` for a negative one: `to_base(255.0, 16)` is `"ff"` and
+/// `to_base(-5.0, 2)` is `"-101"`. An answer from `bitand` and the other
+/// bit functions, which are whole numbers below 2^53, always has one; a
+/// fraction, or a number that size or larger, is [`Error::NotAnInteger`].
+///
+/// # Panics
+///
+/// If `radix` isn't from 2 to 36.
+pub fn to_base(value: f64, radix: u32) -> Result<String, Error> {
+    assert!(
+        (2..=36).contains(&radix),
+        "radix {} is not from 2 to 36",
+        radix
+    );
+    if value.fract() != 0.0 || value.abs() >= 2f64.powi(53) {
+        return Err(Error::NotAnInteger {
+            operation: "to_base",
+        });
+    }
+    let mut magnitude = value.abs() as u64;
+    let mut digits = Vec::new();
+    loop {
+        let digit = (magnitude % u64::from(radix)) as u32;
+        digits.push(char::from_digit(digit, radix).unwrap());
+        magnitude /= u64::from(radix);
+        if magnitude == 0 {
+            break;
+        }
+    }
+    if value < 0.0 {
+        digits.push('-');
+    }
+    Ok(digits.into_iter().rev().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engineering_exponents() {
+        assert_eq!(format_engineering(12_400.0, 3), "12.4e3");
+        assert_eq!(format_engineering(3_300_000.0, 3), "3.30e6");
+        assert_eq!(format_engineering(0.000045, 3), "45.0e-6");
+        assert_eq!(format_engineering(1.0, 3), "1.00e0");
+        assert_eq!(format_engineering(123.0, 3), "123e0");
+        assert_eq!(format_engineering(-0.5, 2), "-500e-3");
+        assert_eq!(format_engineering(0.0, 3), "0.00e0");
+    }
+
+    #[test]
+    fn test_rounding_carries_into_next_group() {
+        assert_eq!(format_engineering(999.96, 3), "1.00e3");
+        assert_eq!(format_engineering(999.4, 3), "999e0");
+        assert_eq!(format_engineering(999_949.0, 3), "1.00e6");
+        assert_eq!(format_engineering(12_345.0, 1), "10e3");
+    }
+
+    #[test]
+    fn test_full_si_range() {
+        let expected = [
+            "y", "z", "a", "f", "p", "n", "µ", "m", "", "k", "M", "G", "T", "P", "E", "Z", "Y",
+        ];
+        let format = EngineeringFormat::new(3).si_prefix(true);
+        for (i, prefix) in expected.iter().enumerate() {
+            let exponent = (i as i32 - 8) * 3;
+            let value = 4.56 * 10f64.powi(exponent);
+            assert_eq!(
+                format.format(value),
+                format!("4.56{}", prefix),
+                "10^{}",
+                exponent
+            );
+        }
+        assert_eq!(format.format(12_400.0), "12.4k");
+        assert_eq!(format.format(3_300_000.0), "3.30M");
+        assert_eq!(format.format(-0.000045), "-45.0µ");
+    }
+
+    #[test]
+    fn test_si_fallback_boundaries() {
+        let format = EngineeringFormat::new(3).si_prefix(true);
+        assert_eq!(format.format(1e-24), "1.00y");
+        assert_eq!(format.format(9.99e-25), "999e-27");
+        assert_eq!(format.format(1e-30), "1.00e-30");
+        assert_eq!(format.format(999e24), "999Y");
+        assert_eq!(format.format(1e27), "1.00e27");
+    }
+
+    #[test]
+    fn test_aligned_columns() {
+        let format = EngineeringFormat::new(3).si_prefix(true).aligned(true);
+        let column: Vec<String> = [1.0, 12.5, 999.0, -0.0042, 1e-30]
+            .iter()
+            .map(|&v| format.format(v))
+            .collect();
+        assert_eq!(
+            column,
+            [
+                " 1.00     ",
+                " 12.5     ",
+                "  999     ",
+                "-4.20m    ",
+                " 1.00e-30 "
+            ]
+        );
+
+        let format = EngineeringFormat::new(3).aligned(true);
+        let column: Vec<String> = [12_400.0, 0.000045, 5e-324, -1.7e308]
+            .iter()
+            .map(|&v| format.format(v))
+            .collect();
+        assert_eq!(
+            column,
+            [" 12.4e+03 ", " 45.0e-06 ", " 4.94e-324", " -170e+306"]
+        );
+
+        for sig_figs in 1..6 {
+            for si_prefix in [false, true] {
+                let format = EngineeringFormat::new(sig_figs)
+                    .si_prefix(si_prefix)
+                    .aligned(true);
+                for value in [
+                    0.0,
+                    1.0,
+                    -999.9,
+                    1e-30,
+                    4.2e-7,
+                    -6e23,
+                    1e300,
+                    f64::MIN_POSITIVE,
+                ] {
+                    assert_eq!(
+                        format.format(value).chars().count(),
+                        format.width(),
+                        "{} at {} figures",
+                        value,
+                        sig_figs
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_base() {
+        assert_eq!(to_base(255.0, 16), Ok("ff".to_string()));
+        assert_eq!(to_base(10.0, 2), Ok("1010".to_string()));
+        assert_eq!(to_base(-5.0, 2), Ok("-101".to_string()));
+        assert_eq!(to_base(0.0, 8), Ok("0".to_string()));
+        assert_eq!(to_base(-0.0, 8), Ok("0".to_string()));
+        assert_eq!(to_base(35.0, 36), Ok("z".to_string()));
+        assert_eq!(
+            to_base(2f64.powi(53) - 1.0, 16),
+            Ok("1fffffffffffff".to_string())
+        );
+        let not_an_integer = Err(Error::NotAnInteger {
+            operation: "to_base",
+        });
+        for value in [
+            0.5,
+            2f64.powi(53),
+            -(2f64.powi(53)),
+            f64::INFINITY,
+            f64::NAN,
+        ] {
+            assert_eq!(to_base(value, 10), not_an_integer, "{}", value);
+        }
+        // What the bit functions give back.
+        let mask = crate::Calculator::calculate("bitxor(shl(1, 12) - 1, 255)").unwrap();
+        assert_eq!(to_base(mask, 2), Ok("111100000000".to_string()));
+    }
+
+    #[test]
+    fn test_non_finite() {
+        assert_eq!(format_engineering(f64::INFINITY, 3), "inf");
+        assert_eq!(format_engineering(f64::NEG_INFINITY, 3), "-inf");
+        assert_eq!(format_engineering(f64::NAN, 3), "NaN");
+    }
+}
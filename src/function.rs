@@ -0,0 +1,832 @@
+use std::f64::consts;
+
+use crate::{Error, Operator};
+
+/// The value of a named constant: `pi`, `e` or `tau`, in any case. Each
+/// is the `f64` nearest the true value. A constant is a number, so `pi(3)`
+/// is not a call; like `2(3)` it is rejected unless implicit
+/// multiplication is on.
+pub(crate) fn constant(name: &str) -> Option<f64> {
+    match name.to_ascii_lowercase().as_str() {
+        "pi" => Some(consts::PI),
+        "e" => Some(consts::E),
+        "tau" => Some(consts::TAU),
+        _ => None,
+    }
+}
+
+/// The largest `q` [`power`] reads an exponent as `p / q` with.
+const MAX_ROOT: u32 = 99;
+
+/// The smallest `q` from 2 to [`MAX_ROOT`] for which `exponent` is the
+/// `f64` nearest some `p / q`, as `1 / 3` and `0.4` are.
+fn denominator(exponent: f64) -> Option<u32> {
+    (2..=MAX_ROOT).find(|&q| {
+        let q = f64::from(q);
+        (exponent * q).round() / q == exponent
+    })
+}
+
+/// `base ^ exponent`, for `^` and `pow`. A negative base has a real power
+/// for a whole exponent, which `powf` gives exactly and with its sign
+/// (`(-2) ^ 3` is `-8`), and for an odd root `p / q`, taken with
+/// `real_roots` as the root of `-base` with the sign of `(-1) ^ p`, so
+/// `(-8) ^ (1/3)` is `-2`. Any other would be NaN, and is an
+/// [`Error::DomainError`] instead.
+pub(crate) fn power(base: f64, exponent: f64, real_roots: bool) -> Result<f64, Error> {
+    if base >= 0.0 || base.is_nan() || !exponent.is_finite() || exponent.fract() == 0.0 {
+        return Ok(base.powf(exponent));
+    }
+    let domain_error = |reason| {
+        Err(Error::DomainError {
+            operation: "power",
+            reason,
+        })
+    };
+    match denominator(exponent) {
+        Some(q) if q % 2 == 1 && real_roots => {
+            let root = (-base).powf(exponent);
+            let odd = (exponent * f64::from(q)).round() % 2.0 != 0.0;
+            Ok(if odd { -root } else { root })
+        }
+        Some(q) if q % 2 == 1 => {
+            domain_error("a negative base's odd root is only taken with real roots on")
+        }
+        _ => domain_error("a negative base needs a whole exponent or an odd root"),
+    }
+}
+
+/// How many representable `f64`s apart `a` and `b` are: 0 when equal
+/// (including `0.0` and `-0.0`), 1 for neighbours. Any NaN is `u64::MAX`
+/// from everything. Used to state accuracy without epsilon fudge.
+pub fn ulp_diff(a: f64, b: f64) -> u64 {
+    if a.is_nan() || b.is_nan() {
+        return u64::MAX;
+    }
+    // Reorders the bit patterns so that integer order is numeric order,
+    // with both zeros at 0.
+    let ordered = |x: f64| {
+        let bits = x.to_bits() as i64;
+        if bits < 0 { i64::MIN - bits } else { bits }
+    };
+    ordered(a).abs_diff(ordered(b))
+}
+
+/// A built-in function, written `name(argument)`, or with its arguments
+/// separated by commas for the few that take several: see
+/// [`Function::arity`].
+///
+/// Results follow `f64`: outside a function's domain the value is NaN or
+/// infinite (`sqrt(-1)`, `ln(0)`) rather than an error; only `div` and
+/// `floordiv` can fail, on a zero divisor as `/` and `//` do, `pow`, on
+/// a negative base as `^` does, `pct_change` and `weighted_avg`, where
+/// they have nothing to divide by, and the bit functions, on anything but
+/// a whole number. Angles are in radians.
+///
+/// Accuracy: `sqrt` and `div` are correctly rounded, as IEEE 754 requires,
+/// and `abs`, `min`, `max`, `floordiv` and the bit functions are exact.
+/// The others come from the platform's libm, which Rust doesn't pin down;
+/// they are tested to be within 1 ulp (see [`ulp_diff`]) at a set of
+/// reference points, which mainstream libms meet in practice, but they are
+/// not guaranteed correctly rounded, and results may differ by an ulp
+/// between platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    /// Correctly rounded.
+    Sqrt,
+    /// Within 1 ulp.
+    Cbrt,
+    /// Within 1 ulp. Note that `sin(pi)` is about `1.2e-16`, not 0: the
+    /// argument is the `f64` nearest π, not π.
+    Sin,
+    /// Within 1 ulp.
+    Cos,
+    /// Within 1 ulp.
+    Tan,
+    /// Natural logarithm; within 1 ulp.
+    Ln,
+    /// Within 1 ulp.
+    Log10,
+    /// Within 1 ulp.
+    Exp,
+    /// Exact.
+    Abs,
+    /// The smallest of one or more arguments. Exact; a NaN argument is
+    /// ignored unless every argument is NaN, as with [`f64::min`].
+    Min,
+    /// The largest of one or more arguments. Exact; NaN as for `Min`.
+    Max,
+    /// `pow(x, y)` is `x ^ y`, within 1 ulp.
+    Pow,
+    /// `div(x, y)` is `x / y` as [`Division::FloatAlways`] has it, whatever
+    /// [`EvalOptions::division`] says: `div(7, 2)` is 3.5. Correctly rounded.
+    ///
+    /// [`Division::FloatAlways`]: crate::Division::FloatAlways
+    /// [`EvalOptions::division`]: crate::EvalOptions::division
+    Div,
+    /// `floordiv(x, y)` is `x // y`: `floordiv(-7, 2)` is -4. Exact.
+    FloorDiv,
+    /// `pct_change(old, new)` is the change from `old` to `new` as a
+    /// percentage of `old`'s size, `(new - old) / |old| * 100`, so a rise
+    /// is positive even from below zero: `pct_change(-50, -25)` is 50. A
+    /// zero `old` is an [`Error::DomainError`].
+    PctChange,
+    /// `safe_div(x, y)` is `x / y` as `div` has it, except that a zero `y`
+    /// gives 0 instead of failing, under either
+    /// [`ZeroDivision`](crate::ZeroDivision) policy: for ratios where an
+    /// empty denominator means nothing to report. Correctly rounded.
+    SafeDiv,
+    /// `weighted_avg(v1, w1, v2, w2, ...)` is the mean of the values, each
+    /// counted by the weight after it. The arguments must come in pairs
+    /// and the weights mustn't add up to 0; either is an
+    /// [`Error::DomainError`].
+    WeightedAvg,
+    /// `bitand(x, y)` is the bits set in both `x` and `y`: `bitand(12, 10)`
+    /// is 8. Like the other bit functions it takes whole numbers below
+    /// 2^53, as an `f64` holds exactly, and is [`Error::NotAnInteger`] for
+    /// anything else. There is no two's complement to give a negative
+    /// number bits, so a negative operand is an [`Error::DomainError`]
+    /// rather than being read by its magnitude.
+    BitAnd,
+    /// `bitor(x, y)` is the bits set in either: `bitor(12, 10)` is 14.
+    BitOr,
+    /// `bitxor(x, y)` is the bits set in one but not both: `bitxor(12, 10)`
+    /// is 6.
+    BitXor,
+    /// `shl(x, n)` is `x` shifted left by `n` bits, `x * 2 ^ n`: `shl(3, 4)`
+    /// is 48. A result of 2^53 or more is an [`Error::Overflow`].
+    Shl,
+    /// `shr(x, n)` is `x` shifted right by `n` bits, dropping those shifted
+    /// out: `shr(50, 2)` is 12.
+    Shr,
+}
+
+impl Function {
+    /// Every function, in the order they are documented.
+    pub const ALL: [Function; 22] = [
+        Function::Sqrt,
+        Function::Cbrt,
+        Function::Sin,
+        Function::Cos,
+        Function::Tan,
+        Function::Ln,
+        Function::Log10,
+        Function::Exp,
+        Function::Abs,
+        Function::Min,
+        Function::Max,
+        Function::Pow,
+        Function::Div,
+        Function::FloorDiv,
+        Function::PctChange,
+        Function::SafeDiv,
+        Function::WeightedAvg,
+        Function::BitAnd,
+        Function::BitOr,
+        Function::BitXor,
+        Function::Shl,
+        Function::Shr,
+    ];
+
+    /// The name the function is written with, e.g. `"sqrt"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::Sqrt => "sqrt",
+            Function::Cbrt => "cbrt",
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tan => "tan",
+            Function::Ln => "ln",
+            Function::Log10 => "log10",
+            Function::Exp => "exp",
+            Function::Abs => "abs",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Pow => "pow",
+            Function::Div => "div",
+            Function::FloorDiv => "floordiv",
+            Function::PctChange => "pct_change",
+            Function::SafeDiv => "safe_div",
+            Function::WeightedAvg => "weighted_avg",
+            Function::BitAnd => "bitand",
+            Function::BitOr => "bitor",
+            Function::BitXor => "bitxor",
+            Function::Shl => "shl",
+            Function::Shr => "shr",
+        }
+    }
+
+    /// Looks a function up by name. Names are case-sensitive.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Function::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// How many arguments it takes, or `None` for any number from one up.
+    pub fn arity(self) -> Option<usize> {
+        match self {
+            Function::Min | Function::Max | Function::WeightedAvg => None,
+            Function::Pow
+            | Function::Div
+            | Function::FloorDiv
+            | Function::PctChange
+            | Function::SafeDiv
+            | Function::BitAnd
+            | Function::BitOr
+            | Function::BitXor
+            | Function::Shl
+            | Function::Shr => Some(2),
+            _ => Some(1),
+        }
+    }
+
+    /// Whether the argument is an angle, in the unit
+    /// [`EvalOptions::angle`](crate::EvalOptions::angle) says.
+    pub(crate) fn takes_angle(self) -> bool {
+        matches!(self, Function::Sin | Function::Cos | Function::Tan)
+    }
+
+    /// The operator `div`, `floordiv` and `pow` spell out. They are applied
+    /// as it is, zero divisors and negative bases included, but always with
+    /// [`Division::FloatAlways`](crate::Division::FloatAlways).
+    pub(crate) fn operator(self) -> Option<Operator> {
+        match self {
+            Function::Div => Some(Operator::Divide),
+            Function::FloorDiv => Some(Operator::FloorDivide),
+            Function::Pow => Some(Operator::Power),
+            _ => None,
+        }
+    }
+
+    /// `arguments` must be as many as [`Function::arity`] allows; a zero
+    /// divisor of `div` or `floordiv` isn't checked for here.
+    pub(crate) fn apply(self, arguments: &[f64]) -> Result<f64, Error> {
+        let x = arguments[0];
+        let rest = arguments[1..].iter().copied();
+        let domain_error = |reason| {
+            Err(Error::DomainError {
+                operation: self.name(),
+                reason,
+            })
+        };
+        Ok(match self {
+            Function::Sqrt => x.sqrt(),
+            Function::Cbrt => x.cbrt(),
+            Function::Sin => x.sin(),
+            Function::Cos => x.cos(),
+            Function::Tan => x.tan(),
+            Function::Ln => x.ln(),
+            Function::Log10 => x.log10(),
+            Function::Exp => x.exp(),
+            Function::Abs => x.abs(),
+            Function::Min => rest.fold(x, f64::min),
+            Function::Max => rest.fold(x, f64::max),
+            Function::Pow => x.powf(arguments[1]),
+            Function::Div => x / arguments[1],
+            Function::FloorDiv => (x / arguments[1]).floor(),
+            Function::PctChange if x == 0.0 => return domain_error("the old value is 0"),
+            Function::PctChange => (arguments[1] - x) / x.abs() * 100.0,
+            Function::SafeDiv if arguments[1] == 0.0 => 0.0,
+            Function::SafeDiv => x / arguments[1],
+            Function::WeightedAvg if !arguments.len().is_multiple_of(2) => {
+                return domain_error("the values and weights must come in pairs");
+            }
+            Function::WeightedAvg => {
+                let pairs = arguments.chunks_exact(2);
+                let weight: f64 = pairs.clone().map(|pair| pair[1]).sum();
+                if weight == 0.0 {
+                    return domain_error("the weights add up to 0");
+                }
+                pairs.map(|pair| pair[0] * pair[1]).sum::<f64>() / weight
+            }
+            Function::BitAnd
+            | Function::BitOr
+            | Function::BitXor
+            | Function::Shl
+            | Function::Shr => {
+                let (a, b) = (self.bits(x)?, self.bits(arguments[1])?);
+                let bits = match self {
+                    Function::BitAnd => a & b,
+                    Function::BitOr => a | b,
+                    Function::BitXor => a ^ b,
+                    Function::Shl if a == 0 => 0,
+                    Function::Shl => match a.checked_shl(b.min(64) as u32) {
+                        Some(bits) if bits < EXACT_BITS && bits >> b == a => bits,
+                        _ => return Err(Error::Overflow),
+                    },
+                    _ => a.checked_shr(b.min(64) as u32).unwrap_or(0),
+                };
+                bits as f64
+            }
+        })
+    }
+
+    /// `x` as the whole number a bit function works on.
+    fn bits(self, x: f64) -> Result<u64, Error> {
+        if x.fract() != 0.0 || x.abs() >= EXACT_BITS as f64 {
+            return Err(Error::NotAnInteger {
+                operation: self.name(),
+            });
+        }
+        if x < 0.0 {
+            return Err(Error::DomainError {
+                operation: self.name(),
+                reason: "a negative number has no bits without two's complement",
+            });
+        }
+        Ok(x as u64)
+    }
+}
+
+/// 2^53, below which every whole number is exactly an `f64`.
+const EXACT_BITS: u64 = 1 << 53;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Calculator, Error, EvalOptions};
+
+    fn calc(expr: &str) -> f64 {
+        Calculator::calculate(expr).unwrap()
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(calc("sqrt(16)"), 4.0);
+        assert_eq!(calc("cbrt(27)"), 3.0);
+        assert_eq!(calc("sin(0)"), 0.0);
+        assert_eq!(calc("cos(0)"), 1.0);
+        assert_eq!(calc("tan(0)"), 0.0);
+        assert_eq!(calc("ln(1)"), 0.0);
+        assert_eq!(calc("log10(1000)"), 3.0);
+        assert_eq!(calc("exp(0)"), 1.0);
+        assert_eq!(calc("abs(-2.5)"), 2.5);
+        assert!(calc("sqrt(-1)").is_nan());
+    }
+
+    #[test]
+    fn test_nested_and_embedded_calls() {
+        assert_eq!(calc("sqrt(sqrt(16))"), 2.0);
+        assert_eq!(calc("2 * sin(0) + 1"), 1.0);
+        assert_eq!(calc("abs(1 - sqrt(4 * 4)) * 2"), 6.0);
+        assert_eq!(calc("ln(exp(2))"), 2.0);
+        // A call binds tighter than any operator around it.
+        assert_eq!(calc("sqrt(4)^2"), 4.0);
+        assert_eq!(calc("-sqrt(4)"), -2.0);
+        assert_eq!(calc("2^abs(-3)"), 8.0);
+        assert_eq!(calc("sqrt( 9 )"), 3.0);
+    }
+
+    #[test]
+    fn test_function_errors() {
+        assert_eq!(
+            Calculator::calculate("foo(1)"),
+            Err(Error::UnknownFunction {
+                name: "foo".to_string(),
+                pos: 0
+            })
+        );
+        assert_eq!(
+            Calculator::calculate("2 + x (1)"),
+            Err(Error::UnknownFunction {
+                name: "x".to_string(),
+                pos: 4
+            })
+        );
+        // Without the call it's a variable.
+        assert_eq!(
+            Calculator::calculate("2 + x"),
+            Err(Error::UndefinedVariable("x".to_string()))
+        );
+        assert_eq!(
+            Calculator::calculate("SQRT(4)"),
+            Err(Error::UnknownFunction {
+                name: "SQRT".to_string(),
+                pos: 0
+            })
+        );
+        // Only a call is a function; a bare name is a variable.
+        assert_eq!(
+            Calculator::calculate("sqrt + 1"),
+            Err(Error::UndefinedVariable("sqrt".to_string()))
+        );
+        for (expr, pos) in [
+            ("sqrt 4", 5),
+            ("sqrt()", 5),
+            ("2 sqrt(4)", 2),
+            ("sqrt(4) 2", 8),
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::UnexpectedToken { pos }),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::calculate("sqrt(1 / 0)"),
+            Err(Error::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_several_arguments() {
+        assert_eq!(calc("max(1, 2+3, 4)"), 5.0);
+        assert_eq!(calc("min(1, 2+3, 4)"), 1.0);
+        assert_eq!(calc("max(-2)"), -2.0);
+        assert_eq!(calc("pow(2, 10)"), 1024.0);
+        assert_eq!(calc("pow(2, -1) * 4"), 2.0);
+        assert_eq!(calc("div(7, 2)"), 3.5);
+        assert_eq!(calc("floordiv(-7, 2)"), -4.0);
+        assert_eq!(calc("div(1, 4) * floordiv(9, 2)"), 1.0);
+        assert_eq!(calc("max(min(1,2), 3)"), 3.0);
+        assert_eq!(calc("min(max(1, 2), pow(3, 0), sqrt(4))"), 1.0);
+        assert_eq!(calc("pow(max(2, 1), min(3, 5)) ^ 2"), 64.0);
+        assert_eq!(calc("-max(1, (2), -3)"), -2.0);
+        assert_eq!(calc("max(0 / 1, sqrt(-1))"), 0.0);
+        // A bracketed list is not arguments.
+        assert_eq!(
+            Calculator::calculate("max((1, 2), 3)"),
+            Err(Error::UnexpectedToken { pos: 6 })
+        );
+        for (expr, pos) in [
+            ("max()", 4),
+            ("min(1,)", 6),
+            ("pow(,2)", 4),
+            ("1, 2", 1),
+            ("(1, 2)", 2),
+            ("2 * (3, 4)", 6),
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::UnexpectedToken { pos }),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::calculate("pow(2)"),
+            Err(Error::WrongArity {
+                name: "pow".to_string(),
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            Calculator::calculate("floordiv(1, 2, 3)"),
+            Err(Error::WrongArity {
+                name: "floordiv".to_string(),
+                expected: 2,
+                found: 3
+            })
+        );
+        assert_eq!(
+            Calculator::calculate("max(1, sqrt(4, 9))"),
+            Err(Error::WrongArity {
+                name: "sqrt".to_string(),
+                expected: 1,
+                found: 2
+            })
+        );
+        assert_eq!(
+            Calculator::calculate("max = 2"),
+            Err(Error::ReservedName {
+                name: "max".to_string(),
+                pos: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(calc("pi"), consts::PI);
+        assert_eq!(format!("{:.5}", calc("pi")), "3.14159");
+        assert_eq!(calc("e"), consts::E);
+        assert_eq!(calc("tau"), consts::TAU);
+        assert_eq!(calc("PI"), consts::PI);
+        assert_eq!(calc("Tau"), consts::TAU);
+        assert_eq!(calc("2 * pi * 5"), 2.0 * consts::PI * 5.0);
+        assert_eq!(calc("e ^ 2"), consts::E.powf(2.0));
+        assert_eq!(calc("-pi"), -consts::PI);
+        assert_eq!(calc("cos(pi)"), -1.0);
+        assert_eq!(calc("ln(e)"), 1.0);
+    }
+
+    #[test]
+    fn test_constants_are_not_functions() {
+        let implicit = EvalOptions {
+            implicit_multiplication: true,
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            Calculator::calculate("pi(3)"),
+            Err(Error::UnexpectedToken { pos: 2 })
+        );
+        assert_eq!(
+            Calculator::calculate_with("pi(3)", &implicit),
+            Ok(consts::PI * 3.0)
+        );
+        // A number against a constant is a product only with implicit
+        // multiplication, and otherwise one malformed number.
+        assert_eq!(
+            Calculator::calculate("2pi"),
+            Err(Error::InvalidNumber {
+                literal: "2pi".to_string(),
+                pos: 0
+            })
+        );
+        assert_eq!(
+            Calculator::calculate_with("2pi", &implicit),
+            Ok(consts::TAU)
+        );
+        assert_eq!(
+            Calculator::calculate_with("2(pi)", &implicit),
+            Ok(consts::TAU)
+        );
+        assert_eq!(
+            Calculator::calculate("pie"),
+            Err(Error::UndefinedVariable("pie".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_ulp_diff() {
+        assert_eq!(ulp_diff(1.0, 1.0), 0);
+        assert_eq!(ulp_diff(0.0, -0.0), 0);
+        assert_eq!(ulp_diff(1.0, 1.0 + f64::EPSILON), 1);
+        assert_eq!(ulp_diff(1.0 + f64::EPSILON, 1.0), 1);
+        assert_eq!(ulp_diff(f64::from_bits(1), -f64::from_bits(1)), 2);
+        assert_eq!(ulp_diff(f64::MAX, f64::INFINITY), 1);
+        assert_eq!(ulp_diff(f64::NAN, f64::NAN), u64::MAX);
+    }
+
+    #[test]
+    fn test_constants_correctly_rounded() {
+        // Nearest f64 to π, e and 2π, as bit patterns.
+        assert_eq!(calc("pi").to_bits(), 0x4009_21fb_5444_2d18);
+        assert_eq!(calc("e").to_bits(), 0x4005_bf0a_8b14_5769);
+        assert_eq!(calc("tau").to_bits(), 0x4019_21fb_5444_2d18);
+    }
+
+    #[test]
+    fn test_reference_points() {
+        // Correctly rounded results for the f64 arguments these
+        // expressions produce (`pi / 6` is not the f64 nearest π/6),
+        // worked out at 300 bits of precision.
+        let cases = [
+            ("sin(pi / 6)", 0x3fdf_ffff_ffff_ffff, 1),
+            ("sin(pi / 4)", 0x3fe6_a09e_667f_3bcc, 1),
+            ("sin(pi / 2)", 0x3ff0_0000_0000_0000, 1),
+            ("cos(pi / 3)", 0x3fe0_0000_0000_0001, 1),
+            ("tan(pi / 4)", 0x3fef_ffff_ffff_ffff, 1),
+            ("ln(2)", 0x3fe6_2e42_fefa_39ef, 1),
+            ("log10(2)", 0x3fd3_4413_509f_79ff, 1),
+            ("exp(1)", 0x4005_bf0a_8b14_5769, 1),
+            ("cbrt(2)", 0x3ff4_28a2_f98d_728b, 1),
+            ("sqrt(2)", 0x3ff6_a09e_667f_3bcd, 0),
+            ("abs(-2.5)", 0x4004_0000_0000_0000, 0),
+            ("pow(2, 0.5)", 0x3ff6_a09e_667f_3bcd, 1),
+        ];
+        for (expr, reference, max_ulps) in cases {
+            let value = calc(expr);
+            let off = ulp_diff(value, f64::from_bits(reference));
+            assert!(off <= max_ulps, "{} = {:e}, {} ulp off", expr, value, off);
+        }
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for function in Function::ALL {
+            assert_eq!(Function::from_name(function.name()), Some(function));
+        }
+        assert_eq!(Function::from_name("log"), None);
+    }
+
+    #[test]
+    fn test_powers_of_negative_bases() {
+        let real_roots = EvalOptions {
+            real_roots: true,
+            ..EvalOptions::default()
+        };
+        let domain_error = |reason| {
+            Err(Error::DomainError {
+                operation: "power",
+                reason,
+            })
+        };
+        let no_root = domain_error("a negative base needs a whole exponent or an odd root");
+        let odd_root = domain_error("a negative base's odd root is only taken with real roots on");
+        // Each exponent of -8, then the result without and with real roots.
+        let cases = [
+            // Whole exponents, exactly and with their sign.
+            ("3", Ok(-512.0), Ok(-512.0)),
+            ("2", Ok(64.0), Ok(64.0)),
+            ("-1", Ok(-0.125), Ok(-0.125)),
+            ("0", Ok(1.0), Ok(1.0)),
+            // Odd roots, as close as a positive base's: `8 ^ (2/3)` isn't
+            // quite 4 either.
+            ("(1/3)", odd_root.clone(), Ok(-2.0)),
+            ("(2/3)", odd_root.clone(), Ok(8f64.powf(2.0 / 3.0))),
+            ("(-1/3)", odd_root.clone(), Ok(-0.5)),
+            ("(5/3)", odd_root.clone(), Ok(-(8f64.powf(5.0 / 3.0)))),
+            // Even roots.
+            ("0.5", no_root.clone(), no_root.clone()),
+            ("(3/4)", no_root.clone(), no_root.clone()),
+            // Irrational, or with too large a denominator.
+            ("pi", no_root.clone(), no_root.clone()),
+            ("sqrt(2)", no_root.clone(), no_root.clone()),
+            ("0.333", no_root.clone(), no_root.clone()),
+        ];
+        for (exponent, without, with) in cases {
+            for expr in [
+                format!("(-8) ^ {}", exponent),
+                format!("pow(-8, {})", exponent),
+            ] {
+                assert_eq!(Calculator::calculate(&expr), without, "{}", expr);
+                assert_eq!(
+                    Calculator::calculate_with(&expr, &real_roots),
+                    with,
+                    "{}",
+                    expr
+                );
+            }
+        }
+        assert_eq!(Calculator::calculate("(-2) ^ 3"), Ok(-8.0));
+        assert_eq!(Calculator::calculate("(-32) ^ 0.2"), odd_root);
+        assert_eq!(
+            Calculator::calculate_with("(-32) ^ 0.2", &real_roots),
+            Ok(-2.0)
+        );
+        // Only a negative base is checked.
+        assert_eq!(Calculator::calculate("8 ^ (1/3)"), Ok(2.0));
+        assert!(Calculator::calculate("(1e999 * 0) ^ 0.5").unwrap().is_nan());
+        assert_eq!(Calculator::calculate("(-0.5) ^ 1e999"), Ok(0.0));
+        let calc = Calculator::builder().real_roots(true).build();
+        assert_eq!(calc.calculate("(-27) ^ (1/3)"), Ok(-3.0));
+        assert_eq!(
+            no_root.unwrap_err().to_string(),
+            "power has no real result: a negative base needs a whole exponent or an odd root"
+        );
+    }
+
+    #[test]
+    fn test_ratio_functions() {
+        let domain_error = |operation, reason| Err(Error::DomainError { operation, reason });
+        assert_eq!(calc("pct_change(50, 75)"), 50.0);
+        assert_eq!(calc("pct_change(80, 20)"), -75.0);
+        assert_eq!(calc("pct_change(-50, -25)"), 50.0);
+        assert_eq!(calc("pct_change(-50, -100)"), -100.0);
+        assert_eq!(calc("pct_change(3, 3)"), 0.0);
+        assert_eq!(calc("pct_change(1e-300, 2e-300)"), 100.0);
+        for expr in [
+            "pct_change(0, 5)",
+            "pct_change(-0, 0)",
+            "pct_change(1 - 1, 1)",
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                domain_error("pct_change", "the old value is 0"),
+                "{}",
+                expr
+            );
+        }
+
+        assert_eq!(calc("safe_div(7, 2)"), 3.5);
+        assert_eq!(calc("safe_div(-1, 4)"), -0.25);
+        // A zero denominator is 0, whatever the zero-division policy.
+        let ieee = EvalOptions {
+            zero_division: crate::ZeroDivision::Ieee,
+            ..EvalOptions::default()
+        };
+        for expr in [
+            "safe_div(7, 0)",
+            "safe_div(0, 0)",
+            "safe_div(-7, -0)",
+            "safe_div(7, 2 - 2)",
+        ] {
+            assert_eq!(calc(expr), 0.0, "{}", expr);
+            assert_eq!(Calculator::calculate_with(expr, &ieee), Ok(0.0), "{}", expr);
+        }
+        assert_eq!(calc("safe_div(1, 1e-320)"), f64::INFINITY);
+        // Only the division is safe; its arguments still fail as usual.
+        assert_eq!(
+            Calculator::calculate("safe_div(1 / 0, 2)"),
+            Err(Error::DivisionByZero)
+        );
+
+        assert_eq!(calc("weighted_avg(80, 1, 90, 3)"), 87.5);
+        assert_eq!(calc("weighted_avg(5, 2)"), 5.0);
+        assert_eq!(calc("weighted_avg(10, 0, 20, 1)"), 20.0);
+        // Negative weights are allowed while the total isn't 0.
+        assert_eq!(calc("weighted_avg(10, -1, 20, 2)"), 30.0);
+        for expr in ["weighted_avg(1)", "weighted_avg(1, 2, 3)"] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                domain_error("weighted_avg", "the values and weights must come in pairs"),
+                "{}",
+                expr
+            );
+        }
+        for expr in ["weighted_avg(1, 0)", "weighted_avg(1, 2, 3, -2)"] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                domain_error("weighted_avg", "the weights add up to 0"),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::calculate("weighted_avg()"),
+            Err(Error::UnexpectedToken { pos: 13 })
+        );
+        assert_eq!(
+            Calculator::calculate("safe_div(1)"),
+            Err(Error::WrongArity {
+                name: "safe_div".to_string(),
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(
+            Calculator::calculate("pct_change(0, 1)")
+                .unwrap_err()
+                .to_string(),
+            "pct_change has no real result: the old value is 0"
+        );
+    }
+
+    #[test]
+    fn test_bit_functions() {
+        assert_eq!(calc("bitand(12, 10)"), 8.0);
+        assert_eq!(calc("bitor(12, 10)"), 14.0);
+        assert_eq!(calc("bitxor(12, 10)"), 6.0);
+        assert_eq!(calc("shl(3, 4)"), 48.0);
+        assert_eq!(calc("shr(50, 2)"), 12.0);
+        assert_eq!(calc("shr(50, 100)"), 0.0);
+        assert_eq!(calc("shl(0, 100)"), 0.0);
+        assert_eq!(calc("bitand(0xFF, 0b1010) + bitor(1, 2) * 2"), 16.0);
+        assert_eq!(calc("bitxor(2^52 + 1, 1)"), 2f64.powi(52));
+        assert_eq!(calc("shl(1, 52)"), 2f64.powi(52));
+        // Whole-valued floats count, however they were written.
+        assert_eq!(calc("bitor(1.5 * 2, 4e0)"), 7.0);
+        assert_eq!(calc("bitand(-0, 1)"), 0.0);
+
+        let not_an_integer = |operation| Err(Error::NotAnInteger { operation });
+        for (expr, operation) in [
+            ("bitand(1.5, 1)", "bitand"),
+            ("bitor(1, 0.1 + 0.2)", "bitor"),
+            ("bitxor(2^53, 1)", "bitxor"),
+            ("shl(1, 0.5)", "shl"),
+            ("shr(sqrt(-1), 1)", "shr"),
+            ("bitand(1e999, 1)", "bitand"),
+            // Too big is checked before the sign.
+            ("bitand(-(2^53), 1)", "bitand"),
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                not_an_integer(operation),
+                "{}",
+                expr
+            );
+        }
+        // No two's complement, so no bits for a negative number, and no
+        // reading one by its magnitude either.
+        for (expr, operation) in [
+            ("bitand(-1, 1)", "bitand"),
+            ("bitor(4, -4)", "bitor"),
+            ("shl(1, -1)", "shl"),
+            ("shr(-8, 1)", "shr"),
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::DomainError {
+                    operation,
+                    reason: "a negative number has no bits without two's complement"
+                }),
+                "{}",
+                expr
+            );
+        }
+        for expr in ["shl(1, 53)", "shl(3, 52)", "shl(1, 64)", "shl(1, 2^52)"] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::Overflow),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::calculate("bitand(0.5, 1)")
+                .unwrap_err()
+                .to_string(),
+            "bitand needs whole numbers smaller than 2^53"
+        );
+        assert_eq!(
+            Calculator::calculate("shl(1)"),
+            Err(Error::WrongArity {
+                name: "shl".to_string(),
+                expected: 2,
+                found: 1
+            })
+        );
+    }
+}
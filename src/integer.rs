@@ -0,0 +1,439 @@
+//! Exact whole-number arithmetic, for [`Calculator::calculate_integers`].
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::custom::Registry;
+use crate::environment::ANS;
+use crate::lexer::{literal_text, split_radix};
+use crate::{Calculator, Division, Error, EvalOptions, Function, Operator, Token, ZeroDivision};
+
+/// A result of [`Calculator::calculate_integers`]: `Int` while every step
+/// was exact, `Float` once something promoted it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+}
+
+impl Value {
+    /// The nearest `f64`, which for an `Int` past 2^53 may not be exact.
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Value::Int(n) => n as f64,
+            Value::Float(x) => x,
+        }
+    }
+}
+
+impl From<Value> for f64 {
+    fn from(value: Value) -> Self {
+        value.to_f64()
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+impl Calculator {
+    /// [`Calculator::calculate_with`], but carrying whole numbers as `i64`
+    /// so they stay exact past 2^53, where `f64` starts skipping integers:
+    ///
+    /// ```
+    /// use calculator::{Calculator, Error, EvalOptions, Value};
+    ///
+    /// let options = EvalOptions::default();
+    /// assert_eq!(
+    ///     Calculator::calculate_integers("9007199254740993 + 1", &options),
+    ///     Ok(Value::Int(9007199254740994))
+    /// );
+    /// assert_eq!(
+    ///     Calculator::calculate_integers("2 ^ 63", &options),
+    ///     Err(Error::Overflow)
+    /// );
+    /// assert_eq!(
+    ///     Calculator::calculate_integers("7 / 2 + 1", &options),
+    ///     Ok(Value::Float(4.5))
+    /// );
+    /// ```
+    ///
+    /// A literal without a decimal point or exponent is an `Int`, and `+`,
+    /// `-`, `*`, `//`, `%`, `^` with an exponent of zero or more, `abs`,
+    /// `min`, `max` and `pow` keep `Int`s as `Int`s, with
+    /// [`Error::Overflow`] where the result doesn't fit. `/` depends on
+    /// [`EvalOptions::division`]: an exact quotient stays an `Int`, and an
+    /// inexact one is a `Float` by default, truncated with
+    /// `TruncateWhenBothIntegral`, and [`Error::InexactDivision`] with
+    /// `Exact`.
+    ///
+    /// Everything else, and any step with a `Float` operand, is worked out
+    /// as [`Calculator::calculate_with`] would, giving a `Float`: `2.0 + 1`,
+    /// `50%`, `sqrt(4)`, `pi` and `2 ^ -1` are all `Float`s. A literal too
+    /// big for an `i64` is [`Error::Overflow`].
+    pub fn calculate_integers<T: AsRef<str>>(
+        expr: T,
+        options: &EvalOptions,
+    ) -> Result<Value, Error> {
+        Self::calculate_integers_in(expr.as_ref(), options, &Registry::default())
+    }
+
+    /// [`Calculator::calculate_integers`], with what `registry` adds to the
+    /// language.
+    pub(crate) fn calculate_integers_in(
+        expr: &str,
+        options: &EvalOptions,
+        registry: &Registry,
+    ) -> Result<Value, Error> {
+        let (tokens, spans, _) = Self::parse_in(expr, options, false, registry)?;
+        let (postfix, origins) = Self::try_to_postfix_with_origins(tokens)?;
+        let chars: Vec<char> = expr.chars().collect();
+        let mut variables: HashMap<String, Value> = HashMap::new();
+        let mut stack: Vec<Value> = Vec::with_capacity(postfix.len() / 2 + 1);
+
+        for (token, origin) in postfix.into_iter().zip(origins) {
+            // An `f64` has already rounded a long literal, so whole
+            // numbers are read again from the text they were written as.
+            let text = spans.get(origin).map(|span| {
+                let written: String = chars[span.start..span.end].iter().collect();
+                literal_text(&written)
+            });
+            let value = match token {
+                Token::Number(n) => literal(n, text.as_deref())?,
+                Token::Identifier(name) => match variables.get(&*name) {
+                    Some(value) => *value,
+                    None if &*name == ANS => return Err(Error::NoPreviousResult),
+                    None => return Err(Error::UndefinedVariable(name.into_string())),
+                },
+                Token::Assign(name) => match stack.last() {
+                    Some(value) => {
+                        variables.insert(name.into_string(), *value);
+                        continue;
+                    }
+                    None => return Err(Error::InvalidExpression),
+                },
+                token => {
+                    let arity = match &token {
+                        Token::Op(op) if op.is_unary() => 1,
+                        Token::Op(_) | Token::Custom { .. } => 2,
+                        Token::Function { args, .. } | Token::Call { args, .. } => *args as usize,
+                        _ => return Err(Error::InvalidExpression),
+                    };
+                    if arity == 0 || stack.len() < arity {
+                        return Err(Error::InvalidExpression);
+                    }
+                    let operands = stack.split_off(stack.len() - arity);
+                    let ints: Option<Vec<i64>> = operands
+                        .iter()
+                        .map(|value| match value {
+                            Value::Int(n) => Some(*n),
+                            Value::Float(_) => None,
+                        })
+                        .collect();
+                    match ints.and_then(|ints| exact(&token, &ints, options)) {
+                        Some(result) => Value::Int(result?),
+                        None => {
+                            let mut floats: Vec<f64> =
+                                operands.iter().map(|v| v.to_f64()).collect();
+                            let mut unused = HashMap::new();
+                            Self::apply_token(&mut floats, &token, options, &mut unused, registry)?;
+                            Value::Float(floats.pop().expect("one result per token"))
+                        }
+                    }
+                }
+            };
+            stack.push(value);
+        }
+
+        match stack.len() {
+            1 => Ok(stack.pop().unwrap()),
+            _ => Err(Error::InvalidExpression),
+        }
+    }
+}
+
+/// A number token as a [`Value`]: an `Int` if `text`, what it was written
+/// as, is all digits or has a radix prefix.
+fn literal(n: f64, text: Option<&str>) -> Result<Value, Error> {
+    if let Some((radix, digits)) = text.and_then(split_radix) {
+        return i64::from_str_radix(digits, radix)
+            .map(Value::Int)
+            .map_err(|_| Error::Overflow);
+    }
+    match text {
+        Some(text) if !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit()) => {
+            text.parse().map(Value::Int).map_err(|_| Error::Overflow)
+        }
+        _ => Ok(Value::Float(n)),
+    }
+}
+
+/// `token` applied to whole-number `operands`, or `None` where the result
+/// is a `Float`, worked out as usual.
+fn exact(token: &Token, operands: &[i64], options: &EvalOptions) -> Option<Result<i64, Error>> {
+    let overflow = |result: Option<i64>| Some(result.ok_or(Error::Overflow));
+    match (token, operands) {
+        (Token::Op(Operator::Negate), [x]) => overflow(x.checked_neg()),
+        (Token::Op(Operator::Add), [l, r]) => overflow(l.checked_add(*r)),
+        (Token::Op(Operator::Subtract), [l, r]) => overflow(l.checked_sub(*r)),
+        (Token::Op(Operator::Multiply), [l, r]) => overflow(l.checked_mul(*r)),
+        (Token::Op(Operator::Power), [l, r])
+        | (
+            Token::Function {
+                function: Function::Pow,
+                ..
+            },
+            [l, r],
+        ) => power(*l, *r),
+        (Token::Op(Operator::Divide | Operator::FloorDivide | Operator::Modulo), [_, 0])
+        | (
+            Token::Function {
+                function: Function::Div | Function::FloorDiv,
+                ..
+            },
+            [_, 0],
+        ) => {
+            match options.zero_division {
+                ZeroDivision::Error => Some(Err(Error::DivisionByZero)),
+                // Only a float can be infinite or NaN.
+                ZeroDivision::Ieee => None,
+            }
+        }
+        (Token::Op(Operator::Divide), [l, r]) => {
+            let whole = l.wrapping_rem(*r) == 0;
+            match options.division {
+                Division::FloatAlways if !whole => None,
+                Division::Exact if !whole => Some(Err(Error::InexactDivision)),
+                _ => overflow(l.checked_div(*r)),
+            }
+        }
+        // Whole only when it divides exactly, whatever `options.division` says.
+        (
+            Token::Function {
+                function: Function::Div,
+                ..
+            },
+            [l, r],
+        ) => match l.wrapping_rem(*r) {
+            0 => overflow(l.checked_div(*r)),
+            _ => None,
+        },
+        (Token::Op(Operator::FloorDivide), [l, r])
+        | (
+            Token::Function {
+                function: Function::FloorDiv,
+                ..
+            },
+            [l, r],
+        ) => {
+            let quotient = l.checked_div(*r);
+            let rounds_down = l.wrapping_rem(*r) != 0 && (*l < 0) != (*r < 0);
+            overflow(quotient.map(|q| if rounds_down { q - 1 } else { q }))
+        }
+        // Only `i64::MIN % -1` overflows, and its remainder is 0 anyway.
+        (Token::Op(Operator::Modulo), [l, r]) => Some(Ok(l.wrapping_rem(*r))),
+        (Token::Function { function, .. }, _) => match function {
+            Function::Abs => overflow(operands[0].checked_abs()),
+            Function::Min => operands.iter().copied().min().map(Ok),
+            Function::Max => operands.iter().copied().max().map(Ok),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `base ^ exponent`, or `None` for a negative exponent.
+fn power(base: i64, exponent: i64) -> Option<Result<i64, Error>> {
+    if exponent < 0 {
+        return None;
+    }
+    Some(match (base, u32::try_from(exponent)) {
+        (_, Ok(exponent)) => base.checked_pow(exponent).ok_or(Error::Overflow),
+        (0 | 1, Err(_)) => Ok(base),
+        (-1, Err(_)) => Ok(if exponent % 2 == 0 { 1 } else { -1 }),
+        _ => Err(Error::Overflow),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calc(expr: &str) -> Result<Value, Error> {
+        Calculator::calculate_integers(expr, &EvalOptions::default())
+    }
+
+    fn with_division(division: Division) -> impl Fn(&str) -> Result<Value, Error> {
+        move |expr| {
+            let options = EvalOptions {
+                division,
+                ..EvalOptions::default()
+            };
+            Calculator::calculate_integers(expr, &options)
+        }
+    }
+
+    #[test]
+    fn test_exact_large_integers() {
+        // The float API rounds both operands and the sum.
+        assert_eq!(
+            Calculator::calculate("9007199254740993 + 1"),
+            Ok(9007199254740992.0)
+        );
+        assert_eq!(
+            calc("9007199254740993 + 1"),
+            Ok(Value::Int(9007199254740994))
+        );
+        assert_eq!(
+            calc("9223372036854775807 - 1"),
+            Ok(Value::Int(9223372036854775806))
+        );
+        assert_eq!(
+            calc("3037000499 * 3037000499"),
+            Ok(Value::Int(9223372030926249001))
+        );
+        assert_eq!(calc("2 ^ 62 + (2 ^ 62 - 1)"), Ok(Value::Int(i64::MAX)));
+        assert_eq!(calc("-9223372036854775807 - 1"), Ok(Value::Int(i64::MIN)));
+        assert_eq!(calc("pow(3, 39)"), Ok(Value::Int(4052555153018976267)));
+        assert_eq!(
+            calc("abs(-5) + max(1, 7, 3) - min(4, 2)"),
+            Ok(Value::Int(10))
+        );
+        assert_eq!(calc("|-12|"), Ok(Value::Int(12)));
+        assert_eq!(calc("(x = 2 ^ 60) + x"), Ok(Value::Int(1 << 61)));
+        assert_eq!(calc("１２ * 2"), Ok(Value::Int(24)));
+        assert_eq!(calc("1 ^ 10000000000"), Ok(Value::Int(1)));
+        assert_eq!(calc("(-1) ^ 10000000001"), Ok(Value::Int(-1)));
+        assert_eq!(calc("0x7FFFFFFFFFFFFFFF"), Ok(Value::Int(i64::MAX)));
+        assert_eq!(calc("0xFF - 0o17 + 0b1"), Ok(Value::Int(241)));
+        assert_eq!(
+            calc("9_007_199_254_740_993 + 1"),
+            Ok(Value::Int(9007199254740994))
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        for expr in [
+            "9223372036854775807 + 1",
+            "-9223372036854775807 - 2",
+            "4294967296 * 4294967296",
+            "2 ^ 63",
+            "pow(10, 19)",
+            "2 ^ 10000000000",
+            "-(-9223372036854775807 - 1)",
+            "|-9223372036854775807 - 1|",
+            "(-9223372036854775807 - 1) // -1",
+            "9223372036854775808",
+            "0x8000000000000000",
+            "99999999999999999999 * 0",
+        ] {
+            assert_eq!(calc(expr), Err(Error::Overflow), "{}", expr);
+        }
+        // Promoted to floats first, they don't overflow.
+        assert_eq!(
+            calc("9223372036854775807.0 + 1"),
+            Ok(Value::Float(9.223372036854776e18))
+        );
+        assert_eq!(calc("2.0 ^ 63"), Ok(Value::Float(2f64.powi(63))));
+    }
+
+    #[test]
+    fn test_mixed_promotion() {
+        let cases = [
+            ("1 + 2.5", Value::Float(3.5)),
+            ("2.0 * 3", Value::Float(6.0)),
+            ("1e3 + 1", Value::Float(1001.0)),
+            ("2 ^ -1", Value::Float(0.5)),
+            ("50%", Value::Float(0.5)),
+            ("sqrt(16) + 1", Value::Float(5.0)),
+            ("pi * 0", Value::Float(0.0)),
+            ("max(1, 2.5)", Value::Float(2.5)),
+            ("pow(2, 0.5) ^ 2", Value::Float(2f64.sqrt().powi(2))),
+            // Only the steps after the float promote.
+            ("(2 ^ 60 + 1) * 1.0", Value::Float(2f64.powi(60))),
+            ("2 ^ 60 + 1 - 1.0", Value::Float(2f64.powi(60))),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(calc(expr), Ok(expected), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_division() {
+        let float = with_division(Division::FloatAlways);
+        assert_eq!(float("12 / 4"), Ok(Value::Int(3)));
+        assert_eq!(float("7 / 2"), Ok(Value::Float(3.5)));
+        assert_eq!(
+            float("9007199254740993 / 1"),
+            Ok(Value::Int(9007199254740993))
+        );
+
+        let truncate = with_division(Division::TruncateWhenBothIntegral);
+        assert_eq!(truncate("7 / 2"), Ok(Value::Int(3)));
+        assert_eq!(truncate("-7 / 2"), Ok(Value::Int(-3)));
+        assert_eq!(truncate("7.5 / 2"), Ok(Value::Float(3.75)));
+
+        let exact = with_division(Division::Exact);
+        assert_eq!(exact("12 / 4"), Ok(Value::Int(3)));
+        assert_eq!(exact("7 / 2"), Err(Error::InexactDivision));
+        assert_eq!(exact("7.0 / 2"), Err(Error::InexactDivision));
+        assert_eq!(exact("7.5 / 2"), Ok(Value::Float(3.75)));
+
+        for div in [float, truncate, exact] {
+            assert_eq!(div("-7 // 2"), Ok(Value::Int(-4)));
+            assert_eq!(div("7 // -2"), Ok(Value::Int(-4)));
+            assert_eq!(div("-8 // 2"), Ok(Value::Int(-4)));
+            assert_eq!(div("-7 % 3"), Ok(Value::Int(-1)));
+            assert_eq!(div("1 / 0"), Err(Error::DivisionByZero));
+            assert_eq!(div("1 % 0"), Err(Error::DivisionByZero));
+            assert_eq!(div("7 % (-2)"), Ok(Value::Int(1)));
+            assert_eq!(div("(-9223372036854775807 - 1) % (-1)"), Ok(Value::Int(0)));
+            assert_eq!(div("-7 % |-3|"), Ok(Value::Int(-1)));
+            // The function spellings divide as `Division::FloatAlways` does.
+            assert_eq!(div("div(12, 4)"), Ok(Value::Int(3)));
+            assert_eq!(div("div(-7, 2)"), Ok(Value::Float(-3.5)));
+            assert_eq!(div("floordiv(-7, 2)"), Ok(Value::Int(-4)));
+            assert_eq!(div("div(1, 0)"), Err(Error::DivisionByZero));
+        }
+        let ieee = EvalOptions {
+            zero_division: ZeroDivision::Ieee,
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            Calculator::calculate_integers("-1 // 0", &ieee),
+            Ok(Value::Float(f64::NEG_INFINITY))
+        );
+    }
+
+    #[test]
+    fn test_errors_as_in_float_mode() {
+        assert_eq!(calc("ans"), Err(Error::NoPreviousResult));
+        assert_eq!(
+            calc("y + 1"),
+            Err(Error::UndefinedVariable("y".to_string()))
+        );
+        for expr in ["1 +", "()", "2 @ 3", "1 000"] {
+            assert_eq!(
+                calc(expr).map_err(|e| e.kind()),
+                Calculator::calculate(expr)
+                    .map(Value::Float)
+                    .map_err(|e| e.kind()),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_value() {
+        assert_eq!(Value::Int(9007199254740993).to_string(), "9007199254740993");
+        assert_eq!(Value::Float(2.5).to_string(), "2.5");
+        assert_eq!(f64::from(Value::Int(3)), 3.0);
+    }
+}
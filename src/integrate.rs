@@ -0,0 +1,304 @@
+//! Definite integrals of an expression in one variable.
+
+use crate::solve::value_at;
+use crate::{Calculator, Error, StoredExpression, Token};
+
+/// How closely [`Calculator::integrate_with`] and
+/// [`StoredExpression::integrate`] work an integral out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntegrateOptions {
+    /// The absolute error allowed in the result, shared out between the
+    /// pieces the range is split into.
+    pub tolerance: f64,
+    /// How many times a piece may be halved before giving up with
+    /// [`Error::NoConvergence`]; the work can double with each.
+    pub max_depth: usize,
+}
+
+impl Default for IntegrateOptions {
+    fn default() -> Self {
+        IntegrateOptions {
+            tolerance: 1e-10,
+            max_depth: 30,
+        }
+    }
+}
+
+/// How far, as a share of the piece, a sample that fails moves inwards to
+/// try again. The value found there stands in for the end or midpoint
+/// through every halving after, so it must be close enough not to skew
+/// them.
+const NUDGE: f64 = 1.0 / (1u64 << 40) as f64;
+
+impl Calculator {
+    /// [`Calculator::integrate_with`] under the default
+    /// [`IntegrateOptions`].
+    ///
+    /// ```
+    /// use calculator::Calculator;
+    ///
+    /// let third = Calculator::integrate("x*x", "x", 0.0, 1.0).unwrap();
+    /// assert!((third - 1.0 / 3.0).abs() < 1e-12);
+    /// ```
+    pub fn integrate<T: AsRef<str>>(expr: T, var: &str, a: f64, b: f64) -> Result<f64, Error> {
+        Self::integrate_with(expr, var, a, b, &IntegrateOptions::default())
+    }
+
+    /// The integral of `expr` over `var` from `a` to `b`, by adaptive
+    /// Simpson's rule: a piece whose estimate changes too much when halved
+    /// is halved again, until the estimates agree within its share of
+    /// [`IntegrateOptions::tolerance`]. From `b` down to `a` is the
+    /// negative of from `a` to `b`.
+    ///
+    /// A sample where `expr` fails, as `sin(x)/x` does at `0`, or isn't
+    /// finite, is taken again a little way into the piece, so a removable
+    /// gap does no harm and the halving works in around a singularity. One
+    /// that the integral doesn't survive, as in `1/x` from `-1` to `1`,
+    /// and an integrand swinging too fast to pin down, as `sin(1/x)` does
+    /// near `0`, use up [`IntegrateOptions::max_depth`] and are
+    /// [`Error::NoConvergence`], as are infinite limits. Other errors, such
+    /// as [`Error::UndefinedVariable`] for any variable but `var`, are
+    /// returned as soon as they come up.
+    pub fn integrate_with<T: AsRef<str>>(
+        expr: T,
+        var: &str,
+        a: f64,
+        b: f64,
+        options: &IntegrateOptions,
+    ) -> Result<f64, Error> {
+        let postfix = Self::try_to_postfix(Self::parse(expr)?)?;
+        integrate(&postfix, var, a, b, options)
+    }
+}
+
+impl StoredExpression {
+    /// [`Calculator::integrate_with`] for the compiled expression, without
+    /// parsing it again.
+    ///
+    /// ```
+    /// use calculator::{IntegrateOptions, StoredExpression};
+    ///
+    /// let compiled = StoredExpression::compile("2 * t").unwrap();
+    /// let area = compiled.integrate("t", 0.0, 3.0, &IntegrateOptions::default());
+    /// assert_eq!(area, Ok(9.0));
+    /// ```
+    pub fn integrate(
+        &self,
+        var: &str,
+        a: f64,
+        b: f64,
+        options: &IntegrateOptions,
+    ) -> Result<f64, Error> {
+        integrate(&self.tokens, var, a, b, options)
+    }
+}
+
+fn integrate(
+    postfix: &[Token],
+    var: &str,
+    a: f64,
+    b: f64,
+    options: &IntegrateOptions,
+) -> Result<f64, Error> {
+    if !a.is_finite() || !b.is_finite() {
+        return Err(Error::NoConvergence {
+            method: "integrate",
+        });
+    }
+    if a == b {
+        return Ok(0.0);
+    }
+    let integrand = Integrand { postfix, var };
+    let m = a + (b - a) / 2.0;
+    let (fa, fb) = (integrand.sample(a, m)?, integrand.sample(b, m)?);
+    let fm = integrand.sample(m, a)?;
+    let whole = simpson(a, b, fa, fm, fb);
+    integrand.adapt(
+        (a, fa),
+        (m, fm),
+        (b, fb),
+        whole,
+        options.tolerance,
+        options.max_depth,
+    )
+}
+
+/// A sample: where, and the value there.
+type Point = (f64, f64);
+
+struct Integrand<'a> {
+    postfix: &'a [Token],
+    var: &'a str,
+}
+
+impl Integrand<'_> {
+    /// The integral over `[a, b]`, `whole` being its Simpson estimate with
+    /// the midpoint `m`.
+    fn adapt(
+        &self,
+        (a, fa): Point,
+        (m, fm): Point,
+        (b, fb): Point,
+        whole: f64,
+        tolerance: f64,
+        depth: usize,
+    ) -> Result<f64, Error> {
+        let (left_m, right_m) = (a + (m - a) / 2.0, m + (b - m) / 2.0);
+        let (f_left, f_right) = (self.sample(left_m, a)?, self.sample(right_m, b)?);
+        let left = simpson(a, m, fa, f_left, fm);
+        let right = simpson(m, b, fm, f_right, fb);
+        let error = left + right - whole;
+        if error.abs() <= 15.0 * tolerance {
+            return Ok(left + right + error / 15.0);
+        }
+        // Nothing left to halve, or no more halvings allowed.
+        if depth == 0 || left_m == a || right_m == b || !error.is_finite() {
+            return Err(Error::NoConvergence {
+                method: "integrate",
+            });
+        }
+        let tolerance = tolerance / 2.0;
+        let left = self.adapt(
+            (a, fa),
+            (left_m, f_left),
+            (m, fm),
+            left,
+            tolerance,
+            depth - 1,
+        )?;
+        let right = self.adapt(
+            (m, fm),
+            (right_m, f_right),
+            (b, fb),
+            right,
+            tolerance,
+            depth - 1,
+        )?;
+        Ok(left + right)
+    }
+
+    /// The integrand at `x`, or where it fails or isn't finite there, a
+    /// little way towards `inside`.
+    fn sample(&self, x: f64, inside: f64) -> Result<f64, Error> {
+        if let Some(y) = value_at(self.postfix, self.var, x)? {
+            return Ok(y);
+        }
+        let nudged = x + (inside - x) * NUDGE;
+        match value_at(self.postfix, self.var, nudged)? {
+            Some(y) => Ok(y),
+            None => Err(Error::NoConvergence {
+                method: "integrate",
+            }),
+        }
+    }
+}
+
+/// Simpson's rule over `[a, b]`, from the values at the ends and midpoint.
+fn simpson(a: f64, b: f64, fa: f64, fm: f64, fb: f64) -> f64 {
+    (b - a) / 6.0 * (fa + 4.0 * fm + fb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integrate(expr: &str, a: f64, b: f64) -> Result<f64, Error> {
+        Calculator::integrate(expr, "x", a, b)
+    }
+
+    fn assert_integral(expr: &str, a: f64, b: f64, expected: f64) {
+        let value = integrate(expr, a, b).unwrap();
+        assert!(
+            (value - expected).abs() <= 1e-9,
+            "{} from {} to {}: {}, not {}",
+            expr,
+            a,
+            b,
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_polynomials() {
+        assert_integral("x*x", 0.0, 1.0, 1.0 / 3.0);
+        assert_integral("x*x", 1.0, 0.0, -1.0 / 3.0);
+        assert_integral("3*x^2 - 2*x + 1", -1.0, 2.0, 9.0);
+        assert_integral("x^5 - x", 0.0, 2.0, 32.0 / 3.0 - 2.0);
+        assert_integral("x^4", -1.0, 1.0, 0.4);
+        assert_eq!(integrate("x", 2.0, 2.0), Ok(0.0));
+        assert_eq!(integrate("7", 0.0, 3.0), Ok(21.0));
+    }
+
+    #[test]
+    fn test_transcendental() {
+        let pi = std::f64::consts::PI;
+        assert_integral("1/(1+x^2)", 0.0, 1.0, pi / 4.0);
+        assert_integral("sin(x)", 0.0, pi, 2.0);
+        assert_integral("exp(x)", 0.0, 1.0, std::f64::consts::E - 1.0);
+        assert_integral("1/x", 1.0, std::f64::consts::E, 1.0);
+    }
+
+    #[test]
+    fn test_bad_samples() {
+        // Undefined at an end or the middle, but only there.
+        assert_integral("sin(x)/x", 0.0, 1.0, 0.946_083_070_367_183);
+        assert_integral("sin(x)/x", -1.0, 1.0, 2.0 * 0.946_083_070_367_183);
+        assert_integral("x + (x - 1)/(x - 1)", 0.0, 2.0, 4.0);
+        let no_convergence = Err(Error::NoConvergence {
+            method: "integrate",
+        });
+        assert_eq!(integrate("1/x", -1.0, 1.0), no_convergence);
+        assert_eq!(integrate("1/(x - 0.3)", 0.0, 1.0), no_convergence);
+        assert_eq!(integrate("x ^ 0.5", -2.0, -1.0), no_convergence);
+        assert_eq!(integrate("x", 0.0, f64::INFINITY), no_convergence);
+    }
+
+    #[test]
+    fn test_oscillating() {
+        assert_eq!(
+            integrate("sin(1/x)", 0.0, 1.0),
+            Err(Error::NoConvergence {
+                method: "integrate"
+            })
+        );
+        let shallow = IntegrateOptions {
+            max_depth: 3,
+            ..IntegrateOptions::default()
+        };
+        assert_eq!(
+            Calculator::integrate_with("sin(20*x)", "x", 0.0, 3.0, &shallow),
+            Err(Error::NoConvergence {
+                method: "integrate"
+            })
+        );
+        let loose = IntegrateOptions {
+            tolerance: 1e-3,
+            ..IntegrateOptions::default()
+        };
+        let value = Calculator::integrate_with("sin(20*x)", "x", 0.0, 3.0, &loose).unwrap();
+        assert!(
+            (value - (1.0 - 60f64.cos()) / 20.0).abs() < 1e-3,
+            "{}",
+            value
+        );
+    }
+
+    #[test]
+    fn test_compiled() {
+        let compiled = StoredExpression::compile("1/(1+t^2)").unwrap();
+        let options = IntegrateOptions::default();
+        let quarter = compiled.integrate("t", 0.0, 1.0, &options).unwrap();
+        assert!((quarter - std::f64::consts::FRAC_PI_4).abs() < 1e-10);
+        assert_eq!(
+            compiled.integrate("x", 0.0, 1.0, &options),
+            Err(Error::UndefinedVariable("t".to_string()))
+        );
+        // The same as parsing it each time.
+        let loaded = StoredExpression::load(&compiled.save()).unwrap();
+        assert_eq!(
+            loaded.integrate("t", -2.0, 3.0, &options),
+            Calculator::integrate_with("1/(1+t^2)", "t", -2.0, 3.0, &options)
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Minimal JSON string encoding shared by the machine-readable renderings.
+
+/// Quotes and escapes `s` as a JSON string literal.
+pub(crate) fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Encodes `value` under `key` as one or more object fields. JSON has no
+/// infinity or NaN, so non-finite values become `null` with a `"special"`
+/// field (`"infinity"`, `"-infinity"` or `"nan"`) naming what was lost.
+pub(crate) fn number_field(key: &str, value: f64) -> String {
+    if value.is_finite() {
+        format!("{}:{}", string(key), value)
+    } else {
+        let special = if value.is_nan() {
+            "nan"
+        } else if value > 0.0 {
+            "infinity"
+        } else {
+            "-infinity"
+        };
+        format!("{}:null,\"special\":{}", string(key), string(special))
+    }
+}
+
+/// Strict parser used by tests to prove the renderings are valid JSON.
+#[cfg(test)]
+pub(crate) mod strict {
+    #[derive(Debug, PartialEq)]
+    pub enum Value {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Value>),
+        Object(Vec<(String, Value)>),
+    }
+
+    impl Value {
+        pub fn get(&self, key: &str) -> Option<&Value> {
+            match self {
+                Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+                _ => None,
+            }
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<Value, String> {
+        let chars: Vec<char> = input.chars().collect();
+        let mut pos = 0;
+        let value = parse_value(&chars, &mut pos)?;
+        skip_whitespace(&chars, &mut pos);
+        if pos != chars.len() {
+            return Err(format!("trailing input at {}", pos));
+        }
+        Ok(value)
+    }
+
+    fn skip_whitespace(chars: &[char], pos: &mut usize) {
+        while *pos < chars.len() && matches!(chars[*pos], ' ' | '\t' | '\n' | '\r') {
+            *pos += 1;
+        }
+    }
+
+    fn expect(chars: &[char], pos: &mut usize, c: char) -> Result<(), String> {
+        if chars.get(*pos) == Some(&c) {
+            *pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at {}", c, pos))
+        }
+    }
+
+    fn parse_value(chars: &[char], pos: &mut usize) -> Result<Value, String> {
+        skip_whitespace(chars, pos);
+        match chars.get(*pos) {
+            Some('{') => parse_object(chars, pos),
+            Some('[') => parse_array(chars, pos),
+            Some('"') => parse_string(chars, pos).map(Value::String),
+            Some('-' | '0'..='9') => parse_number(chars, pos),
+            Some(_) => {
+                for (word, value) in [
+                    ("null", Value::Null),
+                    ("true", Value::Bool(true)),
+                    ("false", Value::Bool(false)),
+                ] {
+                    let end = *pos + word.len();
+                    if end <= chars.len() && chars[*pos..end].iter().copied().eq(word.chars()) {
+                        *pos = end;
+                        return Ok(value);
+                    }
+                }
+                Err(format!("unexpected character at {}", pos))
+            }
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn parse_object(chars: &[char], pos: &mut usize) -> Result<Value, String> {
+        expect(chars, pos, '{')?;
+        let mut fields = Vec::new();
+        skip_whitespace(chars, pos);
+        if chars.get(*pos) == Some(&'}') {
+            *pos += 1;
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            skip_whitespace(chars, pos);
+            let key = parse_string(chars, pos)?;
+            skip_whitespace(chars, pos);
+            expect(chars, pos, ':')?;
+            fields.push((key, parse_value(chars, pos)?));
+            skip_whitespace(chars, pos);
+            match chars.get(*pos) {
+                Some(',') => *pos += 1,
+                Some('}') => {
+                    *pos += 1;
+                    return Ok(Value::Object(fields));
+                }
+                _ => return Err(format!("expected ',' or '}}' at {}", pos)),
+            }
+        }
+    }
+
+    fn parse_array(chars: &[char], pos: &mut usize) -> Result<Value, String> {
+        expect(chars, pos, '[')?;
+        let mut items = Vec::new();
+        skip_whitespace(chars, pos);
+        if chars.get(*pos) == Some(&']') {
+            *pos += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(parse_value(chars, pos)?);
+            skip_whitespace(chars, pos);
+            match chars.get(*pos) {
+                Some(',') => *pos += 1,
+                Some(']') => {
+                    *pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(format!("expected ',' or ']' at {}", pos)),
+            }
+        }
+    }
+
+    fn parse_string(chars: &[char], pos: &mut usize) -> Result<String, String> {
+        expect(chars, pos, '"')?;
+        let mut out = String::new();
+        loop {
+            let c = *chars.get(*pos).ok_or("unterminated string")?;
+            *pos += 1;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = *chars.get(*pos).ok_or("unterminated escape")?;
+                    *pos += 1;
+                    match escape {
+                        '"' | '\\' | '/' => out.push(escape),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'u' => {
+                            let hex: String = chars
+                                .get(*pos..*pos + 4)
+                                .ok_or("short \\u escape")?
+                                .iter()
+                                .collect();
+                            let code = u32::from_str_radix(&hex, 16).map_err(|e| e.to_string())?;
+                            out.push(char::from_u32(code).ok_or("invalid \\u escape")?);
+                            *pos += 4;
+                        }
+                        _ => return Err(format!("invalid escape at {}", pos)),
+                    }
+                }
+                c if (c as u32) < 0x20 => return Err(format!("control character at {}", pos)),
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn parse_number(chars: &[char], pos: &mut usize) -> Result<Value, String> {
+        let start = *pos;
+        let digits = |pos: &mut usize| {
+            let from = *pos;
+            while *pos < chars.len() && chars[*pos].is_ascii_digit() {
+                *pos += 1;
+            }
+            *pos - from
+        };
+
+        if chars.get(*pos) == Some(&'-') {
+            *pos += 1;
+        }
+        let int_start = *pos;
+        if digits(pos) == 0 || (chars[int_start] == '0' && *pos - int_start > 1) {
+            return Err(format!("invalid number at {}", start));
+        }
+        if chars.get(*pos) == Some(&'.') {
+            *pos += 1;
+            if digits(pos) == 0 {
+                return Err(format!("invalid fraction at {}", start));
+            }
+        }
+        if matches!(chars.get(*pos), Some('e' | 'E')) {
+            *pos += 1;
+            if matches!(chars.get(*pos), Some('+' | '-')) {
+                *pos += 1;
+            }
+            if digits(pos) == 0 {
+                return Err(format!("invalid exponent at {}", start));
+            }
+        }
+
+        let text: String = chars[start..*pos].iter().collect();
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid number at {}", start))
+    }
+
+    #[test]
+    fn test_rejects_non_json_numbers() {
+        assert!(parse("{\"value\":inf}").is_err());
+        assert!(parse("{\"value\":NaN}").is_err());
+        assert!(parse("[01]").is_err());
+        assert!(parse("[1.]").is_err());
+        assert_eq!(
+            parse("[-1.5e3]").unwrap(),
+            Value::Array(vec![Value::Number(-1500.0)])
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! A running total of results, as the REPL's `:ledger` keeps.
+
+use std::fmt::Write;
+
+/// One result in a [`Ledger`], with the total once it was added.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerEntry {
+    pub expression: String,
+    pub value: f64,
+    pub total: f64,
+}
+
+/// Results added up as they come, like a till roll. The total is summed
+/// with Neumaier's compensation, so a long column of cents doesn't drift:
+/// ten `0.1`s make exactly `1`, and `1e100 + 1 - 1e100` keeps its `1`.
+///
+/// ```
+/// use calculator::Ledger;
+///
+/// let mut ledger = Ledger::new();
+/// for _ in 0..10 {
+///     ledger.record("0.1", 0.1);
+/// }
+/// assert_eq!(ledger.total(), 1.0);
+/// assert_eq!(ledger.entries().len(), 10);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ledger {
+    entries: Vec<LedgerEntry>,
+    sum: f64,
+    /// The low-order part of the total that `sum` couldn't hold.
+    compensation: f64,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Ledger::default()
+    }
+
+    /// Adds `value`, the result of `expression`, returning the new total.
+    pub fn record(&mut self, expression: &str, value: f64) -> f64 {
+        let sum = self.sum + value;
+        self.compensation += if self.sum.abs() >= value.abs() {
+            (self.sum - sum) + value
+        } else {
+            (value - sum) + self.sum
+        };
+        self.sum = sum;
+        let total = self.total();
+        self.entries.push(LedgerEntry {
+            expression: expression.to_string(),
+            value,
+            total,
+        });
+        total
+    }
+
+    /// Everything recorded so far added up; `0` when nothing is.
+    pub fn total(&self) -> f64 {
+        self.sum + self.compensation
+    }
+
+    /// What has been recorded, oldest first.
+    pub fn entries(&self) -> &[LedgerEntry] {
+        &self.entries
+    }
+
+    /// Drops every entry and starts the total again from `0`.
+    pub fn clear(&mut self) {
+        *self = Ledger::default();
+    }
+
+    /// The entries as CSV with a header row, `expression,value,total`, one
+    /// line each. An expression with a comma, quote or line break in it is
+    /// quoted, with its quotes doubled.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "expression,value,total\n".to_string();
+        for entry in &self.entries {
+            let _ = writeln!(
+                csv,
+                "{},{},{}",
+                csv_field(&entry.expression),
+                entry.value,
+                entry.total
+            );
+        }
+        csv
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compensated_total() {
+        let mut ledger = Ledger::new();
+        assert_eq!(ledger.total(), 0.0);
+        for value in [1e100, 1.0, -1e100] {
+            ledger.record("x", value);
+        }
+        assert_eq!(ledger.total(), 1.0);
+        assert_eq!(
+            ledger.entries().iter().map(|e| e.total).collect::<Vec<_>>(),
+            [1e100, 1e100, 1.0]
+        );
+        ledger.clear();
+        assert_eq!(ledger, Ledger::new());
+        let mut naive = 0.0;
+        for _ in 0..1000 {
+            ledger.record("0.01", 0.01);
+            naive += 0.01;
+        }
+        assert_ne!(naive, 10.0);
+        assert_eq!(ledger.total(), 10.0);
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut ledger = Ledger::new();
+        ledger.record("2 * 3", 6.0);
+        ledger.record("max(1, 2.5)", 2.5);
+        ledger.record("x = \"1\"", 1.0);
+        assert_eq!(
+            ledger.to_csv(),
+            "expression,value,total\n\
+             2 * 3,6,6\n\
+             \"max(1, 2.5)\",2.5,8.5\n\
+             \"x = \"\"1\"\"\",1,9.5\n"
+        );
+        assert_eq!(Ledger::new().to_csv(), "expression,value,total\n");
+    }
+}
@@ -0,0 +1,1171 @@
+//! Turning source text into tokens.
+
+use crate::adjacency::{self, Glued, Parsed};
+use crate::complex::IMAGINARY_UNIT;
+use crate::custom::{Assoc, Registry};
+use crate::{
+    Calculator, DecimalSeparator, Error, EvalOptions, Function, Normalized, Operator, ParenBalance,
+    ParseWarning, Span, Token, function,
+};
+use crate::{builder, reserved};
+
+/// Tokens, their spans, and warnings about reading them: what normalizing
+/// the input changed, then any `%` that could have been a remainder.
+type Read = (Vec<Token>, Vec<Span>, Vec<ParseWarning>);
+
+/// Length of the exponent suffix (`e3`, `E-9`, `e+10`) starting at
+/// `chars[i]`, if there is one: an `e` or `E`, an optional sign and at least
+/// one digit. A sign without digits is left alone, so `1e-9-2` ends its
+/// literal before the second `-`.
+pub(crate) fn exponent_len(chars: &[char], i: usize) -> Option<usize> {
+    if !matches!(chars.get(i), Some('e' | 'E')) {
+        return None;
+    }
+    let mut j = i + 1;
+    if matches!(chars.get(j), Some('+' | '-')) {
+        j += 1;
+    }
+    let digits = chars[j.min(chars.len())..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    (digits > 0).then_some(j + digits - i)
+}
+
+/// A malformed literal such as `2abc`, split into the number and the name
+/// written against it, for a hint to put a `*` between them. `None` when
+/// the literal is malformed some other way, as `1e5e5` and `0xFG` are.
+pub(crate) fn split_glued(literal: &str) -> Option<(&str, &str)> {
+    let chars: Vec<char> = literal.chars().collect();
+    if radix_prefix(&chars, 0).is_some() {
+        return None;
+    }
+    let mut j = chars
+        .iter()
+        .take_while(|c| c.is_ascii_digit() || matches!(c, '_' | '.' | ','))
+        .count();
+    j += exponent_len(&chars, j).unwrap_or(0);
+    // Everything up to `j` is ASCII, so `j` is also a byte offset.
+    let (number, name) = literal.split_at(j);
+    let name_chars: Vec<char> = name.chars().collect();
+    (j > 0 && builder::is_name(name) && exponent_len(&name_chars, 0).is_none())
+        .then_some((number, name))
+}
+
+/// Where a literal that goes on past its end at `chars[j]`, as `0x1.5`
+/// and `1e5e5` do, really ends: the point, letters, digits and `_` after
+/// it, and a sign straight after an `e`, all belong to it, so the whole of
+/// it is reported as one malformed number.
+fn malformed_end(chars: &[char], mut j: usize, point: char) -> usize {
+    while let Some(&c) = chars.get(j) {
+        let sign = matches!(c, '+' | '-') && matches!(chars[j - 1], 'e' | 'E');
+        if !(c.is_alphanumeric() || c == '_' || c == point || sign) {
+            break;
+        }
+        j += 1;
+    }
+    j
+}
+
+/// The base of the literal starting at `chars[i]`, if it has a radix
+/// prefix: `0x` for hexadecimal, `0o` for octal or `0b` for binary, in
+/// either case.
+pub(crate) fn radix_prefix(chars: &[char], i: usize) -> Option<u32> {
+    if chars.get(i) != Some(&'0') {
+        return None;
+    }
+    match chars.get(i + 1)? {
+        'x' | 'X' => Some(16),
+        'o' | 'O' => Some(8),
+        'b' | 'B' => Some(2),
+        _ => None,
+    }
+}
+
+/// The first `_` in `chars[start..end]` that doesn't sit between two
+/// characters `digit` accepts: `1_000` is fine, `1_`, `1__0` and `1_.5`
+/// aren't.
+fn misplaced_separator(
+    chars: &[char],
+    start: usize,
+    end: usize,
+    digit: fn(&char) -> bool,
+) -> Option<usize> {
+    (start..end).find(|&k| {
+        chars[k] == '_'
+            && !(k > start && k + 1 < end && digit(&chars[k - 1]) && digit(&chars[k + 1]))
+    })
+}
+
+/// A literal's text as [`Calculator::parse`] reads it: normalized,
+/// without `_` separators, and with a decimal comma as a point.
+pub(crate) fn literal_text(written: &str) -> String {
+    Calculator::normalize(written)
+        .text
+        .replace('_', "")
+        .replace(',', ".")
+}
+
+/// The base and digits of a literal written with a radix prefix, such as
+/// `0xFF`.
+pub(crate) fn split_radix(text: &str) -> Option<(u32, &str)> {
+    let prefix: Vec<char> = text.chars().take(2).collect();
+    radix_prefix(&prefix, 0).map(|radix| (radix, &text[2..]))
+}
+
+impl Calculator {
+    pub fn parse<T: AsRef<str>>(expr: T) -> Result<Vec<Token>, Error> {
+        Self::parse_with_spans(expr).map(|(tokens, _)| tokens)
+    }
+
+    /// [`Calculator::parse`], also returning where in the original input
+    /// each token came from.
+    pub fn parse_with_spans<T: AsRef<str>>(expr: T) -> Result<(Vec<Token>, Vec<Span>), Error> {
+        Self::scan(
+            expr.as_ref(),
+            &EvalOptions::default(),
+            false,
+            &Registry::default(),
+        )
+        .map(|(tokens, spans, _)| (tokens, spans))
+    }
+
+    /// A name followed by `(`
+    /// is a call if it is built in or registered. Of `options`, only the
+    /// ones about syntax matter. With `imaginary_unit`, `i` can't be
+    /// assigned and a literal can end in it: `3i` is `3 * i`.
+    pub(crate) fn scan(
+        expr: &str,
+        options: &EvalOptions,
+        imaginary_unit: bool,
+        registry: &Registry,
+    ) -> Result<Parsed, Error> {
+        let (tokens, spans, mut warnings) =
+            Self::read(expr, options, imaginary_unit, registry, false)?;
+        let (mut tokens, spans, ambiguities) =
+            adjacency::resolve(tokens, spans, options.implicit_multiplication)?;
+        count_arguments(&mut tokens, &spans)?;
+        warnings.extend(ambiguities);
+        Ok((tokens, spans, warnings))
+    }
+
+    /// For an `expr` that [`Calculator::parse`] rejects as
+    /// `UnexpectedToken` because one token can't follow another, the
+    /// span of that token, read with the same settings as the parse was.
+    pub(crate) fn rejected_span(
+        expr: &str,
+        options: &EvalOptions,
+        imaginary_unit: bool,
+        registry: &Registry,
+    ) -> Option<Span> {
+        let (tokens, spans, _) = Self::read(expr, options, imaginary_unit, registry, false).ok()?;
+        adjacency::rejected(tokens, spans, options.implicit_multiplication)
+    }
+
+    /// The spans of the tokens in `expr`, read as [`Calculator::parse`]
+    /// would but refusing nothing: a malformed literal is a single span,
+    /// as is a character that starts no token. For showing input that may
+    /// not parse, as [`Calculator::summarize`] does.
+    pub(crate) fn lossy_spans(expr: &str) -> Vec<Span> {
+        let read = Self::read(
+            expr,
+            &EvalOptions::default(),
+            false,
+            &Registry::default(),
+            true,
+        );
+        let (_, mut spans, _) = read.expect("a lossy read refuses nothing");
+        // A bar's `abs(` is two tokens from one character.
+        spans.dedup();
+        spans
+    }
+
+    /// The tokens of `expr` with balanced brackets and bars, before any
+    /// adjacency checks, and the warnings about reading them.
+    ///
+    /// When `lossy`, every error is let through instead, leaving tokens
+    /// only good for their spans: a malformed literal reads as a NaN, a
+    /// stray character as a custom operator.
+    fn read(
+        expr: &str,
+        options: &EvalOptions,
+        imaginary_unit: bool,
+        registry: &Registry,
+        lossy: bool,
+    ) -> Result<Read, Error> {
+        let (point, separator) = match options.decimal_separator {
+            DecimalSeparator::Dot => ('.', ','),
+            DecimalSeparator::Comma => (',', ';'),
+        };
+        let normalized = if options.strict_input {
+            Normalized::verbatim(expr)
+        } else {
+            Self::normalize(expr)
+        };
+        let chars: Vec<char> = normalized.text.chars().collect();
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut spans: Vec<Span> = Vec::new();
+        let mut percents: Vec<ParseWarning> = Vec::new();
+        // Where the last number literal ended, if only whitespace has
+        // come since.
+        let mut literal_end: Option<usize> = None;
+        // Brackets and bars still open, innermost last: the position of
+        // each bar, `None` for a `(`.
+        let mut open: Vec<Option<usize>> = Vec::new();
+        // Where the literal being read turned out malformed, as the end of
+        // the characters it takes up.
+        let mut malformed: Option<usize> = None;
+        // Whether `chars[k]` is a decimal point.
+        let is_point = |k: usize| {
+            chars.get(k) == Some(&point)
+                && (point == '.' || chars.get(k + 1).is_some_and(char::is_ascii_digit))
+        };
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            // `.5` is a literal, but a `.` by itself is not.
+            let starts_literal = c.is_ascii_digit()
+                || (c == point && chars.get(i + 1).is_some_and(char::is_ascii_digit));
+            // `1 000` or `1\u{202F}000` could be a grouped number or, with
+            // implicit multiplication, `1 * 0`; guessing either is worse
+            // than refusing.
+            if let (Some(end), true, false) = (literal_end, starts_literal, lossy) {
+                return Err(Error::SpaceInNumber {
+                    pos: normalized.original_position(end),
+                });
+            }
+            match c {
+                '0' if radix_prefix(&chars, i).is_some() => {
+                    // Every letter or digit up to the next symbol belongs
+                    // to the literal, so `0b102` is one malformed number
+                    // rather than `0b10` next to `2`.
+                    let radix = radix_prefix(&chars, i).unwrap();
+                    let start = i + 2;
+                    let mut j = start;
+                    while j < chars.len() && (chars[j].is_alphanumeric() || chars[j] == '_') {
+                        j += 1;
+                    }
+                    // A prefix without digits, as in `0x` or `0b.1`, a
+                    // misplaced `_`, a digit too big for the base, or a
+                    // fraction, which isn't part of the literal.
+                    let bad_digit = chars[start..j]
+                        .iter()
+                        .any(|d| *d != '_' && d.to_digit(radix).is_none());
+                    if j == start
+                        || bad_digit
+                        || misplaced_separator(&chars, start, j, char::is_ascii_alphanumeric)
+                            .is_some()
+                        || is_point(j)
+                    {
+                        malformed = Some(malformed_end(&chars, j, point));
+                    } else {
+                        let mut num = 0.0;
+                        for d in chars[start..j].iter().filter_map(|d| d.to_digit(radix)) {
+                            num = num * f64::from(radix) + f64::from(d);
+                        }
+                        tokens.push(Token::Number(num));
+                        spans.push(Span {
+                            start: normalized.original_position(i),
+                            end: normalized.original_position(j - 1) + 1,
+                        });
+                        literal_end = Some(j);
+                        i = j - 1;
+                    }
+                }
+                _ if starts_literal => 'literal: {
+                    let mut j = i;
+
+                    // A decimal comma needs a digit after it, so `max(1,5; 2,)`
+                    // ends its second literal before the `,`.
+                    while j < chars.len()
+                        && (chars[j].is_ascii_digit()
+                            || chars[j] == '_'
+                            || (chars[j] == point
+                                && (point == '.'
+                                    || chars.get(j + 1).is_some_and(char::is_ascii_digit))))
+                    {
+                        j += 1;
+                    }
+                    // `1_`, `1__0` and `1_.5` are malformed numbers.
+                    if misplaced_separator(&chars, i, j, char::is_ascii_digit).is_some() {
+                        malformed = Some(malformed_end(&chars, j, point));
+                        break 'literal;
+                    }
+                    if let Some(len) = exponent_len(&chars, j) {
+                        j += len;
+                        // Nothing more can follow an exponent: `1e5.2`,
+                        // `1e5e5` and `1e1_0` are each one malformed number.
+                        if is_point(j)
+                            || exponent_len(&chars, j).is_some()
+                            || chars.get(j) == Some(&'_')
+                        {
+                            malformed = Some(malformed_end(&chars, j, point));
+                            break 'literal;
+                        }
+                    } else if matches!(chars.get(j), Some('e' | 'E'))
+                        && !chars
+                            .get(j + 1)
+                            .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+                    {
+                        // `2e` or `2e-`: an exponent missing its digits,
+                        // not `2` next to the constant `e`.
+                        let sign = matches!(chars.get(j + 1), Some('+' | '-'));
+                        malformed = Some(j + 1 + usize::from(sign));
+                        break 'literal;
+                    }
+                    let num_str: String = chars[i..j]
+                        .iter()
+                        .filter(|c| **c != '_')
+                        .map(|c| if *c == point { '.' } else { *c })
+                        .collect();
+
+                    if let Ok(num) = num_str.parse::<f64>() {
+                        tokens.push(Token::Number(num));
+                        spans.push(Span {
+                            start: normalized.original_position(i),
+                            end: normalized.original_position(j - 1) + 1,
+                        });
+                    } else {
+                        malformed = Some(j);
+                        break 'literal;
+                    }
+                    literal_end = Some(j);
+                    let suffix = imaginary_unit
+                        && chars.get(j) == Some(&'i')
+                        && !chars
+                            .get(j + 1)
+                            .is_some_and(|c| c.is_alphanumeric() || *c == '_');
+                    if suffix {
+                        let start = normalized.original_position(j);
+                        tokens.push(Token::Op(Operator::Multiply));
+                        tokens.push(Token::Identifier(IMAGINARY_UNIT.into()));
+                        spans.extend(
+                            [Span {
+                                start,
+                                end: start + 1,
+                            }; 2],
+                        );
+                        literal_end = None;
+                        j += 1;
+                    } else if chars.get(j).is_some_and(|c| c.is_alphabetic()) {
+                        let mut k = j;
+                        while k < chars.len() && (chars[k].is_alphanumeric() || chars[k] == '_') {
+                            k += 1;
+                        }
+                        let name: String = chars[j..k].iter().collect();
+                        let called = (k..chars.len())
+                            .find(|&k| !matches!(chars[k], ' ' | '\t' | '\n'))
+                            .is_some_and(|k| chars[k] == '(');
+                        let rule = adjacency::glued(
+                            &name,
+                            called,
+                            options.implicit_multiplication,
+                            registry,
+                        );
+                        if rule == Glued::Malformed {
+                            // The number already read goes with the rest.
+                            tokens.pop();
+                            spans.pop();
+                            malformed = Some(k);
+                            break 'literal;
+                        }
+                    }
+                    i = j - 1;
+                }
+                '(' => {
+                    open.push(None);
+                    tokens.push(Token::Bracket(c));
+                }
+                ')' => {
+                    if let (Some(Some(bar)), false) = (open.pop(), lossy) {
+                        return Err(Error::MismatchedBars { pos: bar });
+                    }
+                    tokens.push(Token::Bracket(c));
+                }
+                // `|x|` is `abs(x)`. Like a sign, a bar is read by what
+                // comes before it: after an operand it closes, anywhere
+                // else it opens, so `|2 - |3 - 5||` nests.
+                '|' => {
+                    let pos = normalized.original_position(i);
+                    if tokens.last().is_some_and(ends_operand) {
+                        if !matches!(open.pop(), Some(Some(_))) && !lossy {
+                            return Err(Error::MismatchedBars { pos });
+                        }
+                        tokens.push(Token::Bracket(')'));
+                    } else {
+                        open.push(Some(pos));
+                        tokens.push(Token::Function {
+                            function: Function::Abs,
+                            args: 0,
+                        });
+                        spans.push(Span {
+                            start: pos,
+                            end: pos + 1,
+                        });
+                        tokens.push(Token::Bracket('('));
+                    }
+                }
+                '+' => tokens.push(Token::Op(Operator::Add)),
+                '-' => tokens.push(Token::Op(Operator::Subtract)),
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    tokens.push(Token::Op(Operator::Power));
+                    let start = normalized.original_position(i);
+                    spans.push(Span {
+                        start,
+                        end: normalized.original_position(i + 1) + 1,
+                    });
+                    i += 1;
+                }
+                '*' => tokens.push(Token::Op(Operator::Multiply)),
+                '/' if chars.get(i + 1) == Some(&'/') => {
+                    tokens.push(Token::Op(Operator::FloorDivide));
+                    let start = normalized.original_position(i);
+                    spans.push(Span {
+                        start,
+                        end: normalized.original_position(i + 1) + 1,
+                    });
+                    i += 1;
+                }
+                '/' => tokens.push(Token::Op(Operator::Divide)),
+                '%' => {
+                    // Decided by the token after it, never by spacing:
+                    // before an operand it's a remainder, `7 % 2` or
+                    // `7 % |x|`; before an operator or at the end a
+                    // percentage, `50% * 200` or `50% - 2`. A `|` that can
+                    // close the innermost bar does, as in `|50%|`.
+                    let opens = |c: char| {
+                        c.is_alphanumeric()
+                            || c == '.'
+                            || c == '('
+                            || (c == '|' && !matches!(open.last(), Some(Some(_))))
+                    };
+                    let after = |k: usize| {
+                        (k..chars.len()).find(|&k| !matches!(chars[k], ' ' | '\t' | '\n'))
+                    };
+                    let next = after(i + 1);
+                    let operand = next.is_some_and(|k| opens(chars[k]));
+                    // `7 % -2` is a percentage too, but its author may have
+                    // meant `7 % (-2)`.
+                    let signed = next.is_some_and(|k| {
+                        matches!(chars[k], '+' | '-')
+                            && after(k + 1).is_some_and(|k| opens(chars[k]))
+                    });
+                    if signed {
+                        percents.push(ParseWarning::AmbiguousPercent {
+                            position: normalized.original_position(i),
+                        });
+                    }
+                    tokens.push(Token::Op(if operand {
+                        Operator::Modulo
+                    } else {
+                        Operator::Percent
+                    }));
+                }
+                c if c == separator => tokens.push(Token::Comma),
+                '^' => tokens.push(Token::Op(Operator::Power)),
+                c if c.is_alphabetic() => {
+                    let mut j = i;
+                    while j < chars.len() && (chars[j].is_alphanumeric() || chars[j] == '_') {
+                        j += 1;
+                    }
+                    let name: String = chars[i..j].iter().collect();
+                    let pos = normalized.original_position(i);
+                    // What follows the name, past any whitespace, decides
+                    // whether it is assigned to, called or read. Only a
+                    // call is a function, so a variable named like one
+                    // added later still reads as the variable.
+                    let k = (j..chars.len())
+                        .find(|&k| !matches!(chars[k], ' ' | '\t' | '\n'))
+                        .unwrap_or(chars.len());
+                    let called = chars.get(k) == Some(&'(');
+                    let built_in = function::constant(&name).map(Token::Number).or_else(|| {
+                        Function::from_name(&name)
+                            .filter(|_| called)
+                            .map(|function| Token::Function { function, args: 0 })
+                    });
+                    if chars.get(k) == Some(&'=')
+                        && reserved::meaning(&name, imaginary_unit).is_some()
+                        && !lossy
+                    {
+                        return Err(Error::ReservedName { name, pos });
+                    }
+                    let end = match (built_in, chars.get(k)) {
+                        (None, Some('=')) => {
+                            tokens.push(Token::Assign(name.into()));
+                            k + 1
+                        }
+                        (Some(token), _) => {
+                            tokens.push(token);
+                            j
+                        }
+                        (None, Some('('))
+                            if registry.function(&name).is_some()
+                                || registry.unknown_calls
+                                || lossy =>
+                        {
+                            tokens.push(Token::Call {
+                                name: name.into(),
+                                args: 0,
+                            });
+                            j
+                        }
+                        (None, Some('(')) => return Err(Error::UnknownFunction { name, pos }),
+                        (None, _) => {
+                            tokens.push(Token::Identifier(name.into()));
+                            j
+                        }
+                    };
+                    spans.push(Span {
+                        start: pos,
+                        end: normalized.original_position(end - 1) + 1,
+                    });
+                    i = end - 1;
+                }
+                ' ' | '\t' | '\n' => {}
+                c if registry.operator(c).is_some() => {
+                    let op = registry.operator(c).unwrap();
+                    tokens.push(Token::Custom {
+                        symbol: c,
+                        precedence: op.precedence,
+                        assoc: op.assoc,
+                    });
+                }
+                _ if lossy => tokens.push(Token::Custom {
+                    symbol: c,
+                    precedence: 0,
+                    assoc: Assoc::Left,
+                }),
+                _ => {
+                    return Err(Error::BadToken {
+                        ch: c,
+                        pos: normalized.original_position(i),
+                    });
+                }
+            }
+            if let Some(end) = malformed.take() {
+                if !lossy {
+                    return Err(Error::InvalidNumber {
+                        literal: chars[i..end].iter().collect(),
+                        pos: normalized.original_position(i),
+                    });
+                }
+                tokens.push(Token::Number(f64::NAN));
+                spans.push(Span {
+                    start: normalized.original_position(i),
+                    end: normalized.original_position(end - 1) + 1,
+                });
+                literal_end = Some(end);
+                i = end - 1;
+            }
+            if !starts_literal && !matches!(c, ' ' | '\t' | '\n') {
+                literal_end = None;
+            }
+            // Every token other than a number is a single character.
+            if spans.len() < tokens.len() {
+                let start = normalized.original_position(i);
+                spans.push(Span {
+                    start,
+                    end: start + 1,
+                });
+            }
+            i += 1;
+        }
+
+        if !lossy {
+            if let Some(&Some(bar)) = open.iter().find(|o| o.is_some()) {
+                return Err(Error::MismatchedBars { pos: bar });
+            }
+            ParenBalance::of_tokens(&tokens, &spans).check()?;
+        }
+        let warnings = normalized
+            .changes
+            .into_iter()
+            .map(ParseWarning::Normalized)
+            .chain(percents)
+            .collect();
+        Ok((tokens, spans, warnings))
+    }
+}
+
+/// Whether `token` can be the last of an operand, so that a `|` after it
+/// closes a bar.
+fn ends_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Number(_)
+            | Token::Identifier(_)
+            | Token::Bracket(')')
+            | Token::Op(Operator::Percent)
+    )
+}
+
+/// Fills in each call's argument count from the commas directly inside
+/// its brackets. A comma anywhere else is `UnexpectedToken` at its span in
+/// `spans`, and the wrong number of arguments to a built-in function
+/// `WrongArity`. Brackets must already balance.
+fn count_arguments(tokens: &mut [Token], spans: &[Span]) -> Result<(), Error> {
+    // For each bracket still open, innermost last: the index of the call
+    // it holds the arguments of, if any, and the commas seen so far.
+    let mut open: Vec<(Option<usize>, u32)> = Vec::new();
+    for i in 0..tokens.len() {
+        match tokens[i] {
+            Token::Bracket('(') => {
+                let call = i
+                    .checked_sub(1)
+                    .filter(|&j| matches!(tokens[j], Token::Function { .. } | Token::Call { .. }));
+                open.push((call, 0));
+            }
+            Token::Comma => match open.last_mut() {
+                Some((Some(_), commas)) => *commas += 1,
+                _ => {
+                    return Err(Error::UnexpectedToken {
+                        pos: spans[i].start,
+                    });
+                }
+            },
+            Token::Bracket(_) => {
+                let Some((Some(call), commas)) = open.pop() else {
+                    continue;
+                };
+                match &mut tokens[call] {
+                    Token::Call { args, .. } => *args = commas + 1,
+                    Token::Function { function, args } => {
+                        let found = commas as usize + 1;
+                        match function.arity() {
+                            Some(expected) if expected != found => {
+                                return Err(Error::WrongArity {
+                                    name: function.name().to_string(),
+                                    expected,
+                                    found,
+                                });
+                            }
+                            _ => *args = commas + 1,
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EvalOptions;
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_decimal_numbers() {
+        assert_eq!(Calculator::calculate("2.5 + 3.7").unwrap(), 6.2);
+        assert_eq!(Calculator::calculate("10.5 / 2.1").unwrap(), 5.0);
+        assert_eq!(Calculator::calculate("3.14 * 2").unwrap(), 6.28);
+        assert_eq!(Calculator::calculate("7.5 - 2.25").unwrap(), 5.25);
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        let cases = [
+            ("0xFF - 0o17 + 0b1", 241.0),
+            ("0xff + 0XfF", 510.0),
+            ("0b1010 * 0B11", 30.0),
+            ("0o777", 511.0),
+            ("0x1e3", 483.0),
+            ("-0x10 ^ 2", -256.0),
+            ("0x0", 0.0),
+            ("0.5 + 0", 0.5),
+            ("0e1 + 05", 5.0),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(Calculator::calculate(expr), Ok(expected), "{}", expr);
+        }
+        assert_eq!(
+            Calculator::calculate("0xFFFFFFFFFFFFFFFFFFFF"),
+            Ok(2f64.powi(80))
+        );
+
+        assert_eq!(
+            Calculator::calculate("0x1 0x2"),
+            Err(Error::SpaceInNumber { pos: 3 })
+        );
+        // A fraction isn't part of the literal, but makes it malformed.
+        for (expr, literal, pos) in [
+            ("0b102", "0b102", 0),
+            ("0o8", "0o8", 0),
+            ("1 + 0xFG", "0xFG", 4),
+            ("0x", "0x", 0),
+            ("0x + 1", "0x", 0),
+            ("0b.1", "0b.1", 0),
+            ("(0o)", "0o", 1),
+            ("0x1.5", "0x1.5", 0),
+            ("0x1.8p3 + 1", "0x1.8p3", 0),
+            ("0b1.", "0b1.", 0),
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::InvalidNumber {
+                    literal: literal.to_string(),
+                    pos
+                }),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_digit_separators() {
+        let cases = [
+            ("1_000 + 2_000", 3000.0),
+            ("1_0.0_1", 10.01),
+            ("1_000_000 * 1_0e-6", 10.0),
+            ("0xFF_FF", 65535.0),
+            ("0b1010_1010", 170.0),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(Calculator::calculate(expr), Ok(expected), "{}", expr);
+        }
+        assert_eq!(Calculator::parse("1_234.5_6"), Calculator::parse("1234.56"));
+
+        for (expr, literal, pos) in [
+            ("1_", "1_", 0),
+            ("2 + 3_", "3_", 4),
+            ("1__0", "1__0", 0),
+            ("1_.5", "1_.5", 0),
+            ("1._5", "1._5", 0),
+            ("1_e5", "1_e5", 0),
+            ("1e1_0", "1e1_0", 0),
+            ("0x_FF", "0x_FF", 0),
+            ("0xF_", "0xF_", 0),
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::InvalidNumber {
+                    literal: literal.to_string(),
+                    pos
+                }),
+                "{}",
+                expr
+            );
+        }
+        // Not a number at all.
+        assert_eq!(
+            Calculator::calculate("_1"),
+            Err(Error::BadToken { ch: '_', pos: 0 })
+        );
+    }
+
+    #[test]
+    fn test_bare_decimal_points() {
+        let cases = [
+            (".5 * 4", 2.0),
+            ("2. + .25", 2.25),
+            ("-.5", -0.5),
+            ("(.5)", 0.5),
+            ("5.", 5.0),
+            (".5e1", 5.0),
+            ("1.e2", 100.0),
+            ("max(.5, 1.)", 1.0),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(Calculator::calculate(expr), Ok(expected), "{}", expr);
+        }
+        for (expr, pos) in [
+            (".", 0),
+            ("1 + .", 4),
+            ("2 * . 5", 4),
+            ("(.)", 1),
+            (".e3", 0),
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::BadToken { ch: '.', pos }),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::calculate("2. .5"),
+            Err(Error::SpaceInNumber { pos: 2 })
+        );
+    }
+
+    #[test]
+    fn test_whitespace_handling() {
+        assert_eq!(Calculator::calculate("  2   +   3  ").unwrap(), 5.0);
+        assert_eq!(Calculator::calculate("2+3").unwrap(), 5.0);
+        assert_eq!(Calculator::calculate(" ( 2 + 3 ) * 4 ").unwrap(), 20.0);
+        assert_eq!(Calculator::calculate("\t2\n*\t3\n").unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_invalid_tokens() {
+        assert_eq!(
+            Calculator::calculate("2 + @").unwrap_err(),
+            Error::BadToken { ch: '@', pos: 4 }
+        );
+        assert_eq!(
+            Calculator::calculate("5 & 3").unwrap_err(),
+            Error::BadToken { ch: '&', pos: 2 }
+        );
+    }
+
+    #[test]
+    fn test_mismatched_parentheses() {
+        for (expr, unmatched_open, unmatched_close) in [
+            ("(2 + 3", vec![0], vec![]),
+            ("2 + 3)", vec![], vec![5]),
+            ("((2 + 3)", vec![0], vec![]),
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr).unwrap_err(),
+                Error::MismatchedParens {
+                    unmatched_open,
+                    unmatched_close
+                },
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_positions() {
+        assert_eq!(
+            Calculator::calculate("12 + 3 $ 4"),
+            Err(Error::BadToken { ch: '$', pos: 7 })
+        );
+        assert_eq!(
+            Calculator::calculate("1 + 2 + foo(3)"),
+            Err(Error::UnknownFunction {
+                name: "foo".to_string(),
+                pos: 8
+            })
+        );
+        // Positions count characters of the original input, so they point
+        // past text that normalization rewrote or dropped.
+        assert_eq!(
+            Calculator::calculate("\u{201C}２ + €"),
+            Err(Error::BadToken { ch: '€', pos: 5 })
+        );
+        assert_eq!(
+            Calculator::calculate("((1+2)"),
+            Err(Error::MismatchedParens {
+                unmatched_open: vec![0],
+                unmatched_close: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn test_mismatched_parentheses_positions() {
+        let cases = [
+            ("((2+3)*4", vec![0], vec![]),
+            ("2+3))", vec![], vec![3, 4]),
+            (")1 + (2", vec![5], vec![0]),
+            // Positions point into the original text, not the normalized one.
+            ("“2” * (3", vec![6], vec![]),
+        ];
+        for (expr, unmatched_open, unmatched_close) in cases {
+            assert_eq!(
+                Calculator::calculate(expr).unwrap_err(),
+                Error::MismatchedParens {
+                    unmatched_open,
+                    unmatched_close
+                },
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_absolute_value_bars() {
+        let calc = |expr| Calculator::calculate(expr).unwrap();
+        assert_eq!(calc("|3 - 5| * 2"), 4.0);
+        assert_eq!(calc("|-2.5|"), 2.5);
+        assert_eq!(calc("2 * |1 - 4|"), 6.0);
+        assert_eq!(calc("-|-3|"), -3.0);
+        assert_eq!(calc("|2 - 10|^2"), 64.0);
+        assert_eq!(calc("|2 - |3 - 5||"), 0.0);
+        assert_eq!(calc("||3| - 5|"), 2.0);
+        assert_eq!(calc("|1 - |2 - |3 - 9|||"), 3.0);
+        assert_eq!(calc("sqrt(|-16|) + |(1 - 2)|"), 5.0);
+        assert_eq!(calc("|-50|%"), 0.5);
+        assert_eq!(calc("|50%|"), 0.5);
+        // After `%` a bar opens a divisor unless it can close one.
+        assert_eq!(calc("10 % |-3|"), 1.0);
+        assert_eq!(calc("10 %|-4|"), 2.0);
+        assert_eq!(calc("|10 % (|-3|)|"), 1.0);
+        assert_eq!(
+            Calculator::parse("|x|").unwrap(),
+            Calculator::parse("abs(x)").unwrap()
+        );
+
+        let mismatched = |expr| match Calculator::calculate(expr) {
+            Err(Error::MismatchedBars { pos }) => pos,
+            other => panic!("{}: expected MismatchedBars, got {:?}", expr, other),
+        };
+        assert_eq!(mismatched("|1 + 2"), 0);
+        assert_eq!(mismatched("1 + 2|"), 5);
+        assert_eq!(mismatched("|(1|)"), 3);
+        assert_eq!(mismatched("(|1)|"), 1);
+        assert_eq!(mismatched("|1| + |"), 6);
+        assert_eq!(mismatched("||"), 0);
+        assert_eq!(mismatched("|1|2|"), 4);
+        // After an operator a bar opens, even where it looks like a close.
+        assert_eq!(mismatched("|1 +|"), 0);
+
+        // With the bars matched, an unclosed `(` is still reported.
+        assert!(matches!(
+            Calculator::calculate("(|2 - 3|"),
+            Err(Error::MismatchedParens { .. })
+        ));
+        assert_eq!(
+            Calculator::calculate("|1, 2|"),
+            Err(Error::WrongArity {
+                name: "abs".to_string(),
+                expected: 1,
+                found: 2
+            })
+        );
+        assert_eq!(
+            Calculator::calculate("|* 2|"),
+            Err(Error::UnexpectedToken { pos: 1 })
+        );
+        assert_eq!(
+            Calculator::calculate_located("5 + |1 - 4| / 0")
+                .unwrap_err()
+                .to_string(),
+            "division by zero at position 12"
+        );
+        assert_eq!(
+            Error::MismatchedBars { pos: 4 }.to_string(),
+            "unmatched '|' at position 4"
+        );
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        assert_eq!(Calculator::calculate("-5 + 3").unwrap(), -2.0);
+        assert_eq!(Calculator::calculate("2 * -3").unwrap(), -6.0);
+        assert_eq!(Calculator::calculate("-(2 + 3)").unwrap(), -5.0);
+        assert_eq!(Calculator::calculate("3 - -2").unwrap(), 5.0);
+        assert_eq!(Calculator::calculate("--5").unwrap(), 5.0);
+        assert_eq!(Calculator::calculate("-2 * 3 + 1").unwrap(), -5.0);
+        assert_eq!(Calculator::calculate("(-2)").unwrap(), -2.0);
+        assert_eq!(Calculator::calculate("8 / -(1 + 1)").unwrap(), -4.0);
+        assert!(Calculator::calculate("-0").unwrap().is_sign_negative());
+    }
+
+    #[test]
+    fn test_unary_plus() {
+        assert_eq!(Calculator::calculate("+5").unwrap(), 5.0);
+        assert_eq!(Calculator::calculate("2 * (+3)").unwrap(), 6.0);
+        assert_eq!(Calculator::calculate("+-+5").unwrap(), -5.0);
+        assert_eq!(Calculator::calculate("3 + -+2").unwrap(), 1.0);
+        // Straight after a binary operator a `+` does nothing, and is
+        // rejected as a likely typo.
+        for expr in ["2 * +3", "3 + +2", "2 ^ +1"] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::UnexpectedToken { pos: 4 }),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_binary_minus_unchanged() {
+        assert_eq!(
+            Calculator::parse("3-2").unwrap(),
+            vec![
+                Token::Number(3.0),
+                Token::Op(Operator::Subtract),
+                Token::Number(2.0)
+            ]
+        );
+        assert_eq!(
+            Calculator::parse("3 - -2").unwrap(),
+            vec![
+                Token::Number(3.0),
+                Token::Op(Operator::Subtract),
+                Token::Op(Operator::Negate),
+                Token::Number(2.0)
+            ]
+        );
+        assert_eq!(
+            Calculator::parse("(+2)").unwrap(),
+            vec![Token::Bracket('('), Token::Number(2.0), Token::Bracket(')')]
+        );
+        for expr in ["-", "5 -", "- * 2", "-)"] {
+            assert!(Calculator::calculate(expr).is_err(), "{:?}", expr);
+        }
+    }
+
+    #[test]
+    fn test_scientific_notation() {
+        assert_eq!(Calculator::calculate("1.5e3 + 2").unwrap(), 1502.0);
+        assert_eq!(Calculator::calculate("6.02e23 / 2").unwrap(), 3.01e23);
+        assert_eq!(Calculator::calculate("1e-9").unwrap(), 1e-9);
+        assert_eq!(Calculator::calculate("2.5E+10").unwrap(), 2.5e10);
+        assert_eq!(Calculator::calculate("2e0 * 3").unwrap(), 6.0);
+        // The literal ends where its digits do.
+        assert_eq!(Calculator::calculate("1e-9-2").unwrap(), 1e-9 - 2.0);
+        assert_eq!(Calculator::calculate("1e2^2").unwrap(), 1e4);
+        assert_eq!(
+            Calculator::calculate("2 * e").unwrap(),
+            2.0 * std::f64::consts::E
+        );
+    }
+
+    #[test]
+    fn test_scientific_notation_extremes() {
+        assert_eq!(Calculator::calculate("1e999").unwrap(), f64::INFINITY);
+        assert_eq!(Calculator::calculate("-1e999").unwrap(), f64::NEG_INFINITY);
+        assert_eq!(Calculator::calculate("1e308 * 10").unwrap(), f64::INFINITY);
+        assert_eq!(Calculator::calculate("1e-999").unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_incomplete_exponent() {
+        // A dangling exponent is a malformed number, not `2` times `e`.
+        for (expr, literal) in [
+            ("2e", "2e"),
+            ("2e-", "2e-"),
+            ("2E+", "2E+"),
+            ("2e+ 3", "2e+"),
+            ("2e)", "2e"),
+            ("1.5e * 3", "1.5e"),
+        ] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::InvalidNumber {
+                    literal: literal.to_string(),
+                    pos: 0
+                }),
+                "{}",
+                expr
+            );
+        }
+        // With a space it is the constant, which can't follow a number.
+        assert_eq!(
+            Calculator::calculate("2 e"),
+            Err(Error::UnexpectedToken { pos: 2 })
+        );
+        // Without one it is `2` against the name `ex`, one malformed number.
+        assert_eq!(
+            Calculator::calculate("2ex"),
+            Err(Error::InvalidNumber {
+                literal: "2ex".to_string(),
+                pos: 0
+            })
+        );
+        let implicit = EvalOptions {
+            implicit_multiplication: true,
+            ..EvalOptions::default()
+        };
+        assert_eq!(Calculator::calculate_with("2exp(0)", &implicit), Ok(2.0));
+    }
+
+    #[test]
+    fn test_invalid_numbers() {
+        let cases = [
+            ("1 + 1.2.3", "1.2.3", 4),
+            ("2..5 * 3", "2..5", 0),
+            ("(1_0.5.)", "1_0.5.", 1),
+            ("1 + .5.", ".5.", 4),
+            ("“1.2.3”", "1.2.3", 1),
+            // Nothing can follow an exponent.
+            ("1e5e5", "1e5e5", 0),
+            ("2 * 1.5e3.2", "1.5e3.2", 4),
+            ("1e5.", "1e5.", 0),
+            ("1e-5e+5 + 1", "1e-5e+5", 0),
+        ];
+        for (expr, literal, pos) in cases {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::InvalidNumber {
+                    literal: literal.to_string(),
+                    pos
+                }),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::calculate("1 + 1.2.3").unwrap_err().to_string(),
+            "invalid number '1.2.3' at position 4"
+        );
+    }
+
+    #[test]
+    fn test_percent_or_remainder_whatever_the_spacing() {
+        use Operator::{Modulo, Percent};
+        // What sits before and after the `%`, and how the `%` reads.
+        let cases = [
+            ("10", "3", Modulo, false),
+            ("10", "(3)", Modulo, false),
+            ("10", ".5", Modulo, false),
+            ("10", "pi", Modulo, false),
+            ("10", "sqrt(4)", Modulo, false),
+            ("10", "|-3|", Modulo, false),
+            ("0x10", "0b11", Modulo, false),
+            ("(10)", "3", Modulo, false),
+            ("|-10|", "3", Modulo, false),
+            ("10", "", Percent, false),
+            ("10", "* 3", Percent, false),
+            ("10", "^ 2", Percent, false),
+            ("(10", ")", Percent, false),
+            ("|10", "|", Percent, false),
+            ("(10)", "", Percent, false),
+            // A sign then an operand may have been meant as the remainder
+            // by a signed number, `10 % (-3)`, so it is warned about.
+            ("10", "-3", Percent, true),
+            ("10", "+3", Percent, true),
+            ("10", "- (3)", Percent, true),
+            ("10", "-", Percent, false),
+        ];
+        for (before, after, reading, warned) in cases {
+            for (left, right) in [("", ""), (" ", ""), ("", " "), (" ", " "), ("\t", "  ")] {
+                let expr = format!("{}{}%{}{}", before, left, right, after);
+                let parsed = Calculator::parse_with(&expr, &EvalOptions::default());
+                if after == "-" {
+                    let pos = expr.len() - 1;
+                    assert_eq!(parsed, Err(Error::UnexpectedToken { pos }), "{:?}", expr);
+                    continue;
+                }
+                let (tokens, warnings) = parsed.unwrap();
+                assert!(tokens.contains(&Token::Op(reading.clone())), "{:?}", expr);
+                let position = expr.chars().position(|c| c == '%').unwrap();
+                let expected = warned.then_some(ParseWarning::AmbiguousPercent { position });
+                assert_eq!(warnings, Vec::from_iter(expected), "{:?}", expr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_function() {
+        let tokens = Calculator::parse("2 + 3").unwrap();
+        assert_eq!(tokens.len(), 3);
+
+        match &tokens[0] {
+            Token::Number(n) => assert_eq!(*n, 2.0),
+            _ => panic!("Expected number token"),
+        }
+
+        match &tokens[1] {
+            Token::Op(Operator::Add) => (),
+            _ => panic!("Expected add operator"),
+        }
+
+        match &tokens[2] {
+            Token::Number(n) => assert_eq!(*n, 3.0),
+            _ => panic!("Expected number token"),
+        }
+    }
+}
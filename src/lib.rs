@@ -0,0 +1,712 @@
+use std::fmt;
+
+mod adjacency;
+mod ast;
+mod balance;
+mod batch;
+mod builder;
+mod clipboard;
+mod closure;
+mod complex;
+mod context;
+mod custom;
+mod debugger;
+mod decimal;
+mod environment;
+mod estimate;
+mod eval;
+mod evaluation;
+mod format;
+mod function;
+mod integer;
+mod integrate;
+mod json;
+mod ledger;
+mod lexer;
+mod located;
+mod normalize;
+mod options;
+mod parser;
+mod repl;
+mod reserved;
+mod solve;
+mod speech;
+mod stats;
+mod stored;
+mod summary;
+mod telemetry;
+mod tree;
+mod watch;
+
+pub use adjacency::ParseWarning;
+pub use ast::{Expr, MAX_AST_DEPTH};
+pub use balance::ParenBalance;
+pub use batch::{BatchItem, BatchOptions, BatchProgress, BatchReport};
+pub use builder::{CalculatorBuilder, ConfiguredCalculator};
+pub use complex::Complex;
+pub use context::{Conflict, DuplicatePolicy, MergePolicy, VarContext, VarContextBuilder};
+pub use custom::Assoc;
+pub use debugger::{DebugStep, Debugger};
+pub use environment::Environment;
+pub use estimate::Estimate;
+pub use evaluation::{Evaluation, StepOutcome};
+pub use format::{EngineeringFormat, format_engineering, to_base};
+pub use function::{Function, ulp_diff};
+pub use integer::Value;
+pub use integrate::IntegrateOptions;
+pub use ledger::{Ledger, LedgerEntry};
+pub use located::{LocatedError, Span, SpannedToken};
+pub use normalize::{NormalizationChange, Normalized};
+pub use options::{Angle, DecimalSeparator, Division, EvalOptions, ZeroDivision};
+pub use parser::MAX_OPERATOR_STACK;
+pub use repl::{Repl, ReplOutput, repl};
+pub use solve::SolveOptions;
+pub use speech::{Speech, Verbosity};
+pub use stats::{CorpusStats, LiteralKind};
+pub use stored::{FORMAT_VERSION, StoredExpression, source_hash};
+pub use telemetry::{CharClass, ParseFailureStats};
+pub use tree::{AnnotatedExpr, Node, TreeStyle};
+pub use watch::LineChange;
+
+/// The types most callers need, importable in one line:
+///
+/// ```
+/// use calculator::prelude::*;
+///
+/// let result: Result<f64, Error> = Calculator::calculate("2 + 3 * 4");
+/// assert_eq!(result.unwrap(), 14.0);
+/// ```
+pub mod prelude {
+    pub use crate::{
+        BatchItem, BatchReport, Calculator, CalculatorBuilder, ConfiguredCalculator, Environment,
+        Error, LocatedError, Value,
+    };
+}
+
+#[derive(Debug, Clone)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    /// `//`: the quotient rounded toward negative infinity.
+    FloorDivide,
+    /// `%` before an operand: the remainder of truncating division, which
+    /// takes the sign of the left operand (`-7 % 3` is `-1`, `7 % (-3)` is
+    /// `1`), as Rust's `f64` `%` does.
+    Modulo,
+    /// `^` or `**`; right-associative, so `2 ^ 3 ^ 2` is `2 ^ 9`. A
+    /// negative base takes only whole exponents, as in `(-2) ^ 3`, and odd
+    /// roots with [`EvalOptions::real_roots`]; anything else, such as
+    /// `(-2) ^ 0.5`, is [`Error::DomainError`] rather than NaN.
+    Power,
+    /// Unary minus. `parse` produces it for a `-` where an operand is
+    /// expected, as in `-5`, `2 * -3` or `-(1 + 2)`.
+    Negate,
+    /// Postfix `%`, dividing by 100: `parse` produces it for a `%` that
+    /// isn't followed by an operand, as in `50% * 200` or `(10 + 5)%`. It
+    /// binds tighter than anything, so `-50%` is `-(50%)` and `2 ^ 50%` is
+    /// `2 ^ 0.5`.
+    Percent,
+}
+
+impl PartialEq for Operator {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Operator::Add, Operator::Add)
+                | (Operator::Subtract, Operator::Subtract)
+                | (Operator::Multiply, Operator::Multiply)
+                | (Operator::Divide, Operator::Divide)
+                | (Operator::FloorDivide, Operator::FloorDivide)
+                | (Operator::Modulo, Operator::Modulo)
+                | (Operator::Power, Operator::Power)
+                | (Operator::Negate, Operator::Negate)
+                | (Operator::Percent, Operator::Percent)
+        )
+    }
+}
+
+impl Operator {
+    /// Every operator, in declaration order.
+    pub const ALL: [Operator; 9] = [
+        Operator::Add,
+        Operator::Subtract,
+        Operator::Multiply,
+        Operator::Divide,
+        Operator::FloorDivide,
+        Operator::Modulo,
+        Operator::Power,
+        Operator::Negate,
+        Operator::Percent,
+    ];
+
+    fn symbol(&self) -> char {
+        match self {
+            Operator::Add => '+',
+            Operator::Subtract | Operator::Negate => '-',
+            Operator::Multiply => '*',
+            Operator::Divide | Operator::FloorDivide => '/',
+            Operator::Modulo | Operator::Percent => '%',
+            Operator::Power => '^',
+        }
+    }
+
+    /// The operator as written, which unlike `symbol` tells `//` from `/`.
+    fn text(&self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Subtract | Operator::Negate => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::FloorDivide => "//",
+            Operator::Modulo | Operator::Percent => "%",
+            Operator::Power => "^",
+        }
+    }
+
+    /// How tightly the operator binds; higher binds tighter. Registered
+    /// operators share the scale, so one at 15 sits between `+` and `*`.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Operator::Add | Operator::Subtract => 10,
+            Operator::Multiply | Operator::Divide | Operator::FloorDivide | Operator::Modulo => 20,
+            Operator::Negate => 30,
+            // Above negation, so `-2 ^ 2` is `-(2 ^ 2)`.
+            Operator::Power => 40,
+            Operator::Percent => 50,
+        }
+    }
+
+    /// Whether `a op b op c` groups as `a op (b op c)`.
+    fn is_right_associative(&self) -> bool {
+        matches!(self, Operator::Power)
+    }
+
+    /// Unary operators take a single operand, written after them, or
+    /// before them for postfix ones.
+    fn is_unary(&self) -> bool {
+        matches!(self, Operator::Negate | Operator::Percent)
+    }
+
+    fn is_postfix(&self) -> bool {
+        matches!(self, Operator::Percent)
+    }
+
+    /// Unary operators use only `right`.
+    fn apply(&self, left: f64, right: f64, options: &EvalOptions) -> Result<f64, Error> {
+        match self {
+            Operator::Add => Ok(left + right),
+            Operator::Subtract => Ok(left - right),
+            Operator::Multiply => Ok(left * right),
+            Operator::Divide | Operator::FloorDivide | Operator::Modulo
+                if right == 0.0 && options.zero_division == ZeroDivision::Error =>
+            {
+                Err(Error::DivisionByZero)
+            }
+            Operator::Divide => match options.division {
+                Division::TruncateWhenBothIntegral
+                    if left.fract() == 0.0 && right.fract() == 0.0 =>
+                {
+                    Ok((left / right).trunc())
+                }
+                // A zero divisor gets here only under `ZeroDivision::Ieee`,
+                // and its remainder is NaN, so it takes the IEEE result.
+                Division::Exact
+                    if right != 0.0
+                        && left.fract() == 0.0
+                        && right.fract() == 0.0
+                        && left % right != 0.0 =>
+                {
+                    Err(Error::InexactDivision)
+                }
+                _ => Ok(left / right),
+            },
+            Operator::FloorDivide => Ok((left / right).floor()),
+            Operator::Modulo => Ok(left % right),
+            Operator::Power => function::power(left, right, options.real_roots),
+            Operator::Negate => Ok(-right),
+            Operator::Percent => Ok(right / 100.0),
+        }
+    }
+}
+
+impl PartialOrd for Operator {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.precedence().partial_cmp(&other.precedence())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Number(f64),
+    Op(Operator),
+    Bracket(char),
+    /// A call such as `sqrt(`; always followed by its `(` arguments,
+    /// `args` of them.
+    Function {
+        function: Function,
+        args: u32,
+    },
+    /// A variable, looked up when evaluated. Any name that isn't a
+    /// constant or a function called with `(` is one.
+    Identifier(Name),
+    /// `name =`: stores the value of everything after it, up to the
+    /// enclosing `)` or the end, and is that value itself. It binds looser
+    /// than every operator and may only start an expression or a bracket,
+    /// so `a = b = 3` and `y = (x = 5) * 2` work and `2 * x = 3` doesn't.
+    Assign(Name),
+    /// A call such as `double(`, to a function registered on a
+    /// [`ConfiguredCalculator`]; always followed by its `(` arguments,
+    /// `args` of them.
+    Call {
+        name: Name,
+        args: u32,
+    },
+    /// `,` between a call's arguments.
+    Comma,
+    /// A binary operator registered on a [`ConfiguredCalculator`], with
+    /// how it binds, so [`Calculator::try_to_postfix`] can order it.
+    Custom {
+        symbol: char,
+        precedence: u8,
+        assoc: Assoc,
+    },
+}
+
+const _: () = assert!(std::mem::size_of::<Token>() <= 16);
+const _: () = assert!(std::mem::size_of::<Operator>() == 1);
+
+/// A variable or function name in a [`Token`]. It is one pointer wide, so
+/// a token holding one is no bigger than a [`Token::Number`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Name(Box<Box<str>>);
+
+impl Name {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        (*self.0).into_string()
+    }
+
+    /// Bytes on the heap: the name's own, plus the box pointing at them.
+    pub fn heap_size(&self) -> usize {
+        std::mem::size_of::<Box<str>>() + self.0.len()
+    }
+}
+
+impl std::ops::Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Name {
+    fn from(name: &str) -> Self {
+        Name(Box::new(name.into()))
+    }
+}
+
+impl From<String> for Name {
+    fn from(name: String) -> Self {
+        Name(Box::new(name.into_boxed_str()))
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+pub struct Calculator {}
+
+/// Everything that can go wrong, comparable with `==` so tests can
+/// `assert_eq!` on a whole error. New variants may be added, so a `match`
+/// outside this crate needs a wildcard arm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A character that can't start a token, at character position `pos`
+    /// of the original input.
+    BadToken {
+        ch: char,
+        pos: usize,
+    },
+    /// A number literal that doesn't read as one, such as `1.2.3` or `2e`,
+    /// as written after normalization and starting at character position
+    /// `pos`.
+    InvalidNumber {
+        literal: String,
+        pos: usize,
+    },
+    /// A name that isn't one of the built-in [`Function`]s, starting at
+    /// character position `pos`.
+    UnknownFunction {
+        name: String,
+        pos: usize,
+    },
+    /// Character positions (in the original input) of every `(` left open
+    /// and every `)` that had nothing to close; from
+    /// [`Calculator::try_to_postfix`], their indices among its tokens.
+    MismatchedParens {
+        unmatched_open: Vec<usize>,
+        unmatched_close: Vec<usize>,
+    },
+    /// An absolute-value bar without a partner: one never closed, or one
+    /// closing while a `(` opened inside it is still open, as in `|(1|)`.
+    /// `pos` is the bar's.
+    MismatchedBars {
+        pos: usize,
+    },
+    /// An assignment to a constant or function name, such as `pi = 3`, or
+    /// to `ans`. Constants are numbers by the time an expression is
+    /// evaluated, so they can't be shadowed. See [`Calculator::is_reserved`].
+    ReservedName {
+        name: String,
+        pos: usize,
+    },
+    /// A name [`ConfiguredCalculator::register_function`] can't register
+    /// because [`Calculator::is_reserved`] says the language already gives
+    /// it a meaning, such as `sqrt`, `pi` or `ans`.
+    ReservedWord(String),
+    /// A function [`ConfiguredCalculator::register_function`] can't
+    /// register: one taking no arguments, or whose `name` isn't a name or
+    /// is built in.
+    InvalidFunction {
+        name: String,
+        arity: usize,
+    },
+    /// A `symbol` [`ConfiguredCalculator::register_operator`] can't
+    /// register because it already means something, such as `+` or `x`.
+    InvalidOperator {
+        symbol: char,
+    },
+    /// A call with the wrong number of arguments, such as `sqrt(1, 2)`.
+    WrongArity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A variable read before anything was assigned to it.
+    UndefinedVariable(String),
+    /// A variable given two different values while building a
+    /// [`VarContext`], or by both sides of a [`VarContext::merge`] under
+    /// [`MergePolicy::Error`].
+    ConflictingVariable(String),
+    /// A name that could mean two things, which is never settled silently:
+    /// a function called while a variable of the same name is bound, as
+    /// `min(1, 2)` with a variable `min`, or, with implicit multiplication,
+    /// an unbound name made of bound ones, as `xy` with `x` and `y`.
+    AmbiguousName(String),
+    /// `ans` read before any expression in the [`Environment`] succeeded.
+    NoPreviousResult,
+    /// A number literal straight after another with only whitespace
+    /// between, as in `1 000`. Never read as one number or as a product,
+    /// whatever the options; `pos` is where the whitespace starts.
+    SpaceInNumber {
+        pos: usize,
+    },
+    /// A token that can't follow the one before it, as the second `*` in
+    /// `5 * * 2`, that the expression can't end with, as the `*` in `5 *`,
+    /// or a comma outside a call's brackets, starting at character
+    /// position `pos` of the original input. Input without a single token
+    /// is `InvalidExpression`.
+    UnexpectedToken {
+        pos: usize,
+    },
+    /// Brackets nested `depth` deep, past [`EvalOptions::max_depth`], an
+    /// expression nested past [`MAX_AST_DEPTH`] for
+    /// [`Calculator::parse_ast`], or `depth` tokens waiting at once in
+    /// [`Calculator::try_to_postfix`], past [`MAX_OPERATOR_STACK`].
+    TooDeep {
+        depth: usize,
+        max: usize,
+    },
+    DivisionByZero,
+    /// A whole-number result too big for an `i64`, from
+    /// [`Calculator::calculate_integers`], or for an `f64` to hold exactly,
+    /// from `shl`.
+    Overflow,
+    /// Two whole numbers that don't divide exactly, with
+    /// [`Division::Exact`].
+    InexactDivision,
+    /// A complex number given to something only defined for real ones,
+    /// such as `%`, from [`Calculator::calculate_complex`].
+    NotReal,
+    /// An `operation` with no real result for its operands, such as
+    /// `(-2) ^ 0.5` or `pct_change(0, 5)`, rather than a NaN; `reason` says
+    /// why. See [`EvalOptions::real_roots`].
+    DomainError {
+        operation: &'static str,
+        reason: &'static str,
+    },
+    InvalidExpression,
+    /// An `operation` that only works on whole numbers, such as `bitand`,
+    /// given a fraction, or one of 2^53 or more in size, past where an
+    /// `f64` holds every whole number.
+    NotAnInteger {
+        operation: &'static str,
+    },
+    /// A numerical `method`, such as [`Calculator::solve`] or
+    /// [`Calculator::integrate`], gave up before reaching its tolerance,
+    /// within the iterations it is allowed.
+    NoConvergence {
+        method: &'static str,
+    },
+    /// Placeholder for batch items skipped because the batch was aborted.
+    NotEvaluated,
+    /// A stored expression was written by a newer (or unknown) version of
+    /// the envelope format, or, at a known `found`, its tokens don't match
+    /// their check or the blob was cut short.
+    IncompatibleFormat {
+        found: u32,
+        supported: u32,
+    },
+    /// A stored expression is malformed, its source doesn't match its hash,
+    /// or (when verifying) its tokens don't match its source.
+    InvalidStoredExpression,
+}
+
+/// How many positions an error message lists before summarizing the rest.
+const LISTED_POSITIONS: usize = 3;
+
+fn describe_positions(positions: &[usize]) -> String {
+    let listed: Vec<String> = positions
+        .iter()
+        .take(LISTED_POSITIONS)
+        .map(|p| p.to_string())
+        .collect();
+    let noun = if positions.len() == 1 {
+        "position"
+    } else {
+        "positions"
+    };
+    let mut text = format!("{} {}", noun, listed.join(", "));
+    if positions.len() > LISTED_POSITIONS {
+        text.push_str(&format!(" and {} more", positions.len() - LISTED_POSITIONS));
+    }
+    text
+}
+
+impl Error {
+    /// The variant's name, e.g. `"BadToken"`, for machine-readable output
+    /// that shouldn't depend on the wording of messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::BadToken { .. } => "BadToken",
+            Error::InvalidNumber { .. } => "InvalidNumber",
+            Error::UnknownFunction { .. } => "UnknownFunction",
+            Error::MismatchedParens { .. } => "MismatchedParens",
+            Error::MismatchedBars { .. } => "MismatchedBars",
+            Error::ReservedName { .. } => "ReservedName",
+            Error::ReservedWord(_) => "ReservedWord",
+            Error::InvalidFunction { .. } => "InvalidFunction",
+            Error::InvalidOperator { .. } => "InvalidOperator",
+            Error::WrongArity { .. } => "WrongArity",
+            Error::UndefinedVariable(_) => "UndefinedVariable",
+            Error::ConflictingVariable(_) => "ConflictingVariable",
+            Error::AmbiguousName(_) => "AmbiguousName",
+            Error::NoPreviousResult => "NoPreviousResult",
+            Error::SpaceInNumber { .. } => "SpaceInNumber",
+            Error::UnexpectedToken { .. } => "UnexpectedToken",
+            Error::TooDeep { .. } => "TooDeep",
+            Error::DivisionByZero => "DivisionByZero",
+            Error::Overflow => "Overflow",
+            Error::InexactDivision => "InexactDivision",
+            Error::NotReal => "NotReal",
+            Error::DomainError { .. } => "DomainError",
+            Error::InvalidExpression => "InvalidExpression",
+            Error::NotAnInteger { .. } => "NotAnInteger",
+            Error::NoConvergence { .. } => "NoConvergence",
+            Error::NotEvaluated => "NotEvaluated",
+            Error::IncompatibleFormat { .. } => "IncompatibleFormat",
+            Error::InvalidStoredExpression => "InvalidStoredExpression",
+        }
+    }
+
+    /// The error at character position `pos`, for the kinds evaluating
+    /// postfix can only raise at position 0, since it keeps no positions.
+    pub(crate) fn at(self, pos: usize) -> Error {
+        match self {
+            Error::UnknownFunction { name, .. } => Error::UnknownFunction { name, pos },
+            Error::BadToken { ch, .. } => Error::BadToken { ch, pos },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadToken { ch, pos } => {
+                write!(f, "unexpected character '{}' at position {}", ch, pos)
+            }
+            Error::InvalidNumber { literal, pos } => {
+                write!(f, "invalid number '{}' at position {}", literal, pos)?;
+                match lexer::split_glued(literal) {
+                    Some((number, name)) => {
+                        write!(f, " (write '{} * {}' to multiply)", number, name)
+                    }
+                    None => Ok(()),
+                }
+            }
+            Error::UnknownFunction { name, pos } => {
+                write!(f, "unknown function '{}' at position {}", name, pos)
+            }
+            Error::MismatchedParens {
+                unmatched_open,
+                unmatched_close,
+            } => {
+                if !unmatched_close.is_empty() {
+                    let count = unmatched_close.len();
+                    write!(
+                        f,
+                        "unmatched ')' at {}: remove {} closing ')'",
+                        describe_positions(unmatched_close),
+                        count
+                    )?;
+                    if !unmatched_open.is_empty() {
+                        write!(f, "; ")?;
+                    }
+                }
+                if !unmatched_open.is_empty() {
+                    let count = unmatched_open.len();
+                    write!(
+                        f,
+                        "unclosed '(' at {}: {} closing ')' missing at end",
+                        describe_positions(unmatched_open),
+                        count
+                    )?;
+                }
+                Ok(())
+            }
+            Error::MismatchedBars { pos } => write!(f, "unmatched '|' at position {}", pos),
+            Error::ReservedName { name, pos } => write!(
+                f,
+                "'{}' at position {} is {} and can't be assigned",
+                name,
+                pos,
+                reserved::meaning(name, true).unwrap_or("built in")
+            ),
+            Error::ReservedWord(name) => write!(
+                f,
+                "can't register '{}': it is {}",
+                name,
+                reserved::meaning(name, true).unwrap_or("built in")
+            ),
+            Error::InvalidFunction { name, arity } => {
+                let reason = if *arity == 0 {
+                    "a function needs at least one argument"
+                } else {
+                    "it isn't a name"
+                };
+                write!(f, "can't register '{}': {}", name, reason)
+            }
+            Error::InvalidOperator { symbol } => write!(
+                f,
+                "can't register '{}' as an operator: it already means something",
+                symbol
+            ),
+            Error::WrongArity {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "'{}' takes {} argument{}, not {}",
+                name,
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found
+            ),
+            Error::UndefinedVariable(name) => write!(f, "undefined variable '{}'", name),
+            Error::ConflictingVariable(name) => {
+                write!(f, "variable '{}' is given two different values", name)
+            }
+            Error::AmbiguousName(name) => write!(
+                f,
+                "ambiguous name '{}': rename the variable, or write a product out with '*'",
+                name
+            ),
+            Error::NoPreviousResult => {
+                write!(f, "'ans' has no value: nothing has been calculated yet")
+            }
+            Error::SpaceInNumber { pos } => write!(
+                f,
+                "space between numbers at position {}: remove it or add an operator",
+                pos
+            ),
+            Error::UnexpectedToken { pos } => write!(f, "unexpected token at position {}", pos),
+            Error::TooDeep { depth, max } => write!(
+                f,
+                "brackets nested {} deep, more than the limit of {}",
+                depth, max
+            ),
+            Error::DivisionByZero => write!(f, "division by zero"),
+            Error::Overflow => write!(f, "integer overflow"),
+            Error::InexactDivision => write!(f, "division is not exact"),
+            Error::NotReal => write!(f, "needs a real number, not a complex one"),
+            Error::DomainError { operation, reason } => {
+                write!(f, "{} has no real result: {}", operation, reason)
+            }
+            Error::InvalidExpression => write!(f, "invalid expression"),
+            Error::NotAnInteger { operation } => {
+                write!(f, "{} needs whole numbers smaller than 2^53", operation)
+            }
+            Error::NoConvergence { method } => write!(
+                f,
+                "{} gave up: no answer within its tolerance and iteration limit",
+                method
+            ),
+            Error::NotEvaluated => write!(f, "not evaluated (batch aborted)"),
+            Error::IncompatibleFormat { found, supported } if *found == 0 || found > supported => {
+                write!(
+                    f,
+                    "stored expression format v{} is not supported (newest known: v{})",
+                    found, supported
+                )
+            }
+            Error::IncompatibleFormat { found, .. } => write!(
+                f,
+                "stored expression (format v{}) is damaged: its tokens fail their check",
+                found
+            ),
+            Error::InvalidStoredExpression => write!(f, "stored expression is corrupted"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mismatched_parentheses_messages() {
+        let message = |expr: &str| Calculator::calculate(expr).unwrap_err().to_string();
+
+        assert_eq!(
+            message("((2+3)*4"),
+            "unclosed '(' at position 0: 1 closing ')' missing at end"
+        );
+        assert_eq!(
+            message("2+3))"),
+            "unmatched ')' at positions 3, 4: remove 2 closing ')'"
+        );
+        assert_eq!(
+            message("((((((1"),
+            "unclosed '(' at positions 0, 1, 2 and 3 more: 6 closing ')' missing at end"
+        );
+        assert_eq!(
+            message(")("),
+            "unmatched ')' at position 0: remove 1 closing ')'; \
+             unclosed '(' at position 1: 1 closing ')' missing at end"
+        );
+    }
+}
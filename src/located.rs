@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::custom::Registry;
+use crate::{Calculator, Error, EvalOptions, Token};
+
+/// Character positions `start..end` in the original input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A token with the characters of the original input it was read from.
+/// A number's span covers the whole literal; whitespace is never part of
+/// a span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl From<SpannedToken> for Token {
+    fn from(spanned: SpannedToken) -> Self {
+        spanned.token
+    }
+}
+
+/// An error together with the source token it was raised at.
+#[derive(Debug, PartialEq)]
+pub struct LocatedError {
+    pub error: Error,
+    /// Index of the failing token in the infix stream passed to
+    /// [`Calculator::try_to_postfix_with_origins`]. `None` for errors that
+    /// don't belong to one token, such as parse errors (which carry their
+    /// own positions, if any) or a postfix stream that ends malformed.
+    pub token: Option<usize>,
+    /// Where that token is in the original input, when known. For input
+    /// rejected because a token can't follow the one before it, as in
+    /// `5 * * 2`, this is where that token is, though `token` is `None`;
+    /// for unbalanced brackets, the first one without a partner.
+    pub span: Option<Span>,
+}
+
+impl LocatedError {
+    /// An evaluation error with its token's span among `spans`, those of
+    /// the infix tokens, and the error itself moved there.
+    pub(crate) fn within(mut self, spans: &[Span]) -> LocatedError {
+        self.span = self.token.and_then(|i| spans.get(i).copied());
+        if let Some(span) = self.span {
+            self.error = self.error.at(span.start);
+        }
+        self
+    }
+}
+
+impl From<Error> for LocatedError {
+    fn from(error: Error) -> Self {
+        LocatedError {
+            error,
+            token: None,
+            span: None,
+        }
+    }
+}
+
+impl fmt::Display for LocatedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.span, &self.error) {
+            // The message gives the positions already.
+            (_, Error::MismatchedParens { .. } | Error::UnexpectedToken { .. }) | (None, _) => {
+                write!(f, "{}", self.error)
+            }
+            (Some(span), _) => write!(f, "{} at position {}", self.error, span.start),
+        }
+    }
+}
+
+impl std::error::Error for LocatedError {}
+
+impl Calculator {
+    /// [`Calculator::parse_with_spans`] with each span kept next to its
+    /// token. The rest of the pipeline takes plain tokens:
+    ///
+    /// ```
+    /// use calculator::{Calculator, Token};
+    ///
+    /// let spanned = Calculator::parse_spanned("10.5 + 2").unwrap();
+    /// assert_eq!((spanned[0].span.start, spanned[0].span.end), (0, 4));
+    ///
+    /// let tokens: Vec<Token> = spanned.into_iter().map(Token::from).collect();
+    /// assert_eq!(Calculator::evaluate(Calculator::try_to_postfix(tokens).unwrap()), Ok(12.5));
+    /// ```
+    pub fn parse_spanned<T: AsRef<str>>(expr: T) -> Result<Vec<SpannedToken>, Error> {
+        let (tokens, spans) = Self::parse_with_spans(expr)?;
+        Ok(tokens
+            .into_iter()
+            .zip(spans)
+            .map(|(token, span)| SpannedToken { token, span })
+            .collect())
+    }
+
+    /// [`Calculator::evaluate`] for postfix produced by
+    /// [`Calculator::try_to_postfix_with_origins`]: a failure reports the
+    /// original (infix) index of the token it happened at rather than its
+    /// postfix position.
+    pub fn evaluate_with_origins(
+        tokens: Vec<Token>,
+        origins: &[usize],
+    ) -> Result<f64, LocatedError> {
+        Self::evaluate_located(
+            tokens,
+            origins,
+            &EvalOptions::default(),
+            &mut HashMap::new(),
+            &Registry::default(),
+        )
+    }
+
+    /// [`Calculator::evaluate_with_origins`] under `options`, reading and
+    /// assigning `variables`.
+    pub(crate) fn evaluate_located(
+        tokens: Vec<Token>,
+        origins: &[usize],
+        options: &EvalOptions,
+        variables: &mut HashMap<String, f64>,
+        registry: &Registry,
+    ) -> Result<f64, LocatedError> {
+        Self::evaluate_at(tokens, options, variables, registry).map_err(|(error, at)| {
+            LocatedError {
+                error,
+                token: at.and_then(|i| origins.get(i).copied()),
+                span: None,
+            }
+        })
+    }
+
+    /// [`Calculator::calculate`], with evaluation errors pointing at the
+    /// source character that caused them: `10 / (2 - 2)` reports the `/`,
+    /// and `5 * * 2` the second `*`.
+    pub fn calculate_located<T: AsRef<str>>(expr: T) -> Result<f64, LocatedError> {
+        Self::calculate_located_in(expr.as_ref(), &EvalOptions::default(), &Registry::default())
+    }
+
+    /// [`Calculator::calculate_located`] under `options`, with what
+    /// `registry` adds to the language.
+    pub(crate) fn calculate_located_in(
+        expr: &str,
+        options: &EvalOptions,
+        registry: &Registry,
+    ) -> Result<f64, LocatedError> {
+        let (tokens, spans) = Self::parse_located(expr, options, registry)?;
+        let (postfix, origins) = Self::try_to_postfix_with_origins(tokens)?;
+        Self::evaluate_located(postfix, &origins, options, &mut HashMap::new(), registry)
+            .map_err(|e| e.within(&spans))
+    }
+
+    /// [`Calculator::parse_with_spans`] under `options`, with a token that
+    /// can't follow the one before it located in the error.
+    pub(crate) fn parse_located(
+        expr: &str,
+        options: &EvalOptions,
+        registry: &Registry,
+    ) -> Result<(Vec<Token>, Vec<Span>), LocatedError> {
+        Self::parse_in(expr, options, false, registry)
+            .map(|(tokens, spans, _)| (tokens, spans))
+            .map_err(|error| {
+                let span = match &error {
+                    // A comma outside a call passes the adjacency checks.
+                    Error::UnexpectedToken { pos } => {
+                        Self::rejected_span(expr, options, false, registry).or(Some(Span {
+                            start: *pos,
+                            end: pos + 1,
+                        }))
+                    }
+                    // The first bracket without a partner.
+                    Error::MismatchedParens {
+                        unmatched_open,
+                        unmatched_close,
+                    } => unmatched_open
+                        .iter()
+                        .chain(unmatched_close)
+                        .min()
+                        .map(|&start| Span {
+                            start,
+                            end: start + 1,
+                        }),
+                    _ => None,
+                };
+                LocatedError {
+                    error,
+                    token: None,
+                    span,
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Operator;
+
+    fn failing_span(expr: &str) -> (Error, Option<Span>) {
+        let e = Calculator::calculate_located(expr).unwrap_err();
+        (e.error, e.span)
+    }
+
+    fn span(start: usize, end: usize) -> Option<Span> {
+        Some(Span { start, end })
+    }
+
+    #[test]
+    fn test_spans() {
+        let (tokens, spans) = Calculator::parse_with_spans("12.5 *(3-  -4)").unwrap();
+        assert_eq!(tokens.len(), spans.len());
+        assert_eq!(
+            spans,
+            [
+                Span { start: 0, end: 4 },
+                Span { start: 5, end: 6 },
+                Span { start: 6, end: 7 },
+                Span { start: 7, end: 8 },
+                Span { start: 8, end: 9 },
+                Span { start: 11, end: 12 },
+                Span { start: 12, end: 13 },
+                Span { start: 13, end: 14 },
+            ]
+        );
+
+        let (_, spans) = Calculator::parse_with_spans("2**3").unwrap();
+        assert_eq!(spans[1], Span { start: 1, end: 3 });
+
+        // A dropped unary plus takes its span with it, and positions count
+        // the characters normalization removed.
+        let (_, spans) = Calculator::parse_with_spans("“+7” – 1").unwrap();
+        assert_eq!(
+            spans,
+            [
+                Span { start: 2, end: 3 },
+                Span { start: 5, end: 6 },
+                Span { start: 7, end: 8 }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_spanned() {
+        let spanned = Calculator::parse_spanned("10.5 + (2*3)").unwrap();
+        let expected = [
+            (Token::Number(10.5), 0, 4),
+            (Token::Op(Operator::Add), 5, 6),
+            (Token::Bracket('('), 7, 8),
+            (Token::Number(2.0), 8, 9),
+            (Token::Op(Operator::Multiply), 9, 10),
+            (Token::Number(3.0), 10, 11),
+            (Token::Bracket(')'), 11, 12),
+        ];
+        assert_eq!(spanned.len(), expected.len());
+        for (spanned, (token, start, end)) in spanned.into_iter().zip(expected) {
+            assert_eq!(
+                spanned,
+                SpannedToken {
+                    token,
+                    span: Span { start, end }
+                }
+            );
+        }
+
+        assert_eq!(
+            Calculator::parse_spanned("2 + @"),
+            Err(Error::BadToken { ch: '@', pos: 4 })
+        );
+    }
+
+    #[test]
+    fn test_origins() {
+        // 1 + 2 * 3  →  1 2 3 * +
+        let tokens = Calculator::parse("1 + 2 * 3").unwrap();
+        let (postfix, origins) = Calculator::try_to_postfix_with_origins(tokens).unwrap();
+        assert_eq!(postfix.len(), 5);
+        assert_eq!(origins, [0, 2, 4, 3, 1]);
+
+        // Brackets are dropped but indices still count them.
+        let tokens = Calculator::parse("(1 + 2) * 3").unwrap();
+        let (_, origins) = Calculator::try_to_postfix_with_origins(tokens).unwrap();
+        assert_eq!(origins, [1, 3, 2, 6, 5]);
+    }
+
+    #[test]
+    fn test_failure_sites() {
+        assert_eq!(
+            failing_span("10 / (2 - 2)"),
+            (Error::DivisionByZero, span(3, 4))
+        );
+        assert_eq!(
+            failing_span("1 + (8 / (4 - 2 * 2)) * 3"),
+            (Error::DivisionByZero, span(7, 8))
+        );
+        // The inner division fails first even though the outer one is
+        // written first.
+        assert_eq!(
+            failing_span("(1 / 2) / ((3 / 0) + 1)"),
+            (Error::DivisionByZero, span(14, 15))
+        );
+        assert_eq!(
+            failing_span("2 * 3 / (1 - 1) / 0"),
+            (Error::DivisionByZero, span(6, 7))
+        );
+        assert_eq!(
+            failing_span("-(4 / -(2 - 2))"),
+            (Error::DivisionByZero, span(4, 5))
+        );
+    }
+
+    #[test]
+    fn test_unmatched_brackets_located() {
+        let unmatched = |expr| match failing_span(expr) {
+            (Error::MismatchedParens { .. }, span) => span,
+            other => panic!("{}: expected MismatchedParens, got {:?}", expr, other),
+        };
+        assert_eq!(unmatched("((1+2)"), span(0, 1));
+        assert_eq!(unmatched("(1+2))"), span(5, 6));
+        assert_eq!(unmatched("1) + (2"), span(1, 2));
+        assert_eq!(unmatched("“(1”"), span(1, 2));
+        // The message already says where the brackets are.
+        assert_eq!(
+            Calculator::calculate_located("(1+2))")
+                .unwrap_err()
+                .to_string(),
+            "unmatched ')' at position 5: remove 1 closing ')'"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_have_no_span() {
+        assert_eq!(
+            failing_span("2 + @"),
+            (Error::BadToken { ch: '@', pos: 4 }, None)
+        );
+        assert!(Calculator::calculate_located("(1 + 2) * 3").is_ok());
+        assert_eq!(
+            Calculator::calculate_located("10 / (2 - 2)")
+                .unwrap_err()
+                .to_string(),
+            "division by zero at position 3"
+        );
+    }
+
+    #[test]
+    fn test_positions_moved_to_the_token() {
+        // Hand-built postfix calling a function nobody registered.
+        let postfix = vec![
+            Token::Number(2.0),
+            Token::Call {
+                name: "f".into(),
+                args: 1,
+            },
+        ];
+        let e = Calculator::evaluate_with_origins(postfix, &[2, 0]).unwrap_err();
+        let unknown = |pos| Error::UnknownFunction {
+            name: "f".to_string(),
+            pos,
+        };
+        assert_eq!((&e.error, e.token), (&unknown(0), Some(0)));
+        let e = e.within(&[Span { start: 4, end: 5 }, Span { start: 6, end: 7 }]);
+        assert_eq!((e.error, e.span), (unknown(4), span(4, 5)));
+    }
+
+    #[test]
+    fn test_rejected_token_located() {
+        let cases = [
+            ("5 *", span(2, 3)),
+            ("5 * * 3", span(4, 5)),
+            ("(* 2)", span(1, 2)),
+            ("()", span(1, 2)),
+            ("1 + ()", span(5, 6)),
+            ("2 pi", span(2, 4)),
+            ("sqrt 4", span(5, 6)),
+            ("x = 1 +", span(6, 7)),
+            ("5 * -", span(4, 5)),
+            ("-", span(0, 1)),
+            ("2 + + 3", span(4, 5)),
+            ("2 * +3", span(4, 5)),
+            // A misplaced comma is found after the adjacency checks.
+            ("(1, 2)", span(2, 3)),
+            ("", None),
+        ];
+        for (expr, expected) in cases {
+            let error = match expected {
+                Some(span) => Error::UnexpectedToken { pos: span.start },
+                None => Error::InvalidExpression,
+            };
+            assert_eq!(failing_span(expr), (error, expected), "{}", expr);
+        }
+        // A `-` after a binary operator negates; only a `+` there is
+        // rejected.
+        assert_eq!(Calculator::calculate_located("3 - -2"), Ok(5.0));
+        assert_eq!(Calculator::calculate_located("2 * -+3"), Ok(-6.0));
+        assert_eq!(Calculator::calculate_located("+2 - (+3)"), Ok(-1.0));
+        assert_eq!(
+            Calculator::calculate_located("5 * * 3")
+                .unwrap_err()
+                .to_string(),
+            "unexpected token at position 4"
+        );
+    }
+
+    #[test]
+    fn test_evaluate_with_origins_hand_built() {
+        // Origins need not come from try_to_postfix; underflow at the operator
+        // reports that operator's origin.
+        let postfix = vec![Token::Number(1.0), Token::Op(Operator::Add)];
+        let e = Calculator::evaluate_with_origins(postfix, &[7, 9]).unwrap_err();
+        assert_eq!((e.error, e.token), (Error::InvalidExpression, Some(9)));
+
+        let postfix = vec![Token::Number(1.0), Token::Number(2.0)];
+        let e = Calculator::evaluate_with_origins(postfix, &[0, 1]).unwrap_err();
+        assert_eq!((e.error, e.token), (Error::InvalidExpression, None));
+    }
+}
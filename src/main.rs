@@ -1,432 +1,437 @@
-#[derive(Debug, Clone)]
-pub enum Operator {
-    Add,
-    Subtract,
-    Multiply,
-    Divide,
-}
-
-impl PartialEq for Operator {
-    fn eq(&self, other: &Self) -> bool {
-        matches!(
-            (self, other),
-            (Operator::Add, Operator::Add)
-                | (Operator::Subtract, Operator::Subtract)
-                | (Operator::Multiply, Operator::Multiply)
-                | (Operator::Divide, Operator::Divide)
-        )
-    }
-}
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use calculator::{
+    BatchReport, Calculator, EngineeringFormat, LineChange, Repl, Speech, TreeStyle, Verbosity,
+};
+
+const USAGE: &str = "usage: calculator
+       calculator -
+       calculator [-] --json
+       calculator --demo
+       calculator --file <file> [--json]
+       calculator --table <file> [--sig-figs <n>] [--si]
+       calculator --stats <file> [--json]
+       calculator --estimate <expr> [--sig-figs <n>]
+       calculator --add-regression <expr>
+       calculator --watch <file> [--interval <ms>] [--no-color]
+       calculator --verbose-speech <expr> [--terse]
+       calculator --tree <expr> [--ascii]";
+
+/// Significant figures kept by `--estimate` unless `--sig-figs` is given.
+const DEFAULT_SIG_FIGS: usize = 2;
+
+/// Significant figures in a `--table` unless `--sig-figs` is given.
+const DEFAULT_TABLE_SIG_FIGS: usize = 3;
+
+/// Replayed by `tests/regressions.rs`. Found from the crate's source tree,
+/// wherever the binary is run from.
+const REGRESSIONS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/regressions");
+
+/// How often `--watch` checks the file's modification time.
+const DEFAULT_WATCH_INTERVAL_MS: u64 = 500;
+
+/// Clears the terminal and moves the cursor home.
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
 
-impl Operator {
-    fn precedence(&self) -> u8 {
-        match self {
-            Operator::Add | Operator::Subtract => 1,
-            Operator::Multiply | Operator::Divide => 2,
-        }
-    }
-    
-    fn apply(&self, left: f64, right: f64) -> Result<f64, Error> {
-        match self {
-            Operator::Add => Ok(left + right),
-            Operator::Subtract => Ok(left - right),
-            Operator::Multiply => Ok(left * right),
-            Operator::Divide => {
-                if right == 0.0 {
-                    Err(Error::DivisionByZero)
-                } else {
-                    Ok(left / right)
-                }
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        None if !io::stdin().is_terminal() => run_stream(false),
+        None => run_repl(),
+        Some("-") => match &args[1..] {
+            [] => run_stream(false),
+            [flag] if flag == "--json" => run_stream(true),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
             }
+        },
+        Some("--json") if args.len() == 1 => run_stream(true),
+        Some("--demo") => run_demo(),
+        Some("--file") => run_file(&args[1..]),
+        Some("--table") => run_table(&args[1..]),
+        Some("--stats") => run_stats(&args[1..]),
+        Some("--estimate") => run_estimate(&args[1..]),
+        Some("--add-regression") => run_add_regression(&args[1..]),
+        Some("--watch") => run_watch(&args[1..]),
+        Some("--verbose-speech") => run_speech(&args[1..]),
+        Some("--tree") => run_tree(&args[1..]),
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
         }
     }
 }
 
-impl PartialOrd for Operator {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.precedence().partial_cmp(&other.precedence())
+/// Evaluates each line of stdin, as when input is piped in.
+fn run_stream(json: bool) {
+    let (input, output) = (io::stdin().lock(), io::stdout().lock());
+    let status = if json {
+        Calculator::calculate_stream_json(input, output)
+    } else {
+        Calculator::calculate_stream(input, output, io::stderr())
+    };
+    match status {
+        Ok(status) => process::exit(status),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Token {
-    Number(f64),
-     Op(Operator),
-     Bracket(char),
+/// Reads expressions from stdin until end of input or `quit`.
+fn run_repl() {
+    let style = if utf8_locale() {
+        TreeStyle::Unicode
+    } else {
+        TreeStyle::Ascii
+    };
+    // Asks the terminal to mark pasted text, so a pasted block is
+    // evaluated once it has all arrived.
+    let bracketed_paste = io::stdin().is_terminal() && io::stdout().is_terminal();
+    if bracketed_paste {
+        print!("\x1b[?2004h");
+    }
+    let stdout = io::stdout();
+    let outcome = Repl::new()
+        .tree_style(style)
+        .run(io::stdin().lock(), stdout.lock());
+    if bracketed_paste {
+        print!("\x1b[?2004l");
+        let _ = io::stdout().flush();
+    }
+    if let Err(e) = outcome {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
 }
 
-pub struct Calculator {}
-
-#[derive(Debug)]
-pub enum Error {
-    BadToken(char), 
-    MismatchedParens,
-    DivisionByZero,
-    InvalidExpression,
-}
+fn run_demo() {
+    let expression = "2*2 + 48/4";
+    println!("Calculating: {}", expression);
 
-impl Calculator {
-    pub fn parse<T: AsRef<str>>(expr: T) -> Result<Vec<Token>, Error> {
-        let expr = expr.as_ref(); 
-        let chars: Vec<char> = expr.chars().collect();
-        let mut tokens: Vec<Token> = Vec::new(); 
-        let mut parens = Vec::new(); 
-        let mut i = 0;
-
-        while i < chars.len() {
-            let c = chars[i];
-            match c {
-                '0'..='9' => {
-                    let mut num_str = String::new();
-                    let mut j = i;
-                    
-                    while j < chars.len() && (chars[j].is_ascii_digit() || chars[j] == '.') {
-                        num_str.push(chars[j]);
-                        j += 1;
-                    }
-                    
-                    if let Ok(num) = num_str.parse::<f64>() {
-                        tokens.push(Token::Number(num));
-                    } else {
-                        return Err(Error::BadToken(c));
-                    }
-                    i = j - 1; 
-                }
-                '(' => {
-                    tokens.push(Token::Bracket('('));
-                    parens.push(c);
-                }
-                ')' => {
-                    tokens.push(Token::Bracket(')'));
-                    if let Some(p) = parens.pop() {
-                        if p != '(' {
-                            return Err(Error::MismatchedParens);
-                        }
-                    } else {
-                        return Err(Error::MismatchedParens);
-                    }
-                }
-                '+' => tokens.push(Token::Op(Operator::Add)), 
-                '-' => tokens.push(Token::Op(Operator::Subtract)), 
-                '*' => tokens.push(Token::Op(Operator::Multiply)),
-                '/' => tokens.push(Token::Op(Operator::Divide)),
-                ' ' | '\t' | '\n' => {}
-                _ => return Err(Error::BadToken(c)),
-                    }
-            i += 1;
-                }
+    match Calculator::calculate(expression) {
+        Ok(result) => println!("Result: {}", result),
+        Err(e) => println!("Error: {}", e),
+    }
 
-        if !parens.is_empty() {
-                    return Err(Error::MismatchedParens);
-                }
+    let test_expressions = vec![
+        "3 + 4 * 2",
+        "(3 + 4) * 2",
+        "10 / 2 - 3",
+        "2.5 * 4 + 1.5",
+        "((2 + 3) * 4) / 5",
+    ];
 
-                Ok(tokens)
-            }
+    let report = Calculator::calculate_batch(test_expressions);
+    println!("{}", report);
+    process::exit(report.worst_exit_code());
+}
 
-    pub fn to_postfix(mut tokens: Vec<Token>) -> Vec<Token> {
-            tokens.reverse(); 
-
-            let mut queue: Vec<Token> = Vec::new(); 
-            let mut stack: Vec<Token> = Vec::new(); 
-
-        while let Some(token) = tokens.pop() {
-            match token {
-                    Token::Number(_) => queue.push(token), 
-                Token::Op(ref op) => {
-                    while let Some(Token::Op(stack_op)) = stack.last() {
-                        if stack_op >= op {
-                            queue.push(stack.pop().unwrap());
-                        } else {
-                            break;
-                        }
-                        }
-                        stack.push(token); 
-                }
-                    Token::Bracket('(') => stack.push(token),
-                    Token::Bracket(')') => {
-                    while let Some(top) = stack.last() {
-                        if *top == Token::Bracket('(') {
-                            break;
-                        }
-                        queue.push(stack.pop().unwrap());
-                        }
-                        stack.pop(); 
-                }
-                _ => {}
-            }
+/// Evaluates every line of a file, reporting failures with their line and
+/// going on to the end.
+fn run_file(args: &[String]) {
+    let (path, json) = match args {
+        [path] => (path, false),
+        [path, flag] if flag == "--json" => (path, true),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
         }
+    };
 
-        while !stack.is_empty() {
-                    queue.push(stack.pop().unwrap());
+    let report = match Calculator::calculate_file(path) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(2);
         }
+    };
 
-        queue
+    for item in &report.items {
+        if json {
+            println!("{}", item.to_json());
+            continue;
+        }
+        match &item.result {
+            Ok(value) => println!("{}: {} = {}", item.line, item.snippet(), value),
+            Err(e) => eprintln!("{}:{}: {}", path, item.line, e),
+        }
     }
+    process::exit(report.worst_exit_code());
+}
 
-    pub fn evaluate(tokens: Vec<Token>) -> Result<f64, Error> {
-        let mut stack: Vec<f64> = Vec::new();
-
-        for token in tokens {
-            match token {
-                Token::Number(n) => stack.push(n),
-                Token::Op(op) => {
-                    if stack.len() < 2 {
-                        return Err(Error::InvalidExpression);
+/// Evaluates every line of a file into a table whose results are in
+/// engineering notation, aligned so their points line up.
+fn run_table(args: &[String]) {
+    let Some((path, flags)) = args.split_first() else {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    };
+    let mut sig_figs = DEFAULT_TABLE_SIG_FIGS;
+    let mut si_prefix = false;
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match (flag.as_str(), flags.as_slice().first()) {
+            ("--si", _) => si_prefix = true,
+            ("--sig-figs", Some(n)) => {
+                sig_figs = match n.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => {
+                        eprintln!("--sig-figs expects a positive integer, got '{}'", n);
+                        process::exit(2);
                     }
-                    let right = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    let result = op.apply(left, right)?;
-                    stack.push(result);
-                }
-                _ => return Err(Error::InvalidExpression),
+                };
+                flags.next();
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
             }
-        }
-
-        if stack.len() == 1 {
-            Ok(stack.pop().unwrap())
-        } else {
-            Err(Error::InvalidExpression)
         }
     }
 
-    pub fn calculate<T: AsRef<str>>(expr: T) -> Result<f64, Error> {
-        let tokens = Self::parse(expr)?;
-        let postfix = Self::to_postfix(tokens);
-        Self::evaluate(postfix)
-    }
+    let report = match Calculator::calculate_file(path) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(2);
+        }
+    };
+    let format = EngineeringFormat::new(sig_figs)
+        .si_prefix(si_prefix)
+        .aligned(true);
+    println!("{}", report.to_table(&format));
+    process::exit(report.worst_exit_code());
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_basic_arithmetic() {
-        assert_eq!(Calculator::calculate("2 + 3").unwrap(), 5.0);
-        assert_eq!(Calculator::calculate("10 - 4").unwrap(), 6.0);
-        assert_eq!(Calculator::calculate("3 * 4").unwrap(), 12.0);
-        assert_eq!(Calculator::calculate("15 / 3").unwrap(), 5.0);
-    }
-
-    #[test]
-    fn test_operator_precedence() {
-        assert_eq!(Calculator::calculate("2 + 3 * 4").unwrap(), 14.0);
-        assert_eq!(Calculator::calculate("10 - 6 / 2").unwrap(), 7.0);
-        assert_eq!(Calculator::calculate("2 * 3 + 4 * 5").unwrap(), 26.0);
-        assert_eq!(Calculator::calculate("20 / 4 - 2 * 2").unwrap(), 1.0);
-    }
-
-    #[test]
-    fn test_parentheses() {
-        assert_eq!(Calculator::calculate("(2 + 3) * 4").unwrap(), 20.0);
-        assert_eq!(Calculator::calculate("2 * (3 + 4)").unwrap(), 14.0);
-        assert_eq!(Calculator::calculate("(10 - 6) / 2").unwrap(), 2.0);
-        assert_eq!(Calculator::calculate("((2 + 3) * 4) / 5").unwrap(), 4.0);
-    }
-
-    #[test]
-    fn test_nested_parentheses() {
-        assert_eq!(Calculator::calculate("((2 + 3) * (4 + 1))").unwrap(), 25.0);
-        assert_eq!(Calculator::calculate("(2 * (3 + 4)) - 1").unwrap(), 13.0);
-        assert_eq!(Calculator::calculate("((10 / 2) + 3) * 2").unwrap(), 16.0);
-    }
-
-    #[test]
-    fn test_decimal_numbers() {
-        assert_eq!(Calculator::calculate("2.5 + 3.7").unwrap(), 6.2);
-        assert_eq!(Calculator::calculate("10.5 / 2.1").unwrap(), 5.0);
-        assert_eq!(Calculator::calculate("3.14 * 2").unwrap(), 6.28);
-        assert_eq!(Calculator::calculate("7.5 - 2.25").unwrap(), 5.25);
-    }
-
-    #[test]
-    fn test_whitespace_handling() {
-        assert_eq!(Calculator::calculate("  2   +   3  ").unwrap(), 5.0);
-        assert_eq!(Calculator::calculate("2+3").unwrap(), 5.0);
-        assert_eq!(Calculator::calculate(" ( 2 + 3 ) * 4 ").unwrap(), 20.0);
-        assert_eq!(Calculator::calculate("\t2\n*\t3\n").unwrap(), 6.0);
-    }
-
-    #[test]
-    fn test_complex_expressions() {
-        assert_eq!(Calculator::calculate("1 + 2 * 3 + 4").unwrap(), 11.0);
-        assert_eq!(Calculator::calculate("(1 + 2) * (3 + 4)").unwrap(), 21.0);
-        assert_eq!(Calculator::calculate("10 + 5 * 2 - 3 / 3").unwrap(), 19.0);
-        assert_eq!(Calculator::calculate("100 / 4 / 5 + 2 * 3").unwrap(), 11.0);
-    }
-
-    #[test]
-    fn test_division_by_zero() {
-        match Calculator::calculate("5 / 0") {
-            Err(Error::DivisionByZero) => (),
-            _ => panic!("Expected DivisionByZero error"),
+fn run_stats(args: &[String]) {
+    let (path, json) = match args {
+        [path] => (path, false),
+        [path, flag] if flag == "--json" => (path, true),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
         }
-        
-        match Calculator::calculate("10 / (2 - 2)") {
-            Err(Error::DivisionByZero) => (),
-            _ => panic!("Expected DivisionByZero error"),
-        }
-    }
+    };
 
-    #[test]
-    fn test_invalid_tokens() {
-        match Calculator::calculate("2 + @") {
-            Err(Error::BadToken('@')) => (),
-            _ => panic!("Expected BadToken error"),
-        }
-        
-        match Calculator::calculate("5 & 3") {
-            Err(Error::BadToken('&')) => (),
-            _ => panic!("Expected BadToken error"),
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            process::exit(2);
         }
+    };
+
+    let stats = Calculator::corpus_stats(contents.lines());
+    if json {
+        println!("{}", stats.to_json());
+    } else {
+        println!("{}", stats);
     }
+}
 
-    #[test]
-    fn test_mismatched_parentheses() {
-        match Calculator::calculate("(2 + 3") {
-            Err(Error::MismatchedParens) => (),
-            _ => panic!("Expected MismatchedParens error"),
-        }
-        
-        match Calculator::calculate("2 + 3)") {
-            Err(Error::MismatchedParens) => (),
-            _ => panic!("Expected MismatchedParens error"),
-        }
-        
-        match Calculator::calculate("((2 + 3)") {
-            Err(Error::MismatchedParens) => (),
-            _ => panic!("Expected MismatchedParens error"),
+fn run_estimate(args: &[String]) {
+    let (expr, sig_figs) = match args {
+        [expr] => (expr, DEFAULT_SIG_FIGS),
+        [expr, flag, n] if flag == "--sig-figs" => match n.parse() {
+            Ok(n) if n > 0 => (expr, n),
+            _ => {
+                eprintln!("--sig-figs expects a positive integer, got '{}'", n);
+                process::exit(2);
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
         }
-    }
+    };
 
-    #[test]
-    fn test_empty_expression() {
-        match Calculator::calculate("") {
-            Err(Error::InvalidExpression) => (),
-            _ => panic!("Expected InvalidExpression error"),
+    match Calculator::estimate(expr, sig_figs) {
+        Ok(estimate) => println!("{}", estimate),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
         }
     }
+}
 
-    #[test]
-    fn test_operator_precedence_comprehensive() {
-        // Test all combinations of operators
-        assert_eq!(Calculator::calculate("1 + 2 * 3 - 4 / 2").unwrap(), 5.0);
-        assert_eq!(Calculator::calculate("2 * 3 + 4 * 5 - 6 / 2").unwrap(), 23.0);
-        assert_eq!(Calculator::calculate("10 / 2 + 3 * 4 - 5").unwrap(), 12.0);
+fn run_add_regression(args: &[String]) {
+    let expr = match args {
+        [expr] => expr,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if expr.contains('\n') {
+        eprintln!("regression inputs must fit on one line");
+        process::exit(2);
     }
 
-    #[test]
-    fn test_large_numbers() {
-        assert_eq!(Calculator::calculate("1000000 + 2000000").unwrap(), 3000000.0);
-        assert_eq!(Calculator::calculate("999999 * 2").unwrap(), 1999998.0);
-        assert_eq!(Calculator::calculate("1000000 / 1000").unwrap(), 1000.0);
+    let expect = match Calculator::calculate(expr) {
+        Ok(value) => format!("value {}", value),
+        Err(e) => format!("error {:?}", e),
+    };
+
+    let next = fs::read_dir(REGRESSIONS_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| {
+                    entry
+                        .ok()?
+                        .path()
+                        .file_stem()?
+                        .to_str()?
+                        .parse::<u32>()
+                        .ok()
+                })
+                .max()
+                .unwrap_or(0)
+                + 1
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", REGRESSIONS_DIR, e);
+            process::exit(2);
+        });
+
+    let path = format!("{}/{:04}.txt", REGRESSIONS_DIR, next);
+    if let Err(e) = fs::write(&path, format!("input: {}\nexpect: {}\n", expr, expect)) {
+        eprintln!("{}: {}", path, e);
+        process::exit(2);
     }
+    println!("{}: {}", path, expect);
+}
 
-    #[test]
-    fn test_negative_results() {
-        assert_eq!(Calculator::calculate("3 - 5").unwrap(), -2.0);
-        assert_eq!(Calculator::calculate("10 / 2 - 8").unwrap(), -3.0);
-        assert_eq!(Calculator::calculate("(2 - 5) * 3").unwrap(), -9.0);
-    }
+fn run_speech(args: &[String]) {
+    let (expr, verbosity) = match args {
+        [expr] => (expr, Verbosity::Full),
+        [expr, flag] if flag == "--terse" => (expr, Verbosity::Terse),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
 
-    #[test]
-    fn test_fractional_results() {
-        assert_eq!(Calculator::calculate("1 / 2").unwrap(), 0.5);
-        assert_eq!(Calculator::calculate("3 / 4").unwrap(), 0.75);
-        assert_eq!(Calculator::calculate("7 / 8").unwrap(), 0.875);
+    println!("{}", Speech::new().verbosity(verbosity).speak(expr));
+    if Calculator::calculate(expr).is_err() {
+        process::exit(1);
     }
+}
 
-    #[test]
-    fn test_parse_function() {
-        let tokens = Calculator::parse("2 + 3").unwrap();
-        assert_eq!(tokens.len(), 3);
-        
-        match &tokens[0] {
-            Token::Number(n) => assert_eq!(*n, 2.0),
-            _ => panic!("Expected number token"),
-        }
-        
-        match &tokens[1] {
-            Token::Op(Operator::Add) => (),
-            _ => panic!("Expected add operator"),
+/// Prints the expression tree with every node's result. Box drawing is
+/// used only when the locale says the terminal is UTF-8.
+fn run_tree(args: &[String]) {
+    let (expr, style) = match args {
+        [expr] if utf8_locale() => (expr, TreeStyle::Unicode),
+        [expr] => (expr, TreeStyle::Ascii),
+        [expr, flag] if flag == "--ascii" => (expr, TreeStyle::Ascii),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
         }
-        
-        match &tokens[2] {
-            Token::Number(n) => assert_eq!(*n, 3.0),
-            _ => panic!("Expected number token"),
-        }
-    }
+    };
 
-    #[test]
-    fn test_to_postfix_function() {
-        let tokens = Calculator::parse("2 + 3 * 4").unwrap();
-        let postfix = Calculator::to_postfix(tokens);
-        
-        // Should be: 2 3 4 * +
-        assert_eq!(postfix.len(), 5);
-        
-        match &postfix[0] {
-            Token::Number(n) => assert_eq!(*n, 2.0),
-            _ => panic!("Expected number 2"),
-        }
-        
-        match &postfix[1] {
-            Token::Number(n) => assert_eq!(*n, 3.0),
-            _ => panic!("Expected number 3"),
-        }
-        
-        match &postfix[2] {
-            Token::Number(n) => assert_eq!(*n, 4.0),
-            _ => panic!("Expected number 4"),
-        }
-        
-        match &postfix[3] {
-            Token::Op(Operator::Multiply) => (),
-            _ => panic!("Expected multiply operator"),
+    match Calculator::evaluate_tree(expr) {
+        Ok(tree) => {
+            print!("{}", tree.render(style));
+            if tree.value.is_err() {
+                process::exit(1);
+            }
         }
-        
-        match &postfix[4] {
-            Token::Op(Operator::Add) => (),
-            _ => panic!("Expected add operator"),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
         }
     }
+}
 
-    #[test]
-    fn test_evaluate_function() {
-        let postfix = vec![
-            Token::Number(2.0),
-            Token::Number(3.0),
-            Token::Number(4.0),
-            Token::Op(Operator::Multiply),
-            Token::Op(Operator::Add),
-        ];
-        
-        let result = Calculator::evaluate(postfix).unwrap();
-        assert_eq!(result, 14.0); 
-    }
+/// Whether the first of `LC_ALL`, `LC_CTYPE` and `LANG` that is set names
+/// a UTF-8 encoding.
+fn utf8_locale() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|value| {
+            let value = value.to_ascii_lowercase();
+            value.contains("utf-8") || value.contains("utf8")
+        })
 }
 
-fn main() {
-    let expression = "2*2 + 48/4";
-    println!("Calculating: {}", expression);
-    
-    match Calculator::calculate(expression) {
-        Ok(result) => println!("Result: {}", result),
-        Err(e) => println!("Error: {:?}", e),
+/// Re-evaluates `path` whenever its modification time changes. Runs until
+/// interrupted; Ctrl-C's default handling is a clean exit since nothing is
+/// buffered between runs.
+fn run_watch(args: &[String]) {
+    let (path, flags) = match args.split_first() {
+        Some((path, flags)) if !path.starts_with("--") => (path, flags),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut interval = Duration::from_millis(DEFAULT_WATCH_INTERVAL_MS);
+    let mut color = io::stdout().is_terminal();
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--no-color" => color = false,
+            "--interval" => match flags.next().map(|ms| ms.parse::<u64>()) {
+                Some(Ok(ms)) if ms > 0 => interval = Duration::from_millis(ms),
+                _ => {
+                    eprintln!("--interval expects a positive number of milliseconds");
+                    process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        }
     }
-    
-    let test_expressions = vec![
-        "3 + 4 * 2",
-        "(3 + 4) * 2", 
-        "10 / 2 - 3",
-        "2.5 * 4 + 1.5",
-        "((2 + 3) * 4) / 5",
-    ];
-    
-    for expr in test_expressions {
-        match Calculator::calculate(expr) {
-            Ok(result) => println!("{} = {}", expr, result),
-            Err(e) => println!("{} -> Error: {:?}", expr, e),
+
+    let mut previous: Option<BatchReport> = None;
+    let mut last_modified: Option<SystemTime> = None;
+    loop {
+        match fs::metadata(path).and_then(|meta| meta.modified()) {
+            Ok(modified) if last_modified != Some(modified) => {
+                last_modified = Some(modified);
+                // An editor may replace the file between the two calls; the
+                // next poll picks up whatever ends up on disk.
+                if let Ok(contents) = fs::read_to_string(path) {
+                    let report = Calculator::calculate_lines(&contents);
+                    let changes = match &previous {
+                        Some(previous) => report.changes_since(previous),
+                        None => vec![LineChange::Unchanged; report.len()],
+                    };
+                    if color {
+                        print!("{}", CLEAR_SCREEN);
+                    }
+                    print!("{}", report.render_changes(&changes, color));
+                    println!("watching {} (Ctrl-C to stop)", path);
+                    let _ = io::stdout().flush();
+                    previous = Some(report);
+                }
+            }
+            Ok(_) => {}
+            Err(e) if previous.is_none() => {
+                eprintln!("{}: {}", path, e);
+                process::exit(2);
+            }
+            // Report a vanished file once and evaluate it again if it returns.
+            Err(e) => {
+                if last_modified.take().is_some() {
+                    eprintln!("{}: {}", path, e);
+                }
+            }
         }
+        thread::sleep(interval);
     }
 }
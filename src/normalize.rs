@@ -0,0 +1,276 @@
+use crate::Calculator;
+
+/// One character rewritten (or dropped) by [`Calculator::normalize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizationChange {
+    /// Character index in the original input.
+    pub pos: usize,
+    pub from: char,
+    /// Replacement, empty when the character was removed.
+    pub to: String,
+}
+
+/// Input after word-processor artifacts have been replaced by their ASCII
+/// equivalents, with enough bookkeeping to report positions in terms of the
+/// text the user actually typed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Normalized {
+    pub text: String,
+    pub changes: Vec<NormalizationChange>,
+    /// Original character index of each character in `text`.
+    offsets: Vec<usize>,
+    original_len: usize,
+}
+
+impl Normalized {
+    /// `expr` as it is, for [`EvalOptions::strict_input`](crate::EvalOptions::strict_input).
+    pub(crate) fn verbatim(expr: &str) -> Self {
+        let original_len = expr.chars().count();
+        Normalized {
+            text: expr.to_string(),
+            changes: Vec::new(),
+            offsets: (0..original_len).collect(),
+            original_len,
+        }
+    }
+
+    /// Maps a character index in `text` back to the original input. An index
+    /// at or past the end maps to the end of the original.
+    pub fn original_position(&self, pos: usize) -> usize {
+        self.offsets.get(pos).copied().unwrap_or(self.original_len)
+    }
+
+    pub fn is_changed(&self) -> bool {
+        !self.changes.is_empty()
+    }
+}
+
+/// ASCII replacement for characters commonly introduced by copying from
+/// documents: an empty string drops the character, `None` leaves it alone.
+fn replacement(c: char) -> Option<String> {
+    match c {
+        // en dash, em dash, minus sign
+        '\u{2013}' | '\u{2014}' | '\u{2212}' => Some("-".to_string()),
+        // multiplication sign, dot operator
+        '\u{00D7}' | '\u{22C5}' => Some("*".to_string()),
+        // division sign, division slash
+        '\u{00F7}' | '\u{2215}' => Some("/".to_string()),
+        // horizontal ellipsis, the three points it stands for
+        '\u{2026}' => Some("...".to_string()),
+        // curly and angle quotes
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201C}' | '\u{201D}' | '\u{201E}'
+        | '\u{2039}' | '\u{203A}' | '\u{00AB}' | '\u{00BB}' => Some(String::new()),
+        // no-break, thin and ideographic spaces
+        '\u{00A0}' | '\u{2009}' | '\u{202F}' | '\u{3000}' => Some(" ".to_string()),
+        // fullwidth forms of printable ASCII
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).map(String::from),
+        _ => None,
+    }
+}
+
+impl Calculator {
+    /// Replaces dashes, `×` and `÷`, `…`, fullwidth ASCII and unusual spaces
+    /// with their ASCII forms and removes typographic quotes. `parse` applies
+    /// this first, unless [`EvalOptions::strict_input`] is set, so pasted
+    /// text such as `１２ – “3” × 2` tokenizes like `12 - 3 * 2`; each change
+    /// is also a [`ParseWarning::Normalized`] from [`Calculator::parse_with`].
+    ///
+    /// [`EvalOptions::strict_input`]: crate::EvalOptions::strict_input
+    /// [`ParseWarning::Normalized`]: crate::ParseWarning::Normalized
+    pub fn normalize<T: AsRef<str>>(expr: T) -> Normalized {
+        let expr = expr.as_ref();
+        let mut text = String::with_capacity(expr.len());
+        let mut offsets = Vec::with_capacity(expr.len());
+        let mut changes = Vec::new();
+        let mut original_len = 0;
+
+        for (pos, c) in expr.chars().enumerate() {
+            original_len += 1;
+            match replacement(c) {
+                Some(to) => {
+                    for c in to.chars() {
+                        text.push(c);
+                        offsets.push(pos);
+                    }
+                    changes.push(NormalizationChange { pos, from: c, to });
+                }
+                None => {
+                    text.push(c);
+                    offsets.push(pos);
+                }
+            }
+        }
+
+        Normalized {
+            text,
+            changes,
+            offsets,
+            original_len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, EvalOptions, ParseWarning};
+
+    #[test]
+    fn test_ascii_input_untouched() {
+        let normalized = Calculator::normalize("(2 + 3) * 4");
+        assert_eq!(normalized.text, "(2 + 3) * 4");
+        assert!(!normalized.is_changed());
+        for i in 0..=11 {
+            assert_eq!(normalized.original_position(i), i);
+        }
+    }
+
+    #[test]
+    fn test_artifacts_replaced() {
+        assert_eq!(Calculator::normalize("10 \u{2013} 4").text, "10 - 4");
+        assert_eq!(Calculator::normalize("10\u{2014}4").text, "10-4");
+        assert_eq!(Calculator::normalize("\u{2212}3").text, "-3");
+        assert_eq!(
+            Calculator::normalize("\u{201C}2\u{201D} * \u{2018}3\u{2019}").text,
+            "2 * 3"
+        );
+        assert_eq!(Calculator::normalize("１２３＋（４）").text, "123+(4)");
+        assert_eq!(Calculator::normalize("1\u{00A0}+\u{3000}1").text, "1 + 1");
+        assert_eq!(Calculator::normalize("6 × 7 ⋅ 2").text, "6 * 7 * 2");
+        assert_eq!(Calculator::normalize("8 ÷ 4 ∕ 2").text, "8 / 4 / 2");
+        assert_eq!(Calculator::normalize("２＊３－１").text, "2*3-1");
+        assert_eq!(Calculator::normalize("1\u{2026}3").text, "1...3");
+    }
+
+    #[test]
+    fn test_changes_recorded() {
+        let normalized = Calculator::normalize("“5” – ２");
+        assert_eq!(
+            normalized.changes,
+            vec![
+                NormalizationChange {
+                    pos: 0,
+                    from: '“',
+                    to: String::new()
+                },
+                NormalizationChange {
+                    pos: 2,
+                    from: '”',
+                    to: String::new()
+                },
+                NormalizationChange {
+                    pos: 4,
+                    from: '–',
+                    to: "-".to_string()
+                },
+                NormalizationChange {
+                    pos: 6,
+                    from: '２',
+                    to: "2".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_offsets_skip_removed_characters() {
+        // original:   “ 1 2 ”   +   @
+        // index:      0 1 2 3 4 5 6 7
+        let normalized = Calculator::normalize("“12” + @");
+        assert_eq!(normalized.text, "12 + @");
+        let mapped: Vec<usize> = (0..normalized.text.chars().count())
+            .map(|i| normalized.original_position(i))
+            .collect();
+        assert_eq!(mapped, vec![1, 2, 4, 5, 6, 7]);
+        assert_eq!(normalized.original_position(6), 8);
+        assert_eq!(normalized.original_position(100), 8);
+    }
+
+    #[test]
+    fn test_offsets_repeat_expanded_characters() {
+        // original:   1 …     2 @
+        // index:      0 1     2 3
+        let normalized = Calculator::normalize("1…2@");
+        assert_eq!(normalized.text, "1...2@");
+        let mapped: Vec<usize> = (0..=6).map(|i| normalized.original_position(i)).collect();
+        assert_eq!(mapped, vec![0, 1, 1, 1, 2, 3, 4]);
+        assert_eq!(
+            Calculator::calculate("1…2"),
+            Err(Error::InvalidNumber {
+                literal: "1...2".to_string(),
+                pos: 0
+            })
+        );
+    }
+
+    #[test]
+    fn test_offsets_count_characters_not_bytes() {
+        // Each fullwidth digit is three bytes in UTF-8 but one position.
+        let normalized = Calculator::normalize("１２ – $");
+        assert_eq!(normalized.text, "12 - $");
+        assert_eq!(normalized.original_position(5), 5);
+    }
+
+    #[test]
+    fn test_normalized_input_evaluates() {
+        assert_eq!(Calculator::calculate("１２ – “3”").unwrap(), 9.0);
+        assert_eq!(Calculator::calculate("（２＋３）＊４").unwrap(), 20.0);
+        assert_eq!(
+            Calculator::calculate("7\u{00A0}\u{2014}\u{00A0}2").unwrap(),
+            5.0
+        );
+        assert_eq!(Calculator::calculate("6 × 7 ÷ 2 − 1").unwrap(), 20.0);
+        assert_eq!(Calculator::calculate("2⋅3 ∕ 4").unwrap(), 1.5);
+        // Positions still count the characters as typed.
+        assert_eq!(
+            Calculator::calculate("6 × 7 ÷ @"),
+            Err(Error::BadToken { ch: '@', pos: 8 })
+        );
+    }
+
+    #[test]
+    fn test_changes_are_warnings() {
+        let (tokens, warnings) =
+            Calculator::parse_with("“１” – 2", &EvalOptions::default()).unwrap();
+        assert_eq!(tokens, Calculator::parse("1 - 2").unwrap());
+        assert_eq!(
+            warnings,
+            Calculator::normalize("“１” – 2")
+                .changes
+                .into_iter()
+                .map(ParseWarning::Normalized)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(warnings.len(), 4);
+        assert_eq!(warnings[0].to_string(), "'“' at position 0 is ignored");
+        assert_eq!(warnings[1].to_string(), "'１' at position 1 is read as '1'");
+        assert_eq!(
+            Calculator::parse_with("1 - 2", &EvalOptions::default())
+                .unwrap()
+                .1,
+            []
+        );
+    }
+
+    #[test]
+    fn test_strict_input_leaves_text_alone() {
+        let strict = EvalOptions {
+            strict_input: true,
+            ..EvalOptions::default()
+        };
+        assert_eq!(
+            Calculator::calculate_with("10 – 4", &strict),
+            Err(Error::BadToken { ch: '–', pos: 3 })
+        );
+        assert_eq!(
+            Calculator::calculate_with("“2”", &strict),
+            Err(Error::BadToken { ch: '“', pos: 0 })
+        );
+        assert_eq!(
+            Calculator::calculate_with("１２", &strict),
+            Err(Error::BadToken { ch: '１', pos: 0 })
+        );
+        assert_eq!(Calculator::calculate_with("10 - 4", &strict), Ok(6.0));
+        assert_eq!(Calculator::parse_with("1 - 2", &strict).unwrap().1, []);
+    }
+}
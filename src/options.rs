@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+
+use crate::adjacency::Parsed;
+use crate::custom::Registry;
+use crate::{Calculator, Error, ParseWarning, Token};
+
+/// What `/` does with two whole-number operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Division {
+    /// `7 / 2` is `3.5`, as in Python 3.
+    #[default]
+    FloatAlways,
+    /// `7 / 2` is `3` and `-7 / 2` is `-3`, as in C: when both operand
+    /// values are whole numbers the quotient is truncated toward zero.
+    /// Operands are judged by value, so `(3.5 * 2) / 2` truncates too.
+    /// Anything with a fractional operand still divides exactly.
+    TruncateWhenBothIntegral,
+    /// `8 / 2` is `4` and `7 / 2` is [`Error::InexactDivision`]: whole
+    /// numbers must divide exactly. Judged by value, as for
+    /// `TruncateWhenBothIntegral`, and a fractional operand divides as usual.
+    /// A zero divisor is left to [`ZeroDivision`].
+    Exact,
+}
+
+/// Which unit `sin`, `cos` and `tan` take their argument in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Angle {
+    #[default]
+    Radians,
+    /// `sin(90)` is `1`. Converted to radians first, so `sin(180)` is as
+    /// close to zero as `sin(pi)` is, not exactly zero.
+    Degrees,
+}
+
+/// What `/`, `//` and `%` do with a zero divisor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ZeroDivision {
+    /// [`Error::DivisionByZero`].
+    #[default]
+    Error,
+    /// The IEEE 754 result: `1 / 0` is infinity, `-1 // 0` negative
+    /// infinity, and `0 / 0` and `1 % 0` NaN.
+    Ieee,
+}
+
+/// What separates a number's whole part from its fraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecimalSeparator {
+    /// `3.14`, with `,` between a function's arguments: `max(1.5, 2)`.
+    #[default]
+    Dot,
+    /// `3,14`, as in much of Europe, with `;` between arguments instead:
+    /// `max(1,5; 2)`. A `,` is only a decimal point before a digit, so
+    /// `max(1,2)` is `max(1.2)` and `,5` is `0.5`; anywhere else, like a
+    /// `.` or a `;` in a literal, it is [`Error::BadToken`]. Digits can
+    /// still be grouped with `_`, as in `1_000,5`.
+    Comma,
+}
+
+/// Settings for [`Calculator::calculate_with`] and
+/// [`Calculator::evaluate_with`], and for [`Calculator::parse_with`]. The
+/// values are `f64`s, except in [`Calculator::calculate_integers`], and
+/// `Division` only changes what `/` returns. `//` always floors (`-7 // 2` is `-4`)
+/// whatever the setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EvalOptions {
+    pub division: Division,
+    /// Read two operands in a row as a product: `2(3)`, `(1+2)(3+4)`,
+    /// `3pi`, `2 pi` and `2sqrt(4)`. Off by default, when those are
+    /// `Error::InvalidExpression`. Two number literals with only space
+    /// between, as in `2 2`, are `Error::SpaceInNumber` either way.
+    pub implicit_multiplication: bool,
+    pub angle: Angle,
+    pub zero_division: ZeroDivision,
+    pub decimal_separator: DecimalSeparator,
+    /// Read the input as typed: the dashes, `×`, fullwidth forms and other
+    /// pasted characters that [`Calculator::normalize`] rewrites are then
+    /// [`Error::BadToken`]. Off by default.
+    pub strict_input: bool,
+    /// The deepest brackets may nest, counting function calls' brackets;
+    /// deeper input is [`Error::TooDeep`] before anything else is checked.
+    /// No limit by default.
+    pub max_depth: Option<usize>,
+    /// Take the real odd root of a negative base, as in `(-8) ^ (1/3)`,
+    /// which is then `-2`. Off by default, when it is
+    /// [`Error::DomainError`] like every other power of a negative base
+    /// but whole ones; see [`Operator::Power`](crate::Operator::Power).
+    /// [`Calculator::calculate_complex`] gives the principal, complex root
+    /// unless this is on.
+    pub real_roots: bool,
+}
+
+impl Calculator {
+    /// [`Calculator::evaluate`] with explicit settings.
+    pub fn evaluate_with(tokens: Vec<Token>, options: &EvalOptions) -> Result<f64, Error> {
+        // Assignments last only as long as the expression.
+        Self::evaluate_at(tokens, options, &mut HashMap::new(), &Registry::default())
+            .map_err(|(error, _)| error)
+    }
+
+    /// [`Calculator::parse`] with explicit settings, also returning
+    /// warnings about input the settings make ambiguous.
+    pub fn parse_with<T: AsRef<str>>(
+        expr: T,
+        options: &EvalOptions,
+    ) -> Result<(Vec<Token>, Vec<ParseWarning>), Error> {
+        Self::parse_in(expr.as_ref(), options, false, &Registry::default())
+            .map(|(tokens, _, warnings)| (tokens, warnings))
+    }
+
+    /// [`Calculator::parse_with`], with what `registry` adds to the language
+    /// and, with `imaginary_unit`, `3i` read as `3 * i`. Every parse by a
+    /// `ConfiguredCalculator` goes through here, so any failure, `TooDeep`
+    /// included, reaches its parse-error hook.
+    pub(crate) fn parse_in(
+        expr: &str,
+        options: &EvalOptions,
+        imaginary_unit: bool,
+        registry: &Registry,
+    ) -> Result<Parsed, Error> {
+        let too_deep = options.max_depth.and_then(|max| {
+            let depth = Self::paren_balance(expr).max_depth;
+            (depth > max).then_some(Error::TooDeep { depth, max })
+        });
+        let parsed = match too_deep {
+            Some(e) => Err(e),
+            None => Self::scan(expr, options, imaginary_unit, registry),
+        };
+        if let Err(e) = &parsed {
+            registry.parse_failed(expr, e);
+        }
+        parsed
+    }
+
+    /// [`Calculator::calculate`] with explicit settings. Warnings are
+    /// dropped; use [`Calculator::parse_with`] to see them.
+    pub fn calculate_with<T: AsRef<str>>(expr: T, options: &EvalOptions) -> Result<f64, Error> {
+        let (tokens, _) = Self::parse_with(expr, options)?;
+        Self::evaluate_with(Self::try_to_postfix(tokens)?, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRUNCATE: EvalOptions = EvalOptions {
+        division: Division::TruncateWhenBothIntegral,
+        implicit_multiplication: false,
+        angle: Angle::Radians,
+        zero_division: ZeroDivision::Error,
+        decimal_separator: DecimalSeparator::Dot,
+        strict_input: false,
+        max_depth: None,
+        real_roots: false,
+    };
+
+    const IMPLICIT: EvalOptions = EvalOptions {
+        division: Division::FloatAlways,
+        implicit_multiplication: true,
+        angle: Angle::Radians,
+        zero_division: ZeroDivision::Error,
+        decimal_separator: DecimalSeparator::Dot,
+        strict_input: false,
+        max_depth: None,
+        real_roots: false,
+    };
+
+    #[test]
+    fn test_float_division_by_default() {
+        assert_eq!(Calculator::calculate("7 / 2").unwrap(), 3.5);
+        assert_eq!(
+            Calculator::calculate_with("7 / 2", &EvalOptions::default()).unwrap(),
+            3.5
+        );
+    }
+
+    #[test]
+    fn test_truncating_division() {
+        let cases = [
+            ("7 / 2", 3.0),
+            ("-7 / 2", -3.0),
+            ("7 / -2", -3.0),
+            ("-7 / -2", 3.0),
+            ("6 / 3", 2.0),
+            ("7.5 / 2", 3.75),
+            ("7 / 2.5", 2.8),
+            ("(3.5 * 2) / 2", 3.0),
+            ("1 / 3 * 3", 0.0),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(
+                Calculator::calculate_with(expr, &TRUNCATE).unwrap(),
+                expected,
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            Calculator::calculate_with("7 / 0", &TRUNCATE),
+            Err(Error::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_exact_division() {
+        let exact = EvalOptions {
+            division: Division::Exact,
+            ..EvalOptions::default()
+        };
+        for (expr, expected) in [
+            ("6 / 3", Ok(2.0)),
+            ("-8 / 2", Ok(-4.0)),
+            ("7 / 2", Err(Error::InexactDivision)),
+            ("-7 / 2", Err(Error::InexactDivision)),
+            ("7.5 / 2", Ok(3.75)),
+            ("(3.5 * 2) / 2", Err(Error::InexactDivision)),
+            ("7 // 2", Ok(3.0)),
+            ("7 / 0", Err(Error::DivisionByZero)),
+        ] {
+            assert_eq!(
+                Calculator::calculate_with(expr, &exact),
+                expected,
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_zero_divisor_under_each_division() {
+        for division in [
+            Division::FloatAlways,
+            Division::TruncateWhenBothIntegral,
+            Division::Exact,
+        ] {
+            let error = EvalOptions {
+                division,
+                ..EvalOptions::default()
+            };
+            let ieee = EvalOptions {
+                zero_division: ZeroDivision::Ieee,
+                ..error
+            };
+            for expr in ["7 / 0", "-7 / 0", "0 / 0", "7.5 / 0"] {
+                assert_eq!(
+                    Calculator::calculate_with(expr, &error),
+                    Err(Error::DivisionByZero),
+                    "{:?} {}",
+                    division,
+                    expr
+                );
+            }
+            let calc = |expr| Calculator::calculate_with(expr, &ieee).unwrap();
+            assert_eq!(calc("7 / 0"), f64::INFINITY, "{:?}", division);
+            assert_eq!(calc("-7 / 0"), f64::NEG_INFINITY, "{:?}", division);
+            assert_eq!(calc("7.5 / 0"), f64::INFINITY, "{:?}", division);
+            assert!(calc("0 / 0").is_nan(), "{:?}", division);
+        }
+    }
+
+    #[test]
+    fn test_floor_division_ignores_setting() {
+        for options in [EvalOptions::default(), TRUNCATE] {
+            let calc = |expr| Calculator::calculate_with(expr, &options);
+            assert_eq!(calc("7 // 2").unwrap(), 3.0);
+            // Floor and truncation differ for negative quotients.
+            assert_eq!(calc("-7 // 2").unwrap(), -4.0);
+            assert_eq!(calc("7 // -2").unwrap(), -4.0);
+            assert_eq!(calc("-7 // -2").unwrap(), 3.0);
+            assert_eq!(calc("7.5 // 2").unwrap(), 3.0);
+            assert_eq!(calc("1 + 7 // 2 * 2").unwrap(), 7.0);
+            assert_eq!(calc("7 // 0"), Err(Error::DivisionByZero));
+        }
+        assert!(Calculator::calculate("7 / / 2").is_err());
+    }
+
+    #[test]
+    fn test_division_functions_ignore_setting() {
+        let exact = EvalOptions {
+            division: Division::Exact,
+            ..EvalOptions::default()
+        };
+        for options in [EvalOptions::default(), TRUNCATE, exact] {
+            let calc = |expr| Calculator::calculate_with(expr, &options);
+            assert_eq!(calc("div(7, 2)").unwrap(), 3.5);
+            assert_eq!(calc("div(-7, 2)").unwrap(), -3.5);
+            assert_eq!(calc("floordiv(-7, 2)").unwrap(), -4.0);
+            assert_eq!(calc("floordiv(7, -2)").unwrap(), -4.0);
+            assert_eq!(calc("div(7, 0)"), Err(Error::DivisionByZero));
+            assert_eq!(calc("floordiv(7, 0)"), Err(Error::DivisionByZero));
+        }
+        let ieee = EvalOptions {
+            zero_division: ZeroDivision::Ieee,
+            ..TRUNCATE
+        };
+        assert_eq!(
+            Calculator::calculate_with("div(1, 0)", &ieee),
+            Ok(f64::INFINITY)
+        );
+        assert_eq!(
+            Calculator::calculate_with("floordiv(-1, 0)", &ieee),
+            Ok(f64::NEG_INFINITY)
+        );
+    }
+
+    #[test]
+    fn test_operator_between_brackets() {
+        // Whatever implicit multiplication does, an operator written
+        // between `)` and `(` is that operator, binary.
+        for op in ["+", "-", "*", "/", "//", "%", "^", "**"] {
+            let expected = Calculator::calculate(format!("9 {} 4", op)).unwrap();
+            for expr in [
+                format!("(7+2){}(3+1)", op),
+                format!("(7 + 2) {} (3 + 1)", op),
+            ] {
+                for options in [EvalOptions::default(), IMPLICIT] {
+                    let (tokens, warnings) = Calculator::parse_with(&expr, &options).unwrap();
+                    assert!(warnings.is_empty(), "{}", expr);
+                    assert_eq!(
+                        Calculator::evaluate_with(
+                            Calculator::try_to_postfix(tokens).unwrap(),
+                            &options
+                        ),
+                        Ok(expected),
+                        "{}",
+                        expr
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_minus_between_brackets() {
+        for options in [EvalOptions::default(), IMPLICIT] {
+            let calc = |expr| Calculator::calculate_with(expr, &options);
+            assert_eq!(calc("(1+2)-(3+4)"), Ok(-4.0));
+            assert_eq!(calc("(1+2) - (3+4)"), Ok(-4.0));
+            // U+2212 MINUS SIGN inside, read as unary.
+            assert_eq!(calc("(1+2)-(\u{2212}3)"), Ok(6.0));
+            assert_eq!(calc("(1+2)-\u{2212}(3)"), Ok(6.0));
+            assert_eq!(calc("(1+2)\u{2212}(3+4)"), Ok(-4.0));
+
+            let (tokens, _) = Calculator::parse_with("(1+2)-(3+4)", &options).unwrap();
+            assert!(!tokens.contains(&Token::Op(crate::Operator::Multiply)));
+            assert!(!tokens.contains(&Token::Op(crate::Operator::Negate)));
+        }
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        let calc = |expr| Calculator::calculate_with(expr, &IMPLICIT);
+        assert_eq!(calc("(1+2)(3+4)"), Ok(21.0));
+        assert_eq!(calc("(1+2) (3+4)"), Ok(21.0));
+        assert_eq!(calc("2(3)"), Ok(6.0));
+        assert_eq!(calc("2sqrt(16)"), Ok(8.0));
+        assert_eq!(calc("sqrt(4)(3)"), Ok(6.0));
+        assert_eq!(calc("1 + 2(3)"), Ok(7.0));
+        assert_eq!(calc("2(3)^2"), Ok(18.0));
+        assert_eq!(calc("-(2)(3)"), Ok(-6.0));
+        assert_eq!(calc("(2)3"), Ok(6.0));
+        assert_eq!(calc("3pi"), Ok(3.0 * std::f64::consts::PI));
+        assert_eq!(
+            calc("2 pi e"),
+            Ok(2.0 * std::f64::consts::PI * std::f64::consts::E)
+        );
+        assert_eq!(calc("2(3(4))"), Ok(24.0));
+        assert_eq!(calc("2(3(4)(5))6"), Ok(720.0));
+        assert_eq!(calc("2(3 + 4(5))"), Ok(46.0));
+        for expr in ["(2)-", "2(", "()(1)", "sqrt 4"] {
+            assert!(calc(expr).is_err(), "{}", expr);
+        }
+
+        for (expr, literal) in [("2sqrt(4)", "2sqrt"), ("3pi", "3pi")] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::InvalidNumber {
+                    literal: literal.to_string(),
+                    pos: 0
+                }),
+                "{}",
+                expr
+            );
+        }
+        for (expr, pos) in [("(1+2)(3+4)", 5), ("2(3)", 1), ("2 pi", 2), ("(2)3", 3)] {
+            assert_eq!(
+                Calculator::calculate(expr),
+                Err(Error::UnexpectedToken { pos }),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_decimal_comma() {
+        let comma = EvalOptions {
+            decimal_separator: DecimalSeparator::Comma,
+            ..EvalOptions::default()
+        };
+        let calc = |expr| Calculator::calculate_with(expr, &comma);
+        let cases = [
+            ("3,14 * 2", "3.14 * 2"),
+            ("max(1,5; 2,5)", "max(1.5, 2.5)"),
+            ("pow(2; 0,5)", "pow(2, 0.5)"),
+            ("min(1;2)", "min(1, 2)"),
+            ("1_000,25e2 - 0,5", "1_000.25e2 - 0.5"),
+            ("x = (y = 1,5; 2)", "x = (y = 1.5, 2)"),
+            ("0xFF / 0,5", "0xFF / 0.5"),
+        ];
+        for (with_comma, with_dot) in cases {
+            assert_eq!(
+                calc(with_comma),
+                Calculator::calculate(with_dot),
+                "{}",
+                with_comma
+            );
+        }
+
+        assert_eq!(calc("max(1,2)"), Ok(1.2));
+        assert_eq!(calc("3.14"), Err(Error::BadToken { ch: '.', pos: 1 }));
+        assert_eq!(calc("max(1, 2)"), Err(Error::BadToken { ch: ',', pos: 5 }));
+        assert_eq!(calc("1,"), Err(Error::BadToken { ch: ',', pos: 1 }));
+        assert_eq!(
+            calc("1_,5"),
+            Err(Error::InvalidNumber {
+                literal: "1_,5".to_string(),
+                pos: 0
+            })
+        );
+        assert_eq!(
+            Calculator::calculate("max(1; 2)"),
+            Err(Error::BadToken { ch: ';', pos: 5 })
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_implicit_multiplication_warns() {
+        let warnings = |expr| Calculator::parse_with(expr, &IMPLICIT).unwrap().1;
+
+        // Read left to right, but flagged.
+        assert_eq!(Calculator::calculate_with("6/2(1+2)", &IMPLICIT), Ok(9.0));
+        assert_eq!(
+            warnings("6/2(1+2)"),
+            [ParseWarning::AmbiguousImplicitMultiplication { position: 3 }]
+        );
+        assert_eq!(warnings("6 / (1+1)(3)").len(), 1);
+        assert_eq!(warnings("6 // 2sqrt(4)").len(), 1);
+        assert_eq!(warnings("6 % (-2)(3)").len(), 1);
+
+        // A `*` or a bracket in between settles it.
+        for expr in [
+            "6/2*(1+2)",
+            "(6/2)(1+2)",
+            "6*2(1+2)",
+            "6/(2(1+2))",
+            "2(3)/4",
+        ] {
+            assert!(warnings(expr).is_empty(), "{}", expr);
+        }
+        assert_eq!(
+            ParseWarning::AmbiguousImplicitMultiplication { position: 3 }.to_string(),
+            "implicit multiplication after a division at position 3 is read left to right"
+        );
+    }
+}
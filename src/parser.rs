@@ -0,0 +1,430 @@
+//! Reordering infix tokens into postfix.
+
+use crate::{Assoc, Calculator, Error, ParenBalance, Token};
+
+/// The most operators, calls, assignments and open brackets
+/// [`Calculator::try_to_postfix`] holds back at once, however the tokens were
+/// made; one more is [`Error::TooDeep`].
+pub const MAX_OPERATOR_STACK: usize = 100_000;
+
+impl Calculator {
+    /// Brackets that don't balance, which [`Calculator::parse`] never
+    /// returns but hand-built tokens may have, are `MismatchedParens`
+    /// with token indices in place of character positions. More than
+    /// [`MAX_OPERATOR_STACK`] tokens waiting for their operands at once is
+    /// `TooDeep`.
+    pub fn try_to_postfix(tokens: Vec<Token>) -> Result<Vec<Token>, Error> {
+        Self::try_to_postfix_with_origins(tokens).map(|(postfix, _)| postfix)
+    }
+
+    /// [`Calculator::try_to_postfix`] as it was before it could fail: tokens
+    /// it rejects give an empty postfix, which [`Calculator::evaluate`] then
+    /// rejects as [`Error::InvalidExpression`].
+    #[deprecated(note = "use `Calculator::try_to_postfix`, which reports unbalanced brackets")]
+    pub fn to_postfix(tokens: Vec<Token>) -> Vec<Token> {
+        Self::try_to_postfix(tokens).unwrap_or_default()
+    }
+
+    /// [`Calculator::try_to_postfix`], also returning for each postfix token
+    /// its index in `tokens`, so failures found while evaluating can be
+    /// traced back to the source.
+    pub fn try_to_postfix_with_origins(
+        tokens: Vec<Token>,
+    ) -> Result<(Vec<Token>, Vec<usize>), Error> {
+        ParenBalance::of_indices(&tokens).check()?;
+        let mut tokens: Vec<(usize, Token)> = tokens.into_iter().enumerate().collect();
+        tokens.reverse();
+
+        // Matched brackets never reach the output, so it is at most as
+        // long as the input.
+        let mut queue: Vec<(usize, Token)> = Vec::with_capacity(tokens.len());
+        let mut stack: Vec<(usize, Token)> =
+            Vec::with_capacity(tokens.len().min(MAX_OPERATOR_STACK));
+
+        while let Some((origin, token)) = tokens.pop() {
+            match token {
+                Token::Number(_) | Token::Identifier(_) => queue.push((origin, token)),
+                // A postfix operator's operand is already complete, and
+                // binds tighter than whatever is waiting on the stack.
+                Token::Op(ref op) if op.is_postfix() => queue.push((origin, token)),
+                // A prefix operator's operand hasn't been read yet, so
+                // nothing on the stack can be applied before it.
+                Token::Op(ref op) if op.is_unary() => stack.push((origin, token)),
+                Token::Function { .. } | Token::Call { .. } => stack.push((origin, token)),
+                // Binary operators only pop operators, so an assignment
+                // waits for its `)` or the end.
+                Token::Assign(_) => stack.push((origin, token)),
+                Token::Op(_) | Token::Custom { .. } => {
+                    let (precedence, assoc) = binding(&token).unwrap();
+                    while let Some((stacked, _)) = stack.last().and_then(|(_, t)| binding(t)) {
+                        // Equal precedence pops only for left-associative
+                        // operators.
+                        let pops = match assoc {
+                            Assoc::Right => stacked > precedence,
+                            Assoc::Left => stacked >= precedence,
+                        };
+                        if pops {
+                            queue.push(stack.pop().unwrap());
+                        } else {
+                            break;
+                        }
+                    }
+                    stack.push((origin, token));
+                }
+                Token::Bracket('(') => stack.push((origin, token)),
+                // Ends an argument: everything since its call's `(` is
+                // applied, and the comma itself is dropped.
+                Token::Comma => {
+                    while let Some((_, top)) = stack.last() {
+                        if *top == Token::Bracket('(') {
+                            break;
+                        }
+                        queue.push(stack.pop().unwrap());
+                    }
+                }
+                Token::Bracket(_) => {
+                    // Balanced, so there is a `(` to stop at.
+                    while let Some(entry) = stack.pop() {
+                        if entry.1 == Token::Bracket('(') {
+                            break;
+                        }
+                        queue.push(entry);
+                    }
+                    if let Some((_, Token::Function { .. } | Token::Call { .. })) = stack.last() {
+                        // The call these brackets held the arguments of.
+                        queue.push(stack.pop().unwrap());
+                    }
+                }
+            }
+            if stack.len() > MAX_OPERATOR_STACK {
+                return Err(Error::TooDeep {
+                    depth: stack.len(),
+                    max: MAX_OPERATOR_STACK,
+                });
+            }
+        }
+
+        while let Some(entry) = stack.pop() {
+            queue.push(entry);
+        }
+
+        let (origins, postfix) = queue.into_iter().unzip();
+        Ok((postfix, origins))
+    }
+
+    /// [`Calculator::try_to_postfix_with_origins`] as it was before it could
+    /// fail, empty where that is an error.
+    #[deprecated(
+        note = "use `Calculator::try_to_postfix_with_origins`, which reports unbalanced brackets"
+    )]
+    pub fn to_postfix_with_origins(tokens: Vec<Token>) -> (Vec<Token>, Vec<usize>) {
+        Self::try_to_postfix_with_origins(tokens).unwrap_or_default()
+    }
+}
+
+/// The precedence and associativity of an operator token.
+pub(crate) fn binding(token: &Token) -> Option<(u8, Assoc)> {
+    match token {
+        Token::Op(op) if op.is_right_associative() => Some((op.precedence(), Assoc::Right)),
+        Token::Op(op) => Some((op.precedence(), Assoc::Left)),
+        Token::Custom {
+            precedence, assoc, ..
+        } => Some((*precedence, *assoc)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Operator, ParseWarning};
+
+    #[test]
+    fn test_operator_precedence() {
+        assert_eq!(Calculator::calculate("2 + 3 * 4").unwrap(), 14.0);
+        assert_eq!(Calculator::calculate("10 - 6 / 2").unwrap(), 7.0);
+        assert_eq!(Calculator::calculate("2 * 3 + 4 * 5").unwrap(), 26.0);
+        assert_eq!(Calculator::calculate("20 / 4 - 2 * 2").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_parentheses() {
+        assert_eq!(Calculator::calculate("(2 + 3) * 4").unwrap(), 20.0);
+        assert_eq!(Calculator::calculate("2 * (3 + 4)").unwrap(), 14.0);
+        assert_eq!(Calculator::calculate("(10 - 6) / 2").unwrap(), 2.0);
+        assert_eq!(Calculator::calculate("((2 + 3) * 4) / 5").unwrap(), 4.0);
+    }
+
+    #[test]
+    fn test_nested_parentheses() {
+        assert_eq!(Calculator::calculate("((2 + 3) * (4 + 1))").unwrap(), 25.0);
+        assert_eq!(Calculator::calculate("(2 * (3 + 4)) - 1").unwrap(), 13.0);
+        assert_eq!(Calculator::calculate("((10 / 2) + 3) * 2").unwrap(), 16.0);
+    }
+
+    #[test]
+    fn test_operator_precedence_comprehensive() {
+        // Test all combinations of operators
+        assert_eq!(Calculator::calculate("1 + 2 * 3 - 4 / 2").unwrap(), 5.0);
+        assert_eq!(
+            Calculator::calculate("2 * 3 + 4 * 5 - 6 / 2").unwrap(),
+            23.0
+        );
+        assert_eq!(Calculator::calculate("10 / 2 + 3 * 4 - 5").unwrap(), 12.0);
+    }
+
+    #[test]
+    fn test_power_right_associative() {
+        assert_eq!(Calculator::calculate("2^3^2").unwrap(), 512.0);
+        assert_eq!(Calculator::calculate("(2^3)^2").unwrap(), 64.0);
+        assert_eq!(Calculator::calculate("2 ** 3 ** 2").unwrap(), 512.0);
+        assert_eq!(Calculator::calculate("2 ^ 3 ** 2").unwrap(), 512.0);
+        assert_eq!(
+            Calculator::try_to_postfix(Calculator::parse("2^3^2").unwrap()).unwrap(),
+            vec![
+                Token::Number(2.0),
+                Token::Number(3.0),
+                Token::Number(2.0),
+                Token::Op(Operator::Power),
+                Token::Op(Operator::Power),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_power_with_unary_minus() {
+        assert_eq!(Calculator::calculate("2 ^ -1").unwrap(), 0.5);
+        assert_eq!(Calculator::calculate("-2 ^ 2").unwrap(), -4.0);
+        assert_eq!(Calculator::calculate("(-2) ^ 2").unwrap(), 4.0);
+        assert_eq!(Calculator::calculate("2 ^ -1 ^ 2").unwrap(), 0.5);
+        for expr in ["2 ^", "^ 2", "2 ^ * 3", "2 * * 3", "2 *** 3"] {
+            assert!(Calculator::calculate(expr).is_err(), "{:?}", expr);
+        }
+    }
+
+    #[test]
+    fn test_modulo_precedence() {
+        assert_eq!(Calculator::calculate("10 % 3 + 1").unwrap(), 2.0);
+        assert_eq!(Calculator::calculate("2 + 10 % 4").unwrap(), 4.0);
+        assert_eq!(Calculator::calculate("2 * 10 % 4").unwrap(), 0.0);
+        assert_eq!(Calculator::calculate("10 % 4 * 2").unwrap(), 4.0);
+        assert_eq!(Calculator::calculate("(2 + 10) % 5").unwrap(), 2.0);
+        assert_eq!(Calculator::calculate("2 ^ 3 % 5").unwrap(), 3.0);
+        assert!(Calculator::calculate("% 10").is_err());
+    }
+
+    #[test]
+    fn test_percent() {
+        let calc = |expr| Calculator::calculate(expr).unwrap();
+        assert_eq!(calc("25%"), 0.25);
+        assert_eq!(calc("(10+5)%"), 0.15);
+        assert_eq!(calc("50% * 200"), 100.0);
+        assert_eq!(calc("200 * 50%"), 100.0);
+        assert_eq!(calc("1 + 50%"), 1.5);
+        assert_eq!(calc("50% + 1"), 1.5);
+        assert_eq!(calc("10 % 3 + 20%"), 1.2);
+        assert_eq!(calc("sqrt(25%)"), 0.5);
+        assert_eq!(calc("max(10%, 5%)"), 0.1);
+        assert_eq!(calc("200%%"), 0.02);
+        // Tighter than unary minus and `^`, applying only to the operand
+        // just before it.
+        assert_eq!(calc("-50%"), -0.5);
+        assert_eq!(calc("4 ^ 50%"), 2.0);
+        assert_eq!(calc("2 ^ 2%"), 2f64.powf(0.02));
+        // Before an operand, `%` is still a remainder.
+        assert_eq!(calc("10 % 4"), 2.0);
+        assert_eq!(calc("10%(4)"), 2.0);
+        assert_eq!(calc("7 % pi"), 7.0 % std::f64::consts::PI);
+        assert_eq!(calc("10 % (-4)"), 2.0);
+        // Before an operator it's a percentage, however it is spaced.
+        for expr in ["10%-4", "10 % -4", "10% - 4", "10 % - 4", "10 % -(4)"] {
+            assert_eq!(calc(expr), 0.1 - 4.0, "{}", expr);
+            assert_eq!(
+                Calculator::parse_with(expr, &Default::default()).unwrap().1,
+                [ParseWarning::AmbiguousPercent {
+                    position: expr.find('%').unwrap()
+                }],
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(calc("10 % +4"), 4.1);
+        let (_, warnings) =
+            Calculator::parse_with("50% * 2 + 10 % 3", &Default::default()).unwrap();
+        assert!(warnings.is_empty());
+
+        assert_eq!(
+            Calculator::parse("5%").unwrap(),
+            [Token::Number(5.0), Token::Op(Operator::Percent)]
+        );
+        assert_eq!(
+            Calculator::try_to_postfix(Calculator::parse("-5% ^ 2").unwrap()).unwrap(),
+            [
+                Token::Number(5.0),
+                Token::Op(Operator::Percent),
+                Token::Number(2.0),
+                Token::Op(Operator::Power),
+                Token::Op(Operator::Negate),
+            ]
+        );
+        for expr in ["%", "(%)", "2 * %", "sqrt(%)", "1, 2%"] {
+            assert!(Calculator::calculate(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_to_postfix_function() {
+        let tokens = Calculator::parse("2 + 3 * 4").unwrap();
+        let postfix = Calculator::try_to_postfix(tokens).unwrap();
+
+        // Should be: 2 3 4 * +
+        assert_eq!(postfix.len(), 5);
+
+        match &postfix[0] {
+            Token::Number(n) => assert_eq!(*n, 2.0),
+            _ => panic!("Expected number 2"),
+        }
+
+        match &postfix[1] {
+            Token::Number(n) => assert_eq!(*n, 3.0),
+            _ => panic!("Expected number 3"),
+        }
+
+        match &postfix[2] {
+            Token::Number(n) => assert_eq!(*n, 4.0),
+            _ => panic!("Expected number 4"),
+        }
+
+        match &postfix[3] {
+            Token::Op(Operator::Multiply) => (),
+            _ => panic!("Expected multiply operator"),
+        }
+
+        match &postfix[4] {
+            Token::Op(Operator::Add) => (),
+            _ => panic!("Expected add operator"),
+        }
+    }
+
+    #[test]
+    fn test_unmatched_brackets_rejected() {
+        let open = Token::Bracket('(');
+        let close = Token::Bracket(')');
+        let one = Token::Number(1.0);
+        let plus = Token::Op(Operator::Add);
+        let cases = [
+            (
+                vec![one.clone(), close.clone(), plus.clone(), one.clone()],
+                vec![],
+                vec![1],
+            ),
+            (vec![open.clone(), one.clone()], vec![0], vec![]),
+            (
+                vec![close.clone(), open.clone(), one.clone(), plus, open.clone()],
+                vec![1, 4],
+                vec![0],
+            ),
+            (
+                vec![open.clone(), open.clone(), one.clone(), close.clone()],
+                vec![0],
+                vec![],
+            ),
+            (
+                vec![one.clone(), close.clone(), close.clone()],
+                vec![],
+                vec![1, 2],
+            ),
+        ];
+        for (tokens, unmatched_open, unmatched_close) in cases {
+            let expected = Err(Error::MismatchedParens {
+                unmatched_open,
+                unmatched_close,
+            });
+            assert_eq!(
+                Calculator::try_to_postfix_with_origins(tokens.clone()),
+                expected
+            );
+            assert_eq!(
+                Calculator::try_to_postfix(tokens).map_err(|e| e.kind()),
+                Err("MismatchedParens")
+            );
+        }
+        assert_eq!(
+            Calculator::try_to_postfix(vec![open, one, close]),
+            Ok(vec![Token::Number(1.0)])
+        );
+    }
+
+    #[test]
+    fn test_to_postfix_long_bracket_runs() {
+        let brackets = |depth| {
+            let mut tokens = vec![Token::Bracket('('); depth];
+            tokens.push(Token::Number(1.0));
+            tokens.extend(vec![Token::Bracket(')'); depth]);
+            tokens
+        };
+
+        let postfix = Calculator::try_to_postfix(brackets(MAX_OPERATOR_STACK)).unwrap();
+        assert_eq!(postfix, vec![Token::Number(1.0)]);
+        assert_eq!(Calculator::evaluate(postfix).unwrap(), 1.0);
+        assert_eq!(
+            Calculator::try_to_postfix(brackets(MAX_OPERATOR_STACK + 1)),
+            Err(Error::TooDeep {
+                depth: MAX_OPERATOR_STACK + 1,
+                max: MAX_OPERATOR_STACK
+            })
+        );
+    }
+
+    #[test]
+    fn test_pending_operators_bounded() {
+        // Prefix minuses all wait for the one operand at the end.
+        let mut tokens = vec![Token::Op(Operator::Negate); MAX_OPERATOR_STACK];
+        tokens.push(Token::Number(1.0));
+        assert_eq!(
+            Calculator::evaluate(Calculator::try_to_postfix(tokens.clone()).unwrap()),
+            Ok(1.0)
+        );
+        tokens.insert(0, Token::Op(Operator::Negate));
+        assert_eq!(
+            Calculator::try_to_postfix(tokens),
+            Err(Error::TooDeep {
+                depth: MAX_OPERATOR_STACK + 1,
+                max: MAX_OPERATOR_STACK
+            })
+        );
+
+        // Right-associative powers pile up too, from a string as well.
+        let powers = |n| format!("1{}", " ^ 1".repeat(n));
+        assert_eq!(Calculator::calculate(powers(MAX_OPERATOR_STACK)), Ok(1.0));
+        assert_eq!(
+            Calculator::calculate(powers(MAX_OPERATOR_STACK + 1)),
+            Err(Error::TooDeep {
+                depth: MAX_OPERATOR_STACK + 1,
+                max: MAX_OPERATOR_STACK
+            })
+        );
+        let brackets = |n| format!("{}1{}", "(".repeat(n), ")".repeat(n));
+        assert_eq!(Calculator::calculate(brackets(MAX_OPERATOR_STACK)), Ok(1.0));
+        assert!(matches!(
+            Calculator::calculate(brackets(MAX_OPERATOR_STACK + 1)),
+            Err(Error::TooDeep { .. })
+        ));
+    }
+
+    #[test]
+    fn test_interleaved_bracket_stress() {
+        // ((((1))+((2)))+((3))) ... built up to a few thousand operands.
+        let mut expr = "((1))".to_string();
+        for i in 2..=5_000 {
+            expr = format!("(({})+(({})))", expr, i);
+        }
+        let expected = (5_000.0 * 5_001.0) / 2.0;
+        assert_eq!(Calculator::calculate(&expr).unwrap(), expected);
+
+        let tokens = Calculator::parse(&expr).unwrap();
+        let postfix = Calculator::try_to_postfix(tokens).unwrap();
+        assert_eq!(postfix.len(), 2 * 5_000 - 1);
+        assert_eq!(Calculator::evaluate(postfix).unwrap(), expected);
+    }
+}
@@ -0,0 +1,1132 @@
+use std::io::{self, BufRead, Write};
+
+use crate::builder::is_name;
+use crate::clipboard::{self, CopyText};
+use crate::environment::ANS;
+use crate::reserved;
+use crate::{
+    Calculator, DebugStep, Debugger, Environment, Error, Ledger, Span, Speech, TreeStyle, Verbosity,
+};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+/// None while pasting, so prompts don't interleave with the pasted lines.
+const PASTE_PROMPT: &str = "";
+const DEBUG_PROMPT: &str = "debug> ";
+
+/// What a terminal in bracketed paste mode sends before and after pasted
+/// text.
+pub(crate) const PASTE_START: &str = "\x1b[200~";
+pub(crate) const PASTE_END: &str = "\x1b[201~";
+
+/// How much of each pasted expression is shown beside its result.
+const PASTE_ECHO_WIDTH: usize = 60;
+
+/// Significant figures `:estimate on` rounds literals to unless given.
+const DEFAULT_ESTIMATE_SIG_FIGS: usize = 2;
+
+const HELP: &str = "\
+Enter an expression to evaluate it, e.g. 2 * (3 + 4).
+name = expression assigns a variable for later lines, e.g. x = 5.
+ans is the last result, e.g. ans + 1, and name = ! keeps it as name.
+A line that leaves brackets open continues on the next line.
+Pasted blocks are evaluated once the paste ends, one expression a line.
+Commands:
+  :store <name>  keep the last result as a variable, as name = ! does
+  :copy          copy the last result to the clipboard
+  :paste         take the lines up to :endpaste as a pasted block, for
+                 terminals without bracketed paste
+  :tree <expr>   show the expression tree with every node's result
+  :debug <expr>  step through the evaluation: Enter applies the next
+                 operation and shows the stack, c continues to the end,
+                 q stops without assigning anything
+  :estimate on [n]
+                 also evaluate with literals rounded to n (default 2)
+                 significant figures, showing ≈ 3.6e7 (exact: 36,483,106.115)
+  :estimate off  show exact results only
+  :verbose on    read results and errors out in words, for screen readers
+                 (:verbose terse reads only the outcome); takes the place
+                 of estimates while on
+  :verbose off   show results as numbers again
+  :ledger on     add up results as they come, showing the running total
+  :ledger total  show the total and how many results make it
+  :ledger csv    show every result and running total as CSV
+  :ledger clear  start the total again from 0
+  :ledger off    stop adding up results
+  :help          show this help
+  :quit          leave (also: quit, exit)";
+
+/// What a line given to [`Repl::handle_line`] produced, for the host to
+/// display however it likes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplOutput {
+    /// An expression's value, formatted for display.
+    Result(String),
+    /// Why an expression or command failed, as one line.
+    Error(String),
+    /// The response to a `:` command, possibly several lines.
+    Command(String),
+    /// The input so far leaves brackets open; later lines are appended to
+    /// it until they close. [`Repl::prompt`] changes to say so.
+    Continue,
+    /// Nothing to show, as for a blank line.
+    Empty,
+    /// The user asked to leave.
+    Quit,
+}
+
+/// Splits a pasted `block` into the expressions it holds, one a line, with
+/// a line that leaves brackets open joined to the ones after as when
+/// typed. Bracketed-paste markers and carriage returns are dropped, and so
+/// are blank lines between expressions. The last expression is returned
+/// apart if its brackets are still open at the end, since more may be
+/// typed to finish it.
+pub(crate) fn split_paste(block: &str) -> (Vec<String>, Option<String>) {
+    let block = block
+        .replace(PASTE_START, "")
+        .replace(PASTE_END, "")
+        .replace('\r', "");
+    let mut expressions = Vec::new();
+    let mut pending = String::new();
+    for line in block.lines() {
+        if pending.is_empty() && line.trim().is_empty() {
+            continue;
+        }
+        if !pending.is_empty() {
+            pending.push('\n');
+        }
+        pending.push_str(line);
+        if !Calculator::paren_balance(&pending).is_open() {
+            expressions.push(std::mem::take(&mut pending));
+        }
+    }
+    (expressions, Some(pending).filter(|open| !open.is_empty()))
+}
+
+/// The interactive loop's logic without the loop: the host reads a line,
+/// passes it to [`Repl::handle_line`], and shows the [`ReplOutput`]. No
+/// input or output happens inside, so a `Repl` can sit in one pane of a
+/// larger terminal program, or be driven by a script in a test.
+///
+/// ```
+/// use calculator::{Repl, ReplOutput};
+///
+/// let mut repl = Repl::new();
+/// assert_eq!(repl.handle_line("2 * (3 +"), ReplOutput::Continue);
+/// assert_eq!(repl.prompt(), "... ");
+/// assert_eq!(repl.handle_line("4)"), ReplOutput::Result("14".to_string()));
+/// assert_eq!(repl.handle_line("quit"), ReplOutput::Quit);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Repl {
+    /// Lines read so far of an expression whose brackets are still open.
+    pending: String,
+    /// Variables assigned by earlier lines.
+    env: Environment,
+    tree_style: TreeStyle,
+    /// Significant figures for `:estimate on`, or `None` while it's off.
+    estimate: Option<usize>,
+    /// How `:verbose on` reads results, or `None` while it's off.
+    speech: Option<Speech>,
+    /// What has been pasted so far, while a paste is under way.
+    paste: Option<String>,
+    /// The expression `:debug` is stepping through, while it is.
+    debug: Option<Debugger>,
+    /// Results added up since `:ledger on`, or `None` while it's off.
+    ledger: Option<Ledger>,
+    /// How `:copy` reaches the clipboard.
+    copy: CopyText,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Repl {
+            pending: String::new(),
+            env: Environment::new(),
+            tree_style: TreeStyle::Unicode,
+            estimate: None,
+            speech: None,
+            paste: None,
+            debug: None,
+            ledger: None,
+            copy: clipboard::copy,
+        }
+    }
+
+    /// How `:tree` draws branches; box-drawing characters by default.
+    pub fn tree_style(mut self, tree_style: TreeStyle) -> Self {
+        self.tree_style = tree_style;
+        self
+    }
+
+    /// The prompt to show before reading the next line.
+    pub fn prompt(&self) -> &'static str {
+        if self.paste.is_some() {
+            PASTE_PROMPT
+        } else if self.debug.is_some() {
+            DEBUG_PROMPT
+        } else if self.pending.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        }
+    }
+
+    /// Handles one line of input, without its line ending.
+    ///
+    /// A line starting a bracketed paste, or `:paste`, begins a block that
+    /// lasts until the paste ends or `:endpaste`. Its lines are only
+    /// collected, as [`ReplOutput::Empty`], and once it ends each
+    /// expression in it is evaluated in turn and shown as `expr = result`
+    /// in one [`ReplOutput::Command`]. An expression the block leaves open
+    /// continues on the next line typed.
+    ///
+    /// While `:debug` is stepping, a blank line is the next step, `c`
+    /// continues to the end and `q` stops; nothing else is read until then.
+    pub fn handle_line(&mut self, line: &str) -> ReplOutput {
+        if self.debug.is_some() {
+            return self.debug_line(line.trim());
+        }
+        if let Some(block) = &mut self.paste {
+            let (text, ended) = match line.split_once(PASTE_END) {
+                Some((text, _)) => (text, true),
+                None if line.trim() == ":endpaste" => ("", true),
+                None => (line, false),
+            };
+            block.push_str(text);
+            block.push('\n');
+            if !ended {
+                return ReplOutput::Empty;
+            }
+            let block = self.paste.take().unwrap_or_default();
+            return self.evaluate_paste(&block);
+        }
+        if let Some(rest) = line.strip_prefix(PASTE_START) {
+            // A paste part-way through an expression continues it.
+            let mut block = std::mem::take(&mut self.pending);
+            if !block.is_empty() {
+                block.push('\n');
+            }
+            self.paste = Some(block);
+            return self.handle_line(rest);
+        }
+        let trimmed = line.trim();
+        if trimmed == "quit" || trimmed == "exit" {
+            return ReplOutput::Quit;
+        }
+        if !self.pending.is_empty() {
+            self.pending.push('\n');
+            self.pending.push_str(line);
+            if Calculator::paren_balance(&self.pending).is_open() {
+                return ReplOutput::Continue;
+            }
+            let input = std::mem::take(&mut self.pending);
+            return self.evaluate(&input);
+        }
+
+        if trimmed.is_empty() {
+            return ReplOutput::Empty;
+        }
+        if let Some(command) = trimmed.strip_prefix(':') {
+            return self.command(command);
+        }
+        // `name = !`, which can't be an expression, since `!` isn't one.
+        if let Some(name) = trimmed
+            .strip_suffix('!')
+            .and_then(|rest| rest.trim_end().strip_suffix('='))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            return self.store(name);
+        }
+        if Calculator::paren_balance(line).is_open() {
+            self.pending = line.to_string();
+            return ReplOutput::Continue;
+        }
+        self.evaluate(line)
+    }
+
+    /// Runs the loop over `input` until it ends or the user quits,
+    /// writing prompts, results and errors to `output`.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            write!(output, "{}", self.prompt())?;
+            output.flush()?;
+            let Some(line) = lines.next().transpose()? else {
+                // End the prompt's line, so the shell's starts fresh.
+                return writeln!(output);
+            };
+            match self.handle_line(&line) {
+                ReplOutput::Result(text) | ReplOutput::Error(text) => writeln!(output, "{}", text)?,
+                ReplOutput::Command(text) => writeln!(output, "{}", text.trim_end())?,
+                ReplOutput::Continue | ReplOutput::Empty => {}
+                ReplOutput::Quit => return Ok(()),
+            }
+        }
+    }
+
+    /// Evaluates each expression in a pasted `block` as if typed, giving
+    /// one line per result or error; an open expression at the end is left
+    /// pending.
+    fn evaluate_paste(&mut self, block: &str) -> ReplOutput {
+        let (expressions, open) = split_paste(block);
+        let mut lines = Vec::new();
+        for expr in expressions {
+            let shown = Calculator::summarize(&expr, PASTE_ECHO_WIDTH);
+            match self.handle_line(&expr) {
+                ReplOutput::Result(text) => lines.push(format!("{} = {}", shown, text)),
+                ReplOutput::Error(text) => lines.push(format!("{}: {}", shown, text)),
+                ReplOutput::Command(text) => lines.push(text.trim_end().to_string()),
+                ReplOutput::Continue | ReplOutput::Empty => {}
+                ReplOutput::Quit => return ReplOutput::Quit,
+            }
+        }
+        if let Some(open) = open {
+            self.pending = open;
+        }
+        if lines.is_empty() {
+            ReplOutput::Empty
+        } else {
+            ReplOutput::Command(lines.join("\n"))
+        }
+    }
+
+    /// Starts stepping through `expr`, showing what the first step computes.
+    fn debug_start(&mut self, expr: &str) -> ReplOutput {
+        let variables: std::collections::HashMap<_, _> = self
+            .env
+            .variables()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        let debugger = match Debugger::start(expr, &variables) {
+            Ok(debugger) => debugger,
+            Err(e) => return ReplOutput::Error(format!("error: {}", e)),
+        };
+        let mut text = "debugging: Enter steps, c continues, q stops\n".to_string();
+        text.push_str(&highlight(expr, debugger.next_span(), "next"));
+        self.debug = Some(debugger);
+        ReplOutput::Command(text)
+    }
+
+    /// A line typed while `:debug` is stepping.
+    fn debug_line(&mut self, line: &str) -> ReplOutput {
+        let Some(debugger) = &mut self.debug else {
+            return ReplOutput::Empty;
+        };
+        let done = match line {
+            "" => debugger.step(),
+            "c" => DebugStep::Done(debugger.run()),
+            "q" => {
+                self.debug = None;
+                return ReplOutput::Command("debugging stopped".to_string());
+            }
+            _ => {
+                return ReplOutput::Error(
+                    "error: press Enter to step, c to continue or q to stop".to_string(),
+                );
+            }
+        };
+        let source = debugger.source().to_string();
+        match done {
+            DebugStep::Applied { span, .. } => {
+                let value = debugger.stack().last().copied().unwrap_or(f64::NAN);
+                let stack = debugger
+                    .stack()
+                    .iter()
+                    .map(|value| value.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                ReplOutput::Command(format!(
+                    "{}\nstack: [{}]",
+                    highlight(&source, span, &format!("= {}", value)),
+                    stack
+                ))
+            }
+            // Evaluated again for real, so assignments and `ans` only
+            // change once it has run to the end.
+            DebugStep::Done(Ok(_)) => {
+                self.debug = None;
+                self.evaluate(&source)
+            }
+            DebugStep::Done(Err(e)) => {
+                self.debug = None;
+                ReplOutput::Error(format!("error: {}", e))
+            }
+        }
+    }
+
+    /// Variables assigned so far in the session.
+    pub fn environment(&self) -> &Environment {
+        &self.env
+    }
+
+    /// Evaluates `input`, adding its value to the ledger if one is on. A
+    /// failure isn't recorded, so it leaves the total as it was.
+    fn evaluate(&mut self, input: &str) -> ReplOutput {
+        let output = self.evaluate_unrecorded(input);
+        match (output, &mut self.ledger, self.env.get(ANS)) {
+            (ReplOutput::Result(text), Some(ledger), Some(value)) => {
+                let total = ledger.record(input.trim(), value);
+                ReplOutput::Result(format!("{}  (total: {})", text, total))
+            }
+            (output, _, _) => output,
+        }
+    }
+
+    fn evaluate_unrecorded(&mut self, input: &str) -> ReplOutput {
+        if let Some(speech) = self.speech {
+            let outcome = self.env.calculate_located(input);
+            let spoken = speech.describe(input, outcome.as_ref().map_err(|e| &e.error));
+            return match outcome {
+                Ok(_) => ReplOutput::Result(spoken),
+                Err(_) => ReplOutput::Error(spoken),
+            };
+        }
+        let outcome = match self.estimate {
+            Some(sig_figs) => self
+                .env
+                .estimate(input, sig_figs)
+                .map(|estimate| estimate.to_string()),
+            None => self
+                .env
+                .calculate_located(input)
+                .map(|value| value.to_string()),
+        };
+        match outcome {
+            Ok(text) => ReplOutput::Result(text),
+            Err(e) => ReplOutput::Error(format!("error: {}", e)),
+        }
+    }
+
+    fn command(&mut self, command: &str) -> ReplOutput {
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        match name {
+            "quit" | "q" => ReplOutput::Quit,
+            "help" => ReplOutput::Command(HELP.to_string()),
+            "paste" => {
+                self.paste = Some(String::new());
+                ReplOutput::Command("pasting: end the block with :endpaste".to_string())
+            }
+            "endpaste" => ReplOutput::Error("error: :endpaste without :paste".to_string()),
+            "debug" if argument.trim().is_empty() => {
+                ReplOutput::Error("error: :debug needs an expression".to_string())
+            }
+            "debug" => self.debug_start(argument.trim()),
+            "tree" if argument.trim().is_empty() => {
+                ReplOutput::Error("error: :tree needs an expression".to_string())
+            }
+            "tree" => match Calculator::evaluate_tree(argument) {
+                Ok(tree) => ReplOutput::Command(tree.render(self.tree_style)),
+                Err(e) => ReplOutput::Error(format!("error: {}", e)),
+            },
+            "estimate" => match argument.split_whitespace().collect::<Vec<_>>()[..] {
+                ["off"] => {
+                    self.estimate = None;
+                    ReplOutput::Command("estimates off".to_string())
+                }
+                ["on"] => self.estimate_on(DEFAULT_ESTIMATE_SIG_FIGS),
+                ["on", n] => match n.parse() {
+                    Ok(n) if n > 0 => self.estimate_on(n),
+                    _ => ReplOutput::Error(format!(
+                        "error: :estimate on expects a positive number of figures, got '{}'",
+                        n
+                    )),
+                },
+                _ => {
+                    ReplOutput::Error("error: usage: :estimate on [n] | :estimate off".to_string())
+                }
+            },
+            "ledger" => self.ledger(argument),
+            "store" if argument.trim().is_empty() => {
+                ReplOutput::Error("error: :store needs a name".to_string())
+            }
+            "store" => self.store(argument.trim()),
+            "copy" => self.copy(),
+            "verbose" => {
+                let verbosity = match argument.trim() {
+                    "on" => Verbosity::Full,
+                    "terse" => Verbosity::Terse,
+                    "off" => {
+                        self.speech = None;
+                        return ReplOutput::Command("verbose output off".to_string());
+                    }
+                    _ => {
+                        return ReplOutput::Error(
+                            "error: usage: :verbose on | :verbose terse | :verbose off".to_string(),
+                        );
+                    }
+                };
+                self.speech = Some(Speech::new().verbosity(verbosity));
+                ReplOutput::Command("verbose output on".to_string())
+            }
+            _ => ReplOutput::Error(format!("error: unknown command ':{}'; try :help", name)),
+        }
+    }
+
+    /// Binds `name` to the last result, as `:store name` and `name = !` do.
+    fn store(&mut self, name: &str) -> ReplOutput {
+        if !is_name(name) {
+            return ReplOutput::Error(format!(
+                "error: can't store to '{}': a name is a letter followed by letters, digits or _",
+                name
+            ));
+        }
+        if let Some(meaning) = reserved::meaning(name, false) {
+            return ReplOutput::Error(format!(
+                "error: can't store to '{}': it is {}",
+                name, meaning
+            ));
+        }
+        let Some(value) = self.env.get(ANS) else {
+            return ReplOutput::Error(format!("error: {}", Error::NoPreviousResult));
+        };
+        self.env.assign(name, value);
+        ReplOutput::Command(format!("{} = {}", name, value))
+    }
+
+    /// Copies the last result to the clipboard, saying so either way.
+    fn copy(&self) -> ReplOutput {
+        let Some(value) = self.env.get(ANS) else {
+            return ReplOutput::Error(format!("error: {}", Error::NoPreviousResult));
+        };
+        let text = value.to_string();
+        ReplOutput::Command(match (self.copy)(&text) {
+            Ok(()) => format!("copied {} to the clipboard", text),
+            Err(reason) => format!(
+                "couldn't reach the clipboard ({}), so here it is to copy by hand: {}",
+                reason, text
+            ),
+        })
+    }
+
+    fn ledger(&mut self, argument: &str) -> ReplOutput {
+        let command = ReplOutput::Command;
+        match (argument.trim(), &mut self.ledger) {
+            ("on", Some(_)) => command("ledger already on".to_string()),
+            ("on", None) => {
+                self.ledger = Some(Ledger::new());
+                command("ledger on: results are added up as they come".to_string())
+            }
+            ("off", _) => {
+                self.ledger = None;
+                command("ledger off".to_string())
+            }
+            ("total", Some(ledger)) => {
+                let count = ledger.entries().len();
+                let plural = if count == 1 { "" } else { "s" };
+                command(format!("total: {} ({} result{})", ledger.total(), count, plural))
+            }
+            ("csv", Some(ledger)) => command(ledger.to_csv()),
+            ("clear", Some(ledger)) => {
+                ledger.clear();
+                command("ledger cleared".to_string())
+            }
+            ("total" | "csv" | "clear", None) => {
+                ReplOutput::Error("error: the ledger is off; start it with :ledger on".to_string())
+            }
+            _ => ReplOutput::Error(
+                "error: usage: :ledger on | :ledger total | :ledger csv | :ledger clear | :ledger off"
+                    .to_string(),
+            ),
+        }
+    }
+
+    fn estimate_on(&mut self, sig_figs: usize) -> ReplOutput {
+        self.estimate = Some(sig_figs);
+        let plural = if sig_figs == 1 { "" } else { "s" };
+        ReplOutput::Command(format!(
+            "estimates on: literals rounded to {} significant figure{}",
+            sig_figs, plural
+        ))
+    }
+}
+
+/// `source` with the characters in `span` underlined, followed by `note`.
+fn highlight(source: &str, span: Option<Span>, note: &str) -> String {
+    match span {
+        Some(Span { start, end }) => format!(
+            "{}\n{}{} {}",
+            source,
+            " ".repeat(start),
+            "^".repeat(end.saturating_sub(start).max(1)),
+            note
+        ),
+        None => format!("{}\n{}", source, note),
+    }
+}
+
+/// Runs a [`Repl`] with default settings over `input` and `output`, as
+/// `calculator` does over stdin and stdout when given no arguments.
+pub fn repl<R: BufRead, W: Write>(input: R, output: W) -> io::Result<()> {
+    Repl::new().run(input, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `script` line by line, returning each output.
+    fn converse(repl: &mut Repl, script: &[&str]) -> Vec<ReplOutput> {
+        script.iter().map(|line| repl.handle_line(line)).collect()
+    }
+
+    fn result(text: &str) -> ReplOutput {
+        ReplOutput::Result(text.to_string())
+    }
+
+    fn error(text: &str) -> ReplOutput {
+        ReplOutput::Error(text.to_string())
+    }
+
+    #[test]
+    fn test_results_and_errors() {
+        let mut repl = Repl::new();
+        assert_eq!(
+            converse(
+                &mut repl,
+                &["1 + 1", "  ", "10 / 4", "10 / (2 - 2)", "2 @ 3", "2 +"]
+            ),
+            [
+                result("2"),
+                ReplOutput::Empty,
+                result("2.5"),
+                error("error: division by zero at position 3"),
+                error("error: unexpected character '@' at position 2"),
+                error("error: unexpected token at position 2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut repl = Repl::new();
+        assert_eq!(
+            converse(
+                &mut repl,
+                &["x = 5", "x * 2 + 1", "x = x + 1", "(y", "= x)", "y + z"]
+            ),
+            [
+                result("5"),
+                result("11"),
+                result("6"),
+                ReplOutput::Continue,
+                result("6"),
+                error("error: undefined variable 'z' at position 4"),
+            ]
+        );
+        assert_eq!(repl.environment().get("y"), Some(6.0));
+    }
+
+    #[test]
+    fn test_ans() {
+        let mut repl = Repl::new();
+        assert_eq!(
+            converse(
+                &mut repl,
+                &[
+                    "ans", "2*3", "ans + 1", "ans / 0", "2 @", "ans", "(ans", "* 2)",
+                ]
+            ),
+            [
+                error("error: 'ans' has no value: nothing has been calculated yet at position 0"),
+                result("6"),
+                result("7"),
+                error("error: division by zero at position 4"),
+                error("error: unexpected character '@' at position 2"),
+                result("7"),
+                ReplOutput::Continue,
+                result("14"),
+            ]
+        );
+        assert_eq!(repl.environment().get("ans"), Some(14.0));
+    }
+
+    #[test]
+    fn test_continuation() {
+        let mut repl = Repl::new();
+        assert_eq!(repl.prompt(), "> ");
+        assert_eq!(
+            converse(&mut repl, &["(1 + 2", "", "* (3", "))"]),
+            [
+                ReplOutput::Continue,
+                ReplOutput::Continue,
+                ReplOutput::Continue,
+                result("7"),
+            ]
+        );
+        assert_eq!(repl.prompt(), "> ");
+
+        assert_eq!(
+            converse(&mut repl, &["sqrt(", "16", ")", "2 * 3"]),
+            [
+                ReplOutput::Continue,
+                ReplOutput::Continue,
+                result("4"),
+                result("6")
+            ]
+        );
+        // A stray `)` can't be fixed by more lines, so it fails at once.
+        assert_eq!(
+            repl.handle_line("1) + (2"),
+            error(
+                "error: unmatched ')' at position 1: remove 1 closing ')'; \
+                 unclosed '(' at position 5: 1 closing ')' missing at end"
+            )
+        );
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut repl = Repl::new().tree_style(TreeStyle::Ascii);
+        assert_eq!(
+            converse(&mut repl, &[":tree 1 + 2", ":tree", ":tree 1 +", ":nope"]),
+            [
+                ReplOutput::Command("+ = 3\n|-- 1\n`-- 2\n".to_string()),
+                error("error: :tree needs an expression"),
+                error("error: unexpected token at position 2"),
+                error("error: unknown command ':nope'; try :help"),
+            ]
+        );
+        assert!(
+            matches!(repl.handle_line(":help"), ReplOutput::Command(text) if text.contains(":tree"))
+        );
+    }
+
+    #[test]
+    fn test_estimate_mode() {
+        let mut repl = Repl::new();
+        let command = |text: &str| ReplOutput::Command(text.to_string());
+        assert_eq!(
+            converse(
+                &mut repl,
+                &[
+                    ":estimate on",
+                    "6042.1 * 6038.15",
+                    "x = 1.01",
+                    "1 / (x - 1)",
+                    "ans * 2",
+                    ":estimate on 3",
+                    "1234.5 + y",
+                    ":estimate off",
+                    "6042.1 * 6038.15",
+                ]
+            ),
+            [
+                command("estimates on: literals rounded to 2 significant figures"),
+                result("≈ 3.6e7 (exact: 36,483,106.115)"),
+                result("≈ 1.0e0 (exact: 1.01)"),
+                // The rounded literal is 1.0, but x keeps its exact value.
+                result("≈ 1.0e2 (exact: 99.99999999999991)"),
+                result("≈ 2.0e2 (exact: 199.99999999999983)"),
+                command("estimates on: literals rounded to 3 significant figures"),
+                error("error: undefined variable 'y' at position 9"),
+                command("estimates off"),
+                result("36483106.115"),
+            ]
+        );
+        // The exact evaluation's assignments and `ans` are kept.
+        assert_eq!(repl.environment().get("x"), Some(1.01));
+
+        for line in [
+            ":estimate",
+            ":estimate on 0",
+            ":estimate on two",
+            ":estimate maybe",
+        ] {
+            assert!(
+                matches!(repl.handle_line(line), ReplOutput::Error(_)),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_verbose_mode() {
+        let mut repl = Repl::new();
+        let command = |text: &str| ReplOutput::Command(text.to_string());
+        assert_eq!(
+            converse(
+                &mut repl,
+                &[
+                    ":verbose on",
+                    "(2+3)*4",
+                    "x = 2.5",
+                    "x * 2",
+                    "(x",
+                    "/ 0)",
+                    "2 + @",
+                    ":verbose terse",
+                    "ans + 1",
+                    ":estimate on",
+                    "6042.1 * 6038.15",
+                    ":verbose off",
+                    "6042.1 * 6038.15",
+                ]
+            ),
+            [
+                command("verbose output on"),
+                result("open paren two plus three close paren, times four, equals twenty"),
+                result("x is set to two point five, equals two point five"),
+                result("x times two, equals five"),
+                ReplOutput::Continue,
+                error("open paren x divided by zero close paren. Error: division by zero."),
+                error("Error: the at sign at character five is not allowed here."),
+                command("verbose output on"),
+                result("six"),
+                command("estimates on: literals rounded to 2 significant figures"),
+                // Too big to read as words, so digit by digit.
+                result("three six four eight three one zero six point one one five"),
+                command("verbose output off"),
+                result("≈ 3.6e7 (exact: 36,483,106.115)"),
+            ]
+        );
+
+        for line in [":verbose", ":verbose loud"] {
+            assert!(
+                matches!(repl.handle_line(line), ReplOutput::Error(_)),
+                "{}",
+                line
+            );
+        }
+    }
+
+    /// The transcript of running `script` through [`repl`].
+    fn transcript(script: &str) -> String {
+        let mut output = Vec::new();
+        repl(script.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_repl_transcript() {
+        assert_eq!(
+            transcript("1 + 2\n\n2 * (3\n+ 4)\n1 / 0\n:tree 2 ^ 3\nquit\n1 + 1\n"),
+            "\
+> 3
+> > ... 14
+> error: division by zero at position 2
+> ^ = 8
+├── 2
+└── 3
+> "
+        );
+    }
+
+    #[test]
+    fn test_repl_until_end_of_input() {
+        assert_eq!(
+            transcript("2 +\n   \n7"),
+            "> error: unexpected token at position 2\n> > 7\n> \n"
+        );
+        assert_eq!(transcript(""), "> \n");
+        // An expression still open at the end is dropped.
+        assert_eq!(transcript("(1 +\n"), "> ... \n");
+    }
+
+    #[test]
+    fn test_split_paste() {
+        let owned = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            split_paste("\x1b[200~1 + 1\r\n\n  \nmax(1,\n  2)\nx = 3\x1b[201~"),
+            (owned(&["1 + 1", "max(1,\n  2)", "x = 3"]), None)
+        );
+        assert_eq!(
+            split_paste("2 * 3\n(1 +\n2\n"),
+            (owned(&["2 * 3"]), Some("(1 +\n2".to_string()))
+        );
+        assert_eq!(split_paste(""), (vec![], None));
+        // A stray `)` closes nothing later lines could fix, so it ends its
+        // expression for evaluation to reject.
+        assert_eq!(split_paste("1)\n2"), (owned(&["1)", "2"]), None));
+    }
+
+    #[test]
+    fn test_paste_mode() {
+        let mut repl = Repl::new();
+        let command = |text: &str| ReplOutput::Command(text.to_string());
+        assert_eq!(
+            converse(
+                &mut repl,
+                &[
+                    "\x1b[200~x = 120.5",
+                    "",
+                    "x / 0",
+                    "(x -",
+                    "  20)",
+                    "sqrt(x\x1b[201~",
+                ]
+            ),
+            [
+                ReplOutput::Empty,
+                ReplOutput::Empty,
+                ReplOutput::Empty,
+                ReplOutput::Empty,
+                ReplOutput::Empty,
+                command(
+                    "x = 120.5 = 120.5\n\
+                     x / 0: error: division by zero at position 2\n\
+                     (x - 20) = 100.5"
+                ),
+            ]
+        );
+        // The open `sqrt(x` waits for the rest to be typed.
+        assert_eq!(repl.prompt(), "... ");
+        assert_eq!(repl.handle_line("* 2)"), result("15.524174696260024"));
+
+        assert_eq!(
+            converse(
+                &mut repl,
+                &[":paste", "1 + 1", ":verbose off", ":endpaste", ":endpaste"]
+            ),
+            [
+                command("pasting: end the block with :endpaste"),
+                ReplOutput::Empty,
+                ReplOutput::Empty,
+                command("1 + 1 = 2\nverbose output off"),
+                error("error: :endpaste without :paste"),
+            ]
+        );
+        assert_eq!(
+            converse(&mut repl, &[":paste", "", ":endpaste"])[2],
+            ReplOutput::Empty
+        );
+        // A paste that starts part-way through an expression continues it.
+        assert_eq!(
+            converse(&mut repl, &["(2 *", "\x1b[200~3)\n4 + 4\x1b[201~"]),
+            [ReplOutput::Continue, command("(2 * 3) = 6\n4 + 4 = 8")]
+        );
+        // A long expression is shown cut down, as in logs.
+        let long = format!("\x1b[200~{}0\x1b[201~", "1 + ".repeat(40));
+        assert_eq!(
+            repl.handle_line(&long),
+            command("1 + 1 + 1 + 1 + 1 + 1 ... 1 + 1 + 1 + 1 + 1 + 0 (161 chars) = 40")
+        );
+    }
+
+    #[test]
+    fn test_paste_transcript() {
+        // No prompts while the paste arrives, then the results together.
+        assert_eq!(
+            transcript("\x1b[200~1 + 1\n2 * 3\n\x1b[201~\n7\n"),
+            "> 1 + 1 = 2\n2 * 3 = 6\n> 7\n> \n"
+        );
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut repl = Repl::new();
+        let command = |text: &str| ReplOutput::Command(text.to_string());
+        assert_eq!(
+            converse(&mut repl, &["x = 4", ":debug 2 + 3 * (x - 1)", "", "", ""]),
+            [
+                result("4"),
+                command(
+                    "debugging: Enter steps, c continues, q stops\n\
+                     2 + 3 * (x - 1)\n\
+                     ^ next"
+                ),
+                command("2 + 3 * (x - 1)\n^ = 2\nstack: [2]"),
+                command("2 + 3 * (x - 1)\n    ^ = 3\nstack: [2, 3]"),
+                command("2 + 3 * (x - 1)\n         ^ = 4\nstack: [2, 3, 4]"),
+            ]
+        );
+        assert_eq!(repl.prompt(), "debug> ");
+        assert_eq!(
+            converse(&mut repl, &["", "", "2 + 2", "c", "ans"]),
+            [
+                command("2 + 3 * (x - 1)\n             ^ = 1\nstack: [2, 3, 4, 1]"),
+                command("2 + 3 * (x - 1)\n        ^^^^^^^ = 3\nstack: [2, 3, 3]"),
+                error("error: press Enter to step, c to continue or q to stop"),
+                result("11"),
+                result("11"),
+            ]
+        );
+        assert_eq!(repl.prompt(), "> ");
+
+        // Stopping part-way, or failing, leaves the session as it was.
+        assert_eq!(
+            converse(&mut repl, &[":debug y = x / 0", "", "q", "y", "ans"]),
+            [
+                command("debugging: Enter steps, c continues, q stops\ny = x / 0\n    ^ next"),
+                command("y = x / 0\n    ^ = 4\nstack: [4]"),
+                command("debugging stopped"),
+                error("error: undefined variable 'y' at position 0"),
+                result("11"),
+            ]
+        );
+        assert_eq!(
+            converse(
+                &mut repl,
+                &[":debug y = x / 0", "c", "y", ":debug", ":debug 1 +"]
+            ),
+            [
+                command("debugging: Enter steps, c continues, q stops\ny = x / 0\n    ^ next"),
+                error("error: division by zero at position 6"),
+                error("error: undefined variable 'y' at position 0"),
+                error("error: :debug needs an expression"),
+                error("error: unexpected token at position 2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ledger() {
+        let mut repl = Repl::new();
+        let command = |text: &str| ReplOutput::Command(text.to_string());
+        assert_eq!(
+            converse(
+                &mut repl,
+                &[
+                    "5",
+                    ":ledger total",
+                    ":ledger on",
+                    "0.1",
+                    "0.2",
+                    "1 / 0",
+                    "x = 0.3",
+                    "2 @",
+                    ":ledger total",
+                ]
+            ),
+            [
+                result("5"),
+                error("error: the ledger is off; start it with :ledger on"),
+                command("ledger on: results are added up as they come"),
+                result("0.1  (total: 0.1)"),
+                result("0.2  (total: 0.30000000000000004)"),
+                error("error: division by zero at position 2"),
+                result("0.3  (total: 0.6)"),
+                error("error: unexpected character '@' at position 2"),
+                command("total: 0.6 (3 results)"),
+            ]
+        );
+        assert_eq!(
+            converse(
+                &mut repl,
+                &[":ledger csv", ":ledger clear", "ans", ":ledger"]
+            ),
+            [
+                command(
+                    "expression,value,total\n0.1,0.1,0.1\n0.2,0.2,0.30000000000000004\nx = 0.3,0.3,0.6\n"
+                ),
+                command("ledger cleared"),
+                result("0.3  (total: 0.3)"),
+                error(
+                    "error: usage: :ledger on | :ledger total | :ledger csv | :ledger clear | :ledger off"
+                ),
+            ]
+        );
+        assert_eq!(
+            converse(&mut repl, &[":ledger off", "1"]),
+            [command("ledger off"), result("1")]
+        );
+    }
+
+    #[test]
+    fn test_store() {
+        let mut repl = Repl::new();
+        let command = |text: &str| ReplOutput::Command(text.to_string());
+        assert_eq!(
+            converse(
+                &mut repl,
+                &[
+                    ":store x",
+                    "2 * 21",
+                    ":store x",
+                    "x + 1",
+                    "total = !",
+                    "y=!",
+                    ":store",
+                    ":store pi",
+                    "ans = !",
+                    ":store 2x",
+                    "a b = !",
+                    "total * y",
+                ]
+            ),
+            [
+                error("error: 'ans' has no value: nothing has been calculated yet"),
+                result("42"),
+                command("x = 42"),
+                result("43"),
+                command("total = 43"),
+                command("y = 43"),
+                error("error: :store needs a name"),
+                error("error: can't store to 'pi': it is the constant π"),
+                error("error: can't store to 'ans': it is the last result"),
+                error(
+                    "error: can't store to '2x': a name is a letter followed by letters, digits or _"
+                ),
+                error(
+                    "error: can't store to 'a b': a name is a letter followed by letters, digits or _"
+                ),
+                result("1849"),
+            ]
+        );
+        // Storing isn't a result, so `ans` stays.
+        assert_eq!(repl.environment().get("x"), Some(42.0));
+        assert_eq!(repl.environment().get(ANS), Some(1849.0));
+        // `!` anywhere else is still an error.
+        assert_eq!(
+            repl.handle_line("x = 2 + !"),
+            error("error: unexpected character '!' at position 8")
+        );
+    }
+
+    #[test]
+    fn test_copy() {
+        use std::sync::Mutex;
+
+        static COPIED: Mutex<String> = Mutex::new(String::new());
+        let mut repl = Repl::new();
+        repl.copy = |text| {
+            *COPIED.lock().unwrap() = text.to_string();
+            Ok(())
+        };
+        assert_eq!(
+            converse(&mut repl, &[":copy", "1 / 8", ":copy"]),
+            [
+                error("error: 'ans' has no value: nothing has been calculated yet"),
+                result("0.125"),
+                ReplOutput::Command("copied 0.125 to the clipboard".to_string()),
+            ]
+        );
+        assert_eq!(*COPIED.lock().unwrap(), "0.125");
+
+        repl.copy = |_| Err("xclip: entity not found".to_string());
+        assert_eq!(
+            repl.handle_line(":copy"),
+            ReplOutput::Command(
+                "couldn't reach the clipboard (xclip: entity not found), \
+                 so here it is to copy by hand: 0.125"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_quit() {
+        for line in ["quit", "exit", " exit ", ":quit", ":q"] {
+            assert_eq!(
+                Repl::new().handle_line(line),
+                ReplOutput::Quit,
+                "{:?}",
+                line
+            );
+        }
+        // Also part-way through an expression.
+        let mut repl = Repl::new();
+        assert_eq!(
+            converse(&mut repl, &["(1", "quit"]),
+            [ReplOutput::Continue, ReplOutput::Quit]
+        );
+    }
+}
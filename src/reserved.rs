@@ -0,0 +1,154 @@
+//! Names the language gives a meaning of its own, which can't be assigned
+//! or registered.
+
+use crate::Calculator;
+
+/// What a reserved word is, which decides how it is matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Matched in any case, as the lexer reads constants: `PI` is `pi`.
+    Constant,
+    Function,
+    /// The last result of an [`Environment`](crate::Environment).
+    Ans,
+    /// Reserved only where `i` is read as the imaginary unit, by
+    /// [`Calculator::calculate_complex`].
+    ImaginaryUnit,
+}
+
+/// Every reserved word, with what it means. The lexer, an
+/// [`Environment`](crate::Environment)'s assignments and
+/// [`ConfiguredCalculator::register_function`](crate::ConfiguredCalculator::register_function)
+/// all check names against this, so a built-in name added to the language
+/// must be added here too.
+const RESERVED: [(&str, Kind, &str); 26] = [
+    ("pi", Kind::Constant, "the constant π"),
+    ("e", Kind::Constant, "Euler's number, the constant e"),
+    ("tau", Kind::Constant, "the constant τ, which is 2π"),
+    ("sqrt", Kind::Function, "the square root function"),
+    ("cbrt", Kind::Function, "the cube root function"),
+    ("sin", Kind::Function, "the sine function"),
+    ("cos", Kind::Function, "the cosine function"),
+    ("tan", Kind::Function, "the tangent function"),
+    ("ln", Kind::Function, "the natural logarithm function"),
+    ("log10", Kind::Function, "the base-10 logarithm function"),
+    ("exp", Kind::Function, "the exponential function"),
+    ("abs", Kind::Function, "the absolute value function"),
+    ("min", Kind::Function, "the minimum function"),
+    ("max", Kind::Function, "the maximum function"),
+    ("pow", Kind::Function, "the power function"),
+    ("div", Kind::Function, "the division function"),
+    ("floordiv", Kind::Function, "the floor division function"),
+    ("pct_change", Kind::Function, "the percent change function"),
+    ("safe_div", Kind::Function, "the safe division function"),
+    ("weighted_avg", Kind::Function, "the weighted mean function"),
+    ("bitand", Kind::Function, "the bitwise and function"),
+    ("bitor", Kind::Function, "the bitwise or function"),
+    ("bitxor", Kind::Function, "the bitwise xor function"),
+    ("shl", Kind::Function, "the left shift function"),
+    ("shr", Kind::Function, "the right shift function"),
+    ("ans", Kind::Ans, "the last result"),
+];
+
+/// `i` is reserved apart from the rest, only in complex mode.
+const IMAGINARY_UNIT: (&str, Kind, &str) = ("i", Kind::ImaginaryUnit, "the imaginary unit");
+
+/// What `name` means, if it is reserved; `i` is only with
+/// `imaginary_unit`.
+pub(crate) fn meaning(name: &str, imaginary_unit: bool) -> Option<&'static str> {
+    RESERVED
+        .iter()
+        .chain(imaginary_unit.then_some(&IMAGINARY_UNIT))
+        .find(|(word, kind, _)| match kind {
+            Kind::Constant => word.eq_ignore_ascii_case(name),
+            Kind::Function | Kind::Ans | Kind::ImaginaryUnit => *word == name,
+        })
+        .map(|(_, _, meaning)| *meaning)
+}
+
+impl Calculator {
+    /// Whether `name` means something built in, and so can't be assigned
+    /// or registered as a function: a constant in any case (`pi`, `PI`), a
+    /// built-in function, `ans`, or `i`, which
+    /// [`Calculator::calculate_complex`] reads as the imaginary unit. A UI
+    /// can check names with this before submitting them.
+    ///
+    /// ```
+    /// use calculator::Calculator;
+    ///
+    /// assert!(Calculator::is_reserved("sqrt"));
+    /// assert!(Calculator::is_reserved("E"));
+    /// assert!(!Calculator::is_reserved("rate"));
+    /// ```
+    pub fn is_reserved(name: &str) -> bool {
+        meaning(name, true).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, Function, function};
+
+    #[test]
+    fn test_no_built_in_name_missing() {
+        for function in Function::ALL {
+            assert!(Calculator::is_reserved(function.name()), "{:?}", function);
+        }
+        for (word, kind, _) in RESERVED {
+            match kind {
+                Kind::Constant => assert!(function::constant(word).is_some(), "{}", word),
+                Kind::Function => assert!(Function::from_name(word).is_some(), "{}", word),
+                Kind::Ans | Kind::ImaginaryUnit => {}
+            }
+        }
+        let constants = RESERVED
+            .iter()
+            .filter(|(_, kind, _)| *kind == Kind::Constant)
+            .count();
+        assert_eq!(RESERVED.len(), constants + Function::ALL.len() + 1);
+    }
+
+    #[test]
+    fn test_is_reserved() {
+        for name in ["pi", "Pi", "TAU", "e", "E", "max", "ans", "i"] {
+            assert!(Calculator::is_reserved(name), "{}", name);
+        }
+        for name in ["pie", "e0", "Max", "ANS", "I", "x", "answer"] {
+            assert!(!Calculator::is_reserved(name), "{}", name);
+        }
+        assert_eq!(meaning("i", false), None);
+        assert_eq!(meaning("i", true), Some("the imaginary unit"));
+    }
+
+    #[test]
+    fn test_every_path_rejects_reserved_words() {
+        for name in ["pi", "E", "sqrt", "floordiv", "ans"] {
+            let meaning = meaning(name, false).unwrap();
+            let assigned = Calculator::calculate(format!("{} = 1", name)).unwrap_err();
+            assert_eq!(
+                assigned,
+                Error::ReservedName {
+                    name: name.to_string(),
+                    pos: 0
+                }
+            );
+            assert!(assigned.to_string().contains(meaning), "{}", assigned);
+            let mut env = crate::Environment::new();
+            assert_eq!(env.calculate(format!("{} = 1", name)), Err(assigned));
+
+            let mut calc = Calculator::builder().build();
+            let registered = calc
+                .register_function(name, 1, |args| Ok(args[0]))
+                .unwrap_err();
+            assert_eq!(registered, Error::ReservedWord(name.to_string()));
+            assert!(registered.to_string().contains(meaning), "{}", registered);
+        }
+        // Registering `i` would be shadowed in complex mode.
+        let mut calc = Calculator::builder().build();
+        assert_eq!(
+            calc.register_function("i", 1, |args| Ok(args[0])),
+            Err(Error::ReservedWord("i".to_string()))
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Finding where an expression of one variable is zero.
+
+use std::collections::HashMap;
+
+use crate::custom::Registry;
+use crate::{Calculator, Error, EvalOptions, Token};
+
+/// How hard [`Calculator::solve_with`] looks for a root.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolveOptions {
+    /// How close the ends of the bracket around the root must come before
+    /// it is taken, relative to the root's size once that is over 1.
+    pub tolerance: f64,
+    /// How many times the expression may be evaluated, the search for a
+    /// sign change included, before giving up with
+    /// [`Error::NoConvergence`].
+    pub max_iterations: usize,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        SolveOptions {
+            tolerance: 1e-12,
+            max_iterations: 200,
+        }
+    }
+}
+
+/// The distance from the guess of the first samples either side of it,
+/// relative to the guess's size once that is over 1; each step out doubles
+/// it.
+const FIRST_STEP: f64 = 0.01;
+
+impl Calculator {
+    /// [`Calculator::solve_with`] under the default [`SolveOptions`].
+    ///
+    /// ```
+    /// use calculator::{Calculator, Error};
+    ///
+    /// let root = Calculator::solve("x*x - 2", "x", 1.0).unwrap();
+    /// assert!((root - 2f64.sqrt()).abs() < 1e-12);
+    /// assert_eq!(
+    ///     Calculator::solve("x*x + 1", "x", 0.0),
+    ///     Err(Error::NoConvergence { method: "solve" })
+    /// );
+    /// ```
+    pub fn solve<T: AsRef<str>>(expr: T, var: &str, guess: f64) -> Result<f64, Error> {
+        Self::solve_with(expr, var, guess, &SolveOptions::default())
+    }
+
+    /// A value of `var` near `guess` where `expr` is 0.
+    ///
+    /// Samples step out from `guess` either way, twice as far each time,
+    /// until two neighbours differ in sign; the root between them is then
+    /// closed in on by secant steps, with a bisection every other step so
+    /// the bracket at least halves. A sign change can also be a
+    /// discontinuity, as at the `0` of `1/x - 2`: a bracket that closes on
+    /// a value no nearer 0 than its ends is passed over, and the search
+    /// steps on out. A point where `expr` fails, as `1/x` does at `0`, or
+    /// isn't finite, is skipped as if it wasn't sampled.
+    ///
+    /// A root that touches 0 without crossing, as `x*x` does, is only
+    /// found where a sample lands on it. No sign change within
+    /// [`SolveOptions::max_iterations`] is [`Error::NoConvergence`]. Other
+    /// errors are returned as soon as they come up, such as a parse error
+    /// or [`Error::UndefinedVariable`] for any variable but `var`.
+    pub fn solve_with<T: AsRef<str>>(
+        expr: T,
+        var: &str,
+        guess: f64,
+        options: &SolveOptions,
+    ) -> Result<f64, Error> {
+        let postfix = Self::try_to_postfix(Self::parse(expr)?)?;
+        Solver {
+            postfix,
+            var,
+            evaluations: 0,
+            options,
+        }
+        .solve(guess)
+    }
+}
+
+/// A sample: where, and the finite value there.
+type Point = (f64, f64);
+
+struct Solver<'a> {
+    postfix: Vec<Token>,
+    var: &'a str,
+    evaluations: usize,
+    options: &'a SolveOptions,
+}
+
+impl Solver<'_> {
+    fn solve(&mut self, guess: f64) -> Result<f64, Error> {
+        let no_convergence = Err(Error::NoConvergence { method: "solve" });
+        let centre = match self.sample(guess)? {
+            Some((x, 0.0)) => return Ok(x),
+            Some(point) => Some(point),
+            None => None,
+        };
+        // The samples furthest out on either side so far.
+        let (mut left, mut right) = (centre, centre);
+        let step = FIRST_STEP * guess.abs().max(1.0);
+        for k in 0.. {
+            let distance = step * 2f64.powi(k);
+            for (outer, x) in [
+                (&mut right, guess + distance),
+                (&mut left, guess - distance),
+            ] {
+                if !x.is_finite() {
+                    return no_convergence;
+                }
+                let Some(point) = self.sample(x)? else {
+                    continue;
+                };
+                if point.1 == 0.0 {
+                    return Ok(x);
+                }
+                let crossed = outer
+                    .replace(point)
+                    .filter(|inner| inner.1.signum() != point.1.signum());
+                if let Some(inner) = crossed
+                    && let Some(root) = self.refine(inner, point)?
+                {
+                    return Ok(root);
+                }
+            }
+            if self.evaluations >= self.options.max_iterations {
+                break;
+            }
+        }
+        no_convergence
+    }
+
+    /// The root between `a` and `b`, whose values differ in sign, or
+    /// `None` if they straddle a discontinuity instead.
+    fn refine(&mut self, mut a: Point, mut b: Point) -> Result<Option<f64>, Error> {
+        let ends = a.1.abs().min(b.1.abs());
+        let mut bisect = false;
+        loop {
+            let midpoint = a.0 + (b.0 - a.0) / 2.0;
+            let secant = b.0 - b.1 * (b.0 - a.0) / (b.1 - a.1);
+            let inside = (a.0.min(b.0) < secant) && (secant < a.0.max(b.0));
+            let x = if bisect || !inside { midpoint } else { secant };
+            bisect = !bisect;
+            let width = (b.0 - a.0).abs();
+            if width <= self.options.tolerance * x.abs().max(1.0) || x == a.0 || x == b.0 {
+                let closest = if a.1.abs() <= b.1.abs() { a } else { b };
+                return Ok((closest.1.abs() <= ends).then_some(closest.0));
+            }
+            if self.evaluations >= self.options.max_iterations {
+                return Err(Error::NoConvergence { method: "solve" });
+            }
+            let Some(point) = self.sample(x)? else {
+                // Nothing to go on here, so halve towards one end.
+                if x == midpoint {
+                    return Ok(None);
+                }
+                bisect = true;
+                continue;
+            };
+            if point.1 == 0.0 {
+                return Ok(Some(x));
+            }
+            if point.1.signum() == a.1.signum() {
+                a = point;
+            } else {
+                b = point;
+            }
+        }
+    }
+
+    fn sample(&mut self, x: f64) -> Result<Option<Point>, Error> {
+        self.evaluations += 1;
+        Ok(value_at(&self.postfix, self.var, x)?.map(|y| (x, y)))
+    }
+}
+
+/// `postfix` with `var` bound to `x`, or `None` where it fails only there,
+/// by dividing by zero or leaving its domain, or isn't finite. Any other
+/// error, such as reading another variable, would come up everywhere.
+pub(crate) fn value_at(postfix: &[Token], var: &str, x: f64) -> Result<Option<f64>, Error> {
+    let mut variables = HashMap::from([(var.to_string(), x)]);
+    let value = Calculator::evaluate_at(
+        postfix.to_vec(),
+        &EvalOptions::default(),
+        &mut variables,
+        &Registry::default(),
+    );
+    match value {
+        Ok(y) if y.is_finite() => Ok(Some(y)),
+        Ok(_) | Err((Error::DivisionByZero | Error::DomainError { .. }, _)) => Ok(None),
+        Err((error, _)) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(expr: &str, guess: f64) -> Result<f64, Error> {
+        Calculator::solve(expr, "x", guess)
+    }
+
+    fn assert_root(expr: &str, guess: f64, expected: f64) {
+        let root = solve(expr, guess).unwrap();
+        assert!(
+            (root - expected).abs() <= 1e-10 * expected.abs().max(1.0),
+            "{} from {}: {}, not {}",
+            expr,
+            guess,
+            root,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_polynomial_roots() {
+        assert_root("x*x - 2", 1.0, 2f64.sqrt());
+        assert_root("x*x - 2", -1.0, -(2f64.sqrt()));
+        assert_root("x^3 - 2*x - 5", 2.0, 2.094_551_481_542_326_5);
+        assert_root("(x - 1) * (x - 2) * (x - 3)", 2.2, 2.0);
+        assert_root("x^3 - 1000", 0.0, 10.0);
+        assert_root("x - 1e6", 1e6 + 5.0, 1e6);
+        assert_root("cos(x) - x", 0.0, 0.739_085_133_215_160_6);
+        // Assignments inside still see the variable.
+        assert_root("(y = x - 3) * y * y", 0.0, 3.0);
+    }
+
+    #[test]
+    fn test_root_at_guess() {
+        assert_eq!(solve("x*x - 4", 2.0), Ok(2.0));
+        assert_eq!(solve("x^2", 0.0), Ok(0.0));
+        assert_eq!(solve("sin(x)", 0.0), Ok(0.0));
+    }
+
+    #[test]
+    fn test_no_root() {
+        let no_convergence = Err(Error::NoConvergence { method: "solve" });
+        assert_eq!(solve("x*x + 1", 0.0), no_convergence);
+        assert_eq!(solve("exp(x) + 1", 3.0), no_convergence);
+        assert_eq!(solve("5", 0.0), no_convergence);
+        // Touching 0 without crossing it is missed away from the guess.
+        assert_eq!(solve("(x - 1) ^ 2", 0.0), no_convergence);
+        // Cut short before a sign change is found.
+        let brief = SolveOptions {
+            max_iterations: 4,
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            Calculator::solve_with("x - 100", "x", 0.0, &brief),
+            no_convergence
+        );
+        assert_eq!(
+            Calculator::solve("x - 100", "x", 0.0).map(f64::round),
+            Ok(100.0)
+        );
+    }
+
+    #[test]
+    fn test_discontinuous() {
+        // The sign change at the pole is passed over for the root.
+        assert_root("1/x - 2", 1.0, 0.5);
+        assert_root("1/x - 2", -1.0, 0.5);
+        assert_root("1/x - 2", 0.0, 0.5);
+        assert_eq!(
+            solve("1/x", 1.0),
+            Err(Error::NoConvergence { method: "solve" })
+        );
+        // Samples outside the domain are skipped.
+        assert_root("(x - 4) ^ 0.5 - 1", 4.5, 5.0);
+    }
+
+    #[test]
+    fn test_tolerance() {
+        let loose = SolveOptions {
+            tolerance: 1e-3,
+            ..SolveOptions::default()
+        };
+        let root = Calculator::solve_with("x*x - 2", "x", 1.0, &loose).unwrap();
+        assert!((root - 2f64.sqrt()).abs() < 1e-3, "{}", root);
+    }
+
+    #[test]
+    fn test_refuses_other_variables() {
+        assert_eq!(
+            solve("x * y - 1", 1.0),
+            Err(Error::UndefinedVariable("y".to_string()))
+        );
+        // Even where the guess itself divides by zero.
+        assert_eq!(
+            solve("y / x", 0.0),
+            Err(Error::UndefinedVariable("y".to_string()))
+        );
+        assert_eq!(
+            Calculator::solve("t - 1", "x", 0.0),
+            Err(Error::UndefinedVariable("t".to_string()))
+        );
+        assert_eq!(solve("ans - x", 0.0), Err(Error::NoPreviousResult));
+        assert_eq!(solve("x +", 0.0), Err(Error::UnexpectedToken { pos: 2 }));
+    }
+}
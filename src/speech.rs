@@ -0,0 +1,479 @@
+use crate::{Calculator, Error, Function, Operator, Token};
+
+/// Integers up to this magnitude are read as words; larger ones, like the
+/// digits after a decimal point, are read one digit at a time.
+const LARGEST_SPOKEN_INTEGER: f64 = 999_999.0;
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+/// How much of a calculation [`Speech`] reads out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Only the result or the error.
+    Terse,
+    /// The expression, then the result or the error.
+    Full,
+}
+
+/// Renders calculations as plain English for screen readers, e.g.
+/// `(2+3)*4` as "open paren two plus three close paren, times four, equals
+/// twenty". The phrasing is pinned by tests; change it deliberately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Speech {
+    verbosity: Verbosity,
+}
+
+impl Default for Speech {
+    fn default() -> Self {
+        Speech::new()
+    }
+}
+
+impl Speech {
+    pub fn new() -> Self {
+        Speech {
+            verbosity: Verbosity::Full,
+        }
+    }
+
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Evaluates `expr` and describes it and its outcome in words. Errors
+    /// are full sentences; positions are spelled out and counted from one.
+    pub fn speak<T: AsRef<str>>(&self, expr: T) -> String {
+        let expr = expr.as_ref();
+        let tokens = match Calculator::parse(expr) {
+            Ok(tokens) => tokens,
+            Err(e) => return describe_parse_error(&e, expr),
+        };
+        let reading = read_tokens(&tokens);
+        let outcome = Calculator::try_to_postfix(tokens).and_then(Calculator::evaluate);
+        self.phrase(&reading, outcome.as_ref())
+    }
+
+    /// [`Speech::speak`] for `expr` evaluated elsewhere to `outcome`, as
+    /// in a session where it reads variables.
+    pub(crate) fn describe(&self, expr: &str, outcome: Result<&f64, &Error>) -> String {
+        match Calculator::parse(expr) {
+            Ok(tokens) => self.phrase(&read_tokens(&tokens), outcome),
+            Err(e) => describe_parse_error(&e, expr),
+        }
+    }
+
+    fn phrase(&self, reading: &str, outcome: Result<&f64, &Error>) -> String {
+        match (self.verbosity, outcome) {
+            (Verbosity::Terse, Ok(value)) => number_words(*value),
+            (Verbosity::Terse, Err(e)) => describe_error(e),
+            (Verbosity::Full, Ok(value)) => format!("{}, equals {}", reading, number_words(*value)),
+            (Verbosity::Full, Err(e)) => format!("{}. {}", reading, describe_error(e)),
+        }
+    }
+}
+
+fn read_tokens(tokens: &[Token]) -> String {
+    let mut reading = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            // A pause after each closing paren keeps the grouping audible.
+            if matches!(tokens[i - 1], Token::Bracket(')')) {
+                reading.push(',');
+            }
+            reading.push(' ');
+        }
+        match token {
+            Token::Number(n) => reading.push_str(&number_words(*n)),
+            Token::Op(op) => reading.push_str(operator_words(op)),
+            Token::Custom { symbol, .. } => reading.push(*symbol),
+            Token::Bracket('(') => reading.push_str("open paren"),
+            Token::Bracket(_) => reading.push_str("close paren"),
+            Token::Function { function, .. } => reading.push_str(function_words(*function)),
+            Token::Call { name, .. } => {
+                reading.push_str(name);
+                reading.push_str(" of");
+            }
+            Token::Comma => reading.push_str("and"),
+            Token::Identifier(name) => reading.push_str(name),
+            Token::Assign(name) => {
+                reading.push_str(name);
+                reading.push_str(" is set to");
+            }
+        }
+    }
+    reading
+}
+
+fn operator_words(op: &Operator) -> &'static str {
+    match op {
+        Operator::Add => "plus",
+        Operator::Subtract => "minus",
+        Operator::Multiply => "times",
+        Operator::Divide => "divided by",
+        Operator::FloorDivide => "floor divided by",
+        Operator::Modulo => "modulo",
+        Operator::Power => "to the power of",
+        Operator::Negate => "negative",
+        Operator::Percent => "percent",
+    }
+}
+
+fn function_words(function: Function) -> &'static str {
+    match function {
+        Function::Sqrt => "square root of",
+        Function::Cbrt => "cube root of",
+        Function::Sin => "sine of",
+        Function::Cos => "cosine of",
+        Function::Tan => "tangent of",
+        Function::Ln => "natural log of",
+        Function::Log10 => "log base ten of",
+        Function::Exp => "e to the power of",
+        Function::Abs => "absolute value of",
+        Function::Min => "minimum of",
+        Function::Max => "maximum of",
+        Function::Pow => "power of",
+        Function::Div => "quotient of",
+        Function::FloorDiv => "floored quotient of",
+        Function::PctChange => "percentage change of",
+        Function::SafeDiv => "safe quotient of",
+        Function::WeightedAvg => "weighted average of",
+        Function::BitAnd => "bitwise and of",
+        Function::BitOr => "bitwise or of",
+        Function::BitXor => "bitwise exclusive or of",
+        Function::Shl => "left shift of",
+        Function::Shr => "right shift of",
+    }
+}
+
+/// `20.0` → "twenty", `3.25` → "three point two five",
+/// `1234567.0` → "one two three four five six seven".
+fn number_words(value: f64) -> String {
+    if value.is_nan() {
+        return "not a number".to_string();
+    }
+    if value.is_infinite() {
+        let sign = if value < 0.0 { "minus " } else { "" };
+        return format!("{}infinity", sign);
+    }
+
+    let text = format!("{}", value.abs());
+    let (integer, fraction) = match text.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (text.as_str(), None),
+    };
+
+    let mut words = String::new();
+    if value < 0.0 {
+        words.push_str("minus ");
+    }
+    if value.abs().trunc() <= LARGEST_SPOKEN_INTEGER {
+        words.push_str(&integer_words(integer.parse().unwrap()));
+    } else {
+        words.push_str(&digit_words(integer));
+    }
+    if let Some(fraction) = fraction {
+        words.push_str(" point ");
+        words.push_str(&digit_words(fraction));
+    }
+    words
+}
+
+fn digit_words(digits: &str) -> String {
+    digits
+        .chars()
+        .filter_map(|d| d.to_digit(10))
+        .map(|d| ONES[d as usize])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Words for 0..=999_999.
+fn integer_words(n: u32) -> String {
+    if n < 20 {
+        return ONES[n as usize].to_string();
+    }
+    if n < 100 {
+        return match n % 10 {
+            0 => TENS[(n / 10) as usize].to_string(),
+            ones => format!("{}-{}", TENS[(n / 10) as usize], ONES[ones as usize]),
+        };
+    }
+    let (unit, name) = if n < 1000 {
+        (100, "hundred")
+    } else {
+        (1000, "thousand")
+    };
+    match n % unit {
+        0 => format!("{} {}", integer_words(n / unit), name),
+        rest => format!(
+            "{} {} {}",
+            integer_words(n / unit),
+            name,
+            integer_words(rest)
+        ),
+    }
+}
+
+fn count_words(n: usize) -> String {
+    match u32::try_from(n) {
+        Ok(n) if f64::from(n) <= LARGEST_SPOKEN_INTEGER => integer_words(n),
+        _ => digit_words(&n.to_string()),
+    }
+}
+
+/// "character three" or "characters one, four and seven".
+fn character_words(positions: &[usize]) -> String {
+    let spoken: Vec<String> = positions.iter().map(|&p| count_words(p + 1)).collect();
+    match spoken.split_last() {
+        Some((last, [])) => format!("character {}", last),
+        Some((last, rest)) => format!("characters {} and {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+fn char_words(c: char) -> String {
+    let name = match c {
+        '@' => "at sign",
+        '#' => "hash",
+        '$' => "dollar sign",
+        '&' => "ampersand",
+        '=' => "equals sign",
+        ',' => "comma",
+        '.' => "period",
+        '!' => "exclamation mark",
+        '?' => "question mark",
+        '[' | '{' => "open bracket",
+        ']' | '}' => "close bracket",
+        _ if c.is_ascii_digit() => return format!("digit {}", ONES[c as usize - '0' as usize]),
+        _ if c.is_alphabetic() => return format!("letter {}", c),
+        _ => return format!("character U+{:04X}", c as u32),
+    };
+    name.to_string()
+}
+
+/// [`describe_error`] for an error parsing `expr`: a token it can't end
+/// with leaves it incomplete, and any other out of place is named.
+fn describe_parse_error(error: &Error, expr: &str) -> String {
+    let Error::UnexpectedToken { pos } = error else {
+        return describe_error(error);
+    };
+    let mut rest = expr.chars().skip(*pos);
+    match rest.next() {
+        Some(c) if rest.any(|c| !c.is_whitespace()) => format!(
+            "Error: the {} at {} is not allowed here.",
+            char_words(c),
+            character_words(&[*pos])
+        ),
+        _ => "Error: the expression is incomplete.".to_string(),
+    }
+}
+
+fn describe_error(error: &Error) -> String {
+    match error {
+        Error::InvalidNumber { pos, .. } => format!(
+            "Error: the number starting at {} is malformed.",
+            character_words(&[*pos])
+        ),
+        Error::BadToken { ch, pos } => format!(
+            "Error: the {} at {} is not allowed here.",
+            char_words(*ch),
+            character_words(&[*pos])
+        ),
+        Error::UnknownFunction { name, pos } => format!(
+            "Error: there is no function called {}, at {}.",
+            name,
+            character_words(&[*pos])
+        ),
+        Error::MismatchedParens {
+            unmatched_open,
+            unmatched_close,
+        } => {
+            let mut sentences = vec!["Error: the parentheses don't match.".to_string()];
+            match unmatched_close.len() {
+                0 => {}
+                1 => sentences.push(format!(
+                    "The close paren at {} has no open paren.",
+                    character_words(unmatched_close)
+                )),
+                _ => sentences.push(format!(
+                    "The close parens at {} have no open paren.",
+                    character_words(unmatched_close)
+                )),
+            }
+            match unmatched_open.len() {
+                0 => {}
+                1 => sentences.push(format!(
+                    "The open paren at {} is never closed.",
+                    character_words(unmatched_open)
+                )),
+                _ => sentences.push(format!(
+                    "The open parens at {} are never closed.",
+                    character_words(unmatched_open)
+                )),
+            }
+            sentences.join(" ")
+        }
+        Error::SpaceInNumber { pos } => format!(
+            "Error: two numbers are separated only by a space, at {}.",
+            character_words(&[*pos])
+        ),
+        Error::DivisionByZero => "Error: division by zero.".to_string(),
+        Error::InvalidExpression => "Error: the expression is incomplete.".to_string(),
+        other => format!("Error: {}.", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_words() {
+        let cases = [
+            (0, "zero"),
+            (13, "thirteen"),
+            (20, "twenty"),
+            (42, "forty-two"),
+            (100, "one hundred"),
+            (115, "one hundred fifteen"),
+            (999, "nine hundred ninety-nine"),
+            (1000, "one thousand"),
+            (12_345, "twelve thousand three hundred forty-five"),
+            (
+                999_999,
+                "nine hundred ninety-nine thousand nine hundred ninety-nine",
+            ),
+        ];
+        for (n, words) in cases {
+            assert_eq!(integer_words(n), words);
+        }
+    }
+
+    #[test]
+    fn test_number_words() {
+        assert_eq!(number_words(20.0), "twenty");
+        assert_eq!(number_words(2.5), "two point five");
+        assert_eq!(number_words(12.0625), "twelve point zero six two five");
+        assert_eq!(number_words(-7.0), "minus seven");
+        assert_eq!(
+            number_words(1_000_000.0),
+            "one zero zero zero zero zero zero"
+        );
+        assert_eq!(number_words(0.05), "zero point zero five");
+        assert_eq!(number_words(f64::INFINITY), "infinity");
+    }
+
+    #[test]
+    fn test_speak_pinned_phrasing() {
+        let speech = Speech::new();
+        let cases = [
+            (
+                "(2+3)*4",
+                "open paren two plus three close paren, times four, equals twenty",
+            ),
+            ("10 / 4", "ten divided by four, equals two point five"),
+            ("2 - 7", "two minus seven, equals minus five"),
+            ("3 - -2", "three minus negative two, equals five"),
+            (
+                "sqrt(16) + 1",
+                "square root of open paren sixteen close paren, plus one, equals five",
+            ),
+            (
+                "((1)) + 1",
+                "open paren open paren one close paren, close paren, plus one, equals two",
+            ),
+            (
+                "10 / (2 - 2)",
+                "ten divided by open paren two minus two close paren. Error: division by zero.",
+            ),
+            ("2 +", "Error: the expression is incomplete."),
+        ];
+        for (expr, spoken) in cases {
+            assert_eq!(speech.speak(expr), spoken, "{:?}", expr);
+        }
+    }
+
+    #[test]
+    fn test_speak_errors() {
+        let speech = Speech::new();
+        assert_eq!(
+            speech.speak("2 + @"),
+            "Error: the at sign at character five is not allowed here."
+        );
+        assert_eq!(
+            speech.speak("2 + f(1)"),
+            "Error: there is no function called f, at character five."
+        );
+        assert_eq!(
+            speech.speak("y = x + 1"),
+            "y is set to x plus one. Error: undefined variable 'x'."
+        );
+        assert_eq!(
+            speech.speak("(1, 2) + 3"),
+            "Error: the comma at character three is not allowed here."
+        );
+        assert_eq!(speech.speak("5 * "), "Error: the expression is incomplete.");
+        assert_eq!(
+            speech.speak("1.2.3"),
+            "Error: the number starting at character one is malformed."
+        );
+        assert_eq!(
+            speech.speak("1 000"),
+            "Error: two numbers are separated only by a space, at character two."
+        );
+        assert_eq!(
+            speech.speak("(2 + 3"),
+            "Error: the parentheses don't match. The open paren at character one is never closed."
+        );
+        assert_eq!(
+            speech.speak("1 + 2) * 3 + 4 + 5 + (6"),
+            "Error: the parentheses don't match. \
+             The close paren at character six has no open paren. \
+             The open paren at character twenty-two is never closed."
+        );
+        assert_eq!(
+            speech.speak("(1)) + ((2)"),
+            "Error: the parentheses don't match. \
+             The close paren at character four has no open paren. \
+             The open paren at character eight is never closed."
+        );
+        assert_eq!(
+            speech.speak("((( 1"),
+            "Error: the parentheses don't match. \
+             The open parens at characters one, two and three are never closed."
+        );
+    }
+
+    #[test]
+    fn test_terse_verbosity() {
+        let speech = Speech::new().verbosity(Verbosity::Terse);
+        assert_eq!(speech.speak("(2+3)*4"), "twenty");
+        assert_eq!(speech.speak("1 / 0"), "Error: division by zero.");
+    }
+}
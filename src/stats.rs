@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::lexer::split_radix;
+use crate::{Calculator, Error, Operator, ParenBalance, Span, Token, json};
+
+/// Spelling of a numeric literal. New literal syntaxes get their own
+/// variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LiteralKind {
+    Decimal,
+    /// With an exponent, like `6.02e23`.
+    Scientific,
+    /// With a radix prefix: `0xFF`, `0o17` and `0b1010`.
+    Hexadecimal,
+    Octal,
+    Binary,
+}
+
+impl LiteralKind {
+    fn name(&self) -> &'static str {
+        match self {
+            LiteralKind::Decimal => "decimal",
+            LiteralKind::Scientific => "scientific",
+            LiteralKind::Hexadecimal => "hexadecimal",
+            LiteralKind::Octal => "octal",
+            LiteralKind::Binary => "binary",
+        }
+    }
+
+    /// The kind of the literal spelled `text`.
+    fn of(text: &str) -> Self {
+        match split_radix(text) {
+            Some((16, _)) => return LiteralKind::Hexadecimal,
+            Some((8, _)) => return LiteralKind::Octal,
+            Some(_) => return LiteralKind::Binary,
+            None => {}
+        }
+        if text.contains(['e', 'E']) {
+            LiteralKind::Scientific
+        } else {
+            LiteralKind::Decimal
+        }
+    }
+}
+
+/// Token-level statistics over a corpus of stored expressions. Nothing is
+/// evaluated, so data-dependent failures such as division by zero don't show
+/// up here.
+#[derive(Debug, Default)]
+pub struct CorpusStats {
+    /// Non-blank lines scanned, including those that failed to tokenize.
+    pub lines: usize,
+    /// Keyed by how the operator is written, except that negation is `-x`
+    /// and a percentage `x%`, apart from subtraction and modulo.
+    pub operators: BTreeMap<String, usize>,
+    /// Calls by function name, built-in or registered; `|x|` is `abs`.
+    pub functions: BTreeMap<String, usize>,
+    /// Reads of each variable.
+    pub variables: BTreeMap<String, usize>,
+    /// Reads of each built-in constant by its lowercase name, so `PI` is
+    /// `pi`. Like a variable it is a name, so it isn't counted among the
+    /// literals.
+    pub constants: BTreeMap<String, usize>,
+    /// Assignments to each variable.
+    pub assignments: BTreeMap<String, usize>,
+    pub literals: BTreeMap<LiteralKind, usize>,
+    /// Maximum bracket nesting depth → number of lines reaching it.
+    pub depth_histogram: BTreeMap<usize, usize>,
+    /// `(line, source, error)` for every line that failed to tokenize.
+    /// [`CorpusStats::to_json`] writes each error as a [`BatchItem`]
+    /// does, by [`Error::kind`] and message.
+    ///
+    /// [`BatchItem`]: crate::BatchItem
+    pub failures: Vec<(usize, String, Error)>,
+}
+
+impl CorpusStats {
+    fn record(&mut self, line: &str, tokens: &[Token], spans: &[Span]) {
+        for (token, span) in tokens.iter().zip(spans) {
+            match token {
+                Token::Number(_) => {
+                    let text: String = line
+                        .chars()
+                        .skip(span.start)
+                        .take(span.end - span.start)
+                        .collect();
+                    // A constant comes through as a number too.
+                    if text.starts_with(char::is_alphabetic) {
+                        count(&mut self.constants, &text.to_ascii_lowercase());
+                    } else {
+                        *self.literals.entry(LiteralKind::of(&text)).or_insert(0) += 1;
+                    }
+                }
+                Token::Op(op) => {
+                    let key = match op {
+                        Operator::Negate => "-x",
+                        Operator::Percent => "x%",
+                        _ => op.text(),
+                    };
+                    count(&mut self.operators, key);
+                }
+                Token::Custom { symbol, .. } => count(&mut self.operators, &symbol.to_string()),
+                // Its argument's brackets count as usual.
+                Token::Function { function, .. } => count(&mut self.functions, function.name()),
+                Token::Call { name, .. } => count(&mut self.functions, name),
+                Token::Identifier(name) => count(&mut self.variables, name),
+                Token::Assign(name) => count(&mut self.assignments, name),
+                Token::Bracket(_) | Token::Comma => {}
+            }
+        }
+
+        let depth = ParenBalance::of_tokens(tokens, spans).max_depth;
+        *self.depth_histogram.entry(depth).or_insert(0) += 1;
+    }
+
+    /// Machine-readable rendering of the statistics as a single JSON object.
+    pub fn to_json(&self) -> String {
+        let counts = |map: &BTreeMap<String, usize>| {
+            map.iter()
+                .map(|(key, count)| format!("{}:{}", json::string(key), count))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let literals: Vec<String> = self
+            .literals
+            .iter()
+            .map(|(kind, count)| format!("{}:{}", json::string(kind.name()), count))
+            .collect();
+        let depths: Vec<String> = self
+            .depth_histogram
+            .iter()
+            .map(|(depth, count)| format!("\"{}\":{}", depth, count))
+            .collect();
+        let failures: Vec<String> = self
+            .failures
+            .iter()
+            .map(|(line, source, e)| {
+                format!(
+                    "{{\"line\":{},\"source\":{},\"error\":{},\"message\":{}}}",
+                    line,
+                    json::string(source),
+                    json::string(e.kind()),
+                    json::string(&e.to_string())
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\"lines\":{},\"operators\":{{{}}},\"functions\":{{{}}},\"variables\":{{{}}},\"constants\":{{{}}},\"assignments\":{{{}}},\"literals\":{{{}}},\"depth_histogram\":{{{}}},\"failures\":[{}]}}",
+            self.lines,
+            counts(&self.operators),
+            counts(&self.functions),
+            counts(&self.variables),
+            counts(&self.constants),
+            counts(&self.assignments),
+            literals.join(","),
+            depths.join(","),
+            failures.join(",")
+        )
+    }
+}
+
+impl fmt::Display for CorpusStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "lines scanned: {} ({} failed to tokenize)",
+            self.lines,
+            self.failures.len()
+        )?;
+
+        for (heading, counts) in [
+            ("operator", &self.operators),
+            ("function", &self.functions),
+            ("variable", &self.variables),
+            ("constant", &self.constants),
+            ("assigned", &self.assignments),
+        ] {
+            if heading == "operator" || !counts.is_empty() {
+                writeln!(f, "\n{:<8}  count", heading)?;
+            }
+            for (key, count) in counts {
+                writeln!(f, "{:<8}  {}", key, count)?;
+            }
+        }
+
+        writeln!(f, "\nliteral   count")?;
+        for (kind, count) in &self.literals {
+            writeln!(f, "{:<8}  {}", kind.name(), count)?;
+        }
+
+        write!(f, "\ndepth     lines")?;
+        for (depth, count) in &self.depth_histogram {
+            write!(f, "\n{:<8}  {}", depth, count)?;
+        }
+
+        if !self.failures.is_empty() {
+            write!(f, "\n\nfailed lines:")?;
+            for (line, source, e) in &self.failures {
+                write!(f, "\n{:>5}: {}  ({})", line, source.trim(), e)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds one to `key`'s count in `counts`.
+fn count(counts: &mut BTreeMap<String, usize>, key: &str) {
+    *counts.entry(key.to_string()).or_insert(0) += 1;
+}
+
+impl Calculator {
+    /// Tokenizes (but does not evaluate) every non-blank line and aggregates
+    /// what the corpus uses. Lines that fail to tokenize are collected in
+    /// [`CorpusStats::failures`] instead of aborting the scan.
+    pub fn corpus_stats<'a>(lines: impl Iterator<Item = &'a str>) -> CorpusStats {
+        let mut stats = CorpusStats::default();
+
+        for (index, line) in lines.enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            stats.lines += 1;
+            match Self::parse_with_spans(line) {
+                Ok((tokens, spans)) => stats.record(line, &tokens, &spans),
+                Err(e) => stats.failures.push((index + 1, line.to_string(), e)),
+            }
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corpus_counts() {
+        let corpus = "1 + 2 * 3\n(4 - 1) / 2\n\n((2 + 3) * 4)\n";
+        let stats = Calculator::corpus_stats(corpus.lines());
+
+        assert_eq!(stats.lines, 3);
+        assert_eq!(stats.operators["+"], 2);
+        assert_eq!(stats.operators["*"], 2);
+        assert_eq!(stats.operators["-"], 1);
+        assert_eq!(stats.operators["/"], 1);
+        assert_eq!(stats.literals[&LiteralKind::Decimal], 9);
+        assert!(!stats.literals.contains_key(&LiteralKind::Scientific));
+        assert_eq!(stats.depth_histogram[&0], 1);
+        assert_eq!(stats.depth_histogram[&1], 1);
+        assert_eq!(stats.depth_histogram[&2], 1);
+    }
+
+    #[test]
+    fn test_scientific_literals() {
+        let stats = Calculator::corpus_stats(["6.02e23 / 2", "1E-9 * e", ".5e3 + E"].into_iter());
+        assert_eq!(stats.literals[&LiteralKind::Scientific], 3);
+        // The constant `e` is a name, not a literal.
+        assert_eq!(stats.literals[&LiteralKind::Decimal], 1);
+        assert_eq!(stats.constants["e"], 2);
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        let stats = Calculator::corpus_stats(["0xE + 0XFF", "0o17 * 0b1", "0.5 - 0"].into_iter());
+        assert_eq!(stats.literals[&LiteralKind::Hexadecimal], 2);
+        assert_eq!(stats.literals[&LiteralKind::Octal], 1);
+        assert_eq!(stats.literals[&LiteralKind::Binary], 1);
+        assert_eq!(stats.literals[&LiteralKind::Decimal], 2);
+        assert!(!stats.literals.contains_key(&LiteralKind::Scientific));
+    }
+
+    #[test]
+    fn test_corpus_failures_do_not_abort() {
+        let corpus = ["1 + 1", "2 + @", "(3", "5 / 0"];
+        let stats = Calculator::corpus_stats(corpus.into_iter());
+
+        assert_eq!(stats.lines, 4);
+        assert_eq!(stats.failures.len(), 2);
+        assert_eq!(stats.failures[0].0, 2);
+        assert_eq!(stats.failures[1].0, 3);
+        // Division by zero is an evaluation error, not a tokenizer one.
+        assert_eq!(stats.operators["/"], 1);
+    }
+
+    #[test]
+    fn test_operators_kept_apart() {
+        let stats =
+            Calculator::corpus_stats(["7 // 2 / 1", "-5 - 1", "50% % 3 ** 2 ^ 1"].into_iter());
+        let operators: Vec<(&str, usize)> = stats
+            .operators
+            .iter()
+            .map(|(op, count)| (op.as_str(), *count))
+            .collect();
+        assert_eq!(
+            operators,
+            [
+                ("%", 1),
+                ("-", 1),
+                ("-x", 1),
+                ("/", 1),
+                ("//", 1),
+                ("^", 2),
+                ("x%", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_names_counted() {
+        let stats = Calculator::corpus_stats(
+            ["x = sqrt(y) + |y| * pi", "max(x, y, 2) * x / tau + pi"].into_iter(),
+        );
+        let counts = |map: &BTreeMap<String, usize>| {
+            map.iter().map(|(k, v)| (k.clone(), *v)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            counts(&stats.functions),
+            [
+                ("abs".to_string(), 1),
+                ("max".to_string(), 1),
+                ("sqrt".to_string(), 1)
+            ]
+        );
+        assert_eq!(
+            counts(&stats.variables),
+            [("x".to_string(), 2), ("y".to_string(), 3)]
+        );
+        assert_eq!(
+            counts(&stats.constants),
+            [("pi".to_string(), 2), ("tau".to_string(), 1)]
+        );
+        assert_eq!(stats.literals[&LiteralKind::Decimal], 1);
+        assert_eq!(counts(&stats.assignments), [("x".to_string(), 1)]);
+        assert!(
+            stats
+                .to_string()
+                .contains("\nfunction  count\nabs       1\n")
+        );
+    }
+
+    #[test]
+    fn test_corpus_json() {
+        let stats = Calculator::corpus_stats(["(1 + 2)", "x(1)"].into_iter());
+        assert_eq!(
+            stats.to_json(),
+            "{\"lines\":2,\"operators\":{\"+\":1},\"functions\":{},\"variables\":{},\
+             \"constants\":{},\"assignments\":{},\"literals\":{\"decimal\":2},\
+             \"depth_histogram\":{\"1\":1},\
+             \"failures\":[{\"line\":2,\"source\":\"x(1)\",\"error\":\"UnknownFunction\",\
+             \"message\":\"unknown function 'x' at position 0\"}]}"
+        );
+    }
+}
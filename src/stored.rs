@@ -0,0 +1,588 @@
+//! A versioned text envelope for compiled (postfix) expressions.
+//!
+//! ```text
+//! calculator-expression 12
+//! source-hash fdf96a90e2090a87
+//! source 10 / (2 - 2)
+//! tokens-check 5 8083eeaaf118b86c
+//! tokens 10.0 2.0 2.0 - /
+//! ```
+//!
+//! Every line ends in a newline, including the last, so a blob cut short
+//! anywhere is noticed.
+//!
+//! `FORMAT_VERSION` is bumped whenever a blob written by the new code could
+//! be misread by the old code: a new token kind, a changed token encoding,
+//! or a new required line. Parser changes alone don't need a bump, since the
+//! stored tokens already record how the expression was compiled;
+//! [`StoredExpression::load_verified`] is how to notice that today's parser
+//! would compile the source differently.
+//!
+//! Loading accepts every version up to `FORMAT_VERSION` and rejects newer
+//! ones with [`Error::IncompatibleFormat`].
+
+use std::fmt::Write;
+
+use crate::{Assoc, Calculator, Error, Function, Operator, Token};
+
+/// Newest envelope version this build writes and can read. Version 2
+/// added `neg` for unary minus, version 3 `^`, version 4 `//`,
+/// version 5 `%`, version 6 function names such as `sqrt`, version 7
+/// variables, written `$x`, and assignments, written `x=`, version 8
+/// calls to registered functions, written `name/2` with their argument
+/// count, version 9 registered operators, written `op:@:15:Left` with
+/// their precedence and associativity, version 10 `min` and `max`,
+/// written with their argument count like `max/3`, and `pow`, version 11
+/// `pct` for a postfix percent, and version 12 the required
+/// `tokens-check` line.
+pub const FORMAT_VERSION: u32 = 12;
+
+/// The first version with a `tokens-check` line: the number of tokens and a
+/// [`source_hash`] of the `tokens` line's text.
+const TOKENS_CHECK_VERSION: u32 = 12;
+
+const MAGIC: &str = "calculator-expression";
+
+/// A compiled expression together with the source it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredExpression {
+    pub version: u32,
+    /// [`source_hash`] of `source`, checked on load.
+    pub source_hash: u64,
+    pub source: String,
+    /// Postfix tokens, as produced by [`Calculator::try_to_postfix`].
+    pub tokens: Vec<Token>,
+}
+
+/// 64-bit FNV-1a hash of the UTF-8 bytes of `source`. Unlike
+/// `std::hash::DefaultHasher`, the value is fixed across Rust releases, so
+/// it is safe to persist.
+pub fn source_hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl StoredExpression {
+    /// Parses and compiles `expr` into an envelope at the current version.
+    pub fn compile<T: AsRef<str>>(expr: T) -> Result<Self, Error> {
+        let source = expr.as_ref();
+        let tokens = Calculator::try_to_postfix(Calculator::parse(source)?)?;
+        Ok(StoredExpression {
+            version: FORMAT_VERSION,
+            source_hash: source_hash(source),
+            source: source.to_string(),
+            tokens,
+        })
+    }
+
+    /// Whether this was compiled from exactly `expr`.
+    pub fn matches_source(&self, expr: &str) -> bool {
+        self.source_hash == source_hash(expr)
+    }
+
+    pub fn evaluate(&self) -> Result<f64, Error> {
+        Calculator::evaluate(self.tokens.clone())
+    }
+
+    /// Bytes this takes in memory, for budgeting a cache of them: the
+    /// struct, the allocated capacity of the source and of the tokens, and
+    /// each name the tokens hold. Numbers live in their tokens, so there is
+    /// no separate pool of constants to count.
+    pub fn memory_footprint(&self) -> usize {
+        let names: usize = self
+            .tokens
+            .iter()
+            .map(|token| match token {
+                Token::Identifier(name) | Token::Assign(name) | Token::Call { name, .. } => {
+                    name.heap_size()
+                }
+                _ => 0,
+            })
+            .sum();
+        std::mem::size_of::<Self>()
+            + self.source.capacity()
+            + self.tokens.capacity() * std::mem::size_of::<Token>()
+            + names
+    }
+
+    /// The envelope as text. Newlines and backslashes in the source are
+    /// escaped so every field stays on its own line.
+    pub fn save(&self) -> String {
+        let tokens: Vec<String> = self.tokens.iter().map(encode_token).collect();
+        let mut blob = String::new();
+        let _ = writeln!(blob, "{} {}", MAGIC, self.version);
+        let _ = writeln!(blob, "source-hash {:016x}", self.source_hash);
+        let _ = writeln!(blob, "source {}", escape(&self.source));
+        let tokens = tokens.join(" ");
+        if self.version >= TOKENS_CHECK_VERSION {
+            let _ = writeln!(
+                blob,
+                "tokens-check {} {:016x}",
+                self.tokens.len(),
+                source_hash(&tokens)
+            );
+        }
+        let _ = writeln!(blob, "tokens {}", tokens);
+        blob
+    }
+
+    /// Reads a blob written by [`StoredExpression::save`]. A version this
+    /// build doesn't know is `Error::IncompatibleFormat`, and so is a blob
+    /// whose tokens don't match their `tokens-check` line or that doesn't
+    /// end in a newline, since either way the tokens can't be trusted to be
+    /// the ones saved. Anything else wrong with the blob, including a source
+    /// that no longer matches its hash, is `Error::InvalidStoredExpression`.
+    pub fn load(blob: &str) -> Result<Self, Error> {
+        let mut lines = blob.lines();
+
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix(MAGIC)?.strip_prefix(' '))
+            .and_then(|version| version.parse::<u32>().ok())
+            .ok_or(Error::InvalidStoredExpression)?;
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(Error::IncompatibleFormat {
+                found: version,
+                supported: FORMAT_VERSION,
+            });
+        }
+
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name)?.strip_prefix(' '))
+                .ok_or(Error::InvalidStoredExpression)
+        };
+        let hash = field("source-hash")?;
+        let source = field("source")?;
+        let check = match version >= TOKENS_CHECK_VERSION {
+            true => Some(field("tokens-check")?),
+            false => None,
+        };
+        let tokens = field("tokens")?;
+        if lines.any(|line| !line.is_empty()) {
+            return Err(Error::InvalidStoredExpression);
+        }
+        let damaged = Error::IncompatibleFormat {
+            found: version,
+            supported: FORMAT_VERSION,
+        };
+        if !blob.ends_with('\n') {
+            return Err(damaged);
+        }
+
+        if hash.len() != 16 {
+            return Err(Error::InvalidStoredExpression);
+        }
+        let hash = u64::from_str_radix(hash, 16).map_err(|_| Error::InvalidStoredExpression)?;
+        let source = unescape(source).ok_or(Error::InvalidStoredExpression)?;
+        if hash != source_hash(&source) {
+            return Err(Error::InvalidStoredExpression);
+        }
+        let decoded = tokens
+            .split_whitespace()
+            .map(decode_token)
+            .collect::<Option<Vec<Token>>>()
+            .ok_or(Error::InvalidStoredExpression)?;
+        if let Some(check) = check {
+            let (count, tokens_hash) = check
+                .split_once(' ')
+                .ok_or(Error::InvalidStoredExpression)?;
+            let count: usize = count.parse().map_err(|_| Error::InvalidStoredExpression)?;
+            if tokens_hash.len() != 16 {
+                return Err(Error::InvalidStoredExpression);
+            }
+            let tokens_hash =
+                u64::from_str_radix(tokens_hash, 16).map_err(|_| Error::InvalidStoredExpression)?;
+            if count != decoded.len() || tokens_hash != source_hash(tokens) {
+                return Err(damaged);
+            }
+        }
+
+        Ok(StoredExpression {
+            version,
+            source_hash: hash,
+            source,
+            tokens: decoded,
+        })
+    }
+
+    /// [`StoredExpression::load`], then re-parses the stored source and
+    /// requires today's parser to produce the same tokens. This catches
+    /// blobs whose tokens were altered without touching the source, and
+    /// parser changes that would compile it differently.
+    pub fn load_verified(blob: &str) -> Result<Self, Error> {
+        let stored = Self::load(blob)?;
+        let recompiled = Calculator::parse(&stored.source)
+            .and_then(Calculator::try_to_postfix)
+            .map_err(|_| Error::InvalidStoredExpression)?;
+        if !same_tokens(&recompiled, &stored.tokens) {
+            return Err(Error::InvalidStoredExpression);
+        }
+        Ok(stored)
+    }
+}
+
+fn encode_token(token: &Token) -> String {
+    match token {
+        // `{:?}` writes the shortest text that parses back to the same
+        // f64, including `inf` and `NaN`.
+        Token::Number(n) => format!("{:?}", n),
+        // Operators that share a symbol with another.
+        Token::Op(Operator::Negate) => "neg".to_string(),
+        Token::Op(Operator::FloorDivide) => "//".to_string(),
+        Token::Op(Operator::Percent) => "pct".to_string(),
+        Token::Op(op) => op.symbol().to_string(),
+        Token::Bracket(c) => c.to_string(),
+        Token::Function { function, args } => match function.arity() {
+            Some(_) => function.name().to_string(),
+            None => format!("{}/{}", function.name(), args),
+        },
+        Token::Identifier(name) => format!("${}", name),
+        Token::Assign(name) => format!("{}=", name),
+        Token::Call { name, args } => format!("{}/{}", name, args),
+        Token::Comma => ",".to_string(),
+        Token::Custom {
+            symbol,
+            precedence,
+            assoc,
+        } => format!("op:{}:{}:{:?}", symbol, precedence, assoc),
+    }
+}
+
+fn decode_token(text: &str) -> Option<Token> {
+    Some(match text {
+        "+" => Token::Op(Operator::Add),
+        "-" => Token::Op(Operator::Subtract),
+        "*" => Token::Op(Operator::Multiply),
+        "/" => Token::Op(Operator::Divide),
+        "//" => Token::Op(Operator::FloorDivide),
+        "%" => Token::Op(Operator::Modulo),
+        "^" => Token::Op(Operator::Power),
+        "neg" => Token::Op(Operator::Negate),
+        "pct" => Token::Op(Operator::Percent),
+        "(" | ")" => Token::Bracket(text.chars().next()?),
+        "," => Token::Comma,
+        _ if text.starts_with("op:") => {
+            let mut chars = text["op:".len()..].chars();
+            let symbol = chars.next()?;
+            let (precedence, assoc) = chars.as_str().strip_prefix(':')?.split_once(':')?;
+            Token::Custom {
+                symbol,
+                precedence: precedence.parse().ok()?,
+                assoc: match assoc {
+                    "Left" => Assoc::Left,
+                    "Right" => Assoc::Right,
+                    _ => return None,
+                },
+            }
+        }
+        _ if text.starts_with('$') => Token::Identifier(name(&text[1..])?.into()),
+        _ if text.ends_with('=') => Token::Assign(name(&text[..text.len() - 1])?.into()),
+        _ if text.contains('/') => {
+            let (called, args) = text.split_once('/')?;
+            let args = args.parse().ok()?;
+            match Function::from_name(called) {
+                Some(function) if function.arity().is_none() => Token::Function { function, args },
+                Some(_) => return None,
+                None => Token::Call {
+                    name: name(called)?.into(),
+                    args,
+                },
+            }
+        }
+        _ => match Function::from_name(text) {
+            Some(function) => Token::Function {
+                function,
+                args: function.arity()? as u32,
+            },
+            None => Token::Number(text.parse().ok()?),
+        },
+    })
+}
+
+/// `text` if it could be a variable's name.
+fn name(text: &str) -> Option<String> {
+    let mut chars = text.chars();
+    let valid = chars.next().is_some_and(char::is_alphabetic)
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    valid.then(|| text.to_string())
+}
+
+/// Token equality that treats NaN literals as equal, so a stored NaN
+/// survives verification.
+fn same_tokens(a: &[Token], b: &[Token]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|pair| match pair {
+            (Token::Number(x), Token::Number(y)) => x.to_bits() == y.to_bits(),
+            (x, y) => x == y,
+        })
+}
+
+fn escape(source: &str) -> String {
+    let mut escaped = String::with_capacity(source.len());
+    for c in source.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(escaped: &str) -> Option<String> {
+    let mut source = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            source.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => source.push('\\'),
+            'n' => source.push('\n'),
+            'r' => source.push('\r'),
+            _ => return None,
+        }
+    }
+    Some(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_format() {
+        let stored = StoredExpression::compile("10 / (2 - 2)").unwrap();
+        assert_eq!(
+            stored.save(),
+            format!(
+                "calculator-expression 12\n\
+                 source-hash fdf96a90e2090a87\n\
+                 source 10 / (2 - 2)\n\
+                 tokens-check 5 {:016x}\n\
+                 tokens 10.0 2.0 2.0 - /\n",
+                source_hash("10.0 2.0 2.0 - /")
+            )
+        );
+        let stored = StoredExpression::compile("y = x + 1").unwrap();
+        assert!(stored.save().ends_with("tokens $x 1.0 + y=\n"));
+    }
+
+    #[test]
+    fn test_memory_footprint() {
+        let mut stored = StoredExpression::compile("total = price * 2 + tax").unwrap();
+        stored.tokens.shrink_to_fit();
+        // total= $price 2.0 * $tax +
+        assert_eq!(stored.tokens.len(), 6);
+        let names = ("total".len() + 16) + ("price".len() + 16) + ("tax".len() + 16);
+        assert_eq!(
+            stored.memory_footprint(),
+            std::mem::size_of::<StoredExpression>() + 23 + 6 * 16 + names
+        );
+        let plain = StoredExpression::compile("1 + 2").unwrap();
+        assert_eq!(
+            plain.memory_footprint(),
+            std::mem::size_of::<StoredExpression>() + 5 + plain.tokens.capacity() * 16
+        );
+    }
+
+    #[test]
+    fn test_source_hash_is_stable() {
+        // FNV-1a test vectors; a change here breaks every stored blob.
+        assert_eq!(source_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(source_hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(source_hash("foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for expr in [
+            "2 + 3 * 4",
+            "((1.5))",
+            "0.1 + 0.2",
+            "1.25 / 0.1 - 7",
+            "-(2 - -3)",
+            "2 ^ 3 ** 2",
+            "-7 // 2",
+            "10 % 3",
+            "50% * 200 - 1%",
+            "sqrt(abs(-16))",
+            "max(1, pow(2, 10), min(3)) - 4",
+            "y = (x_1 = 5) * x_1",
+        ] {
+            let stored = StoredExpression::compile(expr).unwrap();
+            let loaded = StoredExpression::load(&stored.save()).unwrap();
+            assert_eq!(loaded, stored, "{:?}", expr);
+            assert_eq!(loaded.evaluate(), Calculator::calculate(expr));
+            assert!(loaded.matches_source(expr));
+        }
+
+        let stored = StoredExpression {
+            version: FORMAT_VERSION,
+            source_hash: source_hash("a\\b\nc"),
+            source: "a\\b\nc".to_string(),
+            tokens: vec![
+                Token::Number(0.1),
+                Token::Number(1e300),
+                Token::Number(f64::INFINITY),
+                Token::Number(-2.5),
+                Token::Op(Operator::Subtract),
+                Token::Call {
+                    name: "hypot".into(),
+                    args: 2,
+                },
+                Token::Custom {
+                    symbol: ':',
+                    precedence: 15,
+                    assoc: Assoc::Right,
+                },
+            ],
+        };
+        assert!(stored.save().ends_with(" - hypot/2 op:::15:Right\n"));
+        assert_eq!(StoredExpression::load(&stored.save()).unwrap(), stored);
+    }
+
+    #[test]
+    fn test_newer_version_rejected() {
+        let blob = StoredExpression::compile("1 + 1")
+            .unwrap()
+            .save()
+            .replace("calculator-expression 12", "calculator-expression 13");
+        assert_eq!(
+            StoredExpression::load(&blob),
+            Err(Error::IncompatibleFormat {
+                found: 13,
+                supported: FORMAT_VERSION
+            })
+        );
+        let blob = blob.replace("calculator-expression 13", "calculator-expression 0");
+        assert!(matches!(
+            StoredExpression::load(&blob),
+            Err(Error::IncompatibleFormat { found: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_older_version_loads() {
+        let mut stored = StoredExpression::compile("1 + 2").unwrap();
+        stored.version = 1;
+        let blob = stored.save();
+        assert!(!blob.contains("tokens-check"));
+        let loaded = StoredExpression::load(&blob).unwrap();
+        assert_eq!(loaded.version, 1);
+        assert_eq!(loaded.evaluate().unwrap(), 3.0);
+
+        // From version 12 the check is required.
+        let unchecked = blob.replace("calculator-expression 1\n", "calculator-expression 12\n");
+        assert_eq!(
+            StoredExpression::load(&unchecked),
+            Err(Error::InvalidStoredExpression)
+        );
+    }
+
+    #[test]
+    fn test_damaged_tokens_rejected() {
+        let blob = StoredExpression::compile("(50% + 1) * 3 - 2.5")
+            .unwrap()
+            .save();
+        assert!(blob.ends_with("tokens 50.0 pct 1.0 + 3.0 * 2.5 -\n"));
+        let damaged = [
+            // Cut short, with or without the final newline.
+            blob[..blob.find(" 1.0 +").unwrap()].to_string(),
+            format!("{}\n", &blob[..blob.find(" 1.0 +").unwrap()]),
+            blob.trim_end().to_string(),
+            // A token changed to another that decodes.
+            blob.replace("tokens 50.0", "tokens 5000"),
+            blob.replace("3.0 * 2.5 -", "3.0 * 2.5 +"),
+            // A token dropped, or one added.
+            blob.replace(" pct ", " "),
+            blob.replace(" -\n", " - neg\n"),
+            // The check altered instead.
+            blob.replace("tokens-check 8", "tokens-check 9"),
+        ];
+        for blob in damaged {
+            assert_eq!(
+                StoredExpression::load(&blob),
+                Err(Error::IncompatibleFormat {
+                    found: FORMAT_VERSION,
+                    supported: FORMAT_VERSION
+                }),
+                "{:?}",
+                blob
+            );
+        }
+        for malformed in [
+            blob.replace("tokens-check 8 ", "tokens-check seven "),
+            blob.replace("tokens-check 8 ", "tokens-check 8"),
+            blob.replace("tokens-check ", "tokens-check 8 0"),
+        ] {
+            assert_eq!(
+                StoredExpression::load(&malformed),
+                Err(Error::InvalidStoredExpression),
+                "{:?}",
+                malformed
+            );
+        }
+        assert_eq!(
+            StoredExpression::load(&blob.replace("tokens 50.0", "tokens 5000"))
+                .unwrap_err()
+                .to_string(),
+            "stored expression (format v12) is damaged: its tokens fail their check"
+        );
+    }
+
+    #[test]
+    fn test_corruption_rejected() {
+        let blob = StoredExpression::compile("1 + 2").unwrap().save();
+        let corrupted = [
+            String::new(),
+            "garbage".to_string(),
+            blob.replace("calculator-expression", "calculator-expr"),
+            blob.replace("source 1 + 2", "source 1 + 3"),
+            blob.replace("source-hash ", "source-hash 0"),
+            blob.replace("tokens 1.0 2.0 +", "tokens 1.0 2.0 @"),
+            blob.replace("tokens ", "tokenz "),
+            blob.lines().take(4).collect::<Vec<_>>().join("\n"),
+            format!("{}extra\n", blob),
+            blob.replace("source 1 + 2", "source 1 + 2\\"),
+        ];
+        for blob in corrupted {
+            assert_eq!(
+                StoredExpression::load(&blob),
+                Err(Error::InvalidStoredExpression),
+                "{:?}",
+                blob
+            );
+        }
+    }
+
+    #[test]
+    fn test_verified_load_catches_token_drift() {
+        let mut stored = StoredExpression::compile("1 + 2 * 3").unwrap();
+        let blob = stored.save();
+        // Tokens rewritten, along with their check, without touching the
+        // source pass the hash check...
+        stored.tokens =
+            Calculator::try_to_postfix(Calculator::parse("(1 + 2) * 3").unwrap()).unwrap();
+        let tampered = stored.save();
+        let loaded = StoredExpression::load(&tampered).unwrap();
+        assert_eq!(loaded.evaluate().unwrap(), 9.0);
+        // ...but not re-verification against the source.
+        assert_eq!(
+            StoredExpression::load_verified(&tampered),
+            Err(Error::InvalidStoredExpression)
+        );
+        assert_eq!(
+            StoredExpression::load_verified(&blob)
+                .unwrap()
+                .evaluate()
+                .unwrap(),
+            7.0
+        );
+    }
+}
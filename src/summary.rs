@@ -0,0 +1,175 @@
+use crate::Calculator;
+
+const ELLIPSIS: &str = " ... ";
+
+/// Splits `expr` into display pieces: the text of each token the lexer
+/// reads, with whitespace inside collapsed, and one `" "` per interior
+/// whitespace run between them. The lexer is run so it never fails, since
+/// log lines have to cope with invalid input too.
+fn pieces(expr: &str, mask_numbers: bool) -> Vec<String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut pieces = Vec::new();
+    let mut at = 0;
+
+    for span in Calculator::lossy_spans(expr) {
+        // A span can only overlap the last where normalizing turned one
+        // character into several tokens, as `²` into `^2`.
+        if span.start < at {
+            continue;
+        }
+        if !pieces.is_empty() && chars[at..span.start].iter().any(|c| c.is_whitespace()) {
+            pieces.push(" ".to_string());
+        }
+        let text = &chars[span.start..span.end];
+        // Constants such as `pi` are numbers too, but not literals.
+        if mask_numbers && (text[0].is_ascii_digit() || text[0] == '.') {
+            pieces.push("<num>".to_string());
+        } else {
+            let text: String = text.iter().collect();
+            pieces.push(text.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        at = span.end;
+    }
+
+    pieces
+}
+
+fn render(expr: &str, max_len: usize, mask_numbers: bool) -> String {
+    let pieces = pieces(expr, mask_numbers);
+    let full_len: usize = pieces.iter().map(|p| p.chars().count()).sum();
+    if full_len <= max_len {
+        return pieces.concat();
+    }
+
+    let note = format!(" ({} chars)", expr.chars().count());
+    let budget = max_len.saturating_sub(ELLIPSIS.len() + note.len());
+
+    let mut head = String::new();
+    let mut head_len = 0;
+    for piece in &pieces {
+        let len = piece.chars().count();
+        if head_len + len > budget.div_ceil(2) {
+            break;
+        }
+        head.push_str(piece);
+        head_len += len;
+    }
+
+    let mut tail = Vec::new();
+    let mut tail_len = 0;
+    for piece in pieces.iter().rev() {
+        let len = piece.chars().count();
+        if head_len + tail_len + len > budget {
+            break;
+        }
+        tail.push(piece.as_str());
+        tail_len += len;
+    }
+    tail.reverse();
+
+    format!(
+        "{}{}{}{}",
+        head.trim_end(),
+        ELLIPSIS,
+        tail.concat().trim_start(),
+        note
+    )
+}
+
+impl Calculator {
+    /// Log-safe rendering of `expr` in at most `max_len` characters (as long
+    /// as `max_len` leaves room for the length note). Long input keeps its
+    /// beginning and end around an ellipsis, cut only between tokens, and
+    /// whitespace runs collapse to single spaces.
+    pub fn summarize<T: AsRef<str>>(expr: T, max_len: usize) -> String {
+        render(expr.as_ref(), max_len, false)
+    }
+
+    /// Like [`Calculator::summarize`], but every numeric literal is replaced
+    /// by `<num>` so logs keep the expression's shape without its data.
+    pub fn summarize_masked<T: AsRef<str>>(expr: T, max_len: usize) -> String {
+        render(expr.as_ref(), max_len, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_expression_unchanged() {
+        assert_eq!(Calculator::summarize("2 + 3 * 4", 80), "2 + 3 * 4");
+        assert_eq!(Calculator::summarize("  2\t+\n3  ", 80), "2 + 3");
+        assert_eq!(Calculator::summarize("", 80), "");
+    }
+
+    #[test]
+    fn test_masking_keeps_structure() {
+        assert_eq!(
+            Calculator::summarize_masked("(12.5 + rate) * 3", 80),
+            "(<num> + rate) * <num>"
+        );
+        assert_eq!(Calculator::summarize_masked("2 + @", 80), "<num> + @");
+        // Exponents are part of the number.
+        assert_eq!(
+            Calculator::summarize_masked("6.02e23 * 1E-9 - e", 80),
+            "<num> * <num> - e"
+        );
+        assert_eq!(
+            Calculator::summarize_masked("0xFF & 0b1010", 80),
+            "<num> & <num>"
+        );
+        assert_eq!(
+            Calculator::summarize_masked("1_000_000 * 0xFF_FF", 80),
+            "<num> * <num>"
+        );
+    }
+
+    #[test]
+    fn test_pieces_follow_the_lexer() {
+        // Malformed literals are one piece each, as the lexer reads them.
+        assert_eq!(
+            Calculator::summarize_masked("1e5e5 + 0b102 - 2e", 80),
+            "<num> + <num> - <num>"
+        );
+        assert_eq!(
+            Calculator::summarize_masked("|x - 50%| + 7%2", 80),
+            "|x - <num>%| + <num>%<num>"
+        );
+        assert_eq!(
+            Calculator::summarize("x   = |-3| ** 2", 80),
+            "x = |-3| ** 2"
+        );
+        // Invalid input still summarizes.
+        assert_eq!(
+            Calculator::summarize_masked("((2 + pi | 0x", 80),
+            "((<num> + pi | <num>"
+        );
+        assert_eq!(Calculator::summarize("foo(1; 2) $", 80), "foo(1; 2) $");
+    }
+
+    #[test]
+    fn test_truncation_never_splits_tokens() {
+        let expr = "123456 + 234567 + 345678 + 456789 + 567890";
+        let summary = Calculator::summarize(expr, 36);
+
+        assert!(summary.chars().count() <= 36);
+        assert_eq!(summary, "123456 + ... + 567890 (42 chars)");
+    }
+
+    #[test]
+    fn test_megabyte_expression_under_cap() {
+        let expr = format!("({}1)", "1 + 2 * 3 - ".repeat(90_000));
+        assert!(expr.len() > 1_000_000);
+
+        let summary = Calculator::summarize(&expr, 120);
+        assert!(summary.chars().count() <= 120);
+        assert!(summary.starts_with("(1 + 2 * 3 - "));
+        assert!(summary.contains(" ... "));
+        assert!(summary.ends_with(&format!("- 1) ({} chars)", expr.len())));
+
+        let masked = Calculator::summarize_masked(&expr, 120);
+        assert!(masked.chars().count() <= 120);
+        assert!(masked.starts_with("(<num> + <num> * <num> - "));
+    }
+}
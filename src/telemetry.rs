@@ -0,0 +1,141 @@
+//! An opt-in hook for learning which inputs fail to parse.
+//!
+//! The hook belongs to one [`ConfiguredCalculator`], set with
+//! [`ConfiguredCalculator::on_parse_error`], so two embedders (or two
+//! tests running in parallel) each see only their own failures. It sees
+//! parse and validation failures only: evaluation errors such as division
+//! by zero depend on the data, not on gaps in the grammar.
+
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+#[cfg(doc)]
+use crate::ConfiguredCalculator;
+use crate::{Calculator, Error};
+
+/// How much of the masked source the hook is given.
+const REPORTED_SOURCE_LEN: usize = 80;
+
+pub(crate) type ParseErrorHook = Arc<dyn Fn(&str, &Error) + Send + Sync>;
+
+/// Hands the failed parse of `source` to `hook`, with its numbers masked
+/// and any panic in the hook caught and discarded.
+pub(crate) fn report(hook: &ParseErrorHook, source: &str, error: &Error) {
+    let masked = Calculator::summarize_masked(source, REPORTED_SOURCE_LEN);
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| hook(&masked, error)));
+}
+
+/// A rough kind for the first character a parse error points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CharClass {
+    Letter,
+    Digit,
+    Bracket,
+    /// Punctuation and other non-alphanumeric ASCII.
+    Symbol,
+    /// Anything outside ASCII that isn't a letter or digit.
+    Other,
+}
+
+impl CharClass {
+    pub fn of(c: char) -> Self {
+        match c {
+            _ if c.is_alphabetic() => CharClass::Letter,
+            _ if c.is_numeric() => CharClass::Digit,
+            '(' | ')' | '[' | ']' | '{' | '}' => CharClass::Bracket,
+            _ if c.is_ascii() => CharClass::Symbol,
+            _ => CharClass::Other,
+        }
+    }
+}
+
+/// Counts of parse failures, for summarizing what a hook set with
+/// [`ConfiguredCalculator::on_parse_error`] has seen:
+///
+/// ```
+/// use std::sync::{Arc, Mutex};
+/// use calculator::{Calculator, ParseFailureStats};
+///
+/// let stats = Arc::new(Mutex::new(ParseFailureStats::default()));
+/// let sink = Arc::clone(&stats);
+/// let mut calc = Calculator::builder().build();
+/// calc.on_parse_error(move |_, error| sink.lock().unwrap().record(error));
+/// let _ = calc.calculate("2 + #");
+/// assert_eq!(stats.lock().unwrap().by_kind["BadToken"], 1);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseFailureStats {
+    pub total: usize,
+    /// Keyed by variant name, e.g. `"BadToken"`.
+    pub by_kind: BTreeMap<&'static str, usize>,
+    /// Keyed by the class of the first character the error points at.
+    /// Errors that point at no character, like `InvalidExpression`, are
+    /// only counted by kind.
+    pub by_class: BTreeMap<CharClass, usize>,
+}
+
+impl ParseFailureStats {
+    pub fn record(&mut self, error: &Error) {
+        self.total += 1;
+        *self.by_kind.entry(error.kind()).or_insert(0) += 1;
+        let offending = match error {
+            Error::BadToken { ch, .. } => Some(CharClass::of(*ch)),
+            Error::UnknownFunction { name, .. } => name.chars().next().map(CharClass::of),
+            Error::MismatchedParens { .. } => Some(CharClass::Bracket),
+            _ => None,
+        };
+        if let Some(class) = offending {
+            *self.by_class.entry(class).or_insert(0) += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_char_class() {
+        assert_eq!(CharClass::of('x'), CharClass::Letter);
+        assert_eq!(CharClass::of('é'), CharClass::Letter);
+        assert_eq!(CharClass::of('7'), CharClass::Digit);
+        assert_eq!(CharClass::of('['), CharClass::Bracket);
+        assert_eq!(CharClass::of('@'), CharClass::Symbol);
+        assert_eq!(CharClass::of('€'), CharClass::Other);
+    }
+
+    #[test]
+    fn test_stats_record() {
+        let mut stats = ParseFailureStats::default();
+        for error in [
+            Error::BadToken { ch: 'x', pos: 0 },
+            Error::BadToken { ch: 'y', pos: 0 },
+            Error::BadToken { ch: '@', pos: 0 },
+            Error::InvalidExpression,
+            Error::MismatchedParens {
+                unmatched_open: vec![0],
+                unmatched_close: vec![],
+            },
+        ] {
+            stats.record(&error);
+        }
+        assert_eq!(stats.total, 5);
+        assert_eq!(
+            stats.by_kind,
+            BTreeMap::from([
+                ("BadToken", 3),
+                ("InvalidExpression", 1),
+                ("MismatchedParens", 1)
+            ])
+        );
+        assert_eq!(
+            stats.by_class,
+            BTreeMap::from([
+                (CharClass::Letter, 2),
+                (CharClass::Bracket, 1),
+                (CharClass::Symbol, 1)
+            ])
+        );
+    }
+}
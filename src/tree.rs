@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::custom::Registry;
+use crate::{Calculator, Error, EvalOptions, Function, Operator, Token};
+
+/// What a node of an [`AnnotatedExpr`] computes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Number(f64),
+    /// Two children, or one for [`Operator::Negate`] and
+    /// [`Operator::Percent`].
+    Op(Operator),
+    /// One child, the argument.
+    Function(Function),
+    /// A registered function, with a child per argument.
+    Call(String),
+    /// A registered binary operator.
+    Custom(char),
+    /// A variable read; its value is whatever was assigned earlier in
+    /// the expression.
+    Variable(String),
+    /// One child, the value assigned.
+    Assign(String),
+}
+
+/// An expression tree where every node carries its own result, for showing
+/// how an expression works out step by step.
+///
+/// A node whose operation fails holds the error; its ancestors hold the
+/// same error, since they can't be computed, but its siblings keep their
+/// values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotatedExpr {
+    pub node: Node,
+    pub value: Result<f64, Error>,
+    pub children: Vec<AnnotatedExpr>,
+}
+
+/// Which characters [`AnnotatedExpr::render`] draws branches with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeStyle {
+    /// Box-drawing characters: `├──`, `└──`, `│`.
+    Unicode,
+    /// `|--`, `` `-- ``, `|`, for terminals that can't show box drawing.
+    Ascii,
+}
+
+impl TreeStyle {
+    /// (branch, last branch, continuing line, blank indent)
+    fn pieces(self) -> [&'static str; 4] {
+        match self {
+            TreeStyle::Unicode => ["├── ", "└── ", "│   ", "    "],
+            TreeStyle::Ascii => ["|-- ", "`-- ", "|   ", "    "],
+        }
+    }
+}
+
+impl Calculator {
+    /// Parses `expr` and evaluates every subexpression, in one post-order
+    /// pass with the same rules as [`Calculator::evaluate`]. Errors from
+    /// parsing are returned; errors from evaluating are kept at the node
+    /// they happen in.
+    ///
+    /// ```
+    /// use calculator::{Calculator, TreeStyle};
+    ///
+    /// let tree = Calculator::evaluate_tree("2 + 3 * (4 - 1)").unwrap();
+    /// assert_eq!(tree.value, Ok(11.0));
+    /// assert_eq!(
+    ///     tree.render(TreeStyle::Unicode),
+    ///     "+ = 11\n\
+    ///      ├── 2\n\
+    ///      └── * = 9\n    \
+    ///          ├── 3\n    \
+    ///          └── - = 3\n        \
+    ///              ├── 4\n        \
+    ///              └── 1\n"
+    /// );
+    /// ```
+    pub fn evaluate_tree<T: AsRef<str>>(expr: T) -> Result<AnnotatedExpr, Error> {
+        let postfix = Self::try_to_postfix(Self::parse(expr)?)?;
+        let options = EvalOptions::default();
+        let mut variables = HashMap::new();
+        let mut stack: Vec<AnnotatedExpr> = Vec::new();
+
+        for token in postfix {
+            let (node, arity) = match token {
+                Token::Number(n) => (Node::Number(n), 0),
+                Token::Identifier(ref name) => (Node::Variable(name.to_string()), 0),
+                Token::Function { function, args } => (Node::Function(function), args as usize),
+                Token::Call { ref name, args } => (Node::Call(name.to_string()), args as usize),
+                Token::Assign(ref name) => (Node::Assign(name.to_string()), 1),
+                Token::Op(ref op) if op.is_unary() => (Node::Op(op.clone()), 1),
+                Token::Op(ref op) => (Node::Op(op.clone()), 2),
+                Token::Custom { symbol, .. } => (Node::Custom(symbol), 2),
+                Token::Bracket(_) | Token::Comma => return Err(Error::InvalidExpression),
+            };
+            if stack.len() < arity {
+                return Err(Error::InvalidExpression);
+            }
+            let children = stack.split_off(stack.len() - arity);
+            let value = compute(token, &children, &options, &mut variables);
+            stack.push(AnnotatedExpr {
+                node,
+                value,
+                children,
+            });
+        }
+
+        match (stack.pop(), stack.is_empty()) {
+            (Some(root), true) => Ok(root),
+            _ => Err(Error::InvalidExpression),
+        }
+    }
+}
+
+/// The value of `token` applied to already computed `children`.
+fn compute(
+    token: Token,
+    children: &[AnnotatedExpr],
+    options: &EvalOptions,
+    variables: &mut HashMap<String, f64>,
+) -> Result<f64, Error> {
+    let mut values = Vec::with_capacity(children.len());
+    for child in children {
+        values.push(child.value.clone()?);
+    }
+    Calculator::apply_token(
+        &mut values,
+        &token,
+        options,
+        variables,
+        &Registry::default(),
+    )?;
+    Calculator::finish(values)
+}
+
+impl AnnotatedExpr {
+    /// An indented tree, one node per line, each operation followed by its
+    /// result: `* = 9`, or `/ = error: division by zero`. Numbers are shown
+    /// as they are, a variable as `x = 5` and an assignment as `x := 5`.
+    pub fn render(&self, style: TreeStyle) -> String {
+        let mut out = String::new();
+        self.render_into(&mut out, "", style);
+        out
+    }
+
+    fn render_into(&self, out: &mut String, prefix: &str, style: TreeStyle) {
+        out.push_str(&self.label());
+        out.push('\n');
+
+        let [branch, last_branch, line, blank] = style.pieces();
+        for (i, child) in self.children.iter().enumerate() {
+            let last = i + 1 == self.children.len();
+            out.push_str(prefix);
+            out.push_str(if last { last_branch } else { branch });
+            let child_prefix = format!("{}{}", prefix, if last { blank } else { line });
+            child.render_into(out, &child_prefix, style);
+        }
+    }
+
+    fn label(&self) -> String {
+        let (mut label, equals) = match &self.node {
+            Node::Number(n) => return format!("{}", n),
+            Node::Op(op) => (op.text().to_string(), "="),
+            Node::Custom(symbol) => (symbol.to_string(), "="),
+            Node::Function(function) => (function.name().to_string(), "="),
+            Node::Call(name) => (name.clone(), "="),
+            Node::Variable(name) => (name.clone(), "="),
+            Node::Assign(name) => (name.clone(), ":="),
+        };
+        match &self.value {
+            Ok(value) => write!(label, " {} {}", equals, value),
+            Err(e) => write!(label, " {} error: {}", equals, e),
+        }
+        .unwrap();
+        label
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(expr: &str, style: TreeStyle) -> String {
+        Calculator::evaluate_tree(expr).unwrap().render(style)
+    }
+
+    #[test]
+    fn test_values_match_evaluate() {
+        for expr in [
+            "2 + 3 * (4 - 1)",
+            "2 ^ 3 ^ 2",
+            "-(1 + 2) * sqrt(16)",
+            "7 // 2 % 3",
+            "1 / 0",
+        ] {
+            let tree = Calculator::evaluate_tree(expr).unwrap();
+            assert_eq!(tree.value, Calculator::calculate(expr), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_render_unicode() {
+        assert_eq!(
+            render("2 + 3 * (4 - 1)", TreeStyle::Unicode),
+            "\
++ = 11
+├── 2
+└── * = 9
+    ├── 3
+    └── - = 3
+        ├── 4
+        └── 1
+"
+        );
+        // A branch that continues below keeps its line going.
+        assert_eq!(
+            render("(1 + 2) * 3", TreeStyle::Unicode),
+            "\
+* = 9
+├── + = 3
+│   ├── 1
+│   └── 2
+└── 3
+"
+        );
+    }
+
+    #[test]
+    fn test_render_ascii_and_unary() {
+        assert_eq!(
+            render("-sqrt(16) // 3", TreeStyle::Ascii),
+            "\
+// = -2
+|-- - = -4
+|   `-- sqrt = 4
+|       `-- 16
+`-- 3
+"
+        );
+        assert_eq!(render("42", TreeStyle::Ascii), "42\n");
+    }
+
+    #[test]
+    fn test_error_shown_at_its_node() {
+        let tree = Calculator::evaluate_tree("(1 / 0) + (2 * 3)").unwrap();
+        assert_eq!(tree.value, Err(Error::DivisionByZero));
+        assert_eq!(tree.children[1].value, Ok(6.0));
+        assert_eq!(
+            tree.render(TreeStyle::Unicode),
+            "\
++ = error: division by zero
+├── / = error: division by zero
+│   ├── 1
+│   └── 0
+└── * = 6
+    ├── 2
+    └── 3
+"
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(
+            render("y = (x = 3) * x", TreeStyle::Ascii),
+            "\
+y := 9
+`-- * = 9
+    |-- x := 3
+    |   `-- 3
+    `-- x = 3
+"
+        );
+        let tree = Calculator::evaluate_tree("1 + z").unwrap();
+        assert_eq!(tree.children[1].node, Node::Variable("z".to_string()));
+        assert_eq!(
+            tree.children[1].value,
+            Err(Error::UndefinedVariable("z".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_returned() {
+        assert_eq!(
+            Calculator::evaluate_tree("2 @ 3"),
+            Err(Error::BadToken { ch: '@', pos: 2 })
+        );
+        assert_eq!(
+            Calculator::evaluate_tree("2 +"),
+            Err(Error::UnexpectedToken { pos: 2 })
+        );
+        assert_eq!(Calculator::evaluate_tree(""), Err(Error::InvalidExpression));
+    }
+}
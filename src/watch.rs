@@ -0,0 +1,179 @@
+use crate::{BatchReport, Error};
+
+const YELLOW: &str = "\x1b[33m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// How one line's result compares with the previous run, as shown by
+/// `calculator --watch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineChange {
+    Unchanged,
+    /// The line existed before and now evaluates to a different value or
+    /// error.
+    Changed,
+    /// No expression was on this line in the previous run.
+    Added,
+}
+
+impl LineChange {
+    pub fn marker(self) -> char {
+        match self {
+            LineChange::Unchanged => ' ',
+            LineChange::Changed => '*',
+            LineChange::Added => '+',
+        }
+    }
+
+    fn color(self) -> Option<&'static str> {
+        match self {
+            LineChange::Unchanged => None,
+            LineChange::Changed => Some(YELLOW),
+            LineChange::Added => Some(GREEN),
+        }
+    }
+}
+
+impl BatchReport {
+    /// Compares each item with the item on the same line of `previous`, one
+    /// entry per item of `self`. Lines are matched by number rather than by
+    /// text, so editing a line is what marks it changed.
+    pub fn changes_since(&self, previous: &BatchReport) -> Vec<LineChange> {
+        self.items
+            .iter()
+            .map(
+                |item| match previous.items.iter().find(|p| p.line == item.line) {
+                    None => LineChange::Added,
+                    Some(p) if same_outcome(&p.result, &item.result) => LineChange::Unchanged,
+                    Some(_) => LineChange::Changed,
+                },
+            )
+            .collect()
+    }
+
+    /// The report table with a marker column from `changes` (see
+    /// [`LineChange::marker`]). With `color`, changed rows are yellow and
+    /// added rows green.
+    pub fn render_changes(&self, changes: &[LineChange], color: bool) -> String {
+        let table = self.to_string();
+        let mut out = String::new();
+        for (i, row) in table.lines().enumerate() {
+            // The first line is the header and the last the summary; the
+            // rows in between follow `items` one to one.
+            let change = i
+                .checked_sub(1)
+                .filter(|&item| item < self.len())
+                .and_then(|item| changes.get(item).copied())
+                .unwrap_or(LineChange::Unchanged);
+            match change.color().filter(|_| color) {
+                Some(code) => {
+                    out.push_str(&format!("{}{} {}{}", code, change.marker(), row, RESET))
+                }
+                None => out.push_str(&format!("{} {}", change.marker(), row)),
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// NaN results compare equal so a line that keeps producing NaN is not
+/// flagged on every run.
+fn same_outcome(a: &Result<f64, Error>, b: &Result<f64, Error>) -> bool {
+    match (a, b) {
+        (Ok(a), Ok(b)) => a == b || (a.is_nan() && b.is_nan()),
+        (Err(a), Err(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BatchItem;
+
+    fn report(results: Vec<(usize, Result<f64, Error>)>) -> BatchReport {
+        let items = results
+            .into_iter()
+            .enumerate()
+            .map(|(index, (line, result))| BatchItem {
+                index,
+                line,
+                source: format!("line {}", line),
+                result,
+            })
+            .collect();
+        BatchReport { items }
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let previous = report(vec![
+            (1, Ok(1.0)),
+            (2, Ok(2.0)),
+            (3, Err(Error::DivisionByZero)),
+            (4, Ok(f64::NAN)),
+            (6, Ok(6.0)),
+        ]);
+        let current = report(vec![
+            (1, Ok(1.0)),
+            (2, Ok(2.5)),
+            (3, Err(Error::InvalidExpression)),
+            (4, Ok(f64::NAN)),
+            (5, Ok(5.0)),
+        ]);
+        assert_eq!(
+            current.changes_since(&previous),
+            [
+                LineChange::Unchanged,
+                LineChange::Changed,
+                LineChange::Changed,
+                LineChange::Unchanged,
+                LineChange::Added,
+            ]
+        );
+        assert_eq!(current.changes_since(&current), [LineChange::Unchanged; 5]);
+    }
+
+    #[test]
+    fn test_error_to_value_is_a_change() {
+        let previous = report(vec![(1, Err(Error::DivisionByZero))]);
+        let current = report(vec![(1, Ok(0.0))]);
+        assert_eq!(current.changes_since(&previous), [LineChange::Changed]);
+        assert_eq!(previous.changes_since(&current), [LineChange::Changed]);
+    }
+
+    #[test]
+    fn test_render_changes_plain() {
+        let current = report(vec![(1, Ok(1.0)), (2, Ok(2.5)), (4, Ok(4.0))]);
+        let changes = [
+            LineChange::Unchanged,
+            LineChange::Changed,
+            LineChange::Added,
+        ];
+        assert_eq!(
+            current.render_changes(&changes, false),
+            "   line  expression  result\n\
+             \x20     1  line 1      1\n\
+             *     2  line 2      2.5\n\
+             +     4  line 4      4\n\
+             \x20 3 expressions: 3 succeeded, 0 failed\n"
+        );
+    }
+
+    #[test]
+    fn test_render_changes_color() {
+        let current = report(vec![(1, Ok(1.0)), (2, Ok(2.5)), (3, Ok(3.0))]);
+        let changes = [
+            LineChange::Unchanged,
+            LineChange::Changed,
+            LineChange::Added,
+        ];
+        let rendered = current.render_changes(&changes, true);
+        let rows: Vec<&str> = rendered.lines().collect();
+        assert_eq!(rows[1], "      1  line 1      1");
+        assert_eq!(rows[2], "\x1b[33m*     2  line 2      2.5\x1b[0m");
+        assert_eq!(rows[3], "\x1b[32m+     3  line 3      3\x1b[0m");
+        assert!(!rows[4].contains('\x1b'));
+    }
+}
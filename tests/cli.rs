@@ -0,0 +1,37 @@
+//! Runs the built binary, checking the exit status scripts depend on.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Pipes `input` to `calculator -` and returns its exit status and stderr.
+fn run(input: &str) -> (i32, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calculator"))
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_exit_status() {
+    assert_eq!(run("1 + 2\n").0, 0);
+    assert_eq!(run("1 / 0\n2\n").0, 1);
+    for malformed in ["5 *", "2 + + 3", "2 @ 3"] {
+        let (status, errors) = run(&format!("1 / 0\n{}\n", malformed));
+        assert_eq!(status, 2, "{}", malformed);
+        assert!(errors.contains("line 2"), "{}: {}", malformed, errors);
+    }
+}
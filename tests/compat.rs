@@ -0,0 +1,47 @@
+//! The deprecated signatures, kept for callers written against them, must
+//! agree with their replacements wherever those succeed.
+#![allow(deprecated)]
+
+use calculator::{Calculator, Error, Token};
+
+#[test]
+fn test_to_postfix_matches_try_to_postfix() {
+    for expr in [
+        "2 * (3 + 4)",
+        "2 ^ 3 ^ 2",
+        "max(1, 2, 3) - -4",
+        "x = 5",
+        "50% * 2",
+    ] {
+        let tokens = Calculator::parse(expr).unwrap();
+        let postfix = Calculator::try_to_postfix(tokens.clone()).unwrap();
+        assert_eq!(Calculator::to_postfix(tokens.clone()), postfix, "{}", expr);
+        assert_eq!(
+            Calculator::to_postfix_with_origins(tokens.clone()),
+            Calculator::try_to_postfix_with_origins(tokens.clone()).unwrap(),
+            "{}",
+            expr
+        );
+        let calc = Calculator::builder().build();
+        assert_eq!(calc.to_postfix(tokens), postfix, "{}", expr);
+    }
+}
+
+#[test]
+fn test_to_postfix_of_unbalanced_tokens() {
+    let tokens = vec![Token::Bracket('('), Token::Number(1.0)];
+    assert_eq!(
+        Calculator::try_to_postfix(tokens.clone()),
+        Err(Error::MismatchedParens {
+            unmatched_open: vec![0],
+            unmatched_close: vec![],
+        })
+    );
+    // As before `try_to_postfix`, the failure shows up on evaluating.
+    let postfix = Calculator::to_postfix(tokens.clone());
+    assert_eq!(Calculator::evaluate(postfix), Err(Error::InvalidExpression));
+    assert_eq!(
+        Calculator::to_postfix_with_origins(tokens),
+        (vec![], vec![])
+    );
+}
@@ -0,0 +1,433 @@
+//! Runs generated expressions and the regression corpus through both
+//! `Calculator::calculate` and the independent evaluator in `reference.rs`,
+//! which must agree on every value and on the kind of every error.
+//!
+//! When the grammar grows, `reference.rs` grows with it;
+//! `test_reference_covers_grammar` fails until it does.
+
+mod reference;
+
+use std::fs;
+use std::path::Path;
+
+use calculator::{Calculator, Error, Function, Operator, Token};
+use reference::Kind;
+
+fn kind(error: &Error) -> Option<Kind> {
+    match error {
+        Error::BadToken { .. } => Some(Kind::BadToken),
+        Error::InvalidNumber { .. } => Some(Kind::InvalidNumber),
+        Error::UnknownFunction { .. } => Some(Kind::UnknownFunction),
+        Error::ReservedName { .. } => Some(Kind::ReservedName),
+        Error::UndefinedVariable(_) => Some(Kind::UndefinedVariable),
+        Error::NoPreviousResult => Some(Kind::NoPreviousResult),
+        Error::WrongArity { .. } => Some(Kind::WrongArity),
+        Error::SpaceInNumber { .. } => Some(Kind::SpaceInNumber),
+        Error::MismatchedParens { .. } => Some(Kind::MismatchedParens),
+        Error::MismatchedBars { .. } => Some(Kind::MismatchedBars),
+        // The reference doesn't say where malformed input goes wrong.
+        Error::InvalidExpression | Error::UnexpectedToken { .. } => Some(Kind::InvalidExpression),
+        Error::DivisionByZero => Some(Kind::DivisionByZero),
+        Error::DomainError { .. } => Some(Kind::DomainError),
+        Error::NotAnInteger { .. } => Some(Kind::NotAnInteger),
+        Error::Overflow => Some(Kind::Overflow),
+        _ => None,
+    }
+}
+
+/// `None` when both agree, otherwise a line describing the difference.
+fn compare(input: &str) -> Option<String> {
+    let actual = Calculator::calculate(input);
+    let expected = reference::calculate(input);
+    let agree = match (&actual, &expected) {
+        (Ok(a), Ok(b)) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+        (Err(a), Err(b)) => kind(a) == Some(*b),
+        _ => false,
+    };
+    (!agree).then(|| {
+        format!(
+            "{:?}: calculator {:?}, reference {:?}",
+            input, actual, expected
+        )
+    })
+}
+
+fn assert_agree<I: IntoIterator<Item = String>>(inputs: I) {
+    let mut total = 0;
+    let disagreements: Vec<String> = inputs
+        .into_iter()
+        .inspect(|_| total += 1)
+        .filter_map(|input| compare(&input))
+        .collect();
+    assert!(
+        disagreements.is_empty(),
+        "{} of {} inputs disagree:\n{}",
+        disagreements.len(),
+        total,
+        disagreements[..disagreements.len().min(20)].join("\n")
+    );
+}
+
+/// xorshift64*, so the corpus is the same on every run.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 33) as usize % n
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+const NUMBERS: [&str; 10] = ["0", "1", "2", "3", "7", "10", "2.5", "0.1", "1e3", "4E-2"];
+const CONSTANTS: [&str; 3] = ["pi", "e", "TAU"];
+
+/// Mostly well-formed expressions, nested without regard to precedence:
+/// `-` in front of `1 + 2` gives `-1 + 2`, which is what needs testing.
+fn well_formed(rng: &mut Rng, depth: usize) -> String {
+    if depth == 0 || rng.below(4) == 0 {
+        return match rng.below(6) {
+            0 => rng.pick(&CONSTANTS).to_string(),
+            _ => rng.pick(&NUMBERS).to_string(),
+        };
+    }
+    let a = well_formed(rng, depth - 1);
+    match rng.below(11) {
+        0 => format!("-{}", a),
+        6 => format!("{}%", a),
+        7 => format!("|{}|", a),
+        1 => format!("({})", a),
+        2 => format!("{}({})", rng.pick(&["sqrt", "abs", "cos", "exp"]), a),
+        3 => format!("{} ** {}", a, well_formed(rng, depth - 1)),
+        // Inner assignments to `x` change it for what follows.
+        4 => format!("(x = {}) * x", a),
+        5 => match rng.pick(&[
+            "max",
+            "min",
+            "weighted_avg",
+            "pow",
+            "div",
+            "floordiv",
+            "pct_change",
+            "safe_div",
+            "bitand",
+            "bitor",
+            "bitxor",
+            "shl",
+            "shr",
+        ]) {
+            name @ ("pow" | "div" | "floordiv" | "pct_change" | "safe_div" | "bitand" | "bitor"
+            | "bitxor" | "shl" | "shr") => {
+                format!("{}({}, {})", name, a, well_formed(rng, depth - 1))
+            }
+            name => {
+                let b = well_formed(rng, depth - 1);
+                format!("{}({}, {}, {})", name, a, b, well_formed(rng, depth - 1))
+            }
+        },
+        _ => {
+            let op = rng.pick(&[
+                "+", "-", "*", "/", "//", "%", "^", "- -", "* +", "^ -", "% +",
+            ]);
+            let space = rng.pick(&["", " "]);
+            format!(
+                "{}{}{}{}{}",
+                a,
+                space,
+                op,
+                space,
+                well_formed(rng, depth - 1)
+            )
+        }
+    }
+}
+
+/// Arbitrary runs of tokens and near-tokens, mostly invalid.
+fn token_soup(rng: &mut Rng) -> String {
+    const PIECES: [&str; 32] = [
+        "1", "2.5", "0", "1e", "3e+", "2.3.4", "pi", "e", "sqrt", "foo", "+", "-", "*", "/", "//",
+        "%", "^", "**", "(", ")", " ", "@", ".", "x", "=", "x =", ",", "max", "pow", "|", "0b",
+        "0xF",
+    ];
+    (0..1 + rng.below(10)).map(|_| rng.pick(&PIECES)).collect()
+}
+
+#[test]
+fn test_generated_expressions_agree() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    assert_agree((0..20_000).map(|_| well_formed(&mut rng, 5)));
+    assert_agree((0..20_000).map(|_| token_soup(&mut rng)));
+}
+
+#[test]
+fn test_hand_picked_expressions_agree() {
+    let cases = [
+        "-2 ^ 2",
+        "2 ^ -1 ^ 2",
+        "2 ^ 3 ^ 2",
+        "-sqrt(4) ^ 2",
+        "2 * -3 ^ 2",
+        "7 // 2 % 3 * 4",
+        "-7 % 3",
+        "8 / 2 / 2",
+        "1 - - - 1",
+        "+-+2",
+        "2e",
+        "2ex",
+        "1 000",
+        "2 pi 3",
+        "1e3 5",
+        "a = b = 3",
+        "y = (x = 5) * 2",
+        "x + (x = 1)",
+        "(x = 1) + x",
+        "sqrt(x = 4) * x",
+        "2 * x = 3",
+        "-x = 1",
+        "pi = 3",
+        "sqrt = 3",
+        "foo (2)",
+        "ans + 1",
+        "ans = 1",
+        "(ans = 1) + 2",
+        "answer = 1",
+        "sqrt(1, 2)",
+        "sqrt(1, 2), 3",
+        "1, sqrt(1, 2)",
+        "sqrt(1, (2, 3))",
+        "sqrt((1, 2), 3)",
+        "(1, 2)",
+        "sqrt(1,)",
+        "max(1, 2+3, 4)",
+        "pow(2, 10)",
+        "max(min(1,2), 3)",
+        "max()",
+        "max(1)",
+        "pow(1)",
+        "pow(1, 2, 3)",
+        "pow(1, 2), 3",
+        "div(7, 2)",
+        "floordiv(-7, 2)",
+        "div(1, 0)",
+        "floordiv(1, 2 - 2)",
+        "div(7)",
+        "min(1, (2, 3))",
+        "max(0 / 0, 1)",
+        "max = 1",
+        "25%",
+        "(10+5)%",
+        "50% * 200",
+        "1 + 50%",
+        "-50% ^ 2",
+        "2 ^ 50%",
+        "200%%",
+        "10%3",
+        "10 % -3",
+        "10 % - 3",
+        "10 %(3)",
+        "10 % |-3|",
+        "|50%|",
+        "|10 % |-3||",
+        "%",
+        "(%)",
+        "% 2",
+        "|3 - 5| * 2",
+        "|2 - |3 - 5||",
+        "||3| - 5|",
+        "|1 +|",
+        "|(1|)",
+        "(|1)|",
+        "(|1|",
+        "|1, 2|",
+        "|1|2|",
+        "|50%|",
+        "10 % |2|",
+        "0xFF - 0o17 + 0b1",
+        "0XfF * 0B11 // 0O7",
+        "0b102",
+        "0x",
+        "0x 1",
+        "0xg",
+        "0x1e3",
+        "0x1.5",
+        "0o1 0o2",
+        "1_000 + 2_000",
+        "1_0.0_1",
+        "0xF_F",
+        "1_",
+        "1__0",
+        "1_.5",
+        "1._5",
+        "_1",
+        "1e1_0",
+        "1.2.3",
+        ".5",
+        "2. + .25",
+        "1 .5",
+        "x.5",
+        ".e3",
+        "0x1.8",
+        "1e5e5",
+        "1.5e3.2",
+        "1e5.",
+        "2e3e",
+        "sqrt 4",
+        "sqrt()",
+        "pi(2)",
+        "()",
+        ")(",
+        "1 / 0 +",
+        "0 ^ -1",
+        "sqrt(-1)",
+    ];
+    assert_agree(cases.map(String::from));
+}
+
+/// `Calculator::parse_ast` and `Expr::evaluate` against the postfix path,
+/// on everything the reference is checked on above.
+#[test]
+fn test_ast_agrees_with_postfix() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut inputs: Vec<String> = (0..20_000).map(|_| well_formed(&mut rng, 5)).collect();
+    inputs.extend((0..20_000).map(|_| token_soup(&mut rng)));
+    let disagreements: Vec<String> = inputs
+        .iter()
+        .filter_map(|input| {
+            let postfix = Calculator::calculate(input);
+            let tree = Calculator::parse_ast(input).and_then(|ast| ast.evaluate());
+            let agree = match (&postfix, &tree) {
+                (Ok(a), Ok(b)) => a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan()),
+                _ => postfix == tree,
+            };
+            (!agree).then(|| format!("{:?}: postfix {:?}, tree {:?}", input, postfix, tree))
+        })
+        .collect();
+    assert!(
+        disagreements.is_empty(),
+        "{} of {} inputs disagree:\n{}",
+        disagreements.len(),
+        inputs.len(),
+        disagreements[..disagreements.len().min(20)].join("\n")
+    );
+}
+
+#[test]
+fn test_printed_ast_parses_back() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let mut inputs: Vec<String> = (0..20_000).map(|_| well_formed(&mut rng, 5)).collect();
+    inputs.extend((0..20_000).map(|_| token_soup(&mut rng)));
+    let mismatches: Vec<String> = inputs
+        .iter()
+        .filter_map(|input| {
+            let ast = Calculator::parse_ast(input).ok()?;
+            let printed = ast.to_string();
+            let reparsed = Calculator::parse_ast(&printed);
+            (reparsed.as_ref() != Ok(&ast)).then(|| {
+                format!(
+                    "{:?} printed as {:?}, reparsed {:?}",
+                    input, printed, reparsed
+                )
+            })
+        })
+        .collect();
+    assert!(
+        mismatches.is_empty(),
+        "{} of {} inputs don't print back:\n{}",
+        mismatches.len(),
+        inputs.len(),
+        mismatches[..mismatches.len().min(20)].join("\n")
+    );
+}
+
+#[test]
+fn test_regression_corpus_agrees() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions");
+    let inputs: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+        .filter_map(|contents| {
+            let input = contents
+                .lines()
+                .find_map(|line| line.strip_prefix("input: "))?;
+            Some(input.to_string())
+        })
+        .collect();
+    assert!(!inputs.is_empty());
+
+    let unsupported: Vec<&String> = inputs.iter().filter(|i| !i.is_ascii()).collect();
+    assert!(
+        unsupported.is_empty(),
+        "the reference only reads ASCII: {:?}",
+        unsupported
+    );
+    assert_agree(inputs);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Fixity {
+    Prefix,
+    Infix,
+    Postfix,
+}
+
+/// How the reference spells each operator, and where it goes.
+fn spelling(op: &Operator) -> (&'static str, Fixity) {
+    match op {
+        Operator::Add => ("+", Fixity::Infix),
+        Operator::Subtract => ("-", Fixity::Infix),
+        Operator::Multiply => ("*", Fixity::Infix),
+        Operator::Divide => ("/", Fixity::Infix),
+        Operator::FloorDivide => ("//", Fixity::Infix),
+        Operator::Modulo => ("%", Fixity::Infix),
+        Operator::Power => ("^", Fixity::Infix),
+        Operator::Negate => ("-", Fixity::Prefix),
+        Operator::Percent => ("%", Fixity::Postfix),
+    }
+}
+
+#[test]
+fn test_reference_covers_grammar() {
+    let mut unsupported = Vec::new();
+
+    for op in Operator::ALL {
+        let (text, fixity) = spelling(&op);
+        let (sample, at) = match fixity {
+            Fixity::Prefix => (format!("{}1", text), 0),
+            Fixity::Infix => (format!("1 {} 1", text), 1),
+            Fixity::Postfix => (format!("1{}", text), 1),
+        };
+        // The spelling must really produce this operator.
+        assert_eq!(
+            Calculator::parse(&sample).unwrap()[at],
+            Token::Op(op.clone())
+        );
+        let known = match fixity {
+            Fixity::Prefix => reference::PREFIX.contains(&text),
+            Fixity::Infix => reference::BINARY.contains(&text) || reference::POWER.contains(&text),
+            Fixity::Postfix => reference::POSTFIX.contains(&text),
+        };
+        if !known {
+            unsupported.push(format!("operator {:?}", op));
+        }
+    }
+
+    for function in Function::ALL {
+        if !reference::FUNCTIONS
+            .iter()
+            .any(|(name, _)| *name == function.name())
+        {
+            unsupported.push(format!("function {}", function.name()));
+        }
+    }
+
+    assert!(
+        unsupported.is_empty(),
+        "tests/differential/reference.rs doesn't handle: {}",
+        unsupported.join(", ")
+    );
+}
@@ -0,0 +1,694 @@
+//! A second evaluator for the language `Calculator::calculate` accepts with
+//! default options, written as plainly as possible so it can be checked by
+//! reading. It shares no code with the library: it lexes, checks brackets,
+//! parses by recursive descent and evaluates the tree, in that order, so
+//! errors come out in the same order as the library reports them.
+//!
+//! ```text
+//! list    := expr ("," expr)*
+//! expr    := name "=" expr | sum
+//! sum     := term (("+" | "-") term)*
+//! term    := unary (("*" | "/" | "//" | "%") unary)*
+//! unary   := ("-" | "+") unary | power       (no "+" right after a binary
+//!                                             operator)
+//! power   := postfix ("^" unary)?            ("**" is "^")
+//! postfix := primary "%"*
+//! primary := number | constant | name | function "(" list ")" | "(" list ")"
+//!          | "|" list "|"                    (as "abs(" list ")")
+//! ```
+//!
+//! A list is only valid as the arguments of a call, and a function takes one
+//! argument, except `pow`, which takes two, and `min` and `max`, which take
+//! any number. Those two errors are found after the rest of the grammar is
+//! checked, and the one earliest in the tokens wins: a stray comma at the
+//! comma, the wrong number of arguments at the call's `)`.
+//!
+//! A `%` followed by something that starts a primary, past any spaces, is the
+//! remainder in `term`; so is one followed by a `-` written directly against
+//! such a thing. Any other `%` is a percentage in `postfix`. A `|` only
+//! starts a primary here when the innermost open bracket isn't a bar, since
+//! otherwise it closes that bar.
+//!
+//! A number is a decimal literal with an optional exponent, or `0x`, `0o` or
+//! `0b` (either case) followed by letters and digits that must all be digits
+//! in that base. A `_` may stand between two digits of either, as in
+//! `1_000`. A `.` straight after a prefixed literal or an exponent, or a
+//! second exponent, makes the whole run of letters, digits, points and signs
+//! one invalid number, as in `0x1.5` or `1e5e5`.
+//!
+//! A `|` after a number, name, `)`, closing `|` or percentage closes the bar
+//! most recently opened, and any other `|` opens one. Bars that don't pair
+//! up, or that cross brackets, are reported where the lexer finds them.
+//!
+//! A negative base to a power that isn't whole, with `^` or `pow`, is a
+//! domain error: odd roots are only real with an option the library is
+//! checked without.
+//!
+//! Names are variables, a function's name too unless a `(` follows it,
+//! evaluated left to right in one environment that starts empty; `ans`, the
+//! previous result, can't be assigned and so never has a value.
+//!
+//! Only ASCII input is handled; the library's normalization of typographic
+//! characters is out of scope.
+
+use std::collections::HashMap;
+
+/// Which error the library should report, without its details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    BadToken,
+    InvalidNumber,
+    UnknownFunction,
+    ReservedName,
+    UndefinedVariable,
+    NoPreviousResult,
+    WrongArity,
+    SpaceInNumber,
+    MismatchedParens,
+    MismatchedBars,
+    InvalidExpression,
+    DivisionByZero,
+    DomainError,
+    NotAnInteger,
+    Overflow,
+}
+
+/// Binary operators, as written.
+pub const BINARY: [&str; 6] = ["+", "-", "*", "/", "//", "%"];
+/// Right-associative power, as written; `**` is read as `^`.
+pub const POWER: [&str; 2] = ["^", "**"];
+/// Prefix signs; `+` does nothing.
+pub const PREFIX: [&str; 2] = ["-", "+"];
+/// Percent, dividing by 100.
+pub const POSTFIX: [&str; 1] = ["%"];
+
+#[derive(Debug, Clone, Copy)]
+pub enum Apply {
+    One(fn(f64) -> f64),
+    /// Two, with a zero second one a division by zero.
+    Quotient(fn(f64, f64) -> f64),
+    /// Any number from one, combined from the left.
+    Fold(fn(f64, f64) -> f64),
+    /// Two, as `^` raises them.
+    Power,
+    /// Two, or pairs of any number of them, combined by a function that can
+    /// fail.
+    Checked(fn(&[f64]) -> Result<f64, Kind>, Count),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Count {
+    Two,
+    Pairs,
+}
+
+impl Apply {
+    fn takes(self, count: usize) -> bool {
+        match self {
+            Apply::One(_) => count == 1,
+            Apply::Quotient(_) | Apply::Power => count == 2,
+            Apply::Fold(_) | Apply::Checked(_, Count::Pairs) => true,
+            Apply::Checked(_, Count::Two) => count == 2,
+        }
+    }
+
+    fn call(self, arguments: &[f64]) -> Result<f64, Kind> {
+        match self {
+            Apply::One(f) => Ok(f(arguments[0])),
+            Apply::Quotient(_) if arguments[1] == 0.0 => Err(Kind::DivisionByZero),
+            Apply::Quotient(f) => Ok(f(arguments[0], arguments[1])),
+            Apply::Fold(f) => Ok(arguments[1..].iter().fold(arguments[0], |a, b| f(a, *b))),
+            Apply::Power => power(arguments[0], arguments[1]),
+            Apply::Checked(f, _) => f(arguments),
+        }
+    }
+}
+
+fn power(base: f64, exponent: f64) -> Result<f64, Kind> {
+    if base < 0.0 && exponent.is_finite() && exponent.fract() != 0.0 {
+        return Err(Kind::DomainError);
+    }
+    Ok(base.powf(exponent))
+}
+
+fn pct_change(arguments: &[f64]) -> Result<f64, Kind> {
+    let (old, new) = (arguments[0], arguments[1]);
+    if old == 0.0 {
+        return Err(Kind::DomainError);
+    }
+    Ok((new - old) / old.abs() * 100.0)
+}
+
+fn safe_div(arguments: &[f64]) -> Result<f64, Kind> {
+    Ok(match arguments[1] {
+        0.0 => 0.0,
+        b => arguments[0] / b,
+    })
+}
+
+fn weighted_avg(arguments: &[f64]) -> Result<f64, Kind> {
+    if !arguments.len().is_multiple_of(2) {
+        return Err(Kind::DomainError);
+    }
+    let (mut sum, mut weight) = (0.0, 0.0);
+    for pair in arguments.chunks(2) {
+        sum += pair[0] * pair[1];
+        weight += pair[1];
+    }
+    if weight == 0.0 {
+        return Err(Kind::DomainError);
+    }
+    Ok(sum / weight)
+}
+
+/// A bit function's operands as whole numbers, both below 2^53 and not
+/// negative.
+fn bits(arguments: &[f64]) -> Result<(u64, u64), Kind> {
+    let mut bits = [0; 2];
+    for (bits, &x) in bits.iter_mut().zip(arguments) {
+        if x.fract() != 0.0 || x.abs() >= 9_007_199_254_740_992.0 {
+            return Err(Kind::NotAnInteger);
+        }
+        if x < 0.0 {
+            return Err(Kind::DomainError);
+        }
+        *bits = x as u64;
+    }
+    Ok((bits[0], bits[1]))
+}
+
+fn shl(arguments: &[f64]) -> Result<f64, Kind> {
+    let (x, n) = bits(arguments)?;
+    let mut value = x as f64;
+    for _ in 0..n.min(54) {
+        value *= 2.0;
+    }
+    if value >= 9_007_199_254_740_992.0 {
+        return Err(Kind::Overflow);
+    }
+    Ok(value)
+}
+
+fn shr(arguments: &[f64]) -> Result<f64, Kind> {
+    let (x, n) = bits(arguments)?;
+    Ok((x as f64 / 2f64.powi(n.min(64) as i32)).floor())
+}
+
+pub const FUNCTIONS: [(&str, Apply); 22] = [
+    ("sqrt", Apply::One(f64::sqrt)),
+    ("cbrt", Apply::One(f64::cbrt)),
+    ("sin", Apply::One(f64::sin)),
+    ("cos", Apply::One(f64::cos)),
+    ("tan", Apply::One(f64::tan)),
+    ("ln", Apply::One(f64::ln)),
+    ("log10", Apply::One(f64::log10)),
+    ("exp", Apply::One(f64::exp)),
+    ("abs", Apply::One(f64::abs)),
+    ("min", Apply::Fold(f64::min)),
+    ("max", Apply::Fold(f64::max)),
+    ("pow", Apply::Power),
+    ("div", Apply::Quotient(|a, b| a / b)),
+    ("floordiv", Apply::Quotient(|a, b| (a / b).floor())),
+    ("pct_change", Apply::Checked(pct_change, Count::Two)),
+    ("safe_div", Apply::Checked(safe_div, Count::Two)),
+    ("weighted_avg", Apply::Checked(weighted_avg, Count::Pairs)),
+    (
+        "bitand",
+        Apply::Checked(|a| bits(a).map(|(x, y)| (x & y) as f64), Count::Two),
+    ),
+    (
+        "bitor",
+        Apply::Checked(|a| bits(a).map(|(x, y)| (x | y) as f64), Count::Two),
+    ),
+    (
+        "bitxor",
+        Apply::Checked(|a| bits(a).map(|(x, y)| (x ^ y) as f64), Count::Two),
+    ),
+    ("shl", Apply::Checked(shl, Count::Two)),
+    ("shr", Apply::Checked(shr, Count::Two)),
+];
+
+const CONSTANTS: [(&str, f64); 3] = [
+    ("pi", std::f64::consts::PI),
+    ("e", std::f64::consts::E),
+    ("tau", std::f64::consts::TAU),
+];
+
+#[derive(Debug, Clone)]
+enum Tok {
+    Num(f64),
+    Var(String),
+    /// `name =`.
+    Assign(String),
+    /// An operator, with `**` already turned into `^`.
+    Sym(&'static str),
+    Call(Apply),
+    Open,
+    Close,
+    Comma,
+    Percent,
+    BarOpen,
+    BarClose,
+}
+
+enum Expr {
+    Num(f64),
+    Var(String),
+    Assign(String, Box<Expr>),
+    Neg(Box<Expr>),
+    Percent(Box<Expr>),
+    Call(Apply, Vec<Expr>),
+    Bin(&'static str, Box<Expr>, Box<Expr>),
+}
+
+pub fn calculate(input: &str) -> Result<f64, Kind> {
+    let toks = lex(input)?;
+    check_brackets(&toks)?;
+    let mut parser = Parser {
+        toks: &toks,
+        at: 0,
+        deferred: None,
+    };
+    let mut list = parser.list(false)?;
+    if parser.at != toks.len() {
+        return Err(Kind::InvalidExpression);
+    }
+    if let Some((_, kind)) = parser.deferred {
+        return Err(kind);
+    }
+    eval(&list.remove(0), &mut HashMap::new())
+}
+
+/// Whether every `_` in `literal` has a digit on both sides.
+fn separated(literal: &[char], digit: fn(&char) -> bool) -> bool {
+    (0..literal.len()).all(|k| {
+        literal[k] != '_'
+            || (k > 0 && k + 1 < literal.len() && digit(&literal[k - 1]) && digit(&literal[k + 1]))
+    })
+}
+
+fn lex(input: &str) -> Result<Vec<Tok>, Kind> {
+    let c: Vec<char> = input.chars().collect();
+    let mut toks = Vec::new();
+    // Whether the last token was a number literal, not counting spaces.
+    let mut after_literal = false;
+    // For each `(` and `|` still open, innermost last, whether it is a bar.
+    let mut open: Vec<bool> = Vec::new();
+    let mut i = 0;
+    while i < c.len() {
+        let ch = c[i];
+        let next = c.get(i + 1).copied();
+        if ch == ' ' || ch == '\t' || ch == '\n' {
+            i += 1;
+            continue;
+        }
+        let literal =
+            ch.is_ascii_digit() || (ch == '.' && next.is_some_and(|n| n.is_ascii_digit()));
+        if literal && after_literal {
+            return Err(Kind::SpaceInNumber);
+        }
+        after_literal = literal;
+        let radix = match (ch, next) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('o' | 'O')) => 8,
+            ('0', Some('b' | 'B')) => 2,
+            _ => 0,
+        };
+        if radix > 0 {
+            i += 2;
+            let start = i;
+            while i < c.len() && (c[i].is_alphanumeric() || c[i] == '_') {
+                i += 1;
+            }
+            if !separated(&c[start..i], char::is_ascii_alphanumeric) {
+                return Err(Kind::InvalidNumber);
+            }
+            if i == start {
+                return Err(Kind::InvalidNumber);
+            }
+            let mut value = 0.0;
+            for d in c[start..i].iter().filter(|d| **d != '_') {
+                let digit = match d {
+                    '0'..='9' => *d as u32 - '0' as u32,
+                    'a'..='z' => *d as u32 - 'a' as u32 + 10,
+                    'A'..='Z' => *d as u32 - 'A' as u32 + 10,
+                    _ => return Err(Kind::InvalidNumber),
+                };
+                if digit >= radix {
+                    return Err(Kind::InvalidNumber);
+                }
+                value = value * radix as f64 + digit as f64;
+            }
+            if c.get(i) == Some(&'.') {
+                return Err(Kind::InvalidNumber);
+            }
+            toks.push(Tok::Num(value));
+        } else if literal {
+            let start = i;
+            while i < c.len() && (c[i].is_ascii_digit() || c[i] == '.' || c[i] == '_') {
+                i += 1;
+            }
+            if !separated(&c[start..i], char::is_ascii_digit) {
+                return Err(Kind::InvalidNumber);
+            }
+            if i < c.len() && (c[i] == 'e' || c[i] == 'E') {
+                let mut j = i + 1;
+                if j < c.len() && (c[j] == '+' || c[j] == '-') {
+                    j += 1;
+                }
+                let digits_start = j;
+                while j < c.len() && c[j].is_ascii_digit() {
+                    j += 1;
+                }
+                if j > digits_start {
+                    i = j;
+                    let mut k = i + 1;
+                    if k < c.len() && (c[k] == '+' || c[k] == '-') {
+                        k += 1;
+                    }
+                    let again = i < c.len()
+                        && (c[i] == 'e' || c[i] == 'E')
+                        && k < c.len()
+                        && c[k].is_ascii_digit();
+                    if c.get(i) == Some(&'.') || c.get(i) == Some(&'_') || again {
+                        return Err(Kind::InvalidNumber);
+                    }
+                } else {
+                    // `2e` is a broken exponent; `2ex` is 2 against a name.
+                    let after = c.get(i + 1);
+                    if !after.is_some_and(|a| a.is_alphanumeric() || *a == '_') {
+                        return Err(Kind::InvalidNumber);
+                    }
+                }
+            }
+            // Without implicit multiplication a name against a literal,
+            // as in `2pi` or `2ex`, makes it malformed.
+            if c.get(i).is_some_and(|a| a.is_alphabetic()) {
+                return Err(Kind::InvalidNumber);
+            }
+            let text: String = c[start..i].iter().filter(|d| **d != '_').collect();
+            match text.parse::<f64>() {
+                Ok(value) => toks.push(Tok::Num(value)),
+                Err(_) => return Err(Kind::InvalidNumber),
+            }
+        } else if ch.is_alphabetic() {
+            let start = i;
+            while i < c.len() && (c[i].is_alphanumeric() || c[i] == '_') {
+                i += 1;
+            }
+            let name: String = c[start..i].iter().collect();
+            let constant = CONSTANTS
+                .iter()
+                .find(|(n, _)| *n == name.to_ascii_lowercase());
+            let function = FUNCTIONS.iter().find(|(n, _)| *n == name);
+            let mut after = i;
+            while after < c.len() && (c[after] == ' ' || c[after] == '\t' || c[after] == '\n') {
+                after += 1;
+            }
+            let follower = c.get(after).copied();
+            if follower == Some('=') {
+                if constant.is_some() || function.is_some() || name == "ans" {
+                    return Err(Kind::ReservedName);
+                }
+                toks.push(Tok::Assign(name));
+                i = after + 1;
+            } else if let Some((_, value)) = constant {
+                toks.push(Tok::Num(*value));
+            } else if let Some((_, f)) = function.filter(|_| follower == Some('(')) {
+                toks.push(Tok::Call(*f));
+            } else if follower == Some('(') {
+                return Err(Kind::UnknownFunction);
+            } else {
+                toks.push(Tok::Var(name));
+            }
+        } else if ch == '|' {
+            let closes = matches!(
+                toks.last(),
+                Some(Tok::Num(_) | Tok::Var(_) | Tok::Close | Tok::BarClose | Tok::Percent)
+            );
+            if closes {
+                if open.pop() != Some(true) {
+                    return Err(Kind::MismatchedBars);
+                }
+                toks.push(Tok::BarClose);
+            } else {
+                open.push(true);
+                toks.push(Tok::BarOpen);
+            }
+            i += 1;
+        } else {
+            match ch {
+                '(' => open.push(false),
+                ')' if open.pop() == Some(true) => return Err(Kind::MismatchedBars),
+                _ => {}
+            }
+            let (tok, len) = match (ch, next) {
+                ('*', Some('*')) => (Tok::Sym("^"), 2),
+                ('/', Some('/')) => (Tok::Sym("//"), 2),
+                ('+', _) => (Tok::Sym("+"), 1),
+                ('-', _) => (Tok::Sym("-"), 1),
+                ('*', _) => (Tok::Sym("*"), 1),
+                ('/', _) => (Tok::Sym("/"), 1),
+                ('%', _) => {
+                    let mut after = i + 1;
+                    while after < c.len()
+                        && (c[after] == ' ' || c[after] == '\t' || c[after] == '\n')
+                    {
+                        after += 1;
+                    }
+                    let in_bar = open.last() == Some(&true);
+                    let starts = |a: Option<&char>| match a {
+                        Some('|') => !in_bar,
+                        Some(a) => a.is_alphanumeric() || *a == '.' || *a == '(',
+                        None => false,
+                    };
+                    if starts(c.get(after)) {
+                        (Tok::Sym("%"), 1)
+                    } else {
+                        (Tok::Percent, 1)
+                    }
+                }
+                ('^', _) => (Tok::Sym("^"), 1),
+                ('(', _) => (Tok::Open, 1),
+                (')', _) => (Tok::Close, 1),
+                (',', _) => (Tok::Comma, 1),
+                _ => return Err(Kind::BadToken),
+            };
+            toks.push(tok);
+            i += len;
+        }
+    }
+    if open.contains(&true) {
+        return Err(Kind::MismatchedBars);
+    }
+    Ok(toks)
+}
+
+fn check_brackets(toks: &[Tok]) -> Result<(), Kind> {
+    let mut depth: i64 = 0;
+    for tok in toks {
+        match tok {
+            Tok::Open => depth += 1,
+            Tok::Close if depth == 0 => return Err(Kind::MismatchedParens),
+            Tok::Close => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth == 0 {
+        Ok(())
+    } else {
+        Err(Kind::MismatchedParens)
+    }
+}
+
+struct Parser<'a> {
+    toks: &'a [Tok],
+    at: usize,
+    /// The earliest error found so far that only counts if the grammar is
+    /// otherwise satisfied, with the token it was found at.
+    deferred: Option<(usize, Kind)>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.at)
+    }
+
+    /// Consumes the next token if it is one of the operators `syms`.
+    fn eat_sym(&mut self, syms: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Tok::Sym(s)) if syms.contains(s) => {
+                let s = *s;
+                self.at += 1;
+                Some(s)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect_close(&mut self) -> Result<(), Kind> {
+        match self.peek() {
+            Some(Tok::Close) => {
+                self.at += 1;
+                Ok(())
+            }
+            _ => Err(Kind::InvalidExpression),
+        }
+    }
+
+    fn defer(&mut self, at: usize, kind: Kind) {
+        if self.deferred.is_none_or(|(earlier, _)| at < earlier) {
+            self.deferred = Some((at, kind));
+        }
+    }
+
+    /// One or more comma-separated expressions. Unless they are `call`
+    /// arguments, a comma among them is an error.
+    fn list(&mut self, call: bool) -> Result<Vec<Expr>, Kind> {
+        let mut items = vec![self.expr()?];
+        while let Some(Tok::Comma) = self.peek() {
+            if !call {
+                self.defer(self.at, Kind::InvalidExpression);
+            }
+            self.at += 1;
+            items.push(self.expr()?);
+        }
+        Ok(items)
+    }
+
+    fn expr(&mut self) -> Result<Expr, Kind> {
+        if let Some(Tok::Assign(name)) = self.peek() {
+            let name = name.clone();
+            self.at += 1;
+            return Ok(Expr::Assign(name, Box::new(self.expr()?)));
+        }
+        self.sum()
+    }
+
+    fn sum(&mut self) -> Result<Expr, Kind> {
+        let mut left = self.term(false)?;
+        while let Some(op) = self.eat_sym(&["+", "-"]) {
+            let right = self.term(true)?;
+            left = Expr::Bin(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// `after_binary` as in [`Parser::unary`], for the first operand.
+    fn term(&mut self, after_binary: bool) -> Result<Expr, Kind> {
+        let mut left = self.unary(after_binary)?;
+        while let Some(op) = self.eat_sym(&["*", "/", "//", "%"]) {
+            let right = self.unary(true)?;
+            left = Expr::Bin(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// Straight after a binary operator, `after_binary`, a `+` is rejected
+    /// as doing nothing, though a `-` is fine: `2 + + 3` is invalid but
+    /// `2 + -3` and `2 + -+3` aren't.
+    fn unary(&mut self, after_binary: bool) -> Result<Expr, Kind> {
+        match self.eat_sym(&["-", "+"]) {
+            Some("-") => Ok(Expr::Neg(Box::new(self.unary(false)?))),
+            Some(_) if after_binary => Err(Kind::InvalidExpression),
+            Some(_) => self.unary(false),
+            None => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Expr, Kind> {
+        let base = self.postfix()?;
+        if self.eat_sym(&["^"]).is_some() {
+            let exponent = self.unary(true)?;
+            return Ok(Expr::Bin("^", Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn postfix(&mut self) -> Result<Expr, Kind> {
+        let mut operand = self.primary()?;
+        while let Some(Tok::Percent) = self.peek() {
+            self.at += 1;
+            operand = Expr::Percent(Box::new(operand));
+        }
+        Ok(operand)
+    }
+
+    fn primary(&mut self) -> Result<Expr, Kind> {
+        match self.peek().cloned() {
+            Some(Tok::Num(value)) => {
+                self.at += 1;
+                Ok(Expr::Num(value))
+            }
+            Some(Tok::Var(name)) => {
+                self.at += 1;
+                Ok(Expr::Var(name))
+            }
+            Some(Tok::Call(f)) => {
+                self.at += 1;
+                if !matches!(self.peek(), Some(Tok::Open)) {
+                    return Err(Kind::InvalidExpression);
+                }
+                self.at += 1;
+                let arguments = self.list(true)?;
+                if !f.takes(arguments.len()) {
+                    self.defer(self.at, Kind::WrongArity);
+                }
+                self.expect_close()?;
+                Ok(Expr::Call(f, arguments))
+            }
+            Some(Tok::BarOpen) => {
+                self.at += 1;
+                let arguments = self.list(true)?;
+                if arguments.len() != 1 {
+                    self.defer(self.at, Kind::WrongArity);
+                }
+                match self.peek() {
+                    Some(Tok::BarClose) => self.at += 1,
+                    _ => return Err(Kind::InvalidExpression),
+                }
+                Ok(Expr::Call(Apply::One(f64::abs), arguments))
+            }
+            Some(Tok::Open) => {
+                self.at += 1;
+                let mut inner = self.list(false)?;
+                self.expect_close()?;
+                Ok(inner.remove(0))
+            }
+            _ => Err(Kind::InvalidExpression),
+        }
+    }
+}
+
+fn eval(expr: &Expr, env: &mut HashMap<String, f64>) -> Result<f64, Kind> {
+    match expr {
+        Expr::Num(value) => Ok(*value),
+        Expr::Var(name) if name == "ans" => Err(Kind::NoPreviousResult),
+        Expr::Var(name) => env.get(name).copied().ok_or(Kind::UndefinedVariable),
+        Expr::Assign(name, value) => {
+            let value = eval(value, env)?;
+            env.insert(name.clone(), value);
+            Ok(value)
+        }
+        Expr::Neg(inner) => Ok(-eval(inner, env)?),
+        Expr::Percent(inner) => Ok(eval(inner, env)? / 100.0),
+        Expr::Call(f, arguments) => {
+            let values: Result<Vec<f64>, Kind> = arguments.iter().map(|a| eval(a, env)).collect();
+            f.call(&values?)
+        }
+        Expr::Bin(op, left, right) => {
+            let (a, b) = (eval(left, env)?, eval(right, env)?);
+            match *op {
+                "+" => Ok(a + b),
+                "-" => Ok(a - b),
+                "*" => Ok(a * b),
+                "/" | "//" | "%" if b == 0.0 => Err(Kind::DivisionByZero),
+                "/" => Ok(a / b),
+                "//" => Ok((a / b).floor()),
+                "%" => Ok(a % b),
+                "^" => power(a, b),
+                _ => unreachable!("no operator {}", op),
+            }
+        }
+    }
+}
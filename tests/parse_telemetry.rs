@@ -0,0 +1,104 @@
+//! The parse-error hook of a `ConfiguredCalculator`, driven through its
+//! public API.
+
+use std::sync::{Arc, Mutex};
+
+use calculator::{Assoc, Calculator, CharClass, ConfiguredCalculator, Error, ParseFailureStats};
+
+/// A calculator whose hook records every masked source and error kind.
+fn hooked(
+    calc: &mut ConfiguredCalculator,
+) -> (Arc<Mutex<Vec<String>>>, Arc<Mutex<ParseFailureStats>>) {
+    let seen: Arc<Mutex<Vec<String>>> = Arc::default();
+    let stats: Arc<Mutex<ParseFailureStats>> = Arc::default();
+    let (seen_sink, stats_sink) = (Arc::clone(&seen), Arc::clone(&stats));
+    calc.on_parse_error(move |source, error| {
+        seen_sink.lock().unwrap().push(source.to_string());
+        stats_sink.lock().unwrap().record(error);
+    });
+    (seen, stats)
+}
+
+#[test]
+fn test_parse_error_hook() {
+    let mut calc = Calculator::builder().max_depth(3).build();
+    let (seen, stats) = hooked(&mut calc);
+
+    assert_eq!(
+        calc.calculate("12 + f(1)"),
+        Err(Error::UnknownFunction {
+            name: "f".to_string(),
+            pos: 5
+        })
+    );
+    assert!(calc.calculate("(1 + 2").is_err());
+    assert!(calc.parse("3 pi").is_err());
+    assert!(calc.calculate_integers("2 * #").is_err());
+    // Found before tokenizing, but a parse error all the same.
+    assert_eq!(
+        calc.calculate_complex("((((1))))"),
+        Err(Error::TooDeep { depth: 4, max: 3 })
+    );
+    // Evaluation errors and successes are not reported.
+    assert_eq!(calc.calculate("1 / 0"), Err(Error::DivisionByZero));
+    assert_eq!(
+        calc.calculate_located("x + 1").unwrap_err().error.kind(),
+        "UndefinedVariable"
+    );
+    assert_eq!(calc.calculate("1 + 1"), Ok(2.0));
+
+    // Numbers are masked before the hook sees the source.
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            "<num> + f(<num>)",
+            "(<num> + <num>",
+            "<num> pi",
+            "<num> * #",
+            "((((<num>))))"
+        ]
+    );
+    let stats = stats.lock().unwrap().clone();
+    assert_eq!(stats.total, 5);
+    assert_eq!(stats.by_kind["UnknownFunction"], 1);
+    assert_eq!(stats.by_kind["BadToken"], 1);
+    assert_eq!(stats.by_kind["MismatchedParens"], 1);
+    assert_eq!(stats.by_kind["UnexpectedToken"], 1);
+    assert_eq!(stats.by_kind["TooDeep"], 1);
+    assert_eq!(stats.by_class[&CharClass::Letter], 1);
+    assert_eq!(stats.by_class[&CharClass::Symbol], 1);
+    assert_eq!(stats.by_class[&CharClass::Bracket], 1);
+
+    // A panicking hook doesn't change what the caller gets.
+    calc.on_parse_error(|_, _| panic!("buggy hook"));
+    assert_eq!(
+        calc.calculate("2 + @"),
+        Err(Error::BadToken { ch: '@', pos: 4 })
+    );
+    assert_eq!(calc.calculate("2 + 2"), Ok(4.0));
+
+    let (seen, _) = hooked(&mut calc);
+    calc.clear_parse_error_hook();
+    assert!(calc.calculate("2 + #").is_err());
+    assert!(seen.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_hooks_are_per_calculator() {
+    let mut first = Calculator::builder().build();
+    let mut second = Calculator::builder().build();
+    second
+        .register_operator('@', 15, Assoc::Left, |l, r| Ok(l + r))
+        .unwrap();
+    let (first_seen, _) = hooked(&mut first);
+    let (second_seen, _) = hooked(&mut second);
+
+    assert!(first.calculate("1 @ 2").is_err());
+    assert_eq!(second.calculate("1 @ 2"), Ok(3.0));
+    assert!(second.calculate("1 @").is_err());
+    // The static API has no hook at all.
+    assert!(Calculator::calculate("1 +").is_err());
+
+    assert_eq!(*first_seen.lock().unwrap(), ["<num> @ <num>"]);
+    assert_eq!(*second_seen.lock().unwrap(), ["<num> @"]);
+}
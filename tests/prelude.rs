@@ -0,0 +1,42 @@
+//! Touches every prelude item so that dropping one breaks the build.
+
+use calculator::prelude::*;
+
+#[test]
+fn test_prelude_calculator_and_error() {
+    assert_eq!(Calculator::calculate("2 + 3").unwrap(), 5.0);
+
+    assert_eq!(
+        Calculator::calculate("1 / 0").unwrap_err(),
+        Error::DivisionByZero
+    );
+}
+
+#[test]
+fn test_prelude_batch_types() {
+    let report: BatchReport = Calculator::calculate_batch(["1 + 1", "2 +"]);
+    let failed: Vec<&BatchItem> = report.failures().collect();
+
+    assert_eq!(report.succeeded(), 1);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].index, 1);
+}
+
+#[test]
+fn test_prelude_configured_calculator() {
+    let builder: CalculatorBuilder = Calculator::builder().max_depth(1);
+    let calc: ConfiguredCalculator = builder.build();
+
+    assert_eq!(calc.calculate("(2)"), Ok(2.0));
+    assert_eq!(calc.calculate_integers("7 // 2"), Ok(Value::Int(3)));
+}
+
+#[test]
+fn test_prelude_environment_and_located_error() {
+    let mut env = Environment::new();
+    env.calculate("x = 4").unwrap();
+    assert_eq!(env.calculate("x * 2"), Ok(8.0));
+
+    let located: LocatedError = Calculator::calculate_located("1 / 0").unwrap_err();
+    assert_eq!(located.error, Error::DivisionByZero);
+}
@@ -0,0 +1,58 @@
+//! Uses the crate only as a dependency would, through its public items.
+
+use calculator::{Calculator, Error, EvalOptions, Operator, Span, Token};
+
+#[test]
+fn test_parse_postfix_evaluate() {
+    let tokens = Calculator::parse("2 * (3 + 4)").unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Number(2.0),
+            Token::Op(Operator::Multiply),
+            Token::Bracket('('),
+            Token::Number(3.0),
+            Token::Op(Operator::Add),
+            Token::Number(4.0),
+            Token::Bracket(')'),
+        ]
+    );
+
+    let postfix = Calculator::try_to_postfix(tokens).unwrap();
+    assert_eq!(postfix.len(), 5);
+    assert_eq!(Calculator::evaluate(postfix), Ok(14.0));
+    assert_eq!(Calculator::calculate("2 * (3 + 4)"), Ok(14.0));
+}
+
+#[test]
+fn test_spans_and_options() {
+    let (_, spans) = Calculator::parse_with_spans("12 + 3").unwrap();
+    assert_eq!(spans[0], Span { start: 0, end: 2 });
+
+    let implicit = EvalOptions {
+        implicit_multiplication: true,
+        ..EvalOptions::default()
+    };
+    assert_eq!(Calculator::calculate_with("2(3)", &implicit), Ok(6.0));
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(Calculator::calculate("1 / 0"), Err(Error::DivisionByZero));
+    assert_eq!(
+        Calculator::calculate("2 +"),
+        Err(Error::UnexpectedToken { pos: 2 })
+    );
+    assert_eq!(
+        Calculator::calculate("2 @ 3"),
+        Err(Error::BadToken { ch: '@', pos: 2 })
+    );
+    assert!(matches!(
+        Calculator::calculate("(1"),
+        Err(Error::MismatchedParens { .. })
+    ));
+    assert_eq!(
+        Calculator::calculate("1 / 0").unwrap_err().to_string(),
+        "division by zero"
+    );
+}
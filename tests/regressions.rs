@@ -0,0 +1,117 @@
+//! Replays every case in `tests/regressions/` through `Calculator::calculate`.
+//!
+//! Each case is a small text file:
+//!
+//! ```text
+//! input: 5 -
+//! expect: error UnexpectedToken { pos: 2 }
+//! ```
+//!
+//! `expect` is either `value <f64 Display>` or `error <Error Debug>`. New
+//! cases are added with `calculator --add-regression "<expr>"`.
+
+use std::fs;
+use std::path::Path;
+
+use calculator::{Calculator, Error, Operator, Token};
+
+fn outcome(result: &Result<f64, Error>) -> String {
+    match result {
+        Ok(value) => format!("value {}", value),
+        Err(e) => format!("error {:?}", e),
+    }
+}
+
+#[test]
+fn test_replay_regression_corpus() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/regressions");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    paths.sort();
+    assert!(
+        !paths.is_empty(),
+        "no regression cases in {}",
+        dir.display()
+    );
+
+    let mut failures = Vec::new();
+    for path in &paths {
+        let contents = fs::read_to_string(path).unwrap();
+        let field = |name: &str| {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix(name))
+                .unwrap_or_else(|| panic!("{}: missing '{}' line", path.display(), name))
+        };
+        let input = field("input: ");
+        let expect = field("expect: ");
+
+        let actual = outcome(&Calculator::calculate(input));
+        if actual != expect {
+            failures.push(format!(
+                "{}: {:?} expected {}, got {}",
+                path.display(),
+                input,
+                expect,
+                actual
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_deep_nesting() {
+    let depth = 50_000;
+    let expr = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+    assert_eq!(outcome(&Calculator::calculate(&expr)), "value 1");
+
+    let unclosed = format!("{}1{}", "(".repeat(depth), ")".repeat(depth - 1));
+    assert_eq!(
+        outcome(&Calculator::calculate(&unclosed)),
+        "error MismatchedParens { unmatched_open: [0], unmatched_close: [] }"
+    );
+}
+
+#[test]
+fn test_hand_built_bracket_tokens() {
+    // Token vectors that never went through `parse` must not panic. An
+    // unmatched bracket of either kind is rejected by `try_to_postfix`, at
+    // its index among the tokens.
+    let cases = vec![
+        (
+            vec![Token::Bracket(')'), Token::Number(1.0)],
+            "error MismatchedParens { unmatched_open: [], unmatched_close: [0] }",
+        ),
+        (
+            vec![Token::Bracket('('), Token::Number(1.0)],
+            "error MismatchedParens { unmatched_open: [0], unmatched_close: [] }",
+        ),
+        (
+            vec![
+                Token::Number(1.0),
+                Token::Op(Operator::Add),
+                Token::Bracket('('),
+            ],
+            "error MismatchedParens { unmatched_open: [2], unmatched_close: [] }",
+        ),
+        (
+            vec![Token::Bracket('[')],
+            "error MismatchedParens { unmatched_open: [], unmatched_close: [0] }",
+        ),
+        // Balanced brackets around nothing are for evaluation to reject.
+        (
+            vec![Token::Bracket('('), Token::Bracket(')')],
+            "error InvalidExpression",
+        ),
+    ];
+
+    for (tokens, expect) in cases {
+        let result = Calculator::try_to_postfix(tokens.clone()).and_then(Calculator::evaluate);
+        assert_eq!(outcome(&result), expect, "{:?}", tokens);
+    }
+}
@@ -0,0 +1,105 @@
+//! A run of digits broken up by whitespace, as in `1 000`, is never read as
+//! a product or as one number, whatever the options: implicit
+//! multiplication would make it `1 * 0`, and ignoring the space would
+//! guess at a grouping the input doesn't declare. Every combination of
+//! settings must report `Error::SpaceInNumber`.
+
+use calculator::{Calculator, Division, Error, EvalOptions};
+
+/// Spaced digits, each with where its first space is.
+const SPACED: [(&str, usize); 14] = [
+    ("1 000", 1),
+    ("1 000 000", 1),
+    ("12 345.67", 2),
+    ("2 2", 1),
+    ("0 5", 1),
+    ("1.5 25", 3),
+    ("1e3 500", 3),
+    ("1\t000", 1),
+    ("1  \n  000", 1),
+    ("-1 000", 2),
+    ("(1 000)", 2),
+    ("2 * 1 000 + 3", 5),
+    // No-break, narrow no-break and ideographic spaces, as pasted from
+    // documents that group thousands with them.
+    ("1\u{00A0}000", 1),
+    ("1\u{202F}000\u{3000}000", 1),
+];
+
+fn every_option() -> Vec<EvalOptions> {
+    let mut all = Vec::new();
+    for division in [Division::FloatAlways, Division::TruncateWhenBothIntegral] {
+        for implicit_multiplication in [false, true] {
+            all.push(EvalOptions {
+                division,
+                implicit_multiplication,
+                ..EvalOptions::default()
+            });
+        }
+    }
+    all
+}
+
+#[test]
+fn test_spaced_digits_rejected_under_every_option() {
+    for options in every_option() {
+        for (expr, pos) in SPACED {
+            let expected = Some(Error::SpaceInNumber { pos });
+            assert_eq!(
+                Calculator::calculate_with(expr, &options).err(),
+                expected,
+                "{:?} with {:?}",
+                expr,
+                options
+            );
+            assert_eq!(
+                Calculator::parse_with(expr, &options).err(),
+                expected,
+                "{:?} with {:?}",
+                expr,
+                options
+            );
+        }
+    }
+}
+
+#[test]
+fn test_spaced_digits_rejected_by_every_entry_point() {
+    for (expr, pos) in SPACED {
+        let expected = Some(Error::SpaceInNumber { pos });
+        assert_eq!(Calculator::calculate(expr).err(), expected, "{:?}", expr);
+        assert_eq!(Calculator::parse(expr).err(), expected, "{:?}", expr);
+        assert_eq!(Calculator::verify(expr).err(), expected, "{:?}", expr);
+        assert_eq!(
+            Calculator::calculate_located(expr).err().map(|e| e.error),
+            expected,
+            "{:?}",
+            expr
+        );
+    }
+}
+
+#[test]
+fn test_numbers_next_to_other_operands_unaffected() {
+    let implicit = EvalOptions {
+        implicit_multiplication: true,
+        ..EvalOptions::default()
+    };
+    let pi = std::f64::consts::PI;
+    for (expr, value) in [
+        ("1000", 1000.0),
+        ("1 + 000", 1.0),
+        ("2 pi", 2.0 * pi),
+        ("pi 2", 2.0 * pi),
+        ("2 (3)", 6.0),
+        ("(2) 3", 6.0),
+        ("2 sqrt(9)", 6.0),
+    ] {
+        assert_eq!(
+            Calculator::calculate_with(expr, &implicit),
+            Ok(value),
+            "{:?}",
+            expr
+        );
+    }
+}